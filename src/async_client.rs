@@ -0,0 +1,196 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! An asynchronous wrapper around `Client`.
+//!
+//! Operations are sent to a pool of threads, each of which owns its own
+//! `Client`, the result is returned via a `Receiver`.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+use ::Client;
+use ::error::EsError;
+use ::operations::search::SearchResult;
+use ::query::Query;
+
+/// `Box<FnOnce>` cannot be called directly, so jobs are boxed as this trait
+/// instead.
+trait Job: Send {
+    fn call_box(self: Box<Self>, client: &mut Client);
+}
+
+impl<F: FnOnce(&mut Client) + Send> Job for F {
+    fn call_box(self: Box<Self>, client: &mut Client) {
+        (*self)(client)
+    }
+}
+
+/// A client which owns a pool of threads, each with its own connection.
+/// Operations return a `Receiver` from which the result can be read when ready,
+/// allowing several requests to be in-flight concurrently.
+pub struct AsyncClient {
+    sender: Mutex<Sender<Box<Job>>>
+}
+
+impl AsyncClient {
+    /// Create a new client with `threads` connections to the given host/port
+    pub fn new(host: &str, port: u32, threads: usize) -> AsyncClient {
+        AsyncClient::with_clients(threads, || Client::new(host, port))
+    }
+
+    /// Create a new client with `threads` threads, each using a `Client` from
+    /// `make_client`, e.g. to use several hosts, a retry policy or a timeout
+    pub fn with_clients<F>(threads: usize, mut make_client: F) -> AsyncClient
+        where F: FnMut() -> Client
+    {
+        let (tx, rx) = channel::<Box<Job>>();
+        let rx = Arc::new(Mutex::new(rx));
+        for _ in 0..threads {
+            let rx = rx.clone();
+            let mut client = make_client();
+            thread::spawn(move || {
+                loop {
+                    let job = match rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_)  => break
+                    };
+                    job.call_box(&mut client);
+                }
+            });
+        }
+        AsyncClient {
+            sender: Mutex::new(tx)
+        }
+    }
+
+    /// Execute an arbitrary operation on one of the pooled clients.  The
+    /// operation must own everything it needs.  If the operation panics the
+    /// result is an error, and the thread remains available for other
+    /// operations.
+    pub fn execute<F, T>(&self, f: F) -> Receiver<Result<T, EsError>>
+        where F: FnOnce(&mut Client) -> Result<T, EsError> + Send + 'static,
+              T: Send + 'static
+    {
+        let (tx, rx) = channel();
+        let no_threads = tx.clone();
+        let job = move |client: &mut Client| {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| f(client)))
+                .unwrap_or_else(|_| Err(EsError::EsError("Operation panicked".to_string())));
+            // The receiver may have been dropped, if so nobody wants the result
+            let _ = tx.send(result);
+        };
+        let sent = match self.sender.lock() {
+            Ok(sender) => sender.send(Box::new(job)).is_ok(),
+            Err(_)     => false
+        };
+        if !sent {
+            let _ = no_threads.send(Err(EsError::EsError("No threads to execute the operation"
+                                                         .to_string())));
+        }
+        rx
+    }
+
+    /// Search via the query DSL
+    pub fn search_query<'a>(&'a self) -> AsyncSearchQueryOperation<'a> {
+        AsyncSearchQueryOperation::new(self)
+    }
+}
+
+/// An asynchronous search using the query DSL, unlike `SearchQueryOperation`
+/// this owns all its parameters so it can be sent to another thread.
+pub struct AsyncSearchQueryOperation<'a> {
+    /// The client that will execute this search
+    client:    &'a AsyncClient,
+
+    /// The indexes to which this query applies
+    indexes:   Vec<String>,
+
+    /// The types to which this query applies
+    doc_types: Vec<String>,
+
+    /// The query
+    query:     Option<Query>,
+
+    /// From
+    from:      i64,
+
+    /// Size
+    size:      i64
+}
+
+impl<'a> AsyncSearchQueryOperation<'a> {
+    fn new(client: &'a AsyncClient) -> AsyncSearchQueryOperation<'a> {
+        AsyncSearchQueryOperation {
+            client:    client,
+            indexes:   Vec::new(),
+            doc_types: Vec::new(),
+            query:     None,
+            from:      0,
+            size:      10
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &[&str]) -> &mut Self {
+        self.indexes = indexes.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &[&str]) -> &mut Self {
+        self.doc_types = doc_types.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_query(&mut self, query: Query) -> &mut Self {
+        self.query = Some(query);
+        self
+    }
+
+    pub fn with_from(&mut self, from: i64) -> &mut Self {
+        self.from = from;
+        self
+    }
+
+    pub fn with_size(&mut self, size: i64) -> &mut Self {
+        self.size = size;
+        self
+    }
+
+    /// Sends the search, the result will be available on the returned
+    /// `Receiver`.  The operation itself can be sent again.
+    pub fn send_async(&self) -> Receiver<Result<SearchResult, EsError>> {
+        let indexes = self.indexes.clone();
+        let doc_types = self.doc_types.clone();
+        let query = self.query.clone();
+        let from = self.from;
+        let size = self.size;
+        self.client.execute(move |client| {
+            let indexes:Vec<&str> = indexes.iter().map(|s| &s[..]).collect();
+            let doc_types:Vec<&str> = doc_types.iter().map(|s| &s[..]).collect();
+            // No query is equivalent to `match_all`
+            let query = query.unwrap_or(Query::build_match_all().build());
+            client.search_query()
+                .with_indexes(&indexes)
+                .with_types(&doc_types)
+                .with_query(&query)
+                .with_from(from)
+                .with_size(size)
+                .send()
+        })
+    }
+}
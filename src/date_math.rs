@@ -0,0 +1,351 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Date-math index names, e.g. `<logs-{now/d}>`, which ElasticSearch resolves
+//! to the name of an index at the time of the request.
+//!
+//! Such names can be used anywhere an index name can, they are encoded as
+//! required when building the URL of a request.  `resolve_date_math` resolves
+//! a name client-side, e.g. to know in advance which index will be used.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ::error::EsError;
+
+/// The format used when a date-math section does not specify one
+const DEFAULT_FORMAT: &'static str = "YYYY.MM.dd";
+
+const MONTH_NAMES: [&'static str; 12] = ["January", "February", "March", "April", "May",
+                                         "June", "July", "August", "September", "October",
+                                         "November", "December"];
+
+const MILLIS_PER_DAY: i64 = 86400000;
+
+/// Whether an index name is a date-math expression
+pub fn is_date_math(index: &str) -> bool {
+    index.starts_with('<') && index.ends_with('>')
+}
+
+/// Percent-encodes a date-math index name for use in a URL, other names are
+/// unchanged
+pub fn encode_index_name(index: &str) -> String {
+    if !is_date_math(index) {
+        return index.to_string();
+    }
+    let mut encoded = String::with_capacity(index.len() * 2);
+    for c in index.chars() {
+        match c {
+            '<' => encoded.push_str("%3C"),
+            '>' => encoded.push_str("%3E"),
+            '{' => encoded.push_str("%7B"),
+            '}' => encoded.push_str("%7D"),
+            '|' => encoded.push_str("%7C"),
+            '+' => encoded.push_str("%2B"),
+            ':' => encoded.push_str("%3A"),
+            ',' => encoded.push_str("%2C"),
+            '/' => encoded.push_str("%2F"),
+            '\\' => encoded.push_str("%5C"),
+            ' ' => encoded.push_str("%20"),
+            _   => encoded.push(c)
+        }
+    }
+    encoded
+}
+
+/// Resolves a date-math index name as ElasticSearch would at the time `now`,
+/// names which are not date-math expressions are returned unchanged.
+///
+/// Each section is of the form `{date_math_expr{date_format|time_zone}}`,
+/// where the format and time zone are optional, e.g. `<logs-{now/M{YYYY.MM}}>`.
+/// The format is a Joda-style pattern; the time zone, used for both rounding
+/// and formatting, must be a fixed offset such as `+01:00`.  Braces can be
+/// escaped with a backslash.
+pub fn resolve_date_math(expr: &str, now: SystemTime) -> Result<String, EsError> {
+    if !is_date_math(expr) {
+        return Ok(expr.to_string());
+    }
+    let now = match now.duration_since(UNIX_EPOCH) {
+        Ok(d)  => (d.as_secs() as i64) * 1000 + (d.subsec_nanos() / 1000000) as i64,
+        Err(e) => -((e.duration().as_secs() as i64) * 1000 +
+                    (e.duration().subsec_nanos() / 1000000) as i64)
+    };
+
+    let mut resolved = String::new();
+    let mut chars = expr[1..expr.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) => resolved.push(escaped),
+                None          => return Err(invalid(expr, "trailing escape"))
+            },
+            '{'  => resolved.push_str(&try!(resolve_section(expr, &mut chars, now))),
+            '}'  => return Err(invalid(expr, "unexpected '}'")),
+            _    => resolved.push(c)
+        }
+    }
+    Ok(resolved)
+}
+
+fn invalid(expr: &str, reason: &str) -> EsError {
+    EsError::EsError(format!("Invalid date-math expression {}: {}", expr, reason))
+}
+
+/// Resolves a single `{...}` section, the opening brace has already been
+/// consumed
+fn resolve_section<I>(expr: &str, chars: &mut I, now: i64) -> Result<String, EsError>
+    where I: Iterator<Item = char> {
+    let mut math = String::new();
+    let mut format_spec = None;
+    loop {
+        match chars.next() {
+            Some('{') => {
+                let mut spec = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c)   => spec.push(c),
+                        None      => return Err(invalid(expr, "unclosed format"))
+                    }
+                }
+                format_spec = Some(spec);
+            },
+            Some('}') => break,
+            Some(c)   => {
+                if format_spec.is_some() {
+                    return Err(invalid(expr, "expected '}' after format"));
+                }
+                math.push(c)
+            },
+            None      => return Err(invalid(expr, "unclosed '{'"))
+        }
+    }
+
+    let (format, offset) = match format_spec {
+        Some(ref spec) => {
+            let mut parts = spec.splitn(2, '|');
+            let format = parts.next().unwrap_or("");
+            let format = if format.is_empty() { DEFAULT_FORMAT } else { format };
+            let offset = match parts.next() {
+                Some(tz) => try!(parse_offset(tz).ok_or_else(|| {
+                    invalid(expr, &format!("unsupported time zone {}", tz))
+                })),
+                None     => 0
+            };
+            (format, offset)
+        },
+        None           => (DEFAULT_FORMAT, 0)
+    };
+
+    let local = try!(evaluate(expr, &math, now + offset));
+    format_date(expr, format, local)
+}
+
+/// A fixed time-zone offset in milliseconds, e.g. `+01:00`, `-0530` or `Z`
+fn parse_offset(tz: &str) -> Option<i64> {
+    if tz == "Z" || tz == "UTC" {
+        return Some(0);
+    }
+    let sign = match tz.chars().next() {
+        Some('+') => 1,
+        Some('-') => -1,
+        _         => return None
+    };
+    let digits:String = tz[1..].chars().filter(|&c| c != ':').collect();
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_digit(10)) {
+        return None;
+    }
+    let hours:i64 = digits[0..2].parse().unwrap();
+    let minutes:i64 = digits[2..4].parse().unwrap();
+    Some(sign * (hours * 60 + minutes) * 60000)
+}
+
+/// Evaluates a date-math expression, e.g. `now-1d/d`, where `now` is local
+/// time in milliseconds
+fn evaluate(expr: &str, math: &str, now: i64) -> Result<i64, EsError> {
+    if !math.starts_with("now") {
+        return Err(invalid(expr, "date math must start with 'now'"));
+    }
+    let mut time = now;
+    let mut chars = math[3..].chars().peekable();
+    while let Some(op) = chars.next() {
+        match op {
+            '+' | '-' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_digit(10) {
+                        break;
+                    }
+                    digits.push(c);
+                    chars.next();
+                }
+                let amount:i64 = if digits.is_empty() {
+                    1
+                } else {
+                    try!(digits.parse().map_err(|_| invalid(expr, "invalid amount")))
+                };
+                let amount = if op == '-' { -amount } else { amount };
+                let unit = try!(chars.next().ok_or_else(|| invalid(expr, "missing unit")));
+                time = try!(add(expr, time, amount, unit));
+            },
+            '/'       => {
+                let unit = try!(chars.next().ok_or_else(|| invalid(expr, "missing unit")));
+                time = try!(round_down(expr, time, unit));
+            },
+            _         => return Err(invalid(expr, &format!("unexpected '{}'", op)))
+        }
+    }
+    Ok(time)
+}
+
+fn add(expr: &str, time: i64, amount: i64, unit: char) -> Result<i64, EsError> {
+    Ok(match unit {
+        'y'       => add_months(time, amount * 12),
+        'M'       => add_months(time, amount),
+        'w'       => time + amount * 7 * MILLIS_PER_DAY,
+        'd'       => time + amount * MILLIS_PER_DAY,
+        'h' | 'H' => time + amount * 3600000,
+        'm'       => time + amount * 60000,
+        's'       => time + amount * 1000,
+        _         => return Err(invalid(expr, &format!("unknown unit '{}'", unit)))
+    })
+}
+
+/// Adds months, the day is clamped to the length of the resulting month
+fn add_months(time: i64, months: i64) -> i64 {
+    let (days, millis) = split_days(time);
+    let (year, month, day) = civil_from_days(days);
+    let total = year * 12 + (month - 1) + months;
+    let (year, month) = (floor_div(total, 12), total - floor_div(total, 12) * 12 + 1);
+    let day = if day > days_in_month(year, month) { days_in_month(year, month) } else { day };
+    days_from_civil(year, month, day) * MILLIS_PER_DAY + millis
+}
+
+fn round_down(expr: &str, time: i64, unit: char) -> Result<i64, EsError> {
+    let (days, millis) = split_days(time);
+    let (year, month, _) = civil_from_days(days);
+    Ok(match unit {
+        'y'       => days_from_civil(year, 1, 1) * MILLIS_PER_DAY,
+        'M'       => days_from_civil(year, month, 1) * MILLIS_PER_DAY,
+        // Weeks start on Monday, the epoch was a Thursday
+        'w'       => (days - (days + 3 - floor_div(days + 3, 7) * 7)) * MILLIS_PER_DAY,
+        'd'       => days * MILLIS_PER_DAY,
+        'h' | 'H' => days * MILLIS_PER_DAY + millis - millis % 3600000,
+        'm'       => days * MILLIS_PER_DAY + millis - millis % 60000,
+        's'       => days * MILLIS_PER_DAY + millis - millis % 1000,
+        _         => return Err(invalid(expr, &format!("unknown unit '{}'", unit)))
+    })
+}
+
+/// Formats a time with a Joda-style pattern, text in single quotes is literal
+fn format_date(expr: &str, format: &str, time: i64) -> Result<String, EsError> {
+    let (days, millis) = split_days(time);
+    let (year, month, day) = civil_from_days(days);
+    let mut formatted = String::new();
+    let chars:Vec<char> = format.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let mut count = 1;
+        while i + count < chars.len() && chars[i + count] == c {
+            count += 1;
+        }
+        match c {
+            'y' | 'Y' | 'x' if count == 2 => formatted.push_str(&pad(year % 100, 2)),
+            'y' | 'Y' | 'x' => formatted.push_str(&pad(year, count)),
+            'M' if count == 3 => formatted.push_str(&MONTH_NAMES[(month - 1) as usize][..3]),
+            'M' if count > 3  => formatted.push_str(MONTH_NAMES[(month - 1) as usize]),
+            'M' => formatted.push_str(&pad(month, count)),
+            'd' => formatted.push_str(&pad(day, count)),
+            'H' => formatted.push_str(&pad(millis / 3600000, count)),
+            'm' => formatted.push_str(&pad(millis / 60000 % 60, count)),
+            's' => formatted.push_str(&pad(millis / 1000 % 60, count)),
+            'S' => formatted.push_str(&pad(millis % 1000, 3)[..if count < 3 { count } else { 3 }]),
+            '\'' => {
+                // Quoted literal text, a doubled quote is a single quote
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != '\'' {
+                    formatted.push(chars[j]);
+                    j += 1;
+                }
+                if j == i + 1 {
+                    formatted.push('\'');
+                }
+                i = j + 1;
+                continue;
+            },
+            c if c.is_alphabetic() => {
+                return Err(invalid(expr, &format!("unsupported format '{}'", c)))
+            },
+            c => for _ in 0..count {
+                formatted.push(c)
+            }
+        }
+        i += count;
+    }
+    Ok(formatted)
+}
+
+fn pad(value: i64, width: usize) -> String {
+    format!("{:0width$}", value, width = width)
+}
+
+fn floor_div(a: i64, b: i64) -> i64 {
+    let d = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { d - 1 } else { d }
+}
+
+/// Splits a time into days since the epoch and milliseconds into the day
+fn split_days(time: i64) -> (i64, i64) {
+    let days = floor_div(time, MILLIS_PER_DAY);
+    (days, time - days * MILLIS_PER_DAY)
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2                => if is_leap_year(year) { 29 } else { 28 },
+        4 | 6 | 9 | 11   => 30,
+        _                => 31
+    }
+}
+
+/// Days since the epoch of a date in the proleptic Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = floor_div(year, 400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day of a number of days since the epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = floor_div(days, 146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 -
+                       day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
@@ -17,13 +17,133 @@
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
 use hyper;
 use hyper::client::response;
 use rustc_serialize::json;
+use rustc_serialize::json::Json;
+
+use operations::ShardFailure;
 
 // Error handling
 
+/// The cause of an error reported by ElasticSearch
+#[derive(Debug, Clone)]
+pub struct ErrorCause {
+    pub error_type: Option<String>,
+    pub reason:     String,
+    pub index:      Option<String>
+}
+
+impl ErrorCause {
+    /// Older versions of ElasticSearch give the error as a string, newer
+    /// versions as an object
+    fn from_json(r: &Json) -> Option<ErrorCause> {
+        match *r {
+            Json::String(ref reason) => Some(ErrorCause {
+                error_type: None,
+                reason:     reason.clone(),
+                index:      None
+            }),
+            Json::Object(_)          => Some(ErrorCause {
+                error_type: r.find("type")
+                    .and_then(|t| t.as_string())
+                    .map(|t| t.to_string()),
+                reason:     r.find("reason")
+                    .and_then(|t| t.as_string())
+                    .unwrap_or("")
+                    .to_string(),
+                index:      r.find("index")
+                    .and_then(|t| t.as_string())
+                    .map(|t| t.to_string())
+            }),
+            _                        => None
+        }
+    }
+}
+
+impl fmt::Display for ErrorCause {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref error_type) = self.error_type {
+            try!(write!(f, "{}: ", error_type));
+        }
+        try!(write!(f, "{}", self.reason));
+        if let Some(ref index) = self.index {
+            try!(write!(f, " [{}]", index));
+        }
+        Ok(())
+    }
+}
+
+/// An error response from ElasticSearch
+#[derive(Debug, Clone)]
+pub struct ResponseError {
+    /// The ID of the request, see `Client::with_request_id`
+    pub request_id: String,
+    pub status:     u16,
+    pub error:      ErrorCause,
+    pub root_cause: Vec<ErrorCause>
+}
+
+impl ResponseError {
+    /// Parses an error response, if it is one
+    pub fn from_json(request_id: &str, status: u16, r: &Json) -> Option<ResponseError> {
+        r.find("error").and_then(|e| ErrorCause::from_json(e)).map(|error| {
+            ResponseError {
+                request_id: request_id.to_string(),
+                status:     status,
+                error:      error,
+                root_cause: r.find_path(&["error", "root_cause"])
+                    .and_then(|rc| rc.as_array())
+                    .map(|rc| rc.iter().filter_map(|c| ErrorCause::from_json(c)).collect())
+                    .unwrap_or(Vec::new())
+            }
+        })
+    }
+
+    /// The ID and description of the block, if this is the error of a
+    /// request rejected by a cluster or index block, e.g. `(8, "index write
+    /// (api)")` when `index.blocks.write` is set.  Only the first block is
+    /// given if there are several.
+    pub fn cluster_block(&self) -> Option<(u32, String)> {
+        // ElasticSearch 1.x only gives the reason, as
+        // `ClusterBlockException[blocked by: [FORBIDDEN/8/index write (api)];]`
+        let is_block = match self.error.error_type {
+            Some(ref error_type) => error_type == "cluster_block_exception",
+            None                 => self.error.reason.starts_with("ClusterBlockException")
+        };
+        if !is_block {
+            return None;
+        }
+        let reason = &self.error.reason;
+        let start = match reason.find("blocked by: [") {
+            Some(start) => start + "blocked by: [".len(),
+            None        => return None
+        };
+        let block = match reason[start..].find(']') {
+            Some(end) => &reason[start..start + end],
+            None      => return None
+        };
+        // The level (e.g. `FORBIDDEN`), ID and description
+        let mut parts = block.splitn(3, '/').skip(1);
+        match (parts.next().and_then(|id| id.parse().ok()), parts.next()) {
+            (Some(id), Some(description)) => Some((id, description.to_string())),
+            _                             => None
+        }
+    }
+
+    /// Whether this is the error of a request with a version which is not the
+    /// current version of the document, e.g. a versioned get in a multi-get
+    pub fn is_version_conflict(&self) -> bool {
+        match self.error.error_type {
+            Some(ref error_type) => error_type == "version_conflict_engine_exception",
+            None                 => self.error.reason.starts_with("VersionConflictEngineException")
+        }
+    }
+}
+
 /// Error that can occur include IO and parsing errors, as well as specific
 /// errors from the ElasticSearch server and logic errors from this library
 #[derive(Debug)]
@@ -34,6 +154,65 @@ pub enum EsError {
     /// An error reported in a JSON response from the ElasticSearch server
     EsServerError(String),
 
+    /// An error response from the ElasticSearch server, with the reason
+    ResponseError(ResponseError),
+
+    /// A conflict reported by the ElasticSearch server, e.g. a version
+    /// conflict or attempting to `create` a document that already exists
+    ConflictError(String),
+
+    /// The document that an operation applies to does not exist
+    DocumentMissingError(String),
+
+    /// An index that an operation applies to does not exist
+    IndexMissingError(String),
+
+    /// An attempt to create an index which already exists
+    IndexAlreadyExistsError(String),
+
+    /// A request rejected by a cluster or index block, e.g. a write to an
+    /// index with `index.blocks.write` set.  See `Client::is_writable`.
+    IndexBlocked {
+        block_id: u32,
+        reason:   String
+    },
+
+    /// A source document that could not be decoded, with the document's ID
+    DocumentDecodeError(String, json::DecoderError),
+
+    /// A URI which exceeds the client's maximum length, with the length
+    UriTooLong(usize),
+
+    /// A document ID which exceeds the client's maximum length, with the
+    /// length in bytes and the maximum.  See `Client::with_max_id_length`.
+    InvalidId(usize, usize),
+
+    /// One or more shards failed, only returned by operations where this has
+    /// been requested
+    ShardFailureError(Vec<ShardFailure>),
+
+    /// An aggregation with more buckets than the client's maximum, with the
+    /// name of the aggregation and the number of buckets.  See
+    /// `Client::with_max_buckets`.
+    TooManyBucketsError(String, usize),
+
+    /// Every attempt at a request failed to connect or timed out, with the
+    /// host of each attempt, in order, and the error of the final attempt.
+    /// See `Client::with_retry_policy`.
+    RetriesExhaustedError(Vec<String>, Box<EsError>),
+
+    /// A batch of a batched request failed as a whole, this is the error of
+    /// each item in that batch, sharing the error of the batch.  See
+    /// `MGetOperation::send_batched`.
+    BatchFailed(Arc<EsError>),
+
+    /// A request did not complete before its deadline, with the time taken and
+    /// the number of attempts made.  See `Client::with_deadline`.
+    DeadlineExceeded {
+        elapsed:  Duration,
+        attempts: u32
+    },
+
     /// Miscellaneous error from the HTTP library
     HttpError(hyper::error::Error),
 
@@ -44,7 +223,28 @@ pub enum EsError {
     JsonError(json::DecoderError),
 
     /// Miscllenaeous JSON building error
-    JsonBuilderError(json::BuilderError)
+    JsonBuilderError(json::BuilderError),
+
+    /// Miscellaneous JSON encoding error
+    JsonEncoderError(json::EncoderError)
+}
+
+/// A cluster or index block is an `EsError::IndexBlocked`, a version conflict
+/// an `EsError::ConflictError`, any other error response an
+/// `EsError::ResponseError`
+impl From<ResponseError> for EsError {
+    fn from(err: ResponseError) -> EsError {
+        match err.cluster_block() {
+            Some((block_id, reason))          => EsError::IndexBlocked {
+                block_id: block_id,
+                reason:   reason
+            },
+            None if err.is_version_conflict() => {
+                EsError::ConflictError(format!("[{}] {}", err.request_id, err.error))
+            },
+            None                              => EsError::ResponseError(err)
+        }
+    }
 }
 
 impl From<io::Error> for EsError {
@@ -71,6 +271,56 @@ impl From<json::BuilderError> for EsError {
     }
 }
 
+impl From<json::EncoderError> for EsError {
+    fn from(err: json::EncoderError) -> EsError {
+        EsError::JsonEncoderError(err)
+    }
+}
+
+impl EsError {
+    /// The IO error underlying this error, if any
+    fn io_error(&self) -> Option<&io::Error> {
+        match *self {
+            EsError::HttpError(hyper::error::Error::Io(ref err)) => Some(err),
+            EsError::IoError(ref err)                            => Some(err),
+            EsError::RetriesExhaustedError(_, ref err)           => err.io_error(),
+            EsError::BatchFailed(ref err)                        => err.io_error(),
+            _                                                    => None
+        }
+    }
+
+    /// Whether a connection to ElasticSearch could not be made, or was lost,
+    /// including a failed TLS handshake.  The underlying error is available
+    /// via `cause`.
+    pub fn is_connection_error(&self) -> bool {
+        match *self {
+            EsError::HttpError(hyper::error::Error::Ssl(_)) => return true,
+            EsError::RetriesExhaustedError(_, ref err)      => return err.is_connection_error(),
+            EsError::BatchFailed(ref err)                   => return err.is_connection_error(),
+            _                                               => ()
+        }
+        match self.io_error().map(|err| err.kind()) {
+            Some(io::ErrorKind::ConnectionRefused) |
+            Some(io::ErrorKind::ConnectionReset) |
+            Some(io::ErrorKind::ConnectionAborted) |
+            Some(io::ErrorKind::NotConnected) |
+            Some(io::ErrorKind::BrokenPipe) |
+            Some(io::ErrorKind::AddrNotAvailable) => true,
+            _                                     => false
+        }
+    }
+
+    /// Whether a request timed out.  Depending on the platform, a read timeout
+    /// is reported as either `TimedOut` or `WouldBlock`.
+    pub fn is_timeout(&self) -> bool {
+        match self.io_error().map(|err| err.kind()) {
+            Some(io::ErrorKind::TimedOut) |
+            Some(io::ErrorKind::WouldBlock) => true,
+            _                               => false
+        }
+    }
+}
+
 impl<'a> From<&'a mut response::Response> for EsError {
     fn from(err: &'a mut response::Response) -> EsError {
         EsError::EsServerError(format!("{} - {:?}", err.status, err))
@@ -82,10 +332,25 @@ impl Error for EsError {
         match *self {
             EsError::EsError(ref err) => err,
             EsError::EsServerError(ref err) => err,
+            EsError::ResponseError(ref err) => &err.error.reason,
+            EsError::ConflictError(ref err) => err,
+            EsError::DocumentMissingError(ref err) => err,
+            EsError::IndexMissingError(ref err) => err,
+            EsError::IndexAlreadyExistsError(ref err) => err,
+            EsError::IndexBlocked { ref reason, .. } => reason,
+            EsError::DocumentDecodeError(_, ref err) => err.description(),
+            EsError::UriTooLong(_) => "URI too long",
+            EsError::InvalidId(_, _) => "Invalid document ID",
+            EsError::ShardFailureError(_) => "Shard failure",
+            EsError::TooManyBucketsError(_, _) => "Too many buckets",
+            EsError::RetriesExhaustedError(_, _) => "Retries exhausted",
+            EsError::BatchFailed(_) => "Batch failed",
+            EsError::DeadlineExceeded { .. } => "Deadline exceeded",
             EsError::HttpError(ref err) => err.description(),
             EsError::IoError(ref err) => err.description(),
             EsError::JsonError(ref err) => err.description(),
-            EsError::JsonBuilderError(ref err) => err.description()
+            EsError::JsonBuilderError(ref err) => err.description(),
+            EsError::JsonEncoderError(ref err) => err.description()
         }
     }
 
@@ -93,10 +358,25 @@ impl Error for EsError {
         match *self {
             EsError::EsError(_)                => None,
             EsError::EsServerError(_)          => None,
+            EsError::ResponseError(_)          => None,
+            EsError::ConflictError(_)          => None,
+            EsError::DocumentMissingError(_)   => None,
+            EsError::IndexMissingError(_)      => None,
+            EsError::IndexAlreadyExistsError(_) => None,
+            EsError::IndexBlocked { .. }       => None,
+            EsError::DocumentDecodeError(_, ref err) => Some(err as &Error),
+            EsError::UriTooLong(_)             => None,
+            EsError::InvalidId(_, _)           => None,
+            EsError::ShardFailureError(_)      => None,
+            EsError::TooManyBucketsError(_, _) => None,
+            EsError::RetriesExhaustedError(_, ref err) => Some(&**err as &Error),
+            EsError::BatchFailed(ref err)      => Some(&**err as &Error),
+            EsError::DeadlineExceeded { .. }   => None,
             EsError::HttpError(ref err)        => Some(err as &Error),
             EsError::IoError(ref err)          => Some(err as &Error),
             EsError::JsonError(ref err)        => Some(err as &Error),
-            EsError::JsonBuilderError(ref err) => Some(err as &Error)
+            EsError::JsonBuilderError(ref err) => Some(err as &Error),
+            EsError::JsonEncoderError(ref err) => Some(err as &Error)
         }
     }
 }
@@ -106,10 +386,47 @@ impl fmt::Display for EsError {
         match *self {
             EsError::EsError(ref s) => fmt::Display::fmt(s, f),
             EsError::EsServerError(ref s) => fmt::Display::fmt(s, f),
+            EsError::ResponseError(ref err) => {
+                write!(f, "[{}] {} - {}", err.request_id, err.status, err.error)
+            },
+            EsError::ConflictError(ref s) => fmt::Display::fmt(s, f),
+            EsError::DocumentMissingError(ref s) => fmt::Display::fmt(s, f),
+            EsError::IndexMissingError(ref s) => fmt::Display::fmt(s, f),
+            EsError::IndexAlreadyExistsError(ref s) => fmt::Display::fmt(s, f),
+            EsError::IndexBlocked { block_id, ref reason } => {
+                write!(f, "Blocked by [{}] {}", block_id, reason)
+            },
+            EsError::DocumentDecodeError(ref id, ref err) => {
+                write!(f, "Cannot decode document {}: {}", id, err)
+            },
+            EsError::UriTooLong(len) => write!(f, "URI too long: {} bytes", len),
+            EsError::InvalidId(len, max) => {
+                write!(f, "Document ID too long: {} bytes, the maximum is {}", len, max)
+            },
+            EsError::ShardFailureError(ref failures) => {
+                try!(write!(f, "Shard failure:"));
+                for failure in failures {
+                    try!(write!(f, " [{:?} {:?}] {};", failure.index, failure.shard, failure.reason));
+                }
+                Ok(())
+            },
+            EsError::TooManyBucketsError(ref name, count) => {
+                write!(f, "Aggregation {} has too many buckets: {}", name, count)
+            },
+            EsError::RetriesExhaustedError(ref hosts, ref err) => {
+                write!(f, "Retries exhausted, tried {}: {}", hosts.join(", "), err)
+            },
+            EsError::BatchFailed(ref err) => write!(f, "Batch failed: {}", err),
+            EsError::DeadlineExceeded { elapsed, attempts } => {
+                write!(f, "Deadline exceeded after {}ms and {} attempts",
+                       elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1000000) as u64,
+                       attempts)
+            },
             EsError::HttpError(ref err) => fmt::Display::fmt(err, f),
             EsError::IoError(ref err) => fmt::Display::fmt(err, f),
             EsError::JsonError(ref err) => fmt::Display::fmt(err, f),
-            EsError::JsonBuilderError(ref err) => fmt::Display::fmt(err, f)
+            EsError::JsonBuilderError(ref err) => fmt::Display::fmt(err, f),
+            EsError::JsonEncoderError(ref err) => fmt::Display::fmt(err, f)
         }
     }
 }
@@ -0,0 +1,92 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Document IDs.
+//!
+//! ElasticSearch limits the length of a document ID to 512 bytes, the client
+//! checks IDs against this limit (see `Client::with_max_id_length`) rather
+//! than sending a request which would fail.  `hashed_id` derives an ID of
+//! fixed length from a string of any length, e.g. a long URL.
+
+/// Derives a document ID from a string of any length, this is the SHA-1 of
+/// the UTF-8 of the string as 40 lowercase hex digits.  The same string always
+/// gives the same ID.
+pub fn hashed_id(s: &str) -> String {
+    sha1(s.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// SHA-1, as specified by RFC 3174
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h:[u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    // Padded with a single bit, then zeros, then the length in bits, to a
+    // multiple of 64 bytes
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bit_len = (data.len() as u64).wrapping_mul(8);
+    for i in (0..8).rev() {
+        message.push((bit_len >> (i * 8)) as u8);
+    }
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = (block[i * 4] as u32) << 24
+                | (block[i * 4 + 1] as u32) << 16
+                | (block[i * 4 + 2] as u32) << 8
+                | (block[i * 4 + 3] as u32);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for i in 0..80 {
+            let (f, k) = match i {
+                0...19  => ((b & c) | (!b & d), 0x5A827999),
+                20...39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40...59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _       => (b ^ c ^ d, 0xCA62C1D6)
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(w[i]);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        h[0] = h[0].wrapping_add(a);
+        h[1] = h[1].wrapping_add(b);
+        h[2] = h[2].wrapping_add(c);
+        h[3] = h[3].wrapping_add(d);
+        h[4] = h[4].wrapping_add(e);
+    }
+
+    let mut digest = [0u8; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            digest[i * 4 + j] = (word >> (24 - j * 8)) as u8;
+        }
+    }
+    digest
+}
@@ -21,79 +21,205 @@
 
 #[macro_use]
 extern crate log;
+#[macro_use]
 extern crate hyper;
 extern crate rustc_serialize;
 
 #[macro_use]
 pub mod util;
 
+pub mod async_client;
+pub mod date_math;
 pub mod error;
+pub mod id;
 pub mod operations;
 pub mod query;
+pub mod retry;
+
+use std::cell::Cell;
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::Read;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use hyper::status::StatusCode;
 
 use rustc_serialize::Encodable;
-use rustc_serialize::json::{self, Json};
+use rustc_serialize::json::{self, Json, ToJson};
 
-use error::EsError;
+use error::{EsError, ResponseError};
+use operations::bulk::{BulkAction, BulkOperation};
+use operations::cat::{CatRecoveryOperation, CatThreadPoolOperation};
+use operations::count::CountOperation;
 use operations::delete::{DeleteOperation, DeleteByQueryOperation};
+use operations::explain::{ExplainOperation, ValidateQueryOperation};
 use operations::get::GetOperation;
 use operations::index::IndexOperation;
-use operations::search::{SearchURIOperation, SearchQueryOperation};
+use operations::index_admin::{index_exists, is_writable};
+use operations::index_admin::{CreateIndexOperation, DeleteIndexOperation};
+use operations::index_admin::{GetMappingOperation, GetSettingsOperation};
+use operations::index_admin::{UpgradeOperation, UpgradeStatusOperation};
+use operations::mget::MGetOperation;
+use operations::mlt::MoreLikeThisOperation;
+use operations::msearch::MultiSearchOperation;
+use operations::percolate::{MultiPercolateOperation, PercolateAction, PercolateOperation};
+use operations::references::{verify_references, ReferenceReport};
+use operations::search::{ClearScrollOperation, DefaultSearchOptions,
+                         DeleteSearchTemplateOperation, GetSearchTemplateOperation,
+                         PutSearchTemplateOperation, RenderSearchTemplateOperation,
+                         ScrollOperation, SearchTemplateOperation, SearchURIOperation,
+                         SearchQueryOperation};
+use operations::search::federated::FederatedSearch;
+use operations::search::suggest::{Suggest, SuggestOperation};
+use operations::stats::{IndicesStatsOperation, NodesStatsOperation};
+use operations::termvectors::{MultiTermVectorsOperation, TermVectorsOperation};
+use operations::update::{UpdateBody, UpdateOperation};
 use operations::RefreshOperation;
+use retry::{ClientEvent, Observer, RetryPolicy};
 
 // The client
 
+// Sent with each request, this identifies requests in ElasticSearch's logs
+header! { (XOpaqueId, "X-Opaque-Id") => [String] }
+
 /// Process the result of an HTTP request
-fn do_req(resp: &mut hyper::client::response::Response)
+fn do_req(resp: &mut hyper::client::response::Response, request_id: &str)
           -> Result<(StatusCode, Option<Json>), EsError> {
-    info!("Response: {:?}", resp);
+    trace!(target: "rs_es::client", "[{}] Response: {:?}", request_id, resp);
     match resp.status {
         StatusCode::Ok |
         StatusCode::Created |
         StatusCode::NotFound => match Json::from_reader(resp) {
-            Ok(json) => Ok((resp.status, Some(json))),
+            Ok(json) => {
+                trace!(target: "rs_es::client", "[{}] Response body: {}", request_id, json);
+                Ok((resp.status, Some(json)))
+            },
             Err(e)   => Err(EsError::from(e))
         },
-        _                    => Err(EsError::from(resp))
+        StatusCode::Conflict => {
+            let json = try!(Json::from_reader(resp));
+            Err(EsError::ConflictError(format!("[{}] {}", request_id, json)))
+        },
+        _                    => {
+            // The body, if any, usually explains the error
+            let mut body = String::new();
+            let _ = resp.read_to_string(&mut body);
+            let error = Json::from_str(&body).ok().and_then(|json| {
+                ResponseError::from_json(request_id, resp.status.to_u16(), &json)
+            });
+            match error {
+                Some(error) => Err(EsError::from(error)),
+                None        => Err(EsError::EsServerError(format!("[{}] {} - {}",
+                                                                  request_id,
+                                                                  resp.status,
+                                                                  body)))
+            }
+        }
     }
 }
 
+/// The default maximum length of a URI, many HTTP servers (including
+/// ElasticSearch by default) limit the request line to 4KB
+const DEFAULT_MAX_URI_LENGTH: usize = 4096;
+
+/// The default maximum length of a document ID in bytes, the limit imposed by
+/// ElasticSearch itself
+const DEFAULT_MAX_ID_LENGTH: usize = 512;
+
 /// The core of the ElasticSearch client, owns a HTTP connection
 pub struct Client {
-    base_url:    String,
-    http_client: hyper::Client
+    base_urls:         Vec<String>,
+
+    /// The host to which the next request is sent, each request is sent to
+    /// the next host in turn
+    next_host:         Cell<usize>,
+
+    /// The number of consecutive requests to each host which failed to
+    /// connect or timed out
+    host_failures:     Vec<Cell<u32>>,
+
+    http_client:       hyper::Client,
+
+    /// The timeout of each attempt at a request, see `with_timeout`
+    timeout:           Option<Duration>,
+    max_uri_length:    usize,
+    max_id_length:     usize,
+
+    /// Used to generate request IDs unique to this client
+    request_prefix:    u64,
+    request_count:     u64,
+
+    /// A caller-supplied ID for the next request
+    next_request_id:   Option<String>,
+
+    /// The ID of the most recent request
+    request_id:        String,
+
+    /// A caller-supplied deadline for the next request
+    next_deadline:     Option<Duration>,
+
+    /// When the most recent request started, and its deadline
+    deadline:          Option<(Instant, Duration)>,
+
+    /// The number of attempts made by the most recent request
+    attempts:          Cell<u32>,
+
+    /// If set, idempotent requests are retried when ElasticSearch is
+    /// overloaded or cannot be reached
+    retry_policy:      Option<RetryPolicy>,
+
+    /// If set, search results with an aggregation with more buckets than this
+    /// are an error
+    max_buckets:       Option<usize>,
+
+    /// The options each search starts from
+    search_defaults:   DefaultSearchOptions,
+
+    /// Receives the retries and failovers of each request
+    observer:          Option<Arc<Observer>>
 }
 
-/// Create a HTTP function for the given method (GET/PUT/POST/DELETE)
+/// Create a HTTP function for the given method (GET/PUT/POST/DELETE), the
+/// request is retried if `$idempotent`, see `Client::with_retry_policy`
 macro_rules! es_op {
-    ($n:ident,$cn:ident) => {
+    ($n:ident,$cn:ident,$idempotent:expr) => {
         fn $n(&mut self, url: &str)
               -> Result<(StatusCode, Option<Json>), EsError> {
-            info!("Doing {} on {}", stringify!($n), url);
-            let mut result = try!(self.http_client.$cn(&format!("{}/{}", self.base_url, url)).send());
-            do_req(&mut result)
+            let request_id = self.start_request();
+            debug!(target: "rs_es::client", "[{}] Doing {} on {}", request_id, stringify!($n), url);
+            let mut result = try!(self.send_req($idempotent, &request_id, url, |http_client, url| {
+                http_client
+                    .$cn(url)
+                    .header(XOpaqueId(request_id.clone()))
+                    .send()
+            }));
+            do_req(&mut result, &request_id)
         }
     }
 }
 
 /// Create a HTTP function with a request body for the given method
-/// (GET/PUT/POST/DELETE)
+/// (GET/PUT/POST/DELETE), the request is retried if `$idempotent`
 macro_rules! es_body_op {
-    ($n:ident,$cn:ident) => {
+    ($n:ident,$cn:ident,$idempotent:expr) => {
         fn $n<E>(&mut self, url: &str, body: &E)
                  -> Result<(StatusCode, Option<Json>), EsError>
             where E: Encodable {
-                info!("Doing {} on {}", stringify!($n), url);
+                let request_id = self.start_request();
+                debug!(target: "rs_es::client", "[{}] Doing {} on {}", request_id, stringify!($n), url);
                 let json_string = json::encode(body).unwrap();
-                info!("Body: {}", json_string);
-                let mut result = try!(self.http_client
-                                      .$cn(&format!("{}/{}", self.base_url, url))
-                                      .body(&json_string)
-                                      .send());
-
-                do_req(&mut result)
+                trace!(target: "rs_es::client", "[{}] Body: {}", request_id, json_string);
+                let mut result = try!(self.send_req($idempotent, &request_id, url, |http_client, url| {
+                    http_client
+                        .$cn(url)
+                        .header(XOpaqueId(request_id.clone()))
+                        .body(&json_string)
+                        .send()
+                }));
+                do_req(&mut result, &request_id)
             }
     }
 }
@@ -101,20 +227,354 @@ macro_rules! es_body_op {
 impl Client {
     /// Create a new client
     pub fn new(host: &str, port: u32) -> Client {
+        Client::with_hosts(&[(host, port)])
+    }
+
+    /// Create a new client for a cluster, requests are sent to each host in
+    /// turn.  If a retry policy is set, a request that cannot be sent to one
+    /// host is retried on the next, see `with_retry_policy`.
+    ///
+    /// Panics if `hosts` is empty.
+    pub fn with_hosts(hosts: &[(&str, u32)]) -> Client {
+        assert!(!hosts.is_empty(), "At least one host is required");
         Client {
-            base_url:    format!("http://{}:{}", host, port),
-            http_client: hyper::Client::new()
+            base_urls:       hosts.iter()
+                .map(|&(host, port)| format!("http://{}:{}", host, port))
+                .collect(),
+            next_host:       Cell::new(0),
+            host_failures:   hosts.iter().map(|_| Cell::new(0)).collect(),
+            http_client:     hyper::Client::new(),
+            timeout:         None,
+            max_uri_length:  DEFAULT_MAX_URI_LENGTH,
+            max_id_length:   DEFAULT_MAX_ID_LENGTH,
+            request_prefix:  RandomState::new().build_hasher().finish(),
+            request_count:   0,
+            next_request_id: None,
+            request_id:      String::new(),
+            next_deadline:   None,
+            deadline:        None,
+            attempts:        Cell::new(0),
+            retry_policy:    None,
+            max_buckets:     None,
+            search_defaults: DefaultSearchOptions::new(),
+            observer:        None
+        }
+    }
+
+    /// Retry requests which fail because ElasticSearch is overloaded, i.e.
+    /// with `429 Too Many Requests` or `503 Service Unavailable`, or because
+    /// the connection failed or timed out.  Each attempt is sent to the next
+    /// host.  Only requests which can safely be repeated are retried: gets,
+    /// searches and counts, but not index, update, delete (including
+    /// delete-index and delete-by-query) or bulk requests unless the policy
+    /// allows it, see `RetryPolicy::with_non_idempotent`.
+    ///
+    /// If every attempt gets an error response, the error is that of the final
+    /// attempt.  If every attempt fails to connect or times out, the error is
+    /// an `EsError::RetriesExhaustedError` listing the hosts tried.
+    ///
+    /// By default requests are not retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Client {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Set the maximum number of buckets of any aggregation in the results of
+    /// a search, if exceeded the search fails with an
+    /// `EsError::TooManyBucketsError`.  Buckets are counted without being
+    /// parsed.  By default there is no maximum.
+    pub fn with_max_buckets(mut self, max_buckets: usize) -> Client {
+        self.max_buckets = Some(max_buckets);
+        self
+    }
+
+    /// Set defaults for the options of every search made by this client, see
+    /// `DefaultSearchOptions` for how these combine with the options of each
+    /// search
+    pub fn with_default_search_options(mut self, defaults: DefaultSearchOptions) -> Client {
+        self.search_defaults = defaults;
+        self
+    }
+
+    /// Set an observer, which is given an event when a request is retried,
+    /// fails over to another host, or a host cannot be reached, see
+    /// `retry::ClientEvent`
+    pub fn with_observer<O: Observer + 'static>(mut self, observer: O) -> Client {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// The number of attempts made by the most recent request, including the
+    /// first, this is more than one if it was retried
+    pub fn attempts(&self) -> u32 {
+        self.attempts.get()
+    }
+
+    fn notify(&self, request_id: &str, event: ClientEvent) {
+        if let Some(ref observer) = self.observer {
+            observer.on_event(request_id, &event);
+        }
+    }
+
+    /// The maximum time to wait to send a request, and for each read of the
+    /// response, after which the request fails with an error for which
+    /// `EsError::is_timeout` is true.  By default there is no timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Client {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// The position of the host to which the next request is sent
+    fn take_host(&self) -> usize {
+        let host = self.next_host.get();
+        self.next_host.set((host + 1) % self.base_urls.len());
+        host
+    }
+
+    /// Sends a request to the next host, retrying according to the retry
+    /// policy if it is `idempotent`.  `send` is given the full URL for the
+    /// host of each attempt.
+    fn send_req<F>(&mut self, idempotent: bool, request_id: &str, url: &str, send: F)
+                   -> Result<hyper::client::response::Response, EsError>
+        where F: Fn(&hyper::Client, &str) -> hyper::Result<hyper::client::response::Response> {
+        let retry_policy = self.retry_policy.as_ref().and_then(|retry_policy| {
+            if idempotent || retry_policy.non_idempotent {
+                Some(retry_policy)
+            } else {
+                None
+            }
+        });
+        let mut hosts:Vec<String> = Vec::new();
+        let mut attempt = 1;
+        loop {
+            // The timeout of each attempt is reduced to the time remaining
+            let remaining = self.remaining();
+            if remaining == Some(Duration::new(0, 0)) {
+                return Err(self.deadline_exceeded(attempt - 1));
+            }
+            let timeout = match (self.timeout, remaining) {
+                (Some(timeout), Some(remaining)) => Some(cmp::min(timeout, remaining)),
+                (timeout, remaining)             => timeout.or(remaining)
+            };
+            self.http_client.set_read_timeout(timeout);
+            self.http_client.set_write_timeout(timeout);
+
+            self.attempts.set(attempt);
+            let host = self.take_host();
+            let base_url = &self.base_urls[host];
+            if hosts.last().map(|last| last != base_url).unwrap_or(false) {
+                self.notify(request_id, ClientEvent::FailoverTo { host: base_url.clone() });
+            }
+            hosts.push(base_url.clone());
+            let result = match send(&self.http_client, &format!("{}/{}", base_url, url)) {
+                Ok(result) => result,
+                Err(err)   => {
+                    let err = EsError::from(err);
+                    if err.is_connection_error() || err.is_timeout() {
+                        let failures = self.host_failures[host].get() + 1;
+                        self.host_failures[host].set(failures);
+                        self.notify(request_id, ClientEvent::HostMarkedDead {
+                            host:                 base_url.clone(),
+                            consecutive_failures: failures
+                        });
+                    }
+                    // Timed out because of the deadline, rather than the timeout
+                    if err.is_timeout() && remaining.is_some() && timeout == remaining {
+                        return Err(self.deadline_exceeded(attempt));
+                    }
+                    match retry_policy {
+                        Some(retry_policy) if retry_policy.should_retry_error(&err, attempt) => {
+                            info!(target: "rs_es::client",
+                                  "[{}] Retrying after {} from {}, attempt {} of {}",
+                                  request_id, err, base_url, attempt + 1, retry_policy.max_attempts);
+                            try!(self.retry_after(request_id, retry_policy, attempt, err.to_string()));
+                            attempt += 1;
+                            continue;
+                        },
+                        Some(_) if attempt > 1 && (err.is_connection_error() || err.is_timeout()) => {
+                            return Err(EsError::RetriesExhaustedError(hosts, Box::new(err)))
+                        },
+                        _ => return Err(err)
+                    }
+                }
+            };
+            self.host_failures[host].set(0);
+            if let Some(retry_policy) = retry_policy {
+                if retry_policy.should_retry(result.status, attempt) {
+                    info!(target: "rs_es::client", "[{}] Retrying after {} from {}, attempt {} of {}",
+                          request_id, result.status, base_url, attempt + 1, retry_policy.max_attempts);
+                    try!(self.retry_after(request_id,
+                                          retry_policy,
+                                          attempt,
+                                          result.status.to_string()));
+                    attempt += 1;
+                    continue;
+                }
+            }
+            return Ok(result);
+        }
+    }
+
+    /// Waits before the attempt after `attempt`, unless the deadline would
+    /// pass first
+    fn retry_after(&self,
+                   request_id:   &str,
+                   retry_policy: &RetryPolicy,
+                   attempt:      u32,
+                   reason:       String) -> Result<(), EsError> {
+        let delay = retry_policy.backoff(attempt);
+        if self.remaining().map(|remaining| remaining <= delay).unwrap_or(false) {
+            return Err(self.deadline_exceeded(attempt));
+        }
+        self.notify(request_id, ClientEvent::RetryScheduled {
+            attempt: attempt + 1,
+            delay:   delay,
+            reason:  reason
+        });
+        thread::sleep(delay);
+        Ok(())
+    }
+
+    /// The time remaining before the deadline of the current request, if it
+    /// has one
+    fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|(started, deadline)| {
+            deadline.checked_sub(started.elapsed()).unwrap_or(Duration::new(0, 0))
+        })
+    }
+
+    fn deadline_exceeded(&self, attempts: u32) -> EsError {
+        EsError::DeadlineExceeded {
+            elapsed:  self.deadline
+                .map(|(started, _)| started.elapsed())
+                .unwrap_or(Duration::new(0, 0)),
+            attempts: attempts
+        }
+    }
+
+    /// Set the ID of the next request, this is sent in the `X-Opaque-Id`
+    /// header and included in log messages and errors.  If not set, an ID is
+    /// generated.
+    pub fn with_request_id<S: Into<String>>(&mut self, request_id: S) -> &mut Client {
+        self.next_request_id = Some(request_id.into());
+        self
+    }
+
+    /// Set a deadline for the next request, the time within which it must
+    /// complete including any retries and the waits between them.  The
+    /// timeout of each attempt (see `with_timeout`) is reduced to the time
+    /// remaining, and a retry is not made if the deadline would pass during
+    /// the wait before it.  If the deadline passes the request fails with
+    /// `EsError::DeadlineExceeded`.
+    pub fn with_deadline(&mut self, deadline: Duration) -> &mut Client {
+        self.next_deadline = Some(deadline);
+        self
+    }
+
+    /// The ID of the most recent request
+    pub fn request_id(&self) -> &str {
+        &self.request_id
+    }
+
+    /// Allocates the ID for a request that is about to be sent
+    fn start_request(&mut self) -> String {
+        self.request_count += 1;
+        self.request_id = match self.next_request_id.take() {
+            Some(request_id) => request_id,
+            None             => format!("{:016x}-{}", self.request_prefix, self.request_count)
+        };
+        self.deadline = self.next_deadline.take().map(|deadline| (Instant::now(), deadline));
+        self.request_id.clone()
+    }
+
+    /// Set the maximum length of a URI.  Operations that would exceed this
+    /// will either be sent with the equivalent request body instead, or fail
+    /// with `EsError::UriTooLong`.
+    pub fn with_max_uri_length(mut self, max_uri_length: usize) -> Client {
+        self.max_uri_length = max_uri_length;
+        self
+    }
+
+    /// Set the maximum length of a document ID in bytes, by default 512 as
+    /// this is the limit of ElasticSearch.  Indexing, getting, updating or
+    /// deleting a document with a longer ID fails with `EsError::InvalidId`
+    /// without sending a request, see `id::hashed_id` for deriving a shorter
+    /// ID.
+    pub fn with_max_id_length(mut self, max_id_length: usize) -> Client {
+        self.max_id_length = max_id_length;
+        self
+    }
+
+    /// Checks the length of a document ID, in bytes rather than characters
+    fn check_id(&self, id: &str) -> Result<(), EsError> {
+        if id.len() > self.max_id_length {
+            Err(EsError::InvalidId(id.len(), self.max_id_length))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks the full length of the URI for the given URL, on whichever host
+    /// has the longest base URL
+    fn check_uri_length(&self, url: &str) -> Result<(), EsError> {
+        let base_url_len = self.base_urls.iter().map(|b| b.len()).max().unwrap_or(0);
+        let len = base_url_len + url.len() + 1;
+        if len > self.max_uri_length {
+            Err(EsError::UriTooLong(len))
+        } else {
+            Ok(())
         }
     }
 
-    es_op!(get_op, get);
+    es_op!(get_op, get, true);
+
+    es_op!(post_op, post, false);
+    es_body_op!(post_body_op, post, false);
+    es_op!(put_op, put, false);
+    es_body_op!(put_body_op, put, false);
+    // A delete may have succeeded even if its response was lost, in which
+    // case a retry would report the document, index, etc., as not found
+    es_op!(delete_op, delete, false);
+    es_body_op!(delete_body_op, delete, false);
+
+    // A POST which only reads, e.g. a search, so can be retried
+    es_body_op!(post_read_body_op, post, true);
 
-    es_op!(post_op, post);
-    es_body_op!(post_body_op, post);
-    es_op!(put_op, put);
-    es_body_op!(put_body_op, put);
-    es_op!(delete_op, delete);
-    es_body_op!(delete_body_op, delete);
+    // A DELETE which can safely be repeated, e.g. clearing a scroll
+    es_op!(delete_idempotent_op, delete, true);
+    es_body_op!(delete_idempotent_body_op, delete, true);
+
+    /// A HEAD request has no response body, so only the status is returned
+    fn head_op(&mut self, url: &str) -> Result<StatusCode, EsError> {
+        let request_id = self.start_request();
+        debug!(target: "rs_es::client", "[{}] Doing head_op on {}", request_id, url);
+        let result = try!(self.send_req(true, &request_id, url, |http_client, url| {
+            http_client
+                .head(url)
+                .header(XOpaqueId(request_id.clone()))
+                .send()
+        }));
+        trace!(target: "rs_es::client", "[{}] Response: {:?}", request_id, result);
+        Ok(result.status)
+    }
+
+    /// POST a pre-formatted body, for those APIs (e.g. bulk) where the body
+    /// is not a single JSON document.  The request is retried if
+    /// `idempotent`, e.g. a multi-search, see `with_retry_policy`.
+    fn post_raw_body_op(&mut self, url: &str, body: &str, idempotent: bool)
+                        -> Result<(StatusCode, Option<Json>), EsError> {
+        let request_id = self.start_request();
+        debug!(target: "rs_es::client", "[{}] Doing post_raw_body_op on {}", request_id, url);
+        trace!(target: "rs_es::client", "[{}] Body: {}", request_id, body);
+        let mut result = try!(self.send_req(idempotent, &request_id, url, |http_client, url| {
+            http_client
+                .post(url)
+                .header(XOpaqueId(request_id.clone()))
+                .body(body)
+                .send()
+        }));
+        do_req(&mut result, &request_id)
+    }
 
     /// Calls the base ES path, returning the version number
     pub fn version(&mut self) -> Result<String, EsError> {
@@ -144,6 +604,23 @@ impl Client {
         GetOperation::new(self, index, id)
     }
 
+    /// Implementation of the ES Multi-GET API
+    pub fn mget<'a>(&'a mut self) -> MGetOperation {
+        MGetOperation::new(self)
+    }
+
+    /// The terms of a document, either stored (`with_id`) or artificial
+    /// (`with_doc`)
+    pub fn termvectors<'a, 'b>(&'a mut self, index: &'b str, doc_type: &'b str)
+                               -> TermVectorsOperation<'a, 'b> {
+        TermVectorsOperation::new(self, index, doc_type)
+    }
+
+    /// The terms of several stored documents in a single request
+    pub fn mtermvectors<'a>(&'a mut self) -> MultiTermVectorsOperation {
+        MultiTermVectorsOperation::new(self)
+    }
+
     /// Delete by ID
     pub fn delete<'a>(&'a mut self,
                       index:    &'a str,
@@ -152,11 +629,135 @@ impl Client {
         DeleteOperation::new(self, index, doc_type, id)
     }
 
+    /// Explain how a query scores a specific document, or why it does not
+    /// match
+    pub fn explain<'a>(&'a mut self,
+                       index:    &'a str,
+                       doc_type: &'a str,
+                       id:       &'a str) -> ExplainOperation {
+        ExplainOperation::new(self, index, doc_type, id)
+    }
+
+    /// Which of the queries registered in an index match a document
+    pub fn percolate<'a, 'b>(&'a mut self, index: &'b str, doc_type: &'b str)
+                             -> PercolateOperation<'a, 'b> {
+        PercolateOperation::new(self, index, doc_type)
+    }
+
+    /// Several percolations in a single request
+    pub fn mpercolate<'a, 'b>(&'a mut self, actions: &'b [PercolateAction<'b>])
+                              -> MultiPercolateOperation<'a, 'b> {
+        MultiPercolateOperation::new(self, actions)
+    }
+
+    /// Search for documents like a stored document, ElasticSearch 1.x only
+    pub fn more_like_this<'a, 'b>(&'a mut self,
+                                  index:    &'b str,
+                                  doc_type: &'b str,
+                                  id:       &'b str) -> MoreLikeThisOperation<'a, 'b> {
+        MoreLikeThisOperation::new(self, index, doc_type, id)
+    }
+
+    /// Validate a query without running it
+    pub fn validate_query<'a>(&'a mut self) -> ValidateQueryOperation {
+        ValidateQueryOperation::new(self)
+    }
+
+    /// Update a specific document
+    pub fn update<'a>(&'a mut self,
+                      index:    &'a str,
+                      doc_type: &'a str,
+                      id:       &'a str) -> UpdateOperation {
+        UpdateOperation::new(self, index, doc_type, id)
+    }
+
+    /// Bulk
+    pub fn bulk<'a, 'b, E: Encodable>(&'a mut self, actions: &'b [BulkAction<E>])
+                                      -> BulkOperation<'a, 'b, E> {
+        BulkOperation::new(self, actions)
+    }
+
     /// Delete by query
     pub fn delete_by_query<'a>(&'a mut self) -> DeleteByQueryOperation {
         DeleteByQueryOperation::new(self)
     }
 
+    /// Create an index
+    pub fn create_index<'a, 'b>(&'a mut self, index: &'b str) -> CreateIndexOperation<'a, 'b> {
+        CreateIndexOperation::new(self, index)
+    }
+
+    /// Delete an index
+    pub fn delete_index<'a, 'b>(&'a mut self, index: &'b str) -> DeleteIndexOperation<'a, 'b> {
+        DeleteIndexOperation::new(self, index)
+    }
+
+    /// Get the mappings of an index
+    pub fn get_mapping<'a, 'b>(&'a mut self, index: &'b str) -> GetMappingOperation<'a, 'b> {
+        GetMappingOperation::new(self, index)
+    }
+
+    /// Get the settings of an index
+    pub fn get_settings<'a, 'b>(&'a mut self, index: &'b str) -> GetSettingsOperation<'a, 'b> {
+        GetSettingsOperation::new(self, index)
+    }
+
+    /// How much of each index is in segments written by an older version of
+    /// Lucene
+    pub fn upgrade_status<'a>(&'a mut self) -> UpgradeStatusOperation {
+        UpgradeStatusOperation::new(self)
+    }
+
+    /// Upgrade indexes to the current version of Lucene
+    pub fn upgrade<'a>(&'a mut self) -> UpgradeOperation {
+        UpgradeOperation::new(self)
+    }
+
+    /// The progress of the recovery of each shard
+    pub fn cat_recovery<'a>(&'a mut self) -> CatRecoveryOperation {
+        CatRecoveryOperation::new(self)
+    }
+
+    /// The active, queued and rejected tasks of thread pools on each node
+    pub fn cat_thread_pool<'a>(&'a mut self) -> CatThreadPoolOperation {
+        CatThreadPoolOperation::new(self)
+    }
+
+    /// Statistics of indexes, e.g. memory used by fielddata
+    pub fn indices_stats<'a>(&'a mut self) -> IndicesStatsOperation {
+        IndicesStatsOperation::new(self)
+    }
+
+    /// Statistics of the indexes on each node
+    pub fn nodes_stats<'a>(&'a mut self) -> NodesStatsOperation {
+        NodesStatsOperation::new(self)
+    }
+
+    /// Whether an index exists
+    pub fn index_exists(&mut self, index: &str) -> Result<bool, EsError> {
+        index_exists(self, index)
+    }
+
+    /// Whether an index can be written to, i.e. neither it nor the cluster has
+    /// a block on writes, e.g. `index.blocks.write` or
+    /// `index.blocks.read_only`.  A write to an index which is not writable is
+    /// an `EsError::IndexBlocked`.
+    pub fn is_writable(&mut self, index: &str) -> Result<bool, EsError> {
+        is_writable(self, index)
+    }
+
+    /// Checks that the documents and indexed scripts referred to by a query
+    /// exist, see `operations::references`
+    pub fn verify_query_references<Q: ToJson>(&mut self, query: &Q)
+                                              -> Result<ReferenceReport, EsError> {
+        verify_references(self, query)
+    }
+
+    /// Count the documents matching a query
+    pub fn count<'a>(&'a mut self) -> CountOperation {
+        CountOperation::new(self)
+    }
+
     /// Refresh
     pub fn refresh<'a>(&'a mut self) -> RefreshOperation {
         RefreshOperation::new(self)
@@ -171,6 +772,68 @@ impl Client {
     pub fn search_query<'a>(&'a mut self) -> SearchQueryOperation {
         SearchQueryOperation::new(self)
     }
+
+    /// Search by a template of the query DSL, with parameters
+    pub fn search_template<'a>(&'a mut self) -> SearchTemplateOperation {
+        SearchTemplateOperation::new(self)
+    }
+
+    /// The search body a template gives with the parameters, for debugging
+    /// templates
+    pub fn render_search_template<'a>(&'a mut self) -> RenderSearchTemplateOperation {
+        RenderSearchTemplateOperation::new(self)
+    }
+
+    /// Store a search template in the cluster, for use by ID
+    pub fn put_search_template<'a, 'b>(&'a mut self,
+                                       id: &'b str) -> PutSearchTemplateOperation<'a, 'b> {
+        PutSearchTemplateOperation::new(self, id)
+    }
+
+    pub fn get_search_template<'a, 'b>(&'a mut self,
+                                       id: &'b str) -> GetSearchTemplateOperation<'a, 'b> {
+        GetSearchTemplateOperation::new(self, id)
+    }
+
+    pub fn delete_search_template<'a, 'b>(&'a mut self,
+                                          id: &'b str) -> DeleteSearchTemplateOperation<'a, 'b> {
+        DeleteSearchTemplateOperation::new(self, id)
+    }
+
+    /// The next page of a scrolling search, `keep_alive` is how long to keep
+    /// the search open for the following page
+    pub fn scroll<'a, 'b>(&'a mut self,
+                          scroll_id:  &'b str,
+                          keep_alive: &'b str) -> ScrollOperation<'a, 'b> {
+        ScrollOperation::new(self, scroll_id, keep_alive)
+    }
+
+    /// Clear the given scrolls, rather than waiting for their keep-alive to
+    /// expire
+    pub fn clear_scroll<'a, 'b>(&'a mut self,
+                                scroll_ids: &'b [&'b str]) -> ClearScrollOperation<'a, 'b> {
+        ClearScrollOperation::new(self, Some(scroll_ids))
+    }
+
+    /// Clear every scroll of the cluster
+    pub fn clear_all_scrolls<'a>(&'a mut self) -> ClearScrollOperation<'a, 'static> {
+        ClearScrollOperation::new(self, None)
+    }
+
+    /// Suggestions, e.g. completions, without a search
+    pub fn suggest<'a, 'b>(&'a mut self, suggest: &'b Suggest) -> SuggestOperation<'a, 'b> {
+        SuggestOperation::new(self, suggest)
+    }
+
+    /// Several searches in a single request
+    pub fn msearch<'a>(&'a mut self) -> MultiSearchOperation {
+        MultiSearchOperation::new(self)
+    }
+
+    /// Search several indexes separately, merging the results by boosted score
+    pub fn federated_search<'a>(&'a mut self) -> FederatedSearch {
+        FederatedSearch::new(self)
+    }
 }
 
 #[cfg(test)]
@@ -179,22 +842,74 @@ mod tests {
     extern crate regex;
 
     use super::Client;
-    use super::operations::index::OpType;
+    use super::error::{EsError, ResponseError};
+    use super::id::hashed_id;
+    use super::async_client::AsyncClient;
+    use super::date_math::{encode_index_name, resolve_date_math};
+    use super::operations::bulk::{ActionOptions, ActionType, BulkAction, BulkResult};
+    use super::operations::cat::{thread_pool_rows, RecoveryRow, ThreadPoolRow};
+    use super::operations::{FromJson, TimeUnit, Timeout, WriteOutcome};
+    use super::operations::delete::{DeleteByQueryResult, DeleteResult};
+    use super::operations::explain::{ExplainResult, ValidateQueryResult};
+    use super::operations::get::GetResult;
+    use super::operations::index::{IndexResult, OpType};
+    use super::operations::index_admin::{needs_ancient_upgrade, needs_upgrade};
+    use super::operations::index_admin::{UpgradeResult, UpgradeStatusResult};
+    use super::operations::index_diff::{compare_indices, Change, IndexDiff};
+    use super::operations::search::{DefaultSearchOptions, Order, SearchQueryOperationBody};
+    use super::operations::search::Preference;
+    use super::operations::search::{MissingSource, SearchResult, SearchType};
+    use super::operations::search::SearchHitsHitsResult;
+    use super::operations::search::GetSearchTemplateResult;
+    use super::operations::search::{Missing, Mode, Rescore, RescoreMode};
+    use super::operations::search::{Sort, SortField, Source};
+    use super::operations::msearch::MultiSearchResult;
+    use super::operations::percolate::{MultiPercolateResult, PercolateAction, PercolateResult};
+    use super::operations::references::{find_references, DocumentReference, Reference};
+    use super::operations::script::{Script, ScriptFormat};
+    use super::operations::search::aggregations::{check_bucket_counts, Aggregation, Aggregations};
+    use super::operations::search::aggregations::{Metric, TermsAggregation};
+    use super::operations::search::federated::FederatedSearchResult;
+    use super::operations::search::highlight::{Highlight, Setting};
+    use super::operations::search::suggest::{Suggest, Suggestion};
+    use super::operations::stats::{IndicesStatsResult, NodesStatsResult};
+    use super::operations::termvectors::{MultiTermVectorsDoc, MultiTermVectorsResult};
+    use super::operations::termvectors::TermVectorsResult;
+    use super::operations::update::UpdateResult;
 
-    use super::query::{Filter, Query};
+    use super::query::{BoostMode, DurationUnit, Filter, IndexedShape, Query, RecencyBoost,
+                       TermsLookup};
+    use super::retry::{ClientEvent, RetryPolicy};
 
+    use std::collections::{BTreeMap, HashMap, HashSet};
     use std::env;
+    use std::error::Error;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use hyper::error::Error as HttpError;
+    use hyper::status::StatusCode;
+
+    use rustc_serialize::json::{Json, ToJson};
 
     use self::regex::Regex;
 
     // test setup
 
-    fn make_client() -> Client {
-        let hostname = match env::var("ES_HOST") {
+    fn es_host() -> String {
+        match env::var("ES_HOST") {
             Ok(val) => val,
             Err(_)  => "localhost".to_string()
-        };
-        Client::new(&hostname, 9200)
+        }
+    }
+
+    fn make_client() -> Client {
+        Client::new(&es_host(), 9200)
+    }
+
+    fn make_short_uri_client() -> Client {
+        Client::new(&es_host(), 9200).with_max_uri_length(80)
     }
 
     #[derive(Debug, RustcDecodable, RustcEncodable)]
@@ -281,6 +996,176 @@ mod tests {
             assert_eq!(result.id, "TEST_INDEXING_2");
             assert!(result.version >= 1);
         }
+        {
+            let result_wrapped = client
+                .index(index_name, "test_type")
+                .with_doc(&TestDocument::new().with_int_field(3))
+                .with_id("TEST_INDEXING_2")
+                .with_op_type(&OpType::Create)
+                .send();
+            match result_wrapped {
+                Err(EsError::ConflictError(_)) => (),
+                _ => panic!("Expected conflict, got: {:?}", result_wrapped)
+            }
+        }
+    }
+
+    #[test]
+    fn test_request_id() {
+        let mut client = make_client();
+        client.version().unwrap();
+        let generated_id = client.request_id().to_string();
+        assert!(generated_id.len() > 0);
+
+        client.with_request_id("test-request-id").version().unwrap();
+        assert_eq!("test-request-id", client.request_id());
+
+        client.version().unwrap();
+        assert!(client.request_id() != generated_id);
+        assert!(client.request_id() != "test-request-id");
+    }
+
+    #[test]
+    fn test_index_admin() {
+        let index_name = "test_index_admin";
+        let mut client = make_client();
+        client.delete_index(index_name).send().unwrap();
+        assert!(!client.index_exists(index_name).unwrap());
+
+        let mut mappings = BTreeMap::new();
+        let mut properties = BTreeMap::new();
+        let mut str_field = BTreeMap::new();
+        str_field.insert("type".to_string(), "string".to_json());
+        properties.insert("str_field".to_string(), Json::Object(str_field));
+        let mut test_type = BTreeMap::new();
+        test_type.insert("properties".to_string(), Json::Object(properties));
+        mappings.insert("test_type".to_string(), Json::Object(test_type));
+
+        let result = client.create_index(index_name)
+            .with_number_of_shards(1)
+            .with_number_of_replicas(0)
+            .with_mappings(&mappings)
+            .send().unwrap();
+        assert!(result.acknowledged);
+        assert!(client.index_exists(index_name).unwrap());
+
+        match client.create_index(index_name).send() {
+            Err(EsError::IndexAlreadyExistsError(_)) => (),
+            _                                        => panic!("Expected IndexAlreadyExistsError")
+        }
+
+        assert!(client.delete_index(index_name).send().unwrap());
+        assert!(!client.index_exists(index_name).unwrap());
+        assert!(!client.delete_index(index_name).send().unwrap());
+    }
+
+    #[test]
+    fn test_compare_indices() {
+        let index_a = "test_compare_indices_a";
+        let index_b = "test_compare_indices_b";
+        let mut client = make_client();
+        client.delete_index(index_a).send().unwrap();
+        client.delete_index(index_b).send().unwrap();
+        client.create_index(index_a).with_number_of_replicas(0).send().unwrap();
+        client.create_index(index_b).with_number_of_replicas(0).send().unwrap();
+
+        let diff = compare_indices(&mut client, index_a, index_b).unwrap();
+        assert!(diff.is_empty(), "Unexpected differences: {}", diff);
+
+        client.delete_index(index_a).send().unwrap();
+        client.delete_index(index_b).send().unwrap();
+    }
+
+    #[test]
+    fn test_index_diff() {
+        let mappings_a = Json::from_str("{\"t\":{\"properties\":{\
+                                         \"a\":{\"type\":\"string\",\"analyzer\":\"standard\"},\
+                                         \"b\":{\"type\":\"long\"}}}}").unwrap();
+        let mappings_b = Json::from_str("{\"t\":{\"properties\":{\
+                                         \"a\":{\"type\":\"string\",\"analyzer\":\"english\"},\
+                                         \"c\":{\"type\":\"long\"}}}}").unwrap();
+        let settings_a = Json::from_str("{\"index\":{\"number_of_shards\":\"5\",\
+                                         \"creation_date\":\"1\",\"uuid\":\"x\",\
+                                         \"version\":{\"created\":\"1\"}}}").unwrap();
+        let settings_b = Json::from_str("{\"index\":{\"number_of_shards\":\"5\",\
+                                         \"creation_date\":\"2\",\"uuid\":\"y\",\
+                                         \"version\":{\"created\":\"2\"}}}").unwrap();
+        let diff = IndexDiff::new(&mappings_a, &mappings_b, &settings_a, &settings_b);
+
+        let paths:Vec<&str> = diff.mappings.iter().map(|d| &d.path[..]).collect();
+        assert_eq!(vec!["t.properties.a.analyzer", "t.properties.b", "t.properties.c"], paths);
+        assert_eq!(Change::Changed("standard".to_json(), "english".to_json()),
+                   diff.mappings[0].change);
+        assert!(diff.settings.is_empty());
+        assert_eq!("Mappings:\n\
+                    \x20 ~ t.properties.a.analyzer: \"standard\" -> \"english\"\n\
+                    \x20 - t.properties.b: {\"type\":\"long\"}\n\
+                    \x20 + t.properties.c: {\"type\":\"long\"}\n",
+                   diff.to_string());
+    }
+
+    #[test]
+    fn test_upgrade_status_result() {
+        let json = "{\"size_in_bytes\":3000,\"size_to_upgrade_in_bytes\":1000,\
+                     \"size_to_upgrade_ancient_in_bytes\":400,\"indices\":{\
+                       \"current\":{\"size_in_bytes\":1000,\"size_to_upgrade_in_bytes\":0,\
+                                    \"size_to_upgrade_ancient_in_bytes\":0},\
+                       \"old\":{\"size_in_bytes\":1000,\"size_to_upgrade_in_bytes\":600,\
+                                \"size_to_upgrade_ancient_in_bytes\":0},\
+                       \"ancient\":{\"size_in_bytes\":1000,\"size_to_upgrade_in_bytes\":400,\
+                                    \"size_to_upgrade_ancient_in_bytes\":400}}}";
+        let result = UpgradeStatusResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert_eq!(600, result.indices.get("old").unwrap().size_to_upgrade_in_bytes);
+        assert_eq!(vec!["ancient", "old"], needs_upgrade(&result));
+        assert_eq!(vec!["ancient"], needs_ancient_upgrade(&result));
+
+        // ElasticSearch 1.x, indexes at the top level and no ancient segments
+        let json = "{\"idx\":{\"size_in_bytes\":1000,\"size_to_upgrade_in_bytes\":10}}";
+        let result = UpgradeStatusResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert_eq!(None, result.indices.get("idx").unwrap().size_to_upgrade_ancient_in_bytes);
+        assert_eq!(vec!["idx"], needs_upgrade(&result));
+        assert!(needs_ancient_upgrade(&result).is_empty());
+
+        let json = "{\"_shards\":{\"total\":2,\"successful\":2,\"failed\":0},\
+                     \"upgraded_indices\":{\"idx\":{\"upgrade_version\":\"2.3.0\",\
+                       \"oldest_lucene_segment_version\":\"5.5.0\"}}}";
+        let result = UpgradeResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        let idx = result.upgraded_indices.get("idx").unwrap();
+        assert_eq!(Some("2.3.0".to_string()), idx.upgrade_version);
+        assert_eq!("5.5.0", idx.oldest_lucene_segment_version);
+
+        let json = "{\"_shards\":{\"total\":2,\"successful\":2,\"failed\":0},\
+                     \"upgraded_indices\":{\"idx\":\"4.10.4\"}}";
+        let result = UpgradeResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        let idx = result.upgraded_indices.get("idx").unwrap();
+        assert_eq!(None, idx.upgrade_version);
+        assert_eq!("4.10.4", idx.oldest_lucene_segment_version);
+    }
+
+    #[test]
+    fn test_upgrade() {
+        let index_name = "test_upgrade";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        // A new index has nothing to upgrade
+        let result = client.upgrade_status().with_indexes(&[index_name]).send().unwrap();
+        assert!(result.indices.get(index_name).unwrap().size_in_bytes > 0);
+        assert!(needs_upgrade(&result).is_empty());
+
+        let result = client.upgrade()
+            .with_indexes(&[index_name])
+            .with_only_ancient_segments(&true)
+            .with_wait_for_completion(&true)
+            .send()
+            .unwrap();
+        assert_eq!(0, result.shards.failed);
+
+        match client.upgrade_status().with_indexes(&["test_upgrade_missing"]).send() {
+            Err(EsError::IndexMissingError(_)) => (),
+            other                              => panic!("Expected IndexMissingError, got: {:?}", other)
+        }
     }
 
     #[test]
@@ -312,117 +1197,3816 @@ mod tests {
     }
 
     #[test]
-    fn test_delete_by_query() {
-        let index_name = "test_delete_by_query";
+    fn test_mget() {
+        let index_name = "test_mget";
         let mut client = make_client();
         clean_db(&mut client, index_name);
 
-        let td1 = TestDocument::new().with_str_field("TEST DOC 1").with_int_field(100);
-        let td2 = TestDocument::new().with_str_field("TEST DOC 2").with_int_field(200);
+        let td1 = TestDocument::new().with_str_field("TEST DOC 1").with_int_field(10);
+        let td2 = TestDocument::new().with_str_field("TEST DOC 2").with_int_field(20);
 
         client
             .index(index_name, "test_type")
-            .with_id("ABC123")
+            .with_id("MGET1")
             .with_doc(&td1)
             .send().unwrap();
         client
             .index(index_name, "test_type")
-            .with_id("ABC124")
+            .with_id("MGET2")
             .with_doc(&td2)
             .send().unwrap();
 
-        let delete_result = client
-            .delete_by_query()
-            .with_indexes(&[index_name])
-            .with_doc_types(&["test_type"])
-            .with_query(&Query::build_match("int_field", 200)
-                        .with_lenient(false)
-                        .build())
+        let result = client
+            .mget()
+            .with_docs(&[(index_name, "test_type", "MGET2"),
+                         (index_name, "test_type", "MISSING"),
+                         (index_name, "test_type", "MGET1")])
             .send().unwrap();
 
-        assert!(delete_result.unwrap().successful());
+        assert_eq!(3, result.docs.len());
+        assert_eq!("MGET2", result.docs[0].id);
+        assert!(result.docs[0].found);
+        assert!(!result.docs[1].found);
+        assert_eq!("MGET1", result.docs[2].id);
+        assert!(result.docs[2].found);
+    }
 
-        let doc1 = client.get(index_name, "ABC123").with_doc_type("test_type").send().unwrap();
-        let doc2 = client.get(index_name, "ABC124").with_doc_type("test_type").send().unwrap();
+    #[test]
+    fn test_mget_batched() {
+        let index_name = "test_mget_batched";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
 
-        assert!(doc1.found);
-        assert!(!doc2.found);
+        let td = TestDocument::new().with_str_field("TEST DOC").with_int_field(10);
+        for &(id, routing) in [("B1", "r1"), ("B2", "r2"), ("B3", "r1")].iter() {
+            client
+                .index(index_name, "test_type")
+                .with_id(id)
+                .with_routing(&routing)
+                .with_doc(&td)
+                .send().unwrap();
+        }
+
+        let results = client
+            .mget()
+            .with_routed_doc(index_name, "test_type", "B2", "r2")
+            .with_routed_doc(index_name, "test_type", "B1", "r1")
+            .with_routed_doc("test_mget_batched_missing", "test_type", "B1", "r1")
+            .with_routed_doc(index_name, "test_type", "MISSING", "r2")
+            .with_routed_doc(index_name, "test_type", "B3", "r1")
+            .with_batch_size(2)
+            .with_group_by_routing(true)
+            .send_batched();
+
+        assert_eq!(5, results.len());
+        assert_eq!("B2", results[0].as_ref().unwrap().id);
+        assert_eq!("B1", results[1].as_ref().unwrap().id);
+        assert!(results[1].as_ref().unwrap().found);
+        assert!(results[2].is_err());
+        assert!(!results[3].as_ref().unwrap().found);
+        assert_eq!("B3", results[4].as_ref().unwrap().id);
+        assert!(results[4].as_ref().unwrap().found);
     }
 
-    fn setup_search_test_data(client: &mut Client, index_name: &str) {
-        let documents = vec![
-            TestDocument::new().with_str_field("Document A123").with_int_field(1),
-            TestDocument::new().with_str_field("Document B456").with_int_field(2),
-            TestDocument::new().with_str_field("Document 1ABC").with_int_field(3)
-                ];
-        for ref doc in documents {
+    #[test]
+    fn test_mget_batch_failed() {
+        // Nothing listens on port 1, so every batch fails to connect
+        let mut client = Client::new("localhost", 1);
+        let results = client
+            .mget()
+            .with_doc("test_mget_batch_failed", "test_type", "1")
+            .with_doc("test_mget_batch_failed", "test_type", "2")
+            .with_doc("test_mget_batch_failed", "test_type", "3")
+            .with_batch_size(2)
+            .send_batched();
+
+        assert_eq!(3, results.len());
+        for result in results.iter() {
+            match *result {
+                Err(ref err @ EsError::BatchFailed(_)) => assert!(err.is_connection_error()),
+                ref other                              => {
+                    panic!("Expected BatchFailed, got: {:?}", other)
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_versioned_get() {
+        let index_name = "test_versioned_get";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let td = TestDocument::new().with_str_field("TEST DOC").with_int_field(10);
+        let version = client
+            .index(index_name, "test_type")
+            .with_id("V1")
+            .with_doc(&td)
+            .with_refresh(&true)
+            .send().unwrap()
+            .version;
+
+        let result = client.get(index_name, "V1")
+            .with_doc_type("test_type")
+            .with_realtime(&false)
+            .with_version(&version)
+            .send().unwrap();
+        assert!(result.found);
+        assert_eq!(Some(version), result.version);
+
+        let conflict = client.get(index_name, "V1")
+            .with_doc_type("test_type")
+            .with_version(&(version + 1))
+            .send();
+        match conflict {
+            Err(EsError::ConflictError(_)) => (),
+            other                          => panic!("Expected ConflictError, got: {:?}", other)
+        }
+
+        let results = client
+            .mget()
+            .with_versioned_doc(index_name, "test_type", "V1", version)
+            .with_versioned_doc(index_name, "test_type", "V1", version + 1)
+            .with_realtime(&false)
+            .send_batched();
+        assert_eq!(Some(version), results[0].as_ref().unwrap().version);
+        match results[1] {
+            Err(EsError::ConflictError(_)) => (),
+            ref other                      => panic!("Expected ConflictError, got: {:?}", other)
+        }
+
+        match client.mget().with_versioned_doc(index_name, "test_type", "V1", version + 1).send() {
+            Err(EsError::ConflictError(_)) => (),
+            other                          => panic!("Expected ConflictError, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_bulk() {
+        let index_name = "test_bulk";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let actions:Vec<BulkAction<TestDocument>> = (1..11).map(|i| {
+            BulkAction::Index(ActionOptions::new().with_id(format!("BULK{}", i)),
+                              TestDocument::new().with_int_field(i))
+        }).collect();
+
+        let result = client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .send().unwrap();
+
+        assert!(!result.errors);
+        assert_eq!(10, result.items.len());
+        assert!(result.items.iter().all(|item| item.successful()));
+        assert_eq!("BULK1", result.items[0].id);
+        assert!(result.is_fully_successful());
+        assert!(result.failed_items().is_empty());
+    }
+
+    #[test]
+    fn test_bulk_index_and_update() {
+        let index_name = "test_bulk_index_and_update";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        client.index(index_name, "test_type")
+            .with_id("UPDATED")
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send()
+            .unwrap();
+
+        let mut partial = BTreeMap::new();
+        partial.insert("int_field".to_string(), Json::I64(2));
+        let actions = vec![
+            BulkAction::Index(ActionOptions::new().with_id("INDEXED"),
+                              TestDocument::new().with_int_field(3)),
+            BulkAction::Update(ActionOptions::new().with_id("UPDATED"),
+                               UpdateBody::doc(&Json::Object(partial)))
+        ];
+        let result = client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .send()
+            .unwrap();
+        assert!(result.is_fully_successful());
+        assert_eq!(ActionType::Index, result.items[0].action);
+        assert_eq!(ActionType::Update, result.items[1].action);
+
+        let result = client.get(index_name, "UPDATED")
+            .with_doc_type("test_type")
+            .send()
+            .unwrap();
+        let source:TestDocument = result.source().unwrap();
+        assert_eq!(2, source.int_field);
+        assert_eq!("I am a test", source.str_field);
+    }
+
+    #[test]
+    fn test_bulk_result_failed_items() {
+        let result = BulkResult::from_json(&Json::from_str("{\"took\":3,\"errors\":true,\"items\":[\
+            {\"create\":{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_version\":1,\
+            \"status\":201}},\
+            {\"create\":{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"status\":409,\
+            \"error\":{\"type\":\"version_conflict_engine_exception\",\
+            \"reason\":\"[t][2]: version conflict, document already exists\"}}},\
+            {\"index\":{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"3\",\"_version\":1,\
+            \"status\":201}},\
+            {\"delete\":{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"4\",\
+            \"status\":409,\"error\":\"VersionConflictEngineException[[i][0] [t][4]]\"}}]}")
+            .unwrap()).unwrap();
+        assert_eq!(3, result.took);
+        assert!(result.errors);
+        assert!(!result.is_fully_successful());
+        assert_eq!(4, result.items.len());
+        assert_eq!(Some(1), result.items[0].version);
+        assert_eq!(None, result.items[0].reason);
+
+        let failed = result.failed_items();
+        assert_eq!(2, failed.len());
+        assert_eq!(ActionType::Create, failed[0].action);
+        assert_eq!("2", failed[0].id);
+        assert_eq!(409, failed[0].status);
+        assert_eq!(None, failed[0].version);
+        assert_eq!(Some("[t][2]: version conflict, document already exists".to_string()),
+                   failed[0].reason);
+        assert_eq!(ActionType::Delete, failed[1].action);
+        assert_eq!(Some("VersionConflictEngineException[[i][0] [t][4]]".to_string()),
+                   failed[1].reason);
+    }
+
+    #[test]
+    fn test_delete_if_exists() {
+        let index_name = "test_delete_if_exists";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        client.index(index_name, "test_type")
+            .with_id("EXISTS1")
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send()
+            .unwrap();
+
+        // A HEAD then a DELETE
+        let before = client.request_count;
+        let result = client.delete(index_name, "test_type", "EXISTS1")
+            .with_if_exists(true)
+            .send()
+            .unwrap();
+        assert_eq!(2, client.request_count - before);
+        assert_eq!(WriteOutcome::Deleted, result.outcome);
+        assert!(result.found);
+
+        // Only the HEAD
+        let before = client.request_count;
+        let result = client.delete(index_name, "test_type", "EXISTS1")
+            .with_if_exists(true)
+            .send()
+            .unwrap();
+        assert_eq!(1, client.request_count - before);
+        assert_eq!(WriteOutcome::Skipped, result.outcome);
+        assert!(!result.found);
+        assert_eq!("EXISTS1", result.id);
+
+        // Only the DELETE, by default
+        let before = client.request_count;
+        let result = client.delete(index_name, "test_type", "EXISTS1").send().unwrap();
+        assert_eq!(1, client.request_count - before);
+        assert_eq!(WriteOutcome::NotFound, result.outcome);
+
+        // The latest timeout replaces any earlier one
+        let result = client.delete(index_name, "test_type", "EXISTS1")
+            .with_timeout("invalid")
+            .with_timeout("10s")
+            .send()
+            .unwrap();
+        assert_eq!(WriteOutcome::NotFound, result.outcome);
+    }
+
+    #[test]
+    fn test_id_length() {
+        let index_name = "test_id_length";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        // 256 characters, but 512 bytes
+        let longest:String = ::std::iter::repeat("\u{e9}").take(256).collect();
+        assert_eq!(256, longest.chars().count());
+        client.index(index_name, "test_type")
+            .with_id(&longest)
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send()
+            .unwrap();
+        assert!(client.get(index_name, &longest).send().unwrap().found);
+
+        // 300 characters, but 600 bytes, fails before any request is sent
+        let too_long:String = ::std::iter::repeat("\u{e9}").take(300).collect();
+        let before = client.request_count;
+        match client.index(index_name, "test_type")
+            .with_id(&too_long)
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send() {
+            Err(EsError::InvalidId(600, 512)) => (),
+            other                             => panic!("Expected InvalidId, got: {:?}", other)
+        }
+        match client.get(index_name, &too_long).send() {
+            Err(EsError::InvalidId(600, 512)) => (),
+            other                             => panic!("Expected InvalidId, got: {:?}", other)
+        }
+        match client.delete(index_name, "test_type", &too_long).send() {
+            Err(EsError::InvalidId(600, 512)) => (),
+            other                             => panic!("Expected InvalidId, got: {:?}", other)
+        }
+        let mut partial = BTreeMap::new();
+        partial.insert("int_field".to_string(), 2i64.to_json());
+        match client.update(index_name, "test_type", &too_long).with_doc(&partial).send() {
+            Err(EsError::InvalidId(600, 512)) => (),
+            other                             => panic!("Expected InvalidId, got: {:?}", other)
+        }
+        assert_eq!(before, client.request_count);
+
+        // The limit is configurable
+        let mut client = make_client().with_max_id_length(8);
+        match client.get(index_name, "\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}").send() {
+            Err(EsError::InvalidId(10, 8)) => (),
+            other                          => panic!("Expected InvalidId, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_hashed_id() {
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", hashed_id("abc"));
+        assert_eq!("da39a3ee5e6b4b0d3255bfef95601890afd80709", hashed_id(""));
+        // Over more than one block
+        assert_eq!("84983e441c3bd26ebaae4aa1f95129e5e54670f1",
+                   hashed_id("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"));
+        // Of the UTF-8 bytes
+        assert_eq!("bf15be717ac1b080b4f1c456692825891ff5073d", hashed_id("\u{e9}"));
+
+        let long_url:String = ::std::iter::repeat("http://example.com/").take(100).collect();
+        assert_eq!(40, hashed_id(&long_url).len());
+        assert_eq!(hashed_id(&long_url), hashed_id(&long_url));
+    }
+
+    #[test]
+    fn test_bulk_delete_if_exists() {
+        let index_name = "test_bulk_delete_if_exists";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        for id in ["BULK1", "BULK2"].iter() {
             client.index(index_name, "test_type")
-                .with_doc(doc)
+                .with_id(*id)
+                .with_doc(&TestDocument::new().with_int_field(1))
+                .send()
+                .unwrap();
+        }
+        let actions:Vec<BulkAction<TestDocument>> = ["BULK1", "MISSING1", "BULK2", "MISSING2"]
+            .iter()
+            .map(|id| BulkAction::Delete(ActionOptions::new().with_id(*id)))
+            .collect();
+
+        // A multi-get then the bulk request, without the missing documents
+        let before = client.request_count;
+        let result = client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .with_if_exists(true)
+            .send()
+            .unwrap();
+        assert_eq!(2, client.request_count - before);
+        let ids:Vec<&str> = result.items.iter().map(|item| &item.id[..]).collect();
+        assert_eq!(vec!["BULK1", "BULK2"], ids);
+        assert_eq!(vec![1, 3], result.skipped);
+
+        // Only the multi-get, as there is nothing to delete
+        let before = client.request_count;
+        let result = client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .with_if_exists(true)
+            .send()
+            .unwrap();
+        assert_eq!(1, client.request_count - before);
+        assert!(result.items.is_empty());
+        assert_eq!(vec![0, 1, 2, 3], result.skipped);
+    }
+
+    #[test]
+    fn test_bulk_no_actions() {
+        let mut client = make_client();
+        let actions:Vec<BulkAction<TestDocument>> = Vec::new();
+
+        let before = client.request_count;
+        let result = client.bulk(&actions).send().unwrap();
+        assert_eq!(before, client.request_count);
+        assert!(!result.errors);
+        assert!(result.items.is_empty());
+        assert!(result.skipped.is_empty());
+    }
+
+    #[test]
+    fn test_bulk_actions_from_hits() {
+        let search = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                     \"hits\":{\"total\":3,\"hits\":[\
+                                     {\"_index\":\"i\",\"_type\":\"child\",\"_id\":\"1\",\
+                                     \"_score\":1.0,\"_routing\":\"r1\",\"_parent\":\"p1\"},\
+                                     {\"_index\":\"i\",\"_type\":\"child\",\"_id\":\"2\",\
+                                     \"_score\":1.0,\"fields\":{\"_routing\":\"r2\",\"_parent\":\"p2\"}},\
+                                     {\"_index\":\"j\",\"_type\":\"t\",\"_id\":\"3\",\
+                                     \"_score\":1.0,\"_source\":{\"int_field\":3}}]}}").unwrap();
+        let result = SearchResult::from_json(&search).unwrap();
+        let metadata:Vec<String> = result.hits.hits.iter()
+            .map(|hit| ActionOptions::from_hit(hit).to_json().to_string())
+            .collect();
+
+        // ElasticSearch 2.x onwards gives the routing and parent alongside the
+        // ID, 1.x in the fields if requested
+        assert_eq!("{\"_id\":\"1\",\"_index\":\"i\",\"_parent\":\"p1\",\
+                    \"_routing\":\"r1\",\"_type\":\"child\"}",
+                   metadata[0]);
+        assert_eq!("{\"_id\":\"2\",\"_index\":\"i\",\"_parent\":\"p2\",\
+                    \"_routing\":\"r2\",\"_type\":\"child\"}",
+                   metadata[1]);
+        assert_eq!("{\"_id\":\"3\",\"_index\":\"j\",\"_type\":\"t\"}",
+                   metadata[2]);
+    }
+
+    #[test]
+    fn test_delete_hits() {
+        let index_name = "test_delete_hits";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        for &(id, routing, int_field) in [("1", "a", 1), ("2", "b", 2), ("3", "c", 2)].iter() {
+            client.index(index_name, "test_type")
+                .with_id(id)
+                .with_routing(&routing)
+                .with_doc(&TestDocument::new().with_int_field(int_field))
                 .send()
                 .unwrap();
         }
-        client.refresh().with_indexes(&[index_name]).send().unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let result:SearchResult<TestDocument> = client.search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_term("int_field", 2).build())
+            .with_fields(&["_source", "_routing"])
+            .send()
+            .unwrap();
+        assert_eq!(2, result.hits.hits.len());
+        let result = client.bulk::<Json>(&[])
+            .delete_hits(&result.hits)
+            .with_refresh(&true)
+            .send()
+            .unwrap();
+        assert!(!result.errors);
+        assert_eq!(2, result.items.len());
+        for item in result.items.iter() {
+            assert!(item.successful());
+            assert_eq!(200, item.status);
+        }
+
+        let result = client.count().with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(1, result.count);
+
+        // Updates of the hits alongside indexing a typed document, only the
+        // hits with a source are updated
+        let result:SearchResult<TestDocument> = client.search_query()
+            .with_indexes(&[index_name])
+            .with_fields(&["_routing"])
+            .send()
+            .unwrap();
+        assert_eq!(1, result.hits.hits.len());
+        let actions = vec![BulkAction::Index(ActionOptions::new()
+                                             .with_index(index_name)
+                                             .with_doc_type("test_type")
+                                             .with_id("4"),
+                                             TestDocument::new().with_int_field(4))];
+        let result = client.bulk(&actions)
+            .update_hits_with(&result.hits, |hit| {
+                hit.source.as_ref().map(|_| Json::from_str("{\"int_field\":5}").unwrap())
+            })
+            .send()
+            .unwrap();
+        assert_eq!(1, result.items.len());
+        assert_eq!(ActionType::Index, result.items[0].action);
+
+        let result:SearchResult<TestDocument> = client.search_query()
+            .with_indexes(&[index_name])
+            .with_fields(&["_source", "_routing"])
+            .send()
+            .unwrap();
+        let result = client.bulk(&actions)
+            .update_hits_with(&result.hits, |hit| {
+                hit.source.as_ref().map(|_| Json::from_str("{\"int_field\":5}").unwrap())
+            })
+            .send()
+            .unwrap();
+        assert!(result.is_fully_successful());
+        assert_eq!(2, result.items.len());
+        assert_eq!(ActionType::Update, result.items[1].action);
+
+        let result = client.get(index_name, "1")
+            .with_doc_type("test_type")
+            .with_routing(&"a")
+            .send()
+            .unwrap();
+        let source:TestDocument = result.source().unwrap();
+        assert_eq!(5, source.int_field);
+    }
+
+    #[test]
+    fn test_update() {
+        let index_name = "test_update";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        client
+            .index(index_name, "test_type")
+            .with_id("UPDATE1")
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send().unwrap();
+
+        let mut partial = BTreeMap::new();
+        partial.insert("int_field".to_string(), 2i64.to_json());
+        let doc_result = client
+            .update(index_name, "test_type", "UPDATE1")
+            .with_doc(&partial)
+            .send().unwrap();
+        assert_eq!(2, doc_result.version);
+
+        let mut params = BTreeMap::new();
+        params.insert("inc".to_string(), 5i64.to_json());
+        let script_result = client
+            .update(index_name, "test_type", "UPDATE1")
+            .with_script("ctx._source.int_field += inc", params, None)
+            .send().unwrap();
+        assert_eq!(3, script_result.version);
+
+        let doc = client.get(index_name, "UPDATE1").send().unwrap();
+        let source:TestDocument = doc.source().unwrap();
+        assert_eq!(7, source.int_field);
+
+        let missing = client
+            .update(index_name, "test_type", "MISSING")
+            .with_script("ctx._source.int_field += 1", BTreeMap::new(), None)
+            .send();
+        match missing {
+            Err(EsError::DocumentMissingError(_)) => (),
+            _ => panic!("Expected document missing, got: {:?}", missing)
+        }
+
+        let conflict = client
+            .update(index_name, "test_type", "UPDATE1")
+            .with_doc(&partial)
+            .with_version(&1)
+            .send();
+        match conflict {
+            Err(EsError::ConflictError(_)) => (),
+            other                          => panic!("Expected ConflictError, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_update_result() {
+        let result = UpdateResult::from_json(&Json::from_str("{\
+            \"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_version\":2,\
+            \"get\":{\"found\":true,\"_source\":{\"str_field\":\"A\",\"int_field\":3},\
+            \"fields\":{\"int_field\":[3]}}}").unwrap()).unwrap();
+        assert_eq!(2, result.version);
+        assert_eq!(WriteOutcome::Updated, result.outcome);
+        let get = result.get.unwrap();
+        assert!(get.found);
+        assert_eq!(3, get.source::<TestDocument>().unwrap().int_field);
+        assert_eq!(Some(3), get.fields.as_ref()
+                   .and_then(|f| f.find("int_field"))
+                   .and_then(|f| f.as_array())
+                   .and_then(|f| f[0].as_i64()));
+
+        let without_get = UpdateResult::from_json(&Json::from_str("{\
+            \"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_version\":1,\
+            \"created\":true}").unwrap()).unwrap();
+        assert_eq!(WriteOutcome::Created, without_get.outcome);
+        assert!(without_get.get.is_none());
+    }
+
+    #[test]
+    fn test_update_upsert() {
+        let index_name = "test_update_upsert";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        // An upsert document, with a script and its parameters
+        let mut upsert = BTreeMap::new();
+        upsert.insert("str_field".to_string(), "I am a test".to_json());
+        upsert.insert("int_field".to_string(), 1i64.to_json());
+        let mut params = BTreeMap::new();
+        params.insert("inc".to_string(), 10i64.to_json());
+        let created = client
+            .update(index_name, "test_type", "UPSERT1")
+            .with_script("ctx._source.int_field += inc", params.clone(), None)
+            .with_upsert(&upsert)
+            .send().unwrap();
+        assert_eq!(WriteOutcome::Created, created.outcome);
+        assert_eq!(1, created.version);
+
+        let updated = client
+            .update(index_name, "test_type", "UPSERT1")
+            .with_script("ctx._source.int_field += inc", params, None)
+            .with_upsert(&upsert)
+            .send().unwrap();
+        assert_eq!(WriteOutcome::Updated, updated.outcome);
+        assert_eq!(2, updated.version);
+
+        let doc = client.get(index_name, "UPSERT1").send().unwrap();
+        let source:TestDocument = doc.source().unwrap();
+        assert_eq!(11, source.int_field);
+
+        // The partial document as the upsert document
+        let mut partial = BTreeMap::new();
+        partial.insert("int_field".to_string(), 5i64.to_json());
+        let created = client
+            .update(index_name, "test_type", "UPSERT2")
+            .with_doc(&partial)
+            .with_doc_as_upsert(true)
+            .send().unwrap();
+        assert_eq!(WriteOutcome::Created, created.outcome);
+
+        partial.insert("int_field".to_string(), 6i64.to_json());
+        let updated = client
+            .update(index_name, "test_type", "UPSERT2")
+            .with_doc(&partial)
+            .with_doc_as_upsert(true)
+            .send().unwrap();
+        assert_eq!(WriteOutcome::Updated, updated.outcome);
+
+        let doc = client.get(index_name, "UPSERT2").send().unwrap();
+        assert_eq!(Some(6), doc.source.as_ref()
+                   .and_then(|s| s.find("int_field"))
+                   .and_then(|i| i.as_i64()));
+    }
+
+    #[test]
+    fn test_update_scripted_upsert() {
+        let index_name = "test_update_scripted_upsert";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let mut upsert = BTreeMap::new();
+        upsert.insert("counter".to_string(), 0i64.to_json());
+        for _ in 0..2 {
+            let mut params = BTreeMap::new();
+            params.insert("count".to_string(), 1i64.to_json());
+            client
+                .update(index_name, "test_type", "COUNTER")
+                .with_script("ctx._source.counter += count", BTreeMap::new(), None)
+                .with_script_params(params)
+                .with_upsert(&upsert)
+                .with_scripted_upsert(true)
+                .send().unwrap();
+        }
+
+        let doc = client.get(index_name, "COUNTER").send().unwrap();
+        assert_eq!(Some(2), doc.source.as_ref()
+                   .and_then(|s| s.find("counter"))
+                   .and_then(|c| c.as_i64()));
+    }
+
+    #[test]
+    fn test_script_formats() {
+        let script = Script::indexed("counter").with_lang("groovy").with_param("count", &1);
+        let mut flat = BTreeMap::new();
+        script.add_to_as(&mut flat, ScriptFormat::Flat);
+        let flat = Json::Object(flat);
+        assert_eq!(Some("counter"), flat.find("script_id").and_then(|s| s.as_string()));
+        assert_eq!(Some("groovy"), flat.find("lang").and_then(|l| l.as_string()));
+        assert_eq!(Some(1), flat.find_path(&["params", "count"]).and_then(|c| c.as_i64()));
+        assert!(flat.find("script").is_none());
+
+        let mut nested = BTreeMap::new();
+        script.add_to_as(&mut nested, ScriptFormat::Nested);
+        let nested = Json::Object(nested);
+        assert_eq!(Some("counter"),
+                   nested.find_path(&["script", "id"]).and_then(|s| s.as_string()));
+        assert_eq!(Some(1),
+                   nested.find_path(&["script", "params", "count"]).and_then(|c| c.as_i64()));
+        assert!(nested.find("params").is_none());
+
+        assert_eq!(Some("x"),
+                   Script::new("x").to_nested_json().find("inline").and_then(|s| s.as_string()));
+        assert_eq!(Some("x"),
+                   Script::file("x").to_json().find("script_file").and_then(|s| s.as_string()));
+    }
+
+    #[test]
+    fn test_update_doc_and_script() {
+        let mut client = make_client();
+        let mut partial = BTreeMap::new();
+        partial.insert("int_field".to_string(), 2i64.to_json());
+
+        // Fails before any request is sent
+        let result = client
+            .update("test_update", "test_type", "UPDATE1")
+            .with_doc(&partial)
+            .with_script("ctx._source.int_field += 1", BTreeMap::new(), None)
+            .send();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_delete_by_query() {
+        let index_name = "test_delete_by_query";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let td1 = TestDocument::new().with_str_field("TEST DOC 1").with_int_field(100);
+        let td2 = TestDocument::new().with_str_field("TEST DOC 2").with_int_field(200);
+
+        client
+            .index(index_name, "test_type")
+            .with_id("ABC123")
+            .with_doc(&td1)
+            .send().unwrap();
+        client
+            .index(index_name, "test_type")
+            .with_id("ABC124")
+            .with_doc(&td2)
+            .send().unwrap();
+
+        let delete_result = client
+            .delete_by_query()
+            .with_indexes(&[index_name])
+            .with_doc_types(&["test_type"])
+            .with_query(&Query::build_match("int_field", 200)
+                        .with_lenient(false)
+                        .build())
+            .send().unwrap();
+
+        assert!(delete_result.unwrap().successful());
+
+        let doc1 = client.get(index_name, "ABC123").with_doc_type("test_type").send().unwrap();
+        let doc2 = client.get(index_name, "ABC124").with_doc_type("test_type").send().unwrap();
+
+        assert!(doc1.found);
+        assert!(!doc2.found);
+    }
+
+    #[test]
+    fn test_scan_and_delete() {
+        let index_name = "test_scan_and_delete";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let actions:Vec<BulkAction<TestDocument>> = (1..11).map(|i| {
+            BulkAction::Index(ActionOptions::new().with_id(format!("{}", i)),
+                              TestDocument::new().with_int_field(i))
+        }).collect();
+        client.bulk(&actions).with_index(index_name).with_doc_type("test_type").send().unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let result = client
+            .delete_by_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_range("int_field").with_gt(3).build())
+            .send_scan_and_delete(3)
+            .unwrap()
+            .unwrap();
+        assert!(result.successful());
+        assert_eq!(7, result.deleted());
+        assert_eq!(7, result.indices[index_name].deleted);
+
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+        let remaining = client.search_query().with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(3, remaining.hits.total);
+
+        let missing = client
+            .delete_by_query()
+            .with_indexes(&["test_scan_and_delete_missing"])
+            .send_scan_and_delete(3)
+            .unwrap();
+        assert!(missing.is_none());
+    }
+
+    #[test]
+    fn test_scan_and_delete_routed() {
+        let index_name = "test_scan_and_delete_routed";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        client.index(index_name, "test_type")
+            .with_id("ROUTED1")
+            .with_routing(&"R1")
+            .with_doc(&TestDocument::new().with_int_field(1))
+            .send()
+            .unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let result = client
+            .delete_by_query()
+            .with_indexes(&[index_name])
+            .send_scan_and_delete(10)
+            .unwrap()
+            .unwrap();
+        assert!(result.successful());
+        assert_eq!(1, result.deleted());
+
+        let doc = client.get(index_name, "ROUTED1")
+            .with_routing(&"R1")
+            .send()
+            .unwrap();
+        assert!(!doc.found);
+    }
+
+    fn setup_search_test_data(client: &mut Client, index_name: &str) {
+        let documents = vec![
+            TestDocument::new().with_str_field("Document A123").with_int_field(1),
+            TestDocument::new().with_str_field("Document B456").with_int_field(2),
+            TestDocument::new().with_str_field("Document 1ABC").with_int_field(3)
+                ];
+        for ref doc in documents {
+            client.index(index_name, "test_type")
+                .with_doc(doc)
+                .send()
+                .unwrap();
+        }
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+    }
+
+    #[test]
+    fn test_find_references() {
+        let lookup = TermsLookup::new("users", "user", "2", "followers").with_routing("r1");
+        let query = Query::build_bool()
+            .with_must(vec![Query::build_terms("user", lookup.clone()).build(),
+                            Query::build_terms("user", lookup).build()])
+            .build();
+        let shape = Filter::build_geo_shape("location")
+            .with_indexed_shape(IndexedShape::new("deu", "country", "shapes", "location"))
+            .build();
+        let scripts = Json::from_str("{\"bool\":{\"should\":[\
+                                        {\"script\":{\"script_id\":\"a\",\"lang\":\"groovy\"}},\
+                                        {\"script\":{\"script\":{\"id\":\"b\"}}},\
+                                        {\"script\":{\"script\":\"doc['x'].value > 1\"}},\
+                                        {\"geo_shape\":{\"location\":{\"indexed_shape\":{\
+                                          \"id\":\"fra\",\"type\":\"country\"}}}}]}}").unwrap();
+
+        // Each lookup once, however often it is used
+        assert_eq!(vec![Reference::TermsLookup(DocumentReference {
+                            index:    "users".to_string(),
+                            doc_type: "user".to_string(),
+                            id:       "2".to_string(),
+                            routing:  Some("r1".to_string()),
+                            path:     "followers".to_string()
+                        })],
+                   find_references(&query.to_json()));
+        assert_eq!(vec![Reference::IndexedShape(DocumentReference {
+                            index:    "shapes".to_string(),
+                            doc_type: "country".to_string(),
+                            id:       "deu".to_string(),
+                            routing:  None,
+                            path:     "location".to_string()
+                        })],
+                   find_references(&shape.to_json()));
+
+        // Inline scripts are not references, an indexed shape has a default
+        // index and path
+        let references = find_references(&scripts);
+        assert_eq!(3, references.len());
+        assert_eq!(Reference::IndexedScript { lang: Some("groovy".to_string()), id: "a".to_string() },
+                   references[0]);
+        assert_eq!(Reference::IndexedScript { lang: None, id: "b".to_string() },
+                   references[1]);
+        match references[2] {
+            Reference::IndexedShape(ref doc) => {
+                assert_eq!("shapes", doc.index);
+                assert_eq!("shape", doc.path);
+            },
+            ref other                        => panic!("Expected IndexedShape, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_verify_query_references() {
+        let index_name = "test_verify_query_references";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+        client.index(index_name, "test_type")
+            .with_id("lookup")
+            .with_doc(&TestDocument::new().with_int_field(2))
+            .with_refresh(&true)
+            .send()
+            .unwrap();
+
+        let found = Query::build_terms("int_field",
+                                       TermsLookup::new(index_name, "test_type", "lookup", "int_field"))
+            .build();
+        let report = client.verify_query_references(&found).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(1, report.references.len());
+        let result:SearchResult<TestDocument> = client.search_query()
+            .with_indexes(&[index_name])
+            .with_query(&found)
+            .send()
+            .unwrap();
+        assert_eq!(2, result.hits.total);
+
+        // A missing document silently matches nothing
+        let missing = Query::build_terms("int_field",
+                                         TermsLookup::new(index_name, "test_type", "absent", "int_field"))
+            .build();
+        let report = client.verify_query_references(&missing).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.references, report.missing);
+    }
+
+    #[test]
+    fn test_reusable_operations() {
+        let index_name = "test_reusable_operations";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Asc)).build()]);
+        {
+            let mut search = client.search_query();
+            search.with_indexes(&[index_name]).with_sort(&sort).with_size(2);
+            let first = search.send().unwrap();
+            let again = search.send().unwrap();
+            assert_eq!(first.hits.hits.len(), again.hits.hits.len());
+            assert_eq!(first.hits.hits[0].id, again.hits.hits[0].id);
+
+            search.with_from(2);
+            let next = search.send().unwrap();
+            assert_eq!(1, next.hits.hits.len());
+            assert_eq!(Some(3), next.hits.hits[0].source.as_ref()
+                       .and_then(|s| s.find("int_field"))
+                       .and_then(|i| i.as_i64()));
+        }
+        {
+            let mut count = client.count();
+            count.with_indexes(&[index_name]).with_query_string("B456".to_string());
+            assert_eq!(1, count.send().unwrap().count);
+            assert_eq!(1, count.send().unwrap().count);
+        }
+        {
+            let mut search = client.search_uri();
+            search.with_indexes(&[index_name]).with_query("A123".to_string());
+            assert_eq!(1, search.send().unwrap().hits.total);
+            assert_eq!(1, search.send().unwrap().hits.total);
+        }
+        {
+            let mut delete = client.delete_by_query();
+            delete.with_indexes(&[index_name]).with_query_string("B456".to_string());
+            assert!(delete.send().unwrap().unwrap().successful());
+            assert!(delete.send().unwrap().unwrap().successful());
+        }
+    }
+
+    #[test]
+    fn test_search_uri() {
+        let index_name = "test_search_uri";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let all_results = client.search_uri().with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(3, all_results.hits.total);
+
+        let doc_a = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_query("A123".to_string())
+            .send()
+            .unwrap();
+        assert_eq!(1, doc_a.hits.total);
+
+        let doc_1 = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_query("str_field:1ABC".to_string())
+            .send()
+            .unwrap();
+        assert_eq!(1, doc_1.hits.total);
+
+        let limited_fields = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_query("str_field:B456".to_string())
+            .with_fields(&["int_field"])
+            .send()
+            .unwrap();
+        assert_eq!(1, limited_fields.hits.total);
+    }
+
+    #[test]
+    fn test_search_uri_too_long() {
+        let index_name = "test_search_uri_too_long";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let mut short_client = make_short_uri_client();
+
+        let get_result = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_query("str_field:B456 OR str_field:1ABC".to_string())
+            .send()
+            .unwrap();
+        let post_result = short_client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_query("str_field:B456 OR str_field:1ABC".to_string())
+            .send()
+            .unwrap();
+        assert_eq!(2, get_result.hits.total);
+        assert_eq!(get_result.hits.total, post_result.hits.total);
+
+        let too_long = short_client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_routing(&"a_very_long_routing_value_that_cannot_be_moved")
+            .send();
+        match too_long {
+            Err(EsError::UriTooLong(_)) => (),
+            _ => panic!("Expected URI too long, got: {:?}", too_long.is_ok())
+        }
+    }
+
+    #[test]
+    fn test_search_body() {
+        let index_name = "test_search_body";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let all_results = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .send().unwrap();
+        assert_eq!(3, all_results.hits.total);
+
+        let within_range = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_filtered(Filter::build_range("int_field")
+                                               .with_gte(2)
+                                               .with_lte(3)
+                                               .build())
+                        .build())
+            .send().unwrap();
+        assert_eq!(2, within_range.hits.total);
+    }
+
+    #[test]
+    fn test_search_async() {
+        let index_name = "test_search_async";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let async_client = AsyncClient::new(&es_host(), 9200, 2);
+        let receivers:Vec<_> = (1..4).map(|i| {
+            async_client.search_query()
+                .with_indexes(&[index_name])
+                .with_query(Query::build_term("int_field", i as i64).build())
+                .send_async()
+        }).collect();
+        for rx in receivers {
+            let result = rx.recv().unwrap().unwrap();
+            assert_eq!(1, result.hits.total);
+        }
+    }
+
+    #[test]
+    fn test_async_execute_panic() {
+        let async_client = AsyncClient::with_clients(1, || {
+            make_client().with_timeout(Duration::from_secs(10))
+        });
+        let rx = async_client.execute(|_| -> Result<(), EsError> { panic!("Expected panic") });
+        assert!(rx.recv().unwrap().is_err());
+
+        // The only thread is still available
+        let rx = async_client.execute(|client| client.version());
+        rx.recv().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_search_highlight() {
+        let index_name = "test_search_highlight";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let highlight = Highlight::new()
+            .with_field_setting("str_field", Setting::new().with_tags(&["<b>"], &["</b>"]));
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "B456").build())
+            .with_highlight(&highlight)
+            .send().unwrap();
+        assert_eq!(1, result.hits.total);
+
+        let hit_highlight = result.hits.hits[0].highlight.as_ref().unwrap();
+        assert_eq!(vec!["Document <b>B456</b>".to_string()],
+                   hit_highlight["str_field"]);
+        assert!(!hit_highlight.contains_key("int_field"));
+
+        let highlight = Highlight::new()
+            .with_global(Setting::new().with_fragment_size(50))
+            .with_fields(&["str_field", "other_field"]);
+        assert_eq!(Json::from_str("{\"fragment_size\":50,\
+                                   \"fields\":{\"str_field\":{},\"other_field\":{}}}").unwrap(),
+                   highlight.to_json());
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "A123").build())
+            .with_highlight(&highlight)
+            .send().unwrap();
+        assert_eq!(vec!["Document <em>A123</em>".to_string()],
+                   result.hits.hits[0].highlight.as_ref().unwrap()["str_field"]);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "A123").build())
+            .send().unwrap();
+        assert!(result.hits.hits[0].highlight.is_none());
+    }
+
+    #[test]
+    fn test_percolate_result() {
+        let json = "{\"took\":3,\"_shards\":{\"total\":5,\"successful\":5,\"failed\":0},\
+                     \"total\":2,\"matches\":[\
+                     {\"_index\":\"alerts\",\"_id\":\"q1\",\"_score\":1.5,\
+                      \"highlight\":{\"body\":[\"the <em>disk</em> is full\"]}},\
+                     {\"_index\":\"alerts\",\"_id\":\"q2\"}]}";
+        let result = PercolateResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert_eq!(2, result.total);
+        assert_eq!("q1", result.matches[0].id);
+        assert_eq!(Some(1.5), result.matches[0].score);
+        assert_eq!(vec!["the <em>disk</em> is full".to_string()],
+                   result.matches[0].highlight.as_ref().unwrap()["body"]);
+        assert_eq!(None, result.matches[1].score);
+        assert!(result.matches[1].highlight.is_none());
+
+        let json = "{\"took\":1,\"_shards\":{\"total\":5,\"successful\":5,\"failed\":0},\
+                     \"total\":0}";
+        let result = PercolateResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert!(result.matches.is_empty());
+    }
+
+    #[test]
+    fn test_percolate() {
+        let index_name = "test_percolate";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        for &(id, value) in [("b456", "B456"), ("a123", "A123")].iter() {
+            let mut doc = BTreeMap::new();
+            doc.insert("query".to_string(),
+                       Query::build_match("str_field", value).build().to_json());
+            client.index(index_name, ".percolator")
+                .with_id(id)
+                .with_doc(&Json::Object(doc))
+                .with_refresh(&true)
+                .send()
+                .unwrap();
+        }
+
+        let mut doc = BTreeMap::new();
+        doc.insert("str_field".to_string(), "Document B456".to_json());
+        let highlight = Highlight::new()
+            .with_field_setting("str_field", Setting::new().with_tags(&["<b>"], &["</b>"]));
+        let query = Query::build_match_all().build();
+        let result = client.percolate(index_name, "test_type")
+            .with_doc(&Json::Object(doc))
+            .with_query(&query)
+            .with_track_scores(true)
+            .with_size(10)
+            .with_highlight(&highlight)
+            .send()
+            .unwrap();
+        assert_eq!(1, result.total);
+        let matched = &result.matches[0];
+        assert_eq!("b456", matched.id);
+        assert!(matched.score.is_some());
+        assert_eq!(vec!["Document <b>B456</b>".to_string()],
+                   matched.highlight.as_ref().unwrap()["str_field"]);
+
+        let mut doc = BTreeMap::new();
+        doc.insert("str_field".to_string(), "Document B456".to_json());
+        let mut other = BTreeMap::new();
+        other.insert("str_field".to_string(), "Document A123 B456".to_json());
+        let actions = [PercolateAction::percolate(index_name, "test_type")
+                       .with_doc(&Json::Object(doc)),
+                       PercolateAction::count(index_name, "test_type")
+                       .with_doc(&Json::Object(other)),
+                       PercolateAction::percolate("test_percolate_missing", "test_type")
+                       .with_doc(&Json::Object(BTreeMap::new()))];
+        let result = client.mpercolate(&actions).send().unwrap();
+        assert_eq!(3, result.responses.len());
+        let first = result.responses[0].as_ref().unwrap();
+        assert_eq!(1, first.total);
+        assert_eq!("b456", first.matches[0].id);
+        let second = result.responses[1].as_ref().unwrap();
+        assert_eq!(2, second.total);
+        assert!(second.matches.is_empty());
+        assert!(result.responses[2].is_err());
+    }
+
+    #[test]
+    fn test_mpercolate_result() {
+        let response = Json::from_str("{\"responses\":[\
+                                       {\"took\":2,\"_shards\":{\"total\":1,\"successful\":1,\
+                                       \"failed\":0},\"total\":1,\"matches\":[\
+                                       {\"_index\":\"alerts\",\"_id\":\"q1\"}]},\
+                                       {\"error\":\"IndexMissingException[[missing] missing]\"}]}")
+            .unwrap();
+        let result = MultiPercolateResult::from_responses("req", &response).unwrap();
+        assert_eq!(2, result.responses.len());
+        assert_eq!("q1", result.responses[0].as_ref().unwrap().matches[0].id);
+        assert!(result.responses[1].is_err());
+    }
+
+    #[test]
+    fn test_search_sort() {
+        let index_name = "test_search_sort";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Desc)).build()]);
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .with_sort(&sort)
+            .send().unwrap();
+        let ints:Vec<i64> = result.hits.hits.into_iter().map(|hit| {
+            let doc:TestDocument = hit.source().unwrap();
+            doc.int_field
+        }).collect();
+        assert_eq!(vec![3, 2, 1], ints);
+
+        let mut missing_int = BTreeMap::new();
+        missing_int.insert("str_field".to_string(), "Document C789".to_json());
+        client.index(index_name, "test_type")
+            .with_id("MISSING_INT")
+            .with_doc(&Json::Object(missing_int))
+            .with_refresh(&true)
+            .send()
+            .unwrap();
+
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Desc))
+                                  .with_mode(Mode::Max)
+                                  .with_missing(Missing::Last)
+                                  .build(),
+                                  SortField::score(None).build()]);
+        assert_eq!(Json::from_str("[{\"int_field\":{\"order\":\"desc\",\"mode\":\"max\",\
+                                   \"missing\":\"_last\"}},{\"_score\":{}}]").unwrap(),
+                   sort.to_json());
+        assert_eq!(Json::from_str("[{\"_doc\":{}}]").unwrap(),
+                   Sort::new(vec![SortField::doc().build()]).to_json());
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .with_sort(&sort)
+            .send().unwrap();
+        let ints:Vec<Option<i64>> = result.hits.hits.iter().map(|hit| {
+            hit.source.as_ref().unwrap().find("int_field").and_then(|i| i.as_i64())
+        }).collect();
+        assert_eq!(vec![Some(3), Some(2), Some(1), None], ints);
+        assert_eq!("MISSING_INT", result.hits.hits[3].id);
+    }
+
+    #[test]
+    fn test_search_track_scores() {
+        let index_name = "test_search_track_scores";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let body = SearchQueryOperationBody::new().with_track_scores(true).to_json();
+        assert_eq!(Some(true), body.find("track_scores").and_then(|t| t.as_boolean()));
+
+        let query = Query::build_match("str_field", "Document").build();
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Asc)).build()]);
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .with_sort(&sort)
+            .send().unwrap();
+        assert_eq!(3, result.hits.hits.len());
+        assert!(result.hits.hits.iter().all(|hit| hit.score.is_none()));
+        assert_eq!(None, result.hits.max_score);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .with_sort(&sort)
+            .with_track_scores(true)
+            .send().unwrap();
+        assert_eq!(3, result.hits.hits.len());
+        assert!(result.hits.hits.iter().all(|hit| hit.score.unwrap() > 0.0));
+        let max_score = result.hits.max_score.unwrap();
+        assert!(result.hits.hits.iter().all(|hit| hit.score.unwrap() <= max_score));
+    }
+
+    #[test]
+    fn test_search_fielddata_fields() {
+        let index_name = "test_search_fielddata_fields";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let body = SearchQueryOperationBody::new()
+            .with_fielddata_fields(&["int_field", "str_field"])
+            .to_json();
+        assert_eq!(Json::from_str("[\"int_field\",\"str_field\"]").unwrap(),
+                   *body.find("fielddata_fields").unwrap());
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_term("int_field", 1).build())
+            .with_fielddata_fields(&["int_field", "str_field"])
+            .send().unwrap();
+        let hit = &result.hits.hits[0];
+        assert_eq!(Some(1), hit.field::<i64>("int_field").unwrap());
+        let mut terms:Vec<String> = hit.field_values("str_field").unwrap();
+        terms.sort();
+        assert_eq!(vec!["a123".to_string(), "document".to_string()], terms);
+        assert!(hit.source.is_some());
+    }
+
+    #[test]
+    fn test_search_partial_fields() {
+        let index_name = "test_search_partial_fields";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let body = SearchQueryOperationBody::new()
+            .with_partial_fields("user", &["user.*"], &["user.bio"])
+            .with_partial_fields("all", &[], &["big"])
+            .to_json();
+        assert_eq!(Json::from_str("{\"all\":{\"exclude\":[\"big\"]},\
+                                   \"user\":{\"include\":[\"user.*\"],\
+                                   \"exclude\":[\"user.bio\"]}}").unwrap(),
+                   *body.find("partial_fields").unwrap());
+
+        let doc = Json::from_str("{\"user\":{\"name\":\"Alice\",\"age\":30,\
+                                  \"bio\":\"A long biography\"},\"big\":\"A big field\"}")
+            .unwrap();
+        client.index(index_name, "test_type")
+            .with_id("PARTIAL")
+            .with_doc(&doc)
+            .with_refresh(&true)
+            .send()
+            .unwrap();
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_source(Source::Disabled)
+            .with_partial_fields("user", &["user.*"], &["user.bio"])
+            .send().unwrap();
+        let hit = &result.hits.hits[0];
+        assert!(hit.source.is_none());
+        let user:Json = hit.field("user").unwrap().unwrap();
+        assert_eq!(Some("Alice"), user.find_path(&["user", "name"]).and_then(|n| n.as_string()));
+        assert_eq!(Some(30), user.find_path(&["user", "age"]).and_then(|a| a.as_i64()));
+        assert!(user.find_path(&["user", "bio"]).is_none());
+        assert!(user.find("big").is_none());
+    }
+
+    #[test]
+    fn test_search_terminate_after() {
+        let index_name = "test_search_terminate_after";
+        let mut client = make_client();
+        client.delete_index(index_name).send().unwrap();
+        client.create_index(index_name).with_number_of_shards(1).send().unwrap();
+        setup_search_test_data(&mut client, index_name);
+
+        let body = SearchQueryOperationBody::new().to_json();
+        assert!(body.find("terminate_after").is_none());
+        let body = SearchQueryOperationBody::new().with_terminate_after(1).to_json();
+        assert_eq!(Some(1), body.find("terminate_after").and_then(|t| t.as_i64()));
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_terminate_after(1)
+            .send().unwrap();
+        assert_eq!(Some(true), result.terminated_early);
+        assert_eq!(1, result.hits.total);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_terminate_after(10)
+            .send().unwrap();
+        assert_eq!(Some(false), result.terminated_early);
+        assert_eq!(3, result.hits.total);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .send().unwrap();
+        assert_eq!(None, result.terminated_early);
+    }
+
+    #[test]
+    fn test_count() {
+        let index_name = "test_count";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let all_result = client
+            .count()
+            .with_indexes(&[index_name])
+            .send().unwrap();
+        assert_eq!(3, all_result.count);
+
+        let query_result = client
+            .count()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_range("int_field").with_gte(2).build())
+            .send().unwrap();
+        assert_eq!(2, query_result.count);
+
+        let query_string_result = client
+            .count()
+            .with_indexes(&[index_name])
+            .with_query_string("str_field:B456".to_string())
+            .send().unwrap();
+        assert_eq!(1, query_string_result.count);
+
+        let min_score_result = client
+            .count()
+            .with_indexes(&[index_name])
+            .with_query_string("str_field:B456".to_string())
+            .with_lenient(&true)
+            .with_min_score(&100)
+            .send().unwrap();
+        assert_eq!(0, min_score_result.count);
+
+        match client.count().with_indexes(&["test_count_no_such_index"]).send() {
+            Err(EsError::IndexMissingError(_)) => (),
+            _                                  => panic!("Expected an IndexMissingError")
+        }
+    }
+
+    #[test]
+    fn test_more_like_this() {
+        let index_name = "test_more_like_this";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        for &(id, value) in [("1", "Document A123"), ("2", "Document B456"), ("3", "Other")].iter() {
+            client.index(index_name, "test_type")
+                .with_id(id)
+                .with_doc(&TestDocument::new().with_str_field(value))
+                .send()
+                .unwrap();
+        }
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let result = client.more_like_this(index_name, "test_type", "1")
+            .with_mlt_fields(&["str_field"])
+            .with_min_term_freq(&1)
+            .with_min_doc_freq(&1)
+            .send()
+            .unwrap();
+        assert_eq!(1, result.hits.total);
+        assert_eq!("2", result.hits.hits[0].id);
+
+        match client.more_like_this(index_name, "test_type", "4").send() {
+            Err(EsError::DocumentMissingError(id)) => assert_eq!("4", id),
+            _                                      => panic!("Expected a DocumentMissingError")
+        }
+    }
+
+    #[test]
+    fn test_search_exists() {
+        let index_name = "test_search_exists";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let query = Query::build_match("str_field", "B456").build();
+        assert!(client
+                .search_query()
+                .with_indexes(&[index_name])
+                .with_query(&query)
+                .exists().unwrap());
+
+        let query = Query::build_match("str_field", "Z999").build();
+        assert!(!client
+                .search_query()
+                .with_indexes(&[index_name])
+                .with_query(&query)
+                .exists().unwrap());
+
+        match client.search_query().with_indexes(&["test_search_exists_no_such_index"]).exists() {
+            Err(EsError::IndexMissingError(_)) => (),
+            _                                  => panic!("Expected an IndexMissingError")
+        }
+    }
+
+    #[test]
+    fn test_search_template() {
+        let index_name = "test_search_template";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let template = Json::from_str("{\"query\":{\"match\":{\"str_field\":\"{{text}}\"}}}")
+            .unwrap();
+        let result = client
+            .search_template()
+            .with_indexes(&[index_name])
+            .with_template(&template)
+            .with_param("text", &"B456")
+            .send_typed::<TestDocument>()
+            .unwrap();
+        assert_eq!(1, result.hits.total);
+        assert_eq!(2, result.hits.hits[0].source.as_ref().unwrap().int_field);
+
+        assert!(client.search_template().with_indexes(&[index_name]).send().is_err());
+    }
+
+    #[test]
+    fn test_render_search_template() {
+        let mut client = make_client();
+
+        let template = Json::from_str("{\"query\":{\"match\":{\"str_field\":\"{{text}}\"}},\
+                                       \"size\":\"{{size}}\"}")
+            .unwrap();
+        let result = client
+            .render_search_template()
+            .with_template(&template)
+            .with_param("text", &"B456")
+            .with_param("size", &5)
+            .send()
+            .unwrap();
+        assert_eq!(Some("B456"),
+                   result.find_path(&["query", "match", "str_field"]).and_then(|s| s.as_string()));
+        assert_eq!(Some("5"), result.find("size").and_then(|s| s.as_string()));
+
+        assert!(client.render_search_template().send().is_err());
+    }
+
+    #[test]
+    fn test_stored_search_template() {
+        let index_name = "test_stored_search_template";
+        let template_id = "test_stored_search_template";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        client.delete_search_template(template_id).send().unwrap();
+        setup_search_test_data(&mut client, index_name);
+
+        assert!(!client.get_search_template(template_id).send().unwrap().found);
+        assert!(client.put_search_template(template_id).send().is_err());
+
+        let template = Json::from_str("{\"query\":{\"match\":{\"str_field\":\"{{text}}\"}}}")
+            .unwrap();
+        client.put_search_template(template_id).with_template(&template).send().unwrap();
+
+        let stored = client.get_search_template(template_id).send().unwrap();
+        assert!(stored.found);
+        assert_eq!(Some(template), stored.template);
+
+        let result = client
+            .search_template()
+            .with_indexes(&[index_name])
+            .with_template_id(template_id)
+            .with_param("text", &"B456")
+            .send_typed::<TestDocument>()
+            .unwrap();
+        assert_eq!(1, result.hits.total);
+
+        assert!(client.delete_search_template(template_id).send().unwrap());
+        assert!(!client.delete_search_template(template_id).send().unwrap());
+        assert!(!client.get_search_template(template_id).send().unwrap().found);
+    }
+
+    #[test]
+    fn test_get_search_template_result() {
+        // ElasticSearch 2.x gives the template as a string
+        let found = GetSearchTemplateResult::from_json(&Json::from_str("{\
+            \"_index\":\".scripts\",\"_type\":\"mustache\",\"_id\":\"t\",\
+            \"_version\":1,\"found\":true,\
+            \"template\":\"{\\\"query\\\":{\\\"match_all\\\":{}}}\"}").unwrap()).unwrap();
+        assert!(found.found);
+        assert_eq!(Some(Json::from_str("{\"query\":{\"match_all\":{}}}").unwrap()),
+                   found.template);
+
+        let unparsed = GetSearchTemplateResult::from_json(&Json::from_str("{\
+            \"found\":true,\"template\":\"{{#size}}\"}").unwrap()).unwrap();
+        assert_eq!(Some(Json::String("{{#size}}".to_string())), unparsed.template);
+
+        let missing = GetSearchTemplateResult::from_json(&Json::from_str("{\
+            \"_index\":\".scripts\",\"_type\":\"mustache\",\"_id\":\"t\",\
+            \"found\":false}").unwrap()).unwrap();
+        assert!(!missing.found);
+        assert_eq!(None, missing.template);
+    }
+
+    #[test]
+    fn test_search_after() {
+        let index_name = "test_search_after";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Asc)).build()]);
+        let query = Query::build_match_all().build();
+        let first_page = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .with_sort(&sort)
+            .with_size(2)
+            .send().unwrap();
+        assert_eq!(2, first_page.hits.hits.len());
+        let last_sort = first_page.hits.hits[1].sort.clone().unwrap();
+        assert_eq!(vec![Json::I64(2)], last_sort);
+
+        let second_page = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .with_sort(&sort)
+            .with_size(2)
+            .with_search_after(&last_sort)
+            .send().unwrap();
+        assert_eq!(1, second_page.hits.hits.len());
+        let doc:TestDocument = second_page.hits.hits.into_iter().next().unwrap().source().unwrap();
+        assert_eq!(3, doc.int_field);
+
+        // search_after without a sort
+        match client.search_query().with_indexes(&[index_name]).with_search_after(&last_sort).send() {
+            Err(EsError::EsError(ref msg)) => assert!(msg.contains("search_after")),
+            other                          => panic!("Expected an error, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_default_search_options() {
+        let index_name = "test_default_search_options";
+        let mut client = make_client();
+        client.delete_index(index_name).send().unwrap();
+        client.create_index(index_name).with_number_of_shards(3).send().unwrap();
+        setup_search_test_data(&mut client, index_name);
+
+        let defaults = DefaultSearchOptions::new()
+            .with_preference("_shards:0")
+            .with_terminate_after(100);
+        let mut client = make_client().with_default_search_options(defaults);
+
+        // The default preference searches a single shard
+        let result = client.search_query().with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(1, result.shards.total);
+        let result = client.search_uri().with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(1, result.shards.total);
+
+        // Which is replaced by the preference of the search, not sent as well
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .with_preference(Preference::Shards(vec![1, 2]))
+            .send()
+            .unwrap();
+        assert_eq!(2, result.shards.total);
+        let result = client.search_uri()
+            .with_indexes(&[index_name])
+            .with_preference(Preference::Shards(vec![1, 2]))
+            .send()
+            .unwrap();
+        assert_eq!(2, result.shards.total);
+
+        // As is an earlier preference of the search
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .with_preference(Preference::Shards(vec![1, 2]))
+            .with_preference_str(&"_shards:1")
+            .send()
+            .unwrap();
+        assert_eq!(1, result.shards.total);
+
+        // Or removed
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .clear_preference()
+            .send()
+            .unwrap();
+        assert_eq!(3, result.shards.total);
+
+        // Values are encoded in the query string
+        let result = client.search_uri()
+            .with_indexes(&[index_name])
+            .with_preference(Preference::Custom("user:1&a=b \u{e9}".to_string()))
+            .send()
+            .unwrap();
+        assert_eq!(3, result.shards.total);
+
+        // An invalid default timeout fails every search which does not
+        // replace or clear it
+        let defaults = DefaultSearchOptions::new().with_timeout("invalid");
+        let mut client = make_client().with_default_search_options(defaults);
+        assert!(client.search_query().with_indexes(&[index_name]).send().is_err());
+        assert!(client.search_uri().with_indexes(&[index_name]).send().is_err());
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .with_timeout("10s")
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+        let result = client.search_uri()
+            .with_indexes(&[index_name])
+            .with_timeout(&"10s")
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .clear_timeout()
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+        let result = client.search_uri()
+            .with_indexes(&[index_name])
+            .clear_timeout()
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+
+        // The latest timeout replaces any earlier one
+        let result = client.search_query()
+            .with_indexes(&[index_name])
+            .with_timeout("invalid")
+            .with_timeout("10s")
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+        let result = client.search_uri()
+            .with_indexes(&[index_name])
+            .with_timeout("invalid")
+            .with_timeout("10s")
+            .send()
+            .unwrap();
+        assert_eq!(3, result.hits.total);
+    }
+
+    #[test]
+    fn test_search_after_pages() {
+        let index_name = "test_search_after_pages";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+        client.index(index_name, "test_type")
+            .with_doc(&TestDocument::new().with_str_field("Document C789").with_int_field(4))
+            .with_routing(&"tenant-a")
+            .send()
+            .unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        // Sorted by int_field, with the ID to break ties
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Asc)).build(),
+                                  SortField::new("_uid", Some(Order::Asc)).build()]);
+        let mut int_fields = Vec::new();
+        let mut search_after = Vec::new();
+        for _ in 0..3 {
+            let page = client
+                .search_query()
+                .with_indexes(&[index_name])
+                .with_sort(&sort)
+                .with_size(2)
+                .with_search_after(&search_after)
+                .with_version(true)
+                .with_preference(Preference::Local)
+                .send()
+                .unwrap();
+            for hit in page.hits.hits.iter() {
+                assert_eq!(Some(1), hit.version);
+                int_fields.push(hit.source.as_ref().unwrap().find("int_field").unwrap().as_i64().unwrap());
+            }
+            search_after = match page.hits.hits.last() {
+                Some(hit) => hit.sort.clone().unwrap(),
+                None      => break
+            };
+        }
+        assert_eq!(vec![1, 2, 3, 4], int_fields);
+
+        // Routed documents report their routing
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_routing(&"tenant-a")
+            .with_query(&Query::build_term("int_field", 4).build())
+            .send()
+            .unwrap();
+        assert_eq!(1, result.hits.hits.len());
+        assert_eq!(Some("tenant-a".to_string()), result.hits.hits[0].routing);
+    }
+
+    #[test]
+    fn test_search_indices_boost() {
+        let boosts:HashMap<&str, f64> = vec![("index-2015", 2.0), ("index-2014", 1.0)]
+            .into_iter()
+            .collect();
+        let body = SearchQueryOperationBody::new().with_indices_boost(boosts).to_json();
+        let reversed = SearchQueryOperationBody::new()
+            .with_indices_boost(vec![("index-2014".to_string(), 1.0),
+                                     ("index-2015".to_string(), 2.0)])
+            .to_json();
+        assert_eq!(body, reversed);
+        assert_eq!(Some(2.0), body.find_path(&["indices_boost", "index-2015"])
+                   .and_then(|b| b.as_f64()));
+        let empty = SearchQueryOperationBody::new()
+            .with_indices_boost(Vec::<(&str, f64)>::new())
+            .to_json();
+        assert!(empty.find("indices_boost").is_none());
+
+        let mut client = make_client();
+        let index_names = ["test_search_indices_boost_a", "test_search_indices_boost_b"];
+        for index_name in index_names.iter() {
+            clean_db(&mut client, index_name);
+            setup_search_test_data(&mut client, index_name);
+        }
+
+        let query = Query::build_match("str_field", "A123").build();
+        for &(boosted, other) in [(0, 1), (1, 0)].iter() {
+            let result = client
+                .search_query()
+                .with_indexes(&index_names)
+                .with_query(&query)
+                .with_indices_boost(vec![(index_names[boosted], 2.0),
+                                         (index_names[other], 1.0)])
+                .send().unwrap();
+            assert_eq!(2, result.hits.hits.len());
+            assert_eq!(index_names[boosted], result.hits.hits[0].index);
+            assert_eq!(index_names[other], result.hits.hits[1].index);
+        }
+    }
+
+    #[test]
+    fn test_search_post_filter() {
+        let index_name = "test_search_post_filter";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let query = Query::build_range("int_field").with_gte(2).build();
+        let post_filter = Query::build_term("int_field", 3i64).build();
+        let body = SearchQueryOperationBody::new()
+            .with_query(&query)
+            .with_post_filter(&post_filter)
+            .with_from(0)
+            .with_size(5)
+            .to_json();
+        assert_eq!(Some(&query.to_json()), body.find("query"));
+        assert_eq!(Some(&post_filter.to_json()), body.find("post_filter"));
+        assert_eq!(Some(5), body.find("size").and_then(|s| s.as_i64()));
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .send().unwrap();
+        assert_eq!(2, result.hits.total);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .with_post_filter(&post_filter)
+            .with_size(5)
+            .send().unwrap();
+        assert_eq!(1, result.hits.total);
+        let doc:TestDocument = result.hits.hits.into_iter().next().unwrap().source().unwrap();
+        assert_eq!(3, doc.int_field);
+    }
+
+    #[test]
+    fn test_search_rescore() {
+        let index_name = "test_search_rescore";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let rescore = Rescore::new(Query::build_match("str_field", "B456").build())
+            .with_window_size(10)
+            .with_query_weight(1.0)
+            .with_rescore_query_weight(10.0)
+            .with_score_mode(RescoreMode::Total);
+        let body = SearchQueryOperationBody::new().with_rescore(&rescore).to_json();
+        assert_eq!(Some(10), body.find_path(&["rescore", "window_size"]).and_then(|w| w.as_u64()));
+        let query = body.find_path(&["rescore", "query"]).unwrap();
+        assert!(query.find("rescore_query").is_some());
+        assert_eq!(Some(1.0), query.find("query_weight").and_then(|w| w.as_f64()));
+        assert_eq!(Some(10.0), query.find("rescore_query_weight").and_then(|w| w.as_f64()));
+        assert_eq!(Some("total"), query.find("score_mode").and_then(|m| m.as_string()));
+
+        let second = Rescore::new(Query::build_match("str_field", "A123").build());
+        let body = SearchQueryOperationBody::new()
+            .with_rescore(&rescore)
+            .with_rescore(&second)
+            .to_json();
+        assert_eq!(Some(2), body.find("rescore").and_then(|r| r.as_array()).map(|r| r.len()));
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .with_rescore(&rescore)
+            .send().unwrap();
+        assert_eq!(3, result.hits.total);
+        let doc:TestDocument = result.hits.hits.into_iter().next().unwrap().source().unwrap();
+        assert_eq!("Document B456", doc.str_field);
+    }
+
+    #[test]
+    fn test_search_suggest() {
+        let json = "{\"took\":2,\"timed_out\":false,\
+                     \"_shards\":{\"total\":5,\"successful\":5,\"failed\":0},\
+                     \"hits\":{\"total\":0,\"max_score\":0.0,\"hits\":[]},\
+                     \"suggest\":{\"fix\":[{\"text\":\"documnt\",\"offset\":0,\"length\":7,\
+                     \"options\":[{\"text\":\"document\",\"score\":0.85,\"freq\":3}]}],\
+                     \"complete\":[{\"text\":\"doc\",\"offset\":0,\"length\":3,\
+                     \"options\":[{\"text\":\"docs\",\"_score\":1.0,\"payload\":{\"id\":7}}]}]}}";
+        let result = SearchResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        let suggest = result.suggest.unwrap();
+        let option = &suggest["fix"][0].options[0];
+        assert_eq!("document", option.text);
+        assert_eq!(0.85, option.score);
+        assert_eq!(Some(3), option.freq);
+        assert_eq!(1.0, suggest["complete"][0].options[0].score);
+        assert_eq!(None, option.payload);
+        assert_eq!(Some(7), suggest["complete"][0].options[0].payload.as_ref()
+                   .and_then(|p| p.find("id"))
+                   .and_then(|i| i.as_i64()));
+
+        let index_name = "test_search_suggest";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let suggest = Suggest::new()
+            .with("fix", Suggestion::term("documnt", "str_field").with_size(1));
+        assert_eq!(Json::from_str("{\"fix\":{\"text\":\"documnt\",\
+                                   \"term\":{\"field\":\"str_field\",\"size\":1}}}").unwrap(),
+                   suggest.to_json());
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_size(0)
+            .with_suggest(&suggest)
+            .send()
+            .unwrap();
+        let suggest = result.suggest.unwrap();
+        assert_eq!(1, suggest["fix"].len());
+        assert_eq!("document", suggest["fix"][0].options[0].text);
+        assert!(client.search_query().with_indexes(&[index_name]).send().unwrap().suggest.is_none());
+
+        let suggest = Suggest::new()
+            .with("fix", Suggestion::term("documnt", "str_field").with_size(1));
+        let suggest = client.suggest(&suggest).with_indexes(&[index_name]).send().unwrap();
+        assert_eq!(1, suggest.len());
+        assert_eq!("document", suggest["fix"][0].options[0].text);
+
+        let missing = Suggest::new().with("fix", Suggestion::term("documnt", "str_field"));
+        match client.suggest(&missing).with_indexes(&["test_suggest_missing"]).send() {
+            Err(EsError::IndexMissingError(_)) => (),
+            _                                  => panic!("Expected an IndexMissingError")
+        }
+    }
+
+    #[test]
+    fn test_scroll() {
+        let index_name = "test_scroll";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let actions:Vec<BulkAction<TestDocument>> = (0..100).map(|i| {
+            BulkAction::Index(ActionOptions::new().with_id(format!("SCROLL{}", i)),
+                              TestDocument::new().with_int_field(i))
+        }).collect();
+        client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .send().unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let mut page = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_size(10)
+            .with_scroll("1m")
+            .send().unwrap();
+        assert_eq!(100, page.hits.total);
+        let mut ids = HashSet::new();
+        let mut pages = 0;
+        while !page.is_exhausted() {
+            pages += 1;
+            for hit in page.hits.hits.iter() {
+                ids.insert(hit.id.clone());
+            }
+            let scroll_id = page.scroll_id.unwrap();
+            page = client.scroll(&scroll_id, "1m").send().unwrap();
+        }
+        assert_eq!(10, pages);
+        assert_eq!(100, ids.len());
+
+        // The latest keep-alive replaces any earlier one
+        let page = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_size(&50)
+            .with_scroll("invalid")
+            .with_scroll("1m")
+            .send().unwrap();
+        assert_eq!(50, page.hits.hits.len());
+        assert!(page.scroll_id.is_some());
+    }
+
+    #[test]
+    fn test_scan() {
+        let index_name = "test_scan";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let actions:Vec<BulkAction<TestDocument>> = (0..25).map(|i| {
+            BulkAction::Index(ActionOptions::new().with_id(format!("SCAN{}", i)),
+                              TestDocument::new().with_int_field(i))
+        }).collect();
+        client.bulk(&actions)
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .send().unwrap();
+        client.refresh().with_indexes(&[index_name]).send().unwrap();
+
+        let indexes = [index_name];
+        let ids:HashSet<String> = {
+            let mut search = client.search_query();
+            search.with_indexes(&indexes).with_size(10);
+            let ids = {
+                let scan = search.scan("1m").unwrap();
+                assert_eq!(25, scan.total());
+                scan.map(|hit| hit.unwrap().id).collect()
+            };
+
+            // The scroll is not kept for later searches
+            assert!(search.send().unwrap().scroll_id.is_none());
+            ids
+        };
+        assert_eq!(25, ids.len());
+
+        // The first page of a scan has no hits
+        {
+            let mut search = client.search_query();
+            search.with_indexes(&indexes)
+                .with_size(2)
+                .with_search_type(SearchType::Scan);
+            assert_eq!(25, search.scan("1m").unwrap().count());
+        }
+
+        // The keep-alive of the scan replaces any given before
+        let query = Query::build_term("int_field", 100).build();
+        let mut search = client.search_query();
+        search.with_indexes(&indexes).with_query(&query).with_scroll("invalid");
+        assert_eq!(0, search.scan("1m").unwrap().count());
+    }
+
+    #[test]
+    fn test_clear_scroll() {
+        let index_name = "test_clear_scroll";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let scroll_id = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_size(1)
+            .with_scroll("1m")
+            .send().unwrap()
+            .scroll_id.unwrap();
+        assert!(client.clear_scroll(&[&scroll_id]).send().unwrap());
+        assert!(client.scroll(&scroll_id, "1m").send().is_err());
+
+        // Already cleared
+        assert!(!client.clear_scroll(&[&scroll_id]).send().unwrap());
+
+        assert!(client.clear_scroll(&[]).send().is_err());
+        client.clear_all_scrolls().send().unwrap();
+    }
+
+    #[test]
+    fn test_timeout() {
+        assert_eq!("10ms", Timeout::new(10, TimeUnit::Millis).to_string());
+        assert_eq!("5s", Timeout::new(5, TimeUnit::Seconds).to_string());
+        assert_eq!("2m", Timeout::new(2, TimeUnit::Minutes).to_string());
+        assert_eq!("1h", Timeout::new(1, TimeUnit::Hours).to_string());
+
+        assert_eq!("1500ms", Timeout::from(Duration::from_millis(1500)).to_string());
+        assert_eq!("30s", Timeout::from(Duration::from_secs(30)).to_string());
+        assert_eq!("2m", Timeout::from(Duration::from_secs(120)).to_string());
+        assert_eq!("2h", Timeout::from(Duration::from_secs(7200)).to_string());
+        assert_eq!("1ms", Timeout::from(Duration::new(0, 1)).to_string());
+        assert_eq!("0ms", Timeout::from(Duration::new(0, 0)).to_string());
+
+        // Strings are sent unchanged
+        assert_eq!("10s", Timeout::from("10s").to_string());
+        assert_eq!("10s", Timeout::from(&"10s").to_string());
+        assert_eq!("10s", Timeout::from(&"10s".to_string()).to_string());
+
+        let body = SearchQueryOperationBody::new()
+            .with_timeout(Duration::from_millis(250))
+            .to_json();
+        assert_eq!(Some("250ms"), body.find("timeout").and_then(|t| t.as_string()));
+    }
+
+    #[test]
+    fn test_preference() {
+        assert_eq!("_primary", Preference::Primary.to_string());
+        assert_eq!("_primary_first", Preference::PrimaryFirst.to_string());
+        assert_eq!("_local", Preference::Local.to_string());
+        assert_eq!("_only_node:xyz", Preference::OnlyNode("xyz".to_string()).to_string());
+        assert_eq!("_prefer_node:xyz", Preference::PreferNode("xyz".to_string()).to_string());
+        assert_eq!("_shards:0,2", Preference::Shards(vec![0, 2]).to_string());
+        assert_eq!("session-1.a_b~c", Preference::Custom("session-1.a_b~c".to_string()).to_string());
+        assert_eq!("user:1&a=b \u{e9}",
+                   Preference::Custom("user:1&a=b \u{e9}".to_string()).to_string());
+    }
+
+    #[test]
+    fn test_search_type() {
+        assert_eq!("dfs_query_then_fetch", SearchType::DFSQueryThenFetch.to_string());
+        assert_eq!("count", SearchType::Count.to_string());
+        assert_eq!("scan", SearchType::Scan.to_string());
+
+        let index_name = "test_search_type";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_search_type(SearchType::DFSQueryThenFetch)
+            .send().unwrap();
+        assert_eq!(3, result.hits.hits.len());
+
+        let result = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_search_type(SearchType::Count)
+            .send().unwrap();
+        assert_eq!(3, result.hits.total);
+        assert!(result.hits.hits.is_empty());
+
+        let result = client
+            .search_uri()
+            .with_indexes(&[index_name])
+            .with_search_type_str(&"query_then_fetch")
+            .send().unwrap();
+        assert_eq!(3, result.hits.hits.len());
+
+        // The latest search type replaces any earlier one
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_search_type(SearchType::Count)
+            .with_search_type_str(&"query_then_fetch")
+            .send().unwrap();
+        assert_eq!(3, result.hits.hits.len());
+    }
+
+    #[test]
+    fn test_search_typed() {
+        let index_name = "test_search_typed";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let sort = Sort::new(vec![SortField::new("int_field", Some(Order::Asc)).build()]);
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .with_sort(&sort)
+            .send_typed::<TestDocument>().unwrap();
+        let ints:Vec<i64> = result.hits.hits.iter().map(|hit| {
+            hit.source.as_ref().unwrap().int_field
+        }).collect();
+        assert_eq!(vec![1, 2, 3], ints);
+    }
+
+    #[test]
+    fn test_search_into_typed() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"a\",\"int_field\":1}},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"3\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"c\",\"int_field\":3}}]}}").unwrap();
+        let docs:Vec<Result<TestDocument, EsError>> = SearchResult::from(&response)
+            .into_typed()
+            .collect();
+        assert_eq!(3, docs.len());
+        assert_eq!(1, docs[0].as_ref().unwrap().int_field);
+        assert!(docs[1].is_err());
+        assert_eq!(3, docs[2].as_ref().unwrap().int_field);
+    }
+
+    #[test]
+    fn test_search_typed_decode_error() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"good\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"a\",\"int_field\":1}},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"bad\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"b\"}}]}}").unwrap();
+        match SearchResult::<TestDocument>::decode(response) {
+            Err(EsError::DocumentDecodeError(id, _)) => assert_eq!("bad", id),
+            _                                         => panic!("Expected a decode error")
+        }
+    }
+
+    #[test]
+    fn test_search_typed_missing_source() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"a\",\"int_field\":1}},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0}]}}")
+            .unwrap();
+
+        let kept = SearchResult::<TestDocument>::decode(response.clone()).unwrap();
+        assert_eq!(2, kept.hits.hits.len());
+        assert!(kept.hits.hits[1].source.is_none());
+
+        let skipped = SearchResult::<TestDocument>::decode_with(response.clone(),
+                                                                MissingSource::Skip).unwrap();
+        assert_eq!(2, skipped.hits.total);
+        assert_eq!(1, skipped.hits.hits.len());
+        assert_eq!("1", skipped.hits.hits[0].id);
+
+        match SearchResult::<TestDocument>::decode_with(response, MissingSource::Error) {
+            Err(EsError::EsError(msg)) => assert_eq!("No source field for document 2", msg),
+            _                          => panic!("Expected a missing source error")
+        }
+    }
+
+    #[test]
+    fn test_search_hit_source_ref() {
+        let hits = Json::from_str("[{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":1.0,\
+                                   \"_source\":{\"str_field\":\"a\",\"int_field\":1}},\
+                                   {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0,\
+                                   \"_source\":{\"str_field\":\"b\"}},\
+                                   {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"3\",\"_score\":1.0}]")
+            .unwrap();
+        let hits:Vec<SearchHitsHitsResult> = hits.as_array().unwrap().iter()
+            .map(|hit| SearchHitsHitsResult::from_json(hit).unwrap())
+            .collect();
+
+        let doc:TestDocument = hits[0].source_ref().unwrap();
+        assert_eq!(1, doc.int_field);
+        assert_eq!("1", hits[0].id);
+        assert!(hits[0].source.is_some());
+
+        match hits[1].source_ref::<TestDocument>() {
+            Err(EsError::DocumentDecodeError(id, _)) => assert_eq!("2", id),
+            _                                         => panic!("Expected a decode error")
+        }
+        assert!(hits[2].source_ref::<TestDocument>().is_err());
+    }
+
+    #[test]
+    fn test_search_hit_iteration() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"a\",\"int_field\":1}},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0,\
+                                       \"_source\":{\"str_field\":\"b\",\"int_field\":2}}]}}").unwrap();
+        let result = SearchResult::from_json(&response).unwrap();
+
+        assert_eq!(vec!["1", "2"], result.ids().collect::<Vec<_>>());
+        assert_eq!(2, result.hits_iter().count());
+        let docs:Vec<TestDocument> = result.sources().map(|doc| doc.unwrap()).collect();
+        assert_eq!(vec![1, 2], docs.iter().map(|doc| doc.int_field).collect::<Vec<_>>());
+
+        let mut scores = 0.0;
+        for hit in &result.hits {
+            scores += hit.score.unwrap();
+        }
+        assert_eq!(2.0, scores);
+
+        let ids:Vec<String> = result.hits.into_iter().map(|hit| hit.id).collect();
+        assert_eq!(vec!["1".to_string(), "2".to_string()], ids);
+    }
+
+    #[test]
+    fn test_malformed_responses() {
+        let parse = |json: &str| Json::from_str(json).unwrap();
+
+        // An error body, e.g. passed through by a proxy with a 200 status
+        assert!(SearchResult::from_json(&parse("{\"error\":\"bad gateway\"}")).is_err());
+        assert!(SearchResult::from_json(&parse("{\"_shards\":{\"total\":1,\"successful\":1,\
+                                                \"failed\":0}}")).is_err());
+        assert!(SearchResult::from_json(&parse("{\"_shards\":{\"total\":1,\"successful\":1,\
+                                                \"failed\":0},\"hits\":{\"total\":1}}")).is_err());
+        assert!(SearchResult::<TestDocument>::decode(parse("{\"hits\":{\"total\":0,\
+                                                            \"hits\":[]}}")).is_err());
+        assert!(SearchHitsHitsResult::from_json(&parse("{\"_index\":\"i\",\"_type\":\"t\"}"))
+                .is_err());
+        assert!(SearchHitsHitsResult::from_json(&parse("[]")).is_err());
+        assert!(DeleteResult::from_json(&parse("{\"_index\":\"i\",\"_type\":\"t\"}")).is_err());
+        assert!(DeleteResult::from_json(&parse("{\"result\":\"vanished\",\"_index\":\"i\",\
+                                                \"_type\":\"t\",\"_id\":\"1\",\"_version\":1}"))
+                .is_err());
+        assert!(DeleteByQueryResult::from_json(&parse("{\"took\":1}")).is_err());
+        assert!(DeleteByQueryResult::from_json(&parse("{\"_indices\":{\"i\":{}}}")).is_err());
+    }
+
+    #[test]
+    fn test_federated_search() {
+        let index_a = "test_federated_search_a";
+        let index_b = "test_federated_search_b";
+        let mut client = make_client();
+        clean_db(&mut client, index_a);
+        clean_db(&mut client, index_b);
+        setup_search_test_data(&mut client, index_a);
+        setup_search_test_data(&mut client, index_b);
+
+        let result = client
+            .federated_search()
+            .with_index(index_a, 1.0)
+            .with_index(index_b, 2.0)
+            .with_query(&Query::build_match("str_field", "A123").build())
+            .send().unwrap();
+        assert_eq!(2, result.total);
+        assert_eq!(2, result.hits.len());
+        assert_eq!(index_b, result.hits[0].hit.index);
+        assert_eq!(index_a, result.hits[1].hit.index);
+        assert!(result.errors.is_empty());
+        assert_eq!(2, result.shards.len());
+    }
+
+    #[test]
+    fn test_federated_search_merge() {
+        fn hit(id: &str, score: f64) -> String {
+            format!("{{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"{}\",\"_score\":{}}}", id, score)
+        }
+        let shards = "\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0}";
+        let response = format!("{{\"responses\":[\
+                                {{{},\"hits\":{{\"total\":3,\"hits\":[{},{},{}]}}}},\
+                                {{\"error\":\"IndexMissingException[[missing] missing]\"}},\
+                                {{{},\"hits\":{{\"total\":2,\"hits\":[{},{}]}}}}]}}",
+                               shards, hit("a1", 4.0), hit("a2", 2.0), hit("a3", 1.0),
+                               shards, hit("b1", 1.0), hit("b2", 0.5));
+        let response = Json::from_str(&response).unwrap();
+        let indexes = [("a", 1.0), ("missing", 1.0), ("b", 2.0)];
+
+        let all = FederatedSearchResult::from_responses(&indexes, &response, 0, 10).unwrap();
+        assert_eq!(5, all.total);
+        let ids:Vec<&str> = all.hits.iter().map(|h| &h.hit.id[..]).collect();
+        // a2 and b1 tie with a boosted score of 2.0, the first index wins
+        assert_eq!(vec!["a1", "a2", "b1", "a3", "b2"], ids);
+        assert_eq!(2.0, all.hits[2].boosted_score);
+        assert_eq!(1, all.errors.len());
+        assert!(all.errors.contains_key("missing"));
+        assert_eq!(2, all.shards.len());
+
+        let page = FederatedSearchResult::from_responses(&indexes, &response, 1, 2).unwrap();
+        let ids:Vec<&str> = page.hits.iter().map(|h| &h.hit.id[..]).collect();
+        assert_eq!(vec!["a2", "b1"], ids);
+    }
+
+    #[test]
+    fn test_hits_by_shard() {
+        let response = Json::from_str("{\"_shards\":{\"total\":2,\"successful\":2,\"failed\":0},\
+                                       \"hits\":{\"total\":3,\"hits\":[\
+                                       {\"_shard\":0,\"_node\":\"n1\",\"_index\":\"i\",\
+                                       \"_type\":\"t\",\"_id\":\"1\",\"_score\":1.0},\
+                                       {\"_shard\":\"[i][1]\",\"_node\":\"n2\",\"_index\":\"i\",\
+                                       \"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0},\
+                                       {\"_shard\":0,\"_node\":\"n1\",\"_index\":\"i\",\
+                                       \"_type\":\"t\",\"_id\":\"3\",\"_score\":1.0}]}}").unwrap();
+        let result = SearchResult::from(&response);
+        assert_eq!(Some(0), result.hits.hits[0].shard);
+        assert_eq!(Some(1), result.hits.hits[1].shard);
+        assert_eq!(Some("n2".to_string()), result.hits.hits[1].node);
+
+        let by_shard = result.hits_by_shard();
+        assert_eq!(2, by_shard.len());
+        assert_eq!(2, by_shard[&("i".to_string(), 0)]);
+        assert_eq!(1, by_shard[&("i".to_string(), 1)]);
+    }
+
+    #[test]
+    fn test_search_max_score() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":1,\"max_score\":2.5,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":2.5}]}}").unwrap();
+        assert_eq!(Some(2.5), SearchResult::from(&response).hits.max_score);
+        let typed:SearchResult<Json> = SearchResult::decode(response).unwrap();
+        assert_eq!(Some(2.5), typed.hits.max_score);
+
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":1,\"max_score\":null,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":null,\"sort\":[1]}]}}").unwrap();
+        assert_eq!(None, SearchResult::from(&response).hits.max_score);
+    }
+
+    #[test]
+    fn test_search_took_and_timed_out() {
+        let response = Json::from_str("{\"took\":12,\"timed_out\":true,\
+                                       \"_shards\":{\"total\":2,\"successful\":2,\"failed\":0},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        let result = SearchResult::from(&response);
+        assert_eq!(12, result.took);
+        assert!(result.timed_out);
+        assert!(result.is_partial());
+
+        let typed:SearchResult<TestDocument> = SearchResult::decode(response).unwrap();
+        assert_eq!(12, typed.took);
+        assert!(typed.timed_out);
+
+        // Both are missing from some responses
+        let response = Json::from_str("{\"_shards\":{\"total\":2,\"successful\":2,\"failed\":0},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        let result = SearchResult::from(&response);
+        assert_eq!(0, result.took);
+        assert!(!result.timed_out);
+        assert!(!result.is_partial());
+
+        let response = Json::from_str("{\"took\":3,\"timed_out\":false,\
+                                       \"_shards\":{\"total\":2,\"successful\":1,\"failed\":1,\
+                                       \"failures\":[{\"index\":\"i\",\"shard\":1,\
+                                       \"reason\":\"boom\"}]},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        assert!(SearchResult::from(&response).is_partial());
+
+        let index_name = "test_search_took_and_timed_out";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+        let result = client.search_query().with_indexes(&[index_name]).send().unwrap();
+        assert!(result.took >= 0);
+        assert!(!result.timed_out);
+        assert!(!result.is_partial());
+    }
+
+    #[test]
+    fn test_search_explanation() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":1.5,\"_explanation\":{\"value\":1.5,\
+                                       \"description\":\"sum of:\",\"details\":[\
+                                       {\"value\":1.5,\"description\":\"weight(str_field:a123)\"}]}},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\
+                                       \"_score\":1.0}]}}").unwrap();
+        let result = SearchResult::from_json(&response).unwrap();
+        let explanation = result.hits.hits[0].explanation.as_ref().unwrap();
+        assert_eq!(1.5, explanation.value);
+        assert_eq!("sum of:", explanation.description);
+        assert_eq!("weight(str_field:a123)", explanation.details[0].description);
+        assert!(result.hits.hits[1].explanation.is_none());
+
+        let index_name = "test_search_explanation";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "A123").build())
+            .with_explain(true)
+            .send().unwrap();
+        let hit = &result.hits.hits[0];
+        assert!(hit.shard.is_some());
+        assert!(!hit.explanation.as_ref().unwrap().description.is_empty());
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .send().unwrap();
+        assert!(result.hits.hits.iter().all(|hit| hit.explanation.is_none()));
+    }
+
+    #[test]
+    fn test_search_result_from_json() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":1,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":null}]}}").unwrap();
+        let result = SearchResult::from_json(&response).unwrap();
+        assert_eq!(None, result.hits.hits[0].score);
+
+        // A sorted search, the scores are not computed
+        let sorted = Json::from_str("{\"took\":2,\"timed_out\":false,\
+                                     \"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                     \"hits\":{\"total\":2,\"max_score\":null,\"hits\":[\
+                                     {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":null,\
+                                     \"_source\":{\"int_field\":1},\"sort\":[1]},\
+                                     {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\
+                                     \"_source\":{\"int_field\":2},\"sort\":[2]}]}}").unwrap();
+        let result = SearchResult::from_json(&sorted).unwrap();
+        assert_eq!(2, result.hits.hits.len());
+        assert!(result.hits.hits.iter().all(|hit| hit.score.is_none()));
+        assert_eq!(None, result.hits.max_score);
+
+        let missing = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                      \"hits\":{\"total\":1,\"hits\":[\
+                                      {\"_index\":\"i\",\"_type\":\"t\",\"_score\":1.0}]}}")
+            .unwrap();
+        match SearchResult::from_json(&missing) {
+            Err(EsError::EsError(msg)) => assert!(msg.contains("_id")),
+            _                          => panic!("Expected an error for the missing _id")
+        }
+    }
+
+    #[test]
+    fn test_search_hit_sort_values() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":2,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\"_score\":null,\
+                                       \"sort\":[\"abc\",3,1.5,null]},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\"_score\":1.0}]}}")
+            .unwrap();
+        let result = SearchResult::from_json(&response).unwrap();
+        let sort = result.hits.hits[0].sort.as_ref().unwrap();
+        assert_eq!(4, sort.len());
+        assert_eq!(Some("abc"), sort[0].as_string());
+        assert_eq!(Some(3), sort[1].as_i64());
+        assert_eq!(Some(1.5), sort[2].as_f64());
+        assert!(sort[3].is_null());
+        assert!(result.hits.hits[1].sort.is_none());
+    }
+
+    #[test]
+    fn test_response_error() {
+        let old = Json::from_str("{\"error\":\"IndexMissingException[[i] missing]\",\
+                                  \"status\":404}").unwrap();
+        let error = ResponseError::from_json("req", 404, &old).unwrap();
+        assert_eq!("req", error.request_id);
+        assert_eq!(None, error.error.error_type);
+        assert_eq!("IndexMissingException[[i] missing]", error.error.reason);
+        assert!(error.root_cause.is_empty());
+
+        let new = Json::from_str("{\"error\":{\"root_cause\":[{\"type\":\"index_not_found_exception\",\
+                                  \"reason\":\"no such index\",\"index\":\"i\"}],\
+                                  \"type\":\"index_not_found_exception\",\
+                                  \"reason\":\"no such index\",\"index\":\"i\"},\
+                                  \"status\":404}").unwrap();
+        let error = ResponseError::from_json("req", 404, &new).unwrap();
+        assert_eq!(Some("index_not_found_exception".to_string()), error.error.error_type);
+        assert_eq!(Some("i".to_string()), error.error.index);
+        assert_eq!(1, error.root_cause.len());
+        assert_eq!("no such index", error.root_cause[0].reason);
+
+        let not_error = Json::from_str("{\"acknowledged\":true}").unwrap();
+        assert!(ResponseError::from_json("req", 400, &not_error).is_none());
+    }
+
+    #[test]
+    fn test_cluster_block_error() {
+        let old = Json::from_str("{\"error\":\"ClusterBlockException[blocked by: \
+                                  [FORBIDDEN/8/index write (api)];]\",\"status\":403}").unwrap();
+        let error = ResponseError::from_json("req", 403, &old).unwrap();
+        assert_eq!(Some((8, "index write (api)".to_string())), error.cluster_block());
+
+        let new = Json::from_str("{\"error\":{\"root_cause\":[{\"type\":\"cluster_block_exception\",\
+                                  \"reason\":\"blocked by: [FORBIDDEN/5/index read-only (api)];\"}],\
+                                  \"type\":\"cluster_block_exception\",\
+                                  \"reason\":\"blocked by: [FORBIDDEN/5/index read-only (api)];\"},\
+                                  \"status\":403}").unwrap();
+        match EsError::from(ResponseError::from_json("req", 403, &new).unwrap()) {
+            EsError::IndexBlocked { block_id, ref reason } => {
+                assert_eq!(5, block_id);
+                assert_eq!("index read-only (api)", reason);
+            },
+            other                                          => {
+                panic!("Expected IndexBlocked, got: {:?}", other)
+            }
+        }
+
+        let global = Json::from_str("{\"error\":{\"type\":\"cluster_block_exception\",\
+                                     \"reason\":\"index [i] blocked by: \
+                                     [FORBIDDEN/12/index read-only / allow delete (api)];\"},\
+                                     \"status\":403}").unwrap();
+        let error = ResponseError::from_json("req", 403, &global).unwrap();
+        assert_eq!(Some((12, "index read-only / allow delete (api)".to_string())),
+                   error.cluster_block());
+
+        let other = Json::from_str("{\"error\":{\"type\":\"security_exception\",\
+                                    \"reason\":\"action is unauthorized\"},\"status\":403}").unwrap();
+        let error = ResponseError::from_json("req", 403, &other).unwrap();
+        assert!(error.cluster_block().is_none());
+        match EsError::from(error) {
+            EsError::ResponseError(_) => (),
+            other                     => panic!("Expected ResponseError, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_version_conflict_error() {
+        let old = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                  \"error\":\"VersionConflictEngineException[[i][2] [t][1]: \
+                                  version conflict, current [1], provided [2]]\"}").unwrap();
+        let error = ResponseError::from_json("req", 200, &old).unwrap();
+        assert!(error.is_version_conflict());
+        match EsError::from(error) {
+            EsError::ConflictError(_) => (),
+            other                     => panic!("Expected ConflictError, got: {:?}", other)
+        }
+
+        let new = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                  \"error\":{\"type\":\"version_conflict_engine_exception\",\
+                                  \"reason\":\"[t][1]: version conflict, current [1], provided [2]\"}}")
+            .unwrap();
+        assert!(ResponseError::from_json("req", 200, &new).unwrap().is_version_conflict());
+
+        let other = Json::from_str("{\"error\":{\"type\":\"index_not_found_exception\",\
+                                    \"reason\":\"no such index\"}}").unwrap();
+        assert!(!ResponseError::from_json("req", 200, &other).unwrap().is_version_conflict());
+    }
+
+    #[test]
+    fn test_is_writable() {
+        let index_name = "test_is_writable";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        assert!(client.is_writable(index_name).unwrap());
+
+        let mut blocked = BTreeMap::new();
+        blocked.insert("index.blocks.write".to_string(), true.to_json());
+        client.put_body_op(&format!("/{}/_settings", index_name), &Json::Object(blocked)).unwrap();
+        assert!(!client.is_writable(index_name).unwrap());
+        match client.index(index_name, "test_type").with_doc(&TestDocument::new()).send() {
+            Err(EsError::IndexBlocked { block_id, .. }) => assert_eq!(8, block_id),
+            other                                       => {
+                panic!("Expected IndexBlocked, got: {:?}", other)
+            }
+        }
+
+        let mut unblocked = BTreeMap::new();
+        unblocked.insert("index.blocks.write".to_string(), false.to_json());
+        client.put_body_op(&format!("/{}/_settings", index_name), &Json::Object(unblocked)).unwrap();
+        assert!(client.is_writable(index_name).unwrap());
+
+        match client.is_writable("test_is_writable_missing") {
+            Err(EsError::IndexMissingError(_)) => (),
+            other                              => panic!("Expected IndexMissingError, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let retry_policy = RetryPolicy::new(3, Duration::from_millis(100));
+        assert!(retry_policy.should_retry(StatusCode::TooManyRequests, 1));
+        assert!(retry_policy.should_retry(StatusCode::ServiceUnavailable, 2));
+        assert!(!retry_policy.should_retry(StatusCode::ServiceUnavailable, 3));
+        assert!(!retry_policy.should_retry(StatusCode::InternalServerError, 1));
+
+        assert_eq!(Duration::from_millis(100), retry_policy.backoff(1));
+        assert_eq!(Duration::from_millis(200), retry_policy.backoff(2));
+        assert_eq!(Duration::from_millis(400), retry_policy.backoff(3));
+    }
+
+    #[test]
+    fn test_ttl_and_timestamp_fields() {
+        let get = Json::from_str("{\"_index\":\"sessions\",\"_type\":\"session\",\"_id\":\"1\",\
+                                  \"_version\":1,\"found\":true,\
+                                  \"fields\":{\"_ttl\":3599250,\"_timestamp\":1450000000000}}")
+            .unwrap();
+        let result = GetResult::from_json(&get).unwrap();
+        assert_eq!(Some(Duration::from_millis(3599250)), result.ttl());
+        assert_eq!(Some(1450000000000), result.timestamp());
+
+        // An expired document which has not yet been purged, with the fields
+        // as arrays
+        let search = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                     \"hits\":{\"total\":2,\"hits\":[\
+                                     {\"_index\":\"sessions\",\"_type\":\"session\",\"_id\":\"2\",\
+                                     \"_score\":1.0,\"fields\":{\"_ttl\":[-20],\
+                                     \"_timestamp\":[1450000000001]}},\
+                                     {\"_index\":\"sessions\",\"_type\":\"session\",\"_id\":\"3\",\
+                                     \"_score\":1.0}]}}").unwrap();
+        let result = SearchResult::from_json(&search).unwrap();
+        assert_eq!(Some(Duration::from_millis(0)), result.hits.hits[0].ttl());
+        assert_eq!(Some(1450000000001), result.hits.hits[0].timestamp());
+        assert_eq!(None, result.hits.hits[1].ttl());
+        assert_eq!(None, result.hits.hits[1].timestamp());
+    }
+
+    #[test]
+    fn test_expiring_within_json() {
+        let query = Query::build_expiring_within(Duration::from_secs(3600)).to_json();
+        let range = query.find_path(&["range", "_ttl"]).unwrap();
+        let gte = range.find("gte").and_then(|v| v.as_i64()).unwrap();
+        let lte = range.find("lte").and_then(|v| v.as_i64()).unwrap();
+        assert_eq!(3600000, lte - gte);
+    }
+
+    #[test]
+    fn test_explain_result() {
+        // Leaf nodes have no `details`
+        let json = Json::from_str("{\"_index\":\"test_idx\",\"_type\":\"test_type\",\
+                                   \"_id\":\"1\",\"matched\":true,\"explanation\":{\
+                                   \"value\":1.5,\"description\":\"sum of:\",\"details\":[\
+                                   {\"value\":1.0,\"description\":\"weight(str_field:a123)\"},\
+                                   {\"value\":0.5,\"description\":\"queryNorm\",\
+                                   \"details\":[]}]}}").unwrap();
+        let result = ExplainResult::from_json(&json).unwrap();
+        assert!(result.found);
+        assert!(result.matched);
+        let explanation = result.explanation.unwrap();
+        assert_eq!(1.5, explanation.value);
+        assert_eq!(2, explanation.details.len());
+        assert_eq!("weight(str_field:a123)", explanation.details[0].description);
+        assert!(explanation.details[0].details.is_empty());
+
+        // A document which does not exist
+        let json = Json::from_str("{\"_index\":\"test_idx\",\"_type\":\"test_type\",\
+                                   \"_id\":\"2\",\"matched\":false}").unwrap();
+        let result = ExplainResult::from_json(&json).unwrap();
+        assert!(!result.found);
+        assert!(!result.matched);
+        assert!(result.explanation.is_none());
+
+        // The value of each node is required
+        let json = Json::from_str("{\"_index\":\"test_idx\",\"_type\":\"test_type\",\
+                                   \"_id\":\"3\",\"matched\":false,\"explanation\":{\
+                                   \"value\":null,\"description\":\"no match\"}}").unwrap();
+        assert!(ExplainResult::from_json(&json).is_err());
+
+        let json = Json::from_str("{\"valid\":false,\
+                                   \"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                   \"explanations\":[{\"index\":\"test_idx\",\"valid\":false,\
+                                   \"error\":\"Failed to parse query\"}]}").unwrap();
+        let result = ValidateQueryResult::from_json(&json).unwrap();
+        assert!(!result.valid);
+        assert_eq!(1, result.explanations.len());
+        assert_eq!(Some("Failed to parse query".to_string()), result.explanations[0].error);
+        assert_eq!(None, result.explanations[0].explanation);
+    }
+
+    #[test]
+    fn test_explain_and_validate() {
+        let index_name = "test_explain_and_validate";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        // The IDs of the documents are generated
+        let sort = Sort::field("int_field", Order::Asc);
+        let ids:Vec<String> = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_sort(&sort)
+            .send()
+            .unwrap()
+            .hits.hits.into_iter().map(|hit| hit.id).collect();
+
+        let query = Query::build_match("str_field", "A123").build();
+        let result = client
+            .explain(index_name, "test_type", &ids[0])
+            .with_query(&query)
+            .send()
+            .unwrap();
+        assert!(result.matched);
+        assert!(result.explanation.unwrap().value > 0.0);
+
+        let result = client
+            .explain(index_name, "test_type", &ids[1])
+            .with_query_string("str_field:A123".to_string())
+            .send()
+            .unwrap();
+        assert!(result.found);
+        assert!(!result.matched);
+
+        let result = client
+            .explain(index_name, "test_type", "no_such_document")
+            .with_query(&query)
+            .send()
+            .unwrap();
+        assert!(!result.found);
+        assert!(!result.matched);
+        assert!(result.explanation.is_none());
+
+        let result = client
+            .validate_query()
+            .with_indexes(&[index_name])
+            .with_query(&query)
+            .send()
+            .unwrap();
+        assert!(result.valid);
+        assert!(result.explanations[0].explanation.is_some());
+
+        let result = client
+            .validate_query()
+            .with_indexes(&[index_name])
+            .with_query_string("str_field:(A123".to_string())
+            .send()
+            .unwrap();
+        assert!(!result.valid);
+        assert!(result.explanations[0].error.is_some());
+    }
+
+    #[test]
+    fn test_resolve_date_math() {
+        // 2015-03-22T23:30:15Z, a Sunday
+        let now = UNIX_EPOCH + Duration::from_secs(1427067015);
+        let resolve = |expr| resolve_date_math(expr, now).unwrap();
+        assert_eq!("logs", resolve("logs"));
+        assert_eq!("logs-2015.03.22", resolve("<logs-{now/d}>"));
+        assert_eq!("logs-2015.03", resolve("<logs-{now/M{YYYY.MM}}>"));
+        assert_eq!("logs-2015.02.01", resolve("<logs-{now/M-1M}>"));
+        assert_eq!("logs-2015.03.16", resolve("<logs-{now/w}>"));
+        assert_eq!("logs-2014", resolve("<logs-{now-1y{YYYY}}>"));
+        assert_eq!("logs-2015.03.23-00", resolve("<logs-{now/h{YYYY.MM.dd-HH|+01:00}}>"));
+        assert_eq!("logs-2015.03.22", resolve("<logs-{now/d{YYYY.MM.dd|-05:00}}>"));
+        assert_eq!("logs-Mar-22", resolve("<logs-{now{MMM-dd}}>"));
+        assert_eq!("{logs}-2015", resolve("<\\{logs\\}-{now{YYYY}}>"));
+
+        // Month arithmetic is clamped to the end of the month
+        let now = UNIX_EPOCH + Duration::from_secs(1422662400); // 2015-01-31
+        assert_eq!("2015.02.28", resolve_date_math("<{now+1M}>", now).unwrap());
+
+        assert!(resolve_date_math("<logs-{now/d>", now).is_err());
+        assert!(resolve_date_math("<logs-{now/q}>", now).is_err());
+        assert!(resolve_date_math("<logs-{now{YYYY|Europe/London}}>", now).is_err());
+    }
+
+    #[test]
+    fn test_date_math_index_names() {
+        assert_eq!("logs", encode_index_name("logs"));
+        assert_eq!("%3Clogs-%7Bnow%2Fd%7BYYYY.MM.dd%7C%2B12%3A00%7D%7D%3E",
+                   encode_index_name("<logs-{now/d{YYYY.MM.dd|+12:00}}>"));
+
+        let mut client = make_client();
+        let index_name = resolve_date_math("<test_date_math-{now/d}>", SystemTime::now()).unwrap();
+        clean_db(&mut client, &index_name);
+        setup_search_test_data(&mut client, &index_name);
+
+        let result = client
+            .count()
+            .with_indexes(&["<test_date_math-{now/d}>"])
+            .send()
+            .unwrap();
+        assert_eq!(3, result.count);
+
+        assert!(client.index_exists("<test_date_math-{now/d}>").unwrap());
+        assert!(client.is_writable("<test_date_math-{now/d}>").unwrap());
+    }
+
+    #[test]
+    fn test_script_fields_json() {
+        let mut script_fields = BTreeMap::new();
+        script_fields.insert("doubled".to_string(),
+                             Script::new("doc['int_field'].value * factor")
+                             .with_lang("expression")
+                             .with_param("factor", &2));
+        script_fields.insert("plain".to_string(), Script::new("_score"));
+        let body = SearchQueryOperationBody::new().with_script_fields(script_fields).to_json();
+        let doubled = body.find_path(&["script_fields", "doubled"]).unwrap();
+        assert_eq!(Some("doc['int_field'].value * factor"),
+                   doubled.find("script").and_then(|s| s.as_string()));
+        assert_eq!(Some("expression"), doubled.find("lang").and_then(|l| l.as_string()));
+        assert_eq!(Some(2), doubled.find_path(&["params", "factor"]).and_then(|f| f.as_i64()));
+
+        // Empty parameters are omitted
+        let plain = body.find_path(&["script_fields", "plain"]).unwrap();
+        assert!(plain.find("params").is_none());
+        assert!(plain.find("lang").is_none());
+
+        // As are no script fields at all
+        let body = SearchQueryOperationBody::new().with_script_fields(BTreeMap::new()).to_json();
+        assert!(body.find("script_fields").is_none());
+    }
+
+    #[test]
+    fn test_search_script_fields() {
+        let index_name = "test_search_script_fields";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let mut script_fields = BTreeMap::new();
+        script_fields.insert("doubled".to_string(),
+                             Script::new("doc['int_field'].value * 2").with_lang("expression"));
+        script_fields.insert("scaled".to_string(),
+                             Script::new("doc['int_field'].value * factor")
+                             .with_lang("expression")
+                             .with_param("factor", &10));
+        let sort = Sort::field("int_field", Order::Asc);
+        let results = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_sort(&sort)
+            .with_script_fields(script_fields)
+            .send()
+            .unwrap();
+        let doubled:Vec<f64> = results.hits.hits.iter().map(|hit| {
+            hit.field("doubled").unwrap().unwrap()
+        }).collect();
+        assert_eq!(vec![2.0, 4.0, 6.0], doubled);
+        let scaled:Vec<f64> = results.hits.hits.iter().map(|hit| {
+            hit.field("scaled").unwrap().unwrap()
+        }).collect();
+        assert_eq!(vec![10.0, 20.0, 30.0], scaled);
+    }
+
+    #[test]
+    fn test_search_aggs() {
+        let index_name = "test_search_aggs";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let aggs = Aggregations::new()
+            .with("by_int", TermsAggregation::new("int_field")
+                  .with_size(2)
+                  .with_aggs(Aggregations::new()
+                             .with("max_int", Aggregation::metric(Metric::Max, "int_field")))
+                  .build())
+            .with("avg_int", Aggregation::metric(Metric::Avg, "int_field"));
+        assert_eq!(Json::from_str("{\"avg_int\":{\"avg\":{\"field\":\"int_field\"}},\
+                                   \"by_int\":{\"terms\":{\"field\":\"int_field\",\"size\":2},\
+                                   \"aggs\":{\"max_int\":{\"max\":{\"field\":\"int_field\"}}}}}")
+                   .unwrap(),
+                   aggs.to_json());
+
+        let result = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match_all().build())
+            .with_size(0)
+            .with_aggs(&aggs)
+            .send()
+            .unwrap();
+        let result_aggs = result.aggs.unwrap();
+        assert_eq!(Some(2.0), result_aggs.get("avg_int").unwrap().value());
+        let by_int = result_aggs.get("by_int").unwrap();
+        assert_eq!(Some(2), by_int.bucket_count());
+        for bucket in by_int.buckets() {
+            let bucket = bucket.unwrap();
+            assert_eq!(1, bucket.doc_count);
+            assert_eq!(bucket.key.as_f64(), bucket.get("max_int").unwrap().value());
+        }
     }
 
     #[test]
-    fn test_search_uri() {
-        let index_name = "test_search_uri";
+    fn test_aggregation_buckets() {
+        // A terms aggregation on a high-cardinality field, with a sub-aggregation
+        let bucket_count = 100000;
+        let buckets:Vec<String> = (0..bucket_count).map(|i| {
+            format!("{{\"key\":\"k{}\",\"doc_count\":{},\"avg_int\":{{\"value\":{}.5}}}}",
+                    i, bucket_count - i, i)
+        }).collect();
+        let json = format!("{{\"took\":120,\"timed_out\":false,\
+                            \"_shards\":{{\"total\":5,\"successful\":5,\"failed\":0}},\
+                            \"hits\":{{\"total\":{},\"max_score\":0.0,\"hits\":[]}},\
+                            \"aggregations\":{{\
+                              \"by_str\":{{\"doc_count_error_upper_bound\":0,\
+                                             \"sum_other_doc_count\":0,\
+                                             \"buckets\":[{}]}},\
+                              \"by_range\":{{\"buckets\":{{\
+                                \"low\":{{\"to\":2.0,\"doc_count\":1}},\
+                                \"high\":{{\"from\":2.0,\"doc_count\":2}}}}}},\
+                              \"max_int\":{{\"value\":3.0}}}}}}",
+                           bucket_count, buckets.join(","));
+        let result = SearchResult::from_json(&Json::from_str(&json).unwrap()).unwrap();
+        let aggs = result.aggs.unwrap();
+
+        let by_str = aggs.get("by_str").unwrap();
+        assert_eq!(Some(bucket_count), by_str.bucket_count());
+        assert_eq!(None, by_str.value());
+
+        // Only the buckets iterated over are parsed
+        let first:Vec<_> = by_str.buckets().take(2).map(|b| b.unwrap()).collect();
+        assert_eq!(Json::String("k0".to_string()), first[0].key);
+        assert_eq!(bucket_count as i64, first[0].doc_count);
+        assert_eq!(Some(1.5), first[1].get("avg_int").and_then(|a| a.value()));
+        assert_eq!(bucket_count, by_str.buckets().count());
+
+        // Keyed buckets take their key from the object
+        let keys:Vec<Json> = aggs.get("by_range").unwrap().buckets()
+            .map(|b| b.unwrap().key)
+            .collect();
+        assert_eq!(vec![Json::String("high".to_string()), Json::String("low".to_string())],
+                   keys);
+
+        let max_int = aggs.get("max_int").unwrap();
+        assert_eq!(Some(3.0), max_int.value());
+        assert_eq!(None, max_int.bucket_count());
+        assert_eq!(0, max_int.buckets().count());
+        assert!(aggs.get("missing").is_none());
+
+        assert!(check_bucket_counts(aggs.raw(), bucket_count).is_ok());
+        match check_bucket_counts(aggs.raw(), 1000) {
+            Err(EsError::TooManyBucketsError(name, count)) => {
+                assert_eq!("by_str", name);
+                assert_eq!(bucket_count, count);
+            },
+            other => panic!("Expected TooManyBucketsError, got: {:?}", other)
+        }
+
+        // Sub-aggregations are also counted
+        let nested = Json::from_str("{\"outer\":{\"buckets\":[{\"key\":1,\"doc_count\":3,\
+                                      \"inner\":{\"buckets\":[{\"key\":\"a\",\"doc_count\":1},\
+                                                                 {\"key\":\"b\",\"doc_count\":2}]}}]}}")
+            .unwrap();
+        assert!(check_bucket_counts(&nested, 2).is_ok());
+        match check_bucket_counts(&nested, 1) {
+            Err(EsError::TooManyBucketsError(name, count)) => {
+                assert_eq!("inner", name);
+                assert_eq!(2, count);
+            },
+            other => panic!("Expected TooManyBucketsError, got: {:?}", other)
+        }
+
+        // Aggregations are optional
+        let json = "{\"_shards\":{\"total\":5,\"successful\":5,\"failed\":0},\
+                     \"hits\":{\"total\":0,\"max_score\":null,\"hits\":[]}}";
+        assert!(SearchResult::from_json(&Json::from_str(json).unwrap()).unwrap().aggs.is_none());
+    }
+
+    #[test]
+    fn test_stats_result() {
+        let json = "{\"_shards\":{\"total\":10,\"successful\":5,\"failed\":0},\
+                     \"_all\":{\
+                       \"primaries\":{\"fielddata\":{\"memory_size_in_bytes\":1200,\"evictions\":0,\
+                                                      \"fields\":{\"tags\":{\"memory_size_in_bytes\":1000},\
+                                                                  \"user\":{\"memory_size_in_bytes\":200}}},\
+                                     \"completion\":{\"size_in_bytes\":300,\
+                                                      \"fields\":{\"suggest\":{\"size_in_bytes\":300}}}},\
+                       \"total\":{\"fielddata\":{\"memory_size_in_bytes\":2400,\"evictions\":3},\
+                                 \"completion\":{\"size_in_bytes\":600}}},\
+                     \"indices\":{\"idx\":{\
+                       \"primaries\":{\"docs\":{\"count\":5,\"deleted\":0}},\
+                       \"total\":{\"docs\":{\"count\":10,\"deleted\":0}}}}}";
+        let result = IndicesStatsResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        let fielddata = result.all.primaries.fielddata.as_ref().unwrap();
+        assert_eq!(1200, fielddata.memory_size_in_bytes);
+        assert_eq!(Some(&1000), fielddata.fields.get("tags"));
+        assert_eq!(Some(&200), fielddata.fields.get("user"));
+        let completion = result.all.primaries.completion.as_ref().unwrap();
+        assert_eq!(Some(&300), completion.fields.get("suggest"));
+
+        // Fields are only reported if requested
+        let fielddata = result.all.total.fielddata.as_ref().unwrap();
+        assert_eq!(3, fielddata.evictions);
+        assert!(fielddata.fields.is_empty());
+
+        // Other metrics are only available as JSON
+        let idx = result.indices.get("idx").unwrap();
+        assert!(idx.primaries.fielddata.is_none());
+        assert_eq!(Some(10), idx.total.raw.find_path(&["docs", "count"]).and_then(|c| c.as_i64()));
+
+        let json = "{\"cluster_name\":\"es\",\"nodes\":{\"n1\":{\"name\":\"node-1\",\
+                     \"host\":\"10.0.0.1\",\"indices\":{\"fielddata\":{\
+                       \"memory_size_in_bytes\":50,\"evictions\":1,\
+                       \"fields\":{\"tags\":{\"memory_size_in_bytes\":50}}}}}}}";
+        let result = NodesStatsResult::from_json(&Json::from_str(json).unwrap()).unwrap();
+        let node = result.nodes.get("n1").unwrap();
+        assert_eq!("node-1", node.name);
+        assert_eq!(Some(&50), node.indices.fielddata.as_ref().unwrap().fields.get("tags"));
+    }
+
+    #[test]
+    fn test_cat_rows() {
+        let json = "{\"index\":\"i\",\"shard\":\"0\",\"time\":\"120\",\"type\":\"replica\",\
+                     \"stage\":\"index\",\"source_host\":\"10.0.0.1\",\"target_host\":\"10.0.0.2\",\
+                     \"files_percent\":\"100.0%\",\"bytes_percent\":\"84.5%\",\"total_bytes\":\"1000\",\
+                     \"translog_percent\":\"-1.0%\"}";
+        let row = RecoveryRow::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert_eq!(0, row.shard);
+        assert_eq!("replica", row.recovery_type);
+        assert_eq!(100.0, row.files_percent);
+        assert_eq!(84.5, row.bytes_percent);
+        assert_eq!(Some(1000), row.total_bytes);
+        assert_eq!(Some(-1.0), row.translog_percent);
+
+        // Columns renamed in ElasticSearch 5.x
+        let json = "{\"index\":\"i\",\"shard\":\"1\",\"time\":\"5ms\",\"type\":\"store\",\
+                     \"stage\":\"done\",\"files_percent\":\"0.0%\",\"bytes_percent\":\"0.0%\",\
+                     \"bytes_total\":\"230\",\"translog_ops_percent\":\"100.0%\"}";
+        let row = RecoveryRow::from_json(&Json::from_str(json).unwrap()).unwrap();
+        assert_eq!(Some(230), row.total_bytes);
+        assert_eq!(Some(100.0), row.translog_percent);
+        assert_eq!(None, row.source_host);
+
+        // A row for each node, with columns for each pool
+        let json = "[{\"host\":\"h1\",\"ip\":\"10.0.0.1\",\
+                      \"bulk.active\":\"1\",\"bulk.queue\":\"2\",\"bulk.rejected\":\"3\",\
+                      \"index.active\":\"0\",\"index.queue\":\"0\",\"index.rejected\":\"0\",\
+                      \"search.active\":\"4\",\"search.queue\":\"5\",\"search.rejected\":\"6\"}]";
+        let rows = thread_pool_rows(&Json::from_str(json).unwrap(), &["bulk", "search"]).unwrap();
+        assert_eq!(vec![ThreadPoolRow {
+                            node:     "h1".to_string(),
+                            pool:     "bulk".to_string(),
+                            active:   1,
+                            queue:    2,
+                            rejected: 3
+                        },
+                        ThreadPoolRow {
+                            node:     "h1".to_string(),
+                            pool:     "search".to_string(),
+                            active:   4,
+                            queue:    5,
+                            rejected: 6
+                        }],
+                   rows);
+
+        // ElasticSearch 5.x onwards, a row for each pool on each node
+        let json = "[{\"node_name\":\"n1\",\"name\":\"get\",\"active\":\"0\",\
+                      \"queue\":\"0\",\"rejected\":\"0\"},\
+                     {\"node_name\":\"n1\",\"name\":\"search\",\"active\":\"2\",\
+                      \"queue\":\"10\",\"rejected\":\"120\"}]";
+        let rows = thread_pool_rows(&Json::from_str(json).unwrap(), &["bulk", "search"]).unwrap();
+        assert_eq!(1, rows.len());
+        assert_eq!("search", rows[0].pool);
+        assert_eq!(120, rows[0].rejected);
+    }
+
+    #[test]
+    fn test_cat() {
+        let index_name = "test_cat";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+
+        let rows = client.cat_recovery().with_indexes(&[index_name]).send().unwrap();
+        assert!(!rows.is_empty());
+        for row in rows.iter() {
+            assert_eq!(index_name, row.index);
+            assert_eq!("done", row.stage);
+        }
+
+        let rows = client.cat_thread_pool().send().unwrap();
+        assert!(rows.iter().any(|row| row.pool == "search"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let index_name = "test_stats";
         let mut client = make_client();
         clean_db(&mut client, index_name);
         setup_search_test_data(&mut client, index_name);
 
-        let all_results = client.search_uri().with_indexes(&[index_name]).send().unwrap();
-        assert_eq!(3, all_results.hits.total);
+        let result = client
+            .indices_stats()
+            .with_indexes(&[index_name])
+            .with_metrics(&["fielddata", "completion"])
+            .with_fields(&["*"])
+            .send()
+            .unwrap();
+        let index = result.indices.get(index_name).unwrap();
+        assert!(index.total.fielddata.is_some());
+        assert!(index.total.completion.is_some());
 
-        let doc_a = client
-            .search_uri()
+        let result = client
+            .nodes_stats()
+            .with_metrics(&["fielddata"])
+            .with_fields(&["*"])
+            .send()
+            .unwrap();
+        assert!(!result.nodes.is_empty());
+        for node in result.nodes.values() {
+            assert!(node.indices.fielddata.is_some());
+        }
+
+        match client.indices_stats().with_indexes(&["test_stats_missing"]).send() {
+            Err(EsError::IndexMissingError(_)) => (),
+            other                              => panic!("Expected IndexMissingError, got: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_named_query_json() {
+        // The name is with the other parameters of the field
+        let query = Query::build_match("str_field", "A123").with_name("a").build().to_json();
+        assert_eq!(Some("a"),
+                   query.find_path(&["match", "str_field", "_name"]).and_then(|n| n.as_string()));
+
+        let query = Query::build_bool()
+            .with_should(vec![Query::build_term("int_field", 1).with_name("one").build()])
+            .with_name("either")
+            .build()
+            .to_json();
+        assert_eq!(Some("either"),
+                   query.find_path(&["bool", "_name"]).and_then(|n| n.as_string()));
+
+        // Unnamed queries are unchanged
+        let query = Query::build_match("str_field", "A123").build().to_json();
+        assert!(query.find_path(&["match", "str_field", "_name"]).is_none());
+    }
+
+    #[test]
+    fn test_matched_queries_from_json() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":3,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":1.0,\"matched_queries\":[\"title\",\"body\"]},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\
+                                       \"_score\":1.0,\"matched_queries\":[]},\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"3\",\
+                                       \"_score\":1.0}]}}").unwrap();
+        let result = SearchResult::from(&response);
+        assert_eq!(Some(vec!["title".to_string(), "body".to_string()]),
+                   result.hits.hits[0].matched_queries);
+        assert_eq!(None, result.hits.hits[1].matched_queries);
+        assert_eq!(None, result.hits.hits[2].matched_queries);
+    }
+
+    #[test]
+    fn test_search_matched_queries() {
+        let index_name = "test_search_matched_queries";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let query = Query::build_bool()
+            .with_should(vec![Query::build_term("int_field", 1).with_name("one").build(),
+                              Query::build_range("int_field").with_lte(2).with_name("low").build(),
+                              Query::build_term("int_field", 3).build()])
+            .build();
+        let results = client
+            .search_query()
             .with_indexes(&[index_name])
-            .with_query("A123".to_string())
+            .with_query(&query)
+            .with_sort(&Sort::field("int_field", Order::Asc))
             .send()
             .unwrap();
-        assert_eq!(1, doc_a.hits.total);
+        let matched:Vec<_> = results.hits.hits.iter().map(|hit| {
+            hit.matched_queries.clone().map(|mut m| {
+                m.sort();
+                m
+            })
+        }).collect();
+        assert_eq!(vec![Some(vec!["low".to_string(), "one".to_string()]),
+                        Some(vec!["low".to_string()]),
+                        None],
+                   matched);
+    }
 
-        let doc_1 = client
-            .search_uri()
+    #[test]
+    fn test_search_with_version() {
+        let index_name = "test_search_with_version";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let results = client
+            .search_query()
             .with_indexes(&[index_name])
-            .with_query("str_field:1ABC".to_string())
+            .with_version(true)
             .send()
             .unwrap();
-        assert_eq!(1, doc_1.hits.total);
+        assert_eq!(3, results.hits.hits.len());
+        for hit in results.hits.hits.iter() {
+            assert_eq!(Some(1), hit.version);
+        }
 
-        let limited_fields = client
+        let results = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .send()
+            .unwrap();
+        assert_eq!(None, results.hits.hits[0].version);
+
+        let results = client
             .search_uri()
             .with_indexes(&[index_name])
-            .with_query("str_field:B456".to_string())
-            .with_fields(&["int_field"])
+            .with_version(&true)
             .send()
             .unwrap();
-        assert_eq!(1, limited_fields.hits.total);
+        assert!(results.hits.hits.iter().all(|hit| hit.version == Some(1)));
     }
 
     #[test]
-    fn test_search_body() {
-        let index_name = "test_search_body";
+    fn test_hit_sequence_metadata() {
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":1,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":1.0,\"_version\":3,\"_seq_no\":7,\
+                                       \"_primary_term\":1}]}}").unwrap();
+        let result = SearchResult::from_json(&response).unwrap();
+        assert_eq!(Some(3), result.hits.hits[0].version);
+        assert_eq!(Some(7), result.hits.hits[0].seq_no);
+        assert_eq!(Some(1), result.hits.hits[0].primary_term);
+
+        let response = Json::from_str("{\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":1,\"hits\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_score\":1.0}]}}").unwrap();
+        let result = SearchResult::from(&response);
+        assert_eq!(None, result.hits.hits[0].version);
+        assert_eq!(None, result.hits.hits[0].seq_no);
+    }
+
+    #[test]
+    fn test_source_json() {
+        assert_eq!("false", Source::Disabled.to_json().to_string());
+        assert_eq!("[\"a\",\"b.*\"]", Source::fields(&["a", "b.*"]).to_json().to_string());
+        assert_eq!("{\"exclude\":[\"big_*\"]}",
+                   Source::filter(&[], &["big_*"]).to_json().to_string());
+        assert_eq!("{\"exclude\":[\"b\"],\"include\":[\"a\"]}",
+                   Source::filter(&["a"], &["b"]).to_json().to_string());
+    }
+
+    #[test]
+    fn test_search_source_filtering() {
+        let index_name = "test_search_source_filtering";
         let mut client = make_client();
         clean_db(&mut client, index_name);
         setup_search_test_data(&mut client, index_name);
 
-        let all_results = client
+        let results = client
             .search_query()
             .with_indexes(&[index_name])
-            .with_query(&Query::build_match_all().build())
-            .send().unwrap();
-        assert_eq!(3, all_results.hits.total);
+            .with_source(Source::filter(&[], &["str_field"]))
+            .send()
+            .unwrap();
+        let source = results.hits.hits[0].source.as_ref().unwrap();
+        assert!(source.find("str_field").is_none());
+        assert!(source.find("int_field").is_some());
 
-        let within_range = client
+        let results = client
             .search_query()
             .with_indexes(&[index_name])
-            .with_query(&Query::build_filtered(Filter::build_range("int_field")
-                                               .with_gte(2)
-                                               .with_lte(3)
-                                               .build())
-                        .build())
-            .send().unwrap();
-        assert_eq!(2, within_range.hits.total);
+            .with_query(&Query::build_match("str_field", "B456").build())
+            .with_source(Source::Disabled)
+            .with_fields(&["int_field"])
+            .send()
+            .unwrap();
+        let hit = &results.hits.hits[0];
+        assert!(hit.source.is_none());
+        assert_eq!(Some(2), hit.field::<i64>("int_field").unwrap());
+        assert_eq!(None, hit.field::<i64>("missing_field").unwrap());
+
+        let body = SearchQueryOperationBody::new().with_fields(&["int_field", "str_field"]).to_json();
+        assert_eq!(Some(&vec!["int_field".to_json(), "str_field".to_json()]),
+                   body.find("fields").and_then(|f| f.as_array()));
+        let results = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "B456").build())
+            .with_fields(&["int_field", "str_field"])
+            .send()
+            .unwrap();
+        let hit = &results.hits.hits[0];
+        assert_eq!(Some(2), hit.field::<i64>("int_field").unwrap());
+        assert_eq!(Some("Document B456".to_string()), hit.field::<String>("str_field").unwrap());
+        assert_eq!(vec![2], hit.field_values::<i64>("int_field").unwrap());
+        assert!(hit.field_values::<i64>("missing_field").unwrap().is_empty());
+
+        // Only the returned fields need be in the type decoded to
+        #[derive(Debug, RustcDecodable)]
+        struct IntFieldOnly {
+            int_field: i64
+        }
+        let results = client
+            .search_query()
+            .with_indexes(&[index_name])
+            .with_query(&Query::build_match("str_field", "B456").build())
+            .with_source(Source::fields(&["int_field"]))
+            .send_typed::<IntFieldOnly>()
+            .unwrap();
+        assert_eq!(2, results.hits.hits[0].source.as_ref().unwrap().int_field);
+    }
+
+    #[test]
+    fn test_error_predicates() {
+        let refused = EsError::from(io::Error::new(io::ErrorKind::ConnectionRefused, "refused"));
+        assert!(refused.is_connection_error());
+        assert!(!refused.is_timeout());
+        assert!(refused.cause().is_some());
+
+        let timeout = EsError::HttpError(HttpError::Io(io::Error::new(io::ErrorKind::TimedOut,
+                                                                      "timed out")));
+        assert!(timeout.is_timeout());
+        assert!(!timeout.is_connection_error());
+
+        let other = EsError::EsError("other".to_string());
+        assert!(!other.is_connection_error());
+        assert!(!other.is_timeout());
+
+        let retry_policy = RetryPolicy::new(2, Duration::from_millis(1));
+        assert!(retry_policy.should_retry_error(&timeout, 1));
+        assert!(!retry_policy.should_retry_error(&timeout, 2));
+        assert!(!retry_policy.should_retry_error(&other, 1));
+    }
+
+    #[test]
+    fn test_multiple_hosts() {
+        // Nothing listens on port 1, so the first attempt fails to connect
+        let es_host = es_host();
+        let retry_policy = RetryPolicy::new(2, Duration::from_millis(1));
+        let mut client = Client::with_hosts(&[("localhost", 1), (&es_host, 9200)])
+            .with_retry_policy(retry_policy.clone())
+            .with_timeout(Duration::from_secs(10));
+        client.version().unwrap();
+
+        let mut unreachable = Client::with_hosts(&[("localhost", 1), ("localhost", 2)])
+            .with_retry_policy(retry_policy);
+        match unreachable.version() {
+            Err(EsError::RetriesExhaustedError(hosts, err)) => {
+                assert_eq!(vec!["http://localhost:1", "http://localhost:2"], hosts);
+                assert!(err.is_connection_error());
+            },
+            other => panic!("Unexpected result: {:?}", other)
+        }
+
+        // Without a retry policy there is no failover
+        let mut no_retry = Client::with_hosts(&[("localhost", 1), (&es_host, 9200)]);
+        assert!(no_retry.version().unwrap_err().is_connection_error());
+    }
+
+    #[test]
+    fn test_delete_not_retried() {
+        let index_name = "test_delete_not_retried";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        client.index(index_name, "test_type")
+            .with_id("DELETE1")
+            .with_doc(&TestDocument::new())
+            .send()
+            .unwrap();
+
+        // Nothing listens on port 1, so the first attempt fails to connect
+        let es_host = es_host();
+        let retry_policy = RetryPolicy::new(2, Duration::from_millis(1));
+        let mut client = Client::with_hosts(&[("localhost", 1), (&es_host, 9200)])
+            .with_retry_policy(retry_policy.clone());
+        let result = client.delete(index_name, "test_type", "DELETE1").send();
+        assert!(result.unwrap_err().is_connection_error());
+        assert_eq!(1, client.attempts());
+
+        let mut client = Client::with_hosts(&[("localhost", 1), (&es_host, 9200)])
+            .with_retry_policy(retry_policy.with_non_idempotent(true));
+        let result = client.delete(index_name, "test_type", "DELETE1").send().unwrap();
+        assert!(result.found);
+        assert_eq!(2, client.attempts());
+    }
+
+    #[test]
+    fn test_deadline() {
+        // Nothing listens on ports 1 and 2, so each attempt fails at once and
+        // the deadline passes during the backoff
+        let mut client = Client::with_hosts(&[("localhost", 1), ("localhost", 2)])
+            .with_retry_policy(RetryPolicy::new(10, Duration::from_millis(100)))
+            .with_timeout(Duration::from_secs(10));
+        let result = client.with_deadline(Duration::from_millis(250)).version();
+        match result {
+            Err(EsError::DeadlineExceeded { elapsed, attempts }) => {
+                assert_eq!(2, attempts);
+                assert_eq!(2, client.attempts());
+                assert!(elapsed < Duration::from_millis(250));
+            },
+            other => panic!("Expected DeadlineExceeded, got: {:?}", other)
+        }
+
+        // The deadline only applies to the next request
+        assert!(client.next_deadline.is_none());
+
+        let mut client = make_client();
+        client.with_deadline(Duration::from_secs(10)).version().unwrap();
+    }
+
+    #[test]
+    fn test_observer() {
+        let es_host = es_host();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let observed = events.clone();
+        let mut client = Client::with_hosts(&[("localhost", 1), (&es_host, 9200)])
+            .with_retry_policy(RetryPolicy::new(3, Duration::from_millis(5)))
+            .with_observer(move |_: &str, event: &ClientEvent| {
+                observed.lock().unwrap().push(event.clone());
+            });
+        client.version().unwrap();
+        assert_eq!(2, client.attempts());
+        {
+            let events = events.lock().unwrap();
+            assert_eq!(3, events.len());
+            assert_eq!(ClientEvent::HostMarkedDead {
+                host:                 "http://localhost:1".to_string(),
+                consecutive_failures: 1
+            }, events[0]);
+            match events[1] {
+                ClientEvent::RetryScheduled { attempt, delay, ref reason } => {
+                    assert_eq!(2, attempt);
+                    assert_eq!(Duration::from_millis(5), delay);
+                    assert!(!reason.is_empty());
+                },
+                ref other => panic!("Expected RetryScheduled, got: {:?}", other)
+            }
+            assert_eq!(ClientEvent::FailoverTo {
+                host: format!("http://{}:9200", es_host)
+            }, events[2]);
+        }
+
+        // Hosts are used in turn, so the next request also starts with the
+        // unreachable host, the failures of which are counted until it
+        // succeeds
+        client.version().unwrap();
+        assert_eq!(2, client.attempts());
+        let events = events.lock().unwrap();
+        assert_eq!(6, events.len());
+        assert_eq!(ClientEvent::HostMarkedDead {
+            host:                 "http://localhost:1".to_string(),
+            consecutive_failures: 2
+        }, events[3]);
+    }
+
+    #[test]
+    fn test_msearch() {
+        let index_name = "test_msearch";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        setup_search_test_data(&mut client, index_name);
+
+        let query = Query::build_match("str_field", "B456").build();
+        let result = client
+            .msearch()
+            .with_search(&[index_name], &[], SearchQueryOperationBody::new())
+            .with_search(&[index_name], &["test_type"],
+                         SearchQueryOperationBody::new().with_query(&query))
+            .with_search(&["test_msearch_missing"], &[], SearchQueryOperationBody::new())
+            .send()
+            .unwrap();
+
+        assert_eq!(3, result.responses.len());
+        assert_eq!(3, result.responses[0].as_ref().unwrap().hits.total);
+        let hits = &result.responses[1].as_ref().unwrap().hits;
+        assert_eq!(1, hits.total);
+        assert_eq!(Some(2), hits.hits[0].source.as_ref()
+                   .and_then(|s| s.find("int_field"))
+                   .and_then(|i| i.as_i64()));
+        assert!(result.responses[2].is_err());
+
+        let result = client
+            .msearch()
+            .with_search_type(SearchType::QueryThenFetch)
+            .with_search(&[index_name], &[], SearchQueryOperationBody::new().with_query(&query))
+            .with_search_of_type(&[index_name], &[], SearchType::DFSQueryThenFetch,
+                                 SearchQueryOperationBody::new().with_query(&query))
+            .send()
+            .unwrap();
+        assert_eq!(2, result.responses.len());
+        assert!(result.responses.iter().all(|r| r.as_ref().unwrap().hits.total == 1));
+    }
+
+    #[test]
+    fn test_msearch_result() {
+        let response = Json::from_str("{\"responses\":[\
+                                       {\"_shards\":{\"total\":1,\"successful\":1,\"failed\":0},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}},\
+                                       {\"error\":{\"type\":\"index_not_found_exception\",\
+                                       \"reason\":\"no such index\"},\"status\":404}]}").unwrap();
+        let result = MultiSearchResult::from_responses("req", &response).unwrap();
+        assert_eq!(0, result.responses[0].as_ref().unwrap().hits.total);
+        match result.responses[1] {
+            Err(EsError::ResponseError(ref err)) => assert_eq!(404, err.status),
+            _                                    => panic!("Expected an error")
+        }
+    }
+
+    #[test]
+    fn test_termvectors_result() {
+        let response = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"_version\":1,\"found\":true,\"took\":1,\
+                                       \"term_vectors\":{\"str_field\":{\
+                                       \"field_statistics\":{\"sum_doc_freq\":6,\"doc_count\":3,\
+                                       \"sum_ttf\":6},\
+                                       \"terms\":{\"document\":{\"doc_freq\":3,\"ttf\":3,\
+                                       \"term_freq\":1,\"tokens\":[{\"position\":0,\
+                                       \"start_offset\":0,\"end_offset\":8}]},\
+                                       \"b456\":{\"term_freq\":1}}}}}").unwrap();
+        let result = TermVectorsResult::from_json(&response).unwrap();
+        assert!(result.found);
+        assert_eq!(Some("1".to_string()), result.id);
+        let field = &result.term_vectors["str_field"];
+        assert_eq!(3, field.field_statistics.as_ref().unwrap().doc_count);
+        let document = &field.terms["document"];
+        assert_eq!(1, document.term_freq);
+        assert_eq!(Some(3), document.doc_freq);
+        assert_eq!(Some(8), document.tokens[0].end_offset);
+        assert_eq!(None, field.terms["b456"].ttf);
+        assert!(field.terms["b456"].tokens.is_empty());
+
+        let response = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"2\",\
+                                       \"found\":false,\"took\":0}").unwrap();
+        let result = TermVectorsResult::from_json(&response).unwrap();
+        assert!(!result.found);
+        assert!(result.term_vectors.is_empty());
+    }
+
+    #[test]
+    fn test_termvectors() {
+        let index_name = "test_termvectors";
+        let mut client = make_client();
+        clean_db(&mut client, index_name);
+        client.index(index_name, "test_type")
+            .with_id("1")
+            .with_doc(&TestDocument::new().with_str_field("Document B456 B456"))
+            .with_refresh(&true)
+            .send()
+            .unwrap();
+
+        let result = client.termvectors(index_name, "test_type")
+            .with_id("1")
+            .with_fields(&["str_field"])
+            .with_term_statistics(&true)
+            .send()
+            .unwrap();
+        assert!(result.found);
+        let terms = &result.term_vectors["str_field"].terms;
+        assert_eq!(2, terms["b456"].term_freq);
+        assert_eq!(Some(1), terms["b456"].doc_freq);
+        assert_eq!(vec![Some(1), Some(2)],
+                   terms["b456"].tokens.iter().map(|t| t.position).collect::<Vec<_>>());
+
+        let result = client.termvectors(index_name, "test_type")
+            .with_id("2")
+            .send()
+            .unwrap();
+        assert!(!result.found);
+
+        let mut doc = BTreeMap::new();
+        doc.insert("str_field".to_string(), "Other Document".to_json());
+        let result = client.termvectors(index_name, "test_type")
+            .with_doc(&Json::Object(doc))
+            .send()
+            .unwrap();
+        assert!(result.term_vectors["str_field"].terms.contains_key("other"));
+
+        let ids = ["1", "2"];
+        let result = client.mtermvectors()
+            .with_index(index_name)
+            .with_doc_type("test_type")
+            .with_ids(&ids)
+            .send()
+            .unwrap();
+        assert_eq!(2, result.docs.len());
+        assert!(result.docs[0].as_ref().unwrap().found);
+        assert!(!result.docs[1].as_ref().unwrap().found);
+
+        let fields = ["str_field"];
+        let result = client.mtermvectors()
+            .with_doc(MultiTermVectorsDoc::new("1")
+                      .with_index(index_name)
+                      .with_doc_type("test_type")
+                      .with_fields(&fields)
+                      .with_term_statistics(true))
+            .with_doc(MultiTermVectorsDoc::new("1")
+                      .with_index("test_termvectors_missing")
+                      .with_doc_type("test_type"))
+            .send()
+            .unwrap();
+        assert_eq!(2, result.docs.len());
+        let doc = result.docs[0].as_ref().unwrap();
+        assert_eq!(Some(1), doc.term_vectors["str_field"].terms["b456"].doc_freq);
+        assert!(result.docs[1].is_err());
+
+        assert!(client.mtermvectors().with_ids(&ids).send().is_err());
+    }
+
+    #[test]
+    fn test_mtermvectors_result() {
+        let response = Json::from_str("{\"docs\":[\
+                                       {\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"found\":true,\"term_vectors\":{\"str_field\":{\
+                                       \"terms\":{\"b456\":{\"term_freq\":2}}}}},\
+                                       {\"_index\":\"missing\",\"_type\":\"t\",\"_id\":\"1\",\
+                                       \"error\":\"[missing] missing\"}]}").unwrap();
+        let result = MultiTermVectorsResult::from_docs("req", 200, &response).unwrap();
+        assert_eq!(2, result.docs.len());
+        let doc = result.docs[0].as_ref().unwrap();
+        assert_eq!(2, doc.term_vectors["str_field"].terms["b456"].term_freq);
+        assert!(result.docs[1].is_err());
+    }
+
+    #[test]
+    fn test_constant_query_json() {
+        let query = Query::build_term("str_field", "value").build().constant(2.5);
+        assert_eq!("{\"constant_score\":{\"boost\":2.5,\"query\":{\"term\":{\"str_field\":{\"value\":\"value\"}}}}}",
+                   query.to_json().to_string());
+    }
+
+    #[test]
+    fn test_recency_boost_json() {
+        let base = Query::build_match("str_field", "value").build();
+        let boost = RecencyBoost::new("updated", ::query::Duration::new(7, DurationUnit::Day), 2.0);
+        assert_eq!("{\"function_score\":{\"boost_mode\":\"sum\",\"functions\":[\
+                    {\"gauss\":{\"updated\":{\"decay\":0.5,\"origin\":\"now\",\"scale\":\"7d\"}},\
+                    \"weight\":2.0}],\
+                    \"query\":{\"match\":{\"str_field\":{\"query\":\"value\"}}}}}",
+                   boost.build(base.clone()).to_json().to_string());
+
+        let boost = boost.with_origin("now/d")
+            .with_offset(::query::Duration::new(1, DurationUnit::Day))
+            .with_decay(0.25);
+        let query = boost.function_score(base)
+            .with_boost_mode(BoostMode::Multiply)
+            .with_max_boost(10.0)
+            .build();
+        assert_eq!("{\"function_score\":{\"boost_mode\":\"multiply\",\"functions\":[\
+                    {\"gauss\":{\"updated\":{\"decay\":0.25,\"offset\":\"1d\",\
+                    \"origin\":\"now/d\",\"scale\":\"7d\"}},\"weight\":2.0}],\
+                    \"max_boost\":10.0,\
+                    \"query\":{\"match\":{\"str_field\":{\"query\":\"value\"}}}}}",
+                   query.to_json().to_string());
+    }
+
+    #[test]
+    fn test_weighted_should_json() {
+        let query = Query::build_bool()
+            .with_weighted_should(vec![(Query::build_match_all().build(), 2.0),
+                                       (Query::build_term("int_field", 1i64).build(), 0.5)])
+            .build();
+        assert_eq!("{\"bool\":{\"should\":[\
+                    {\"bool\":{\"boost\":2.0,\"must\":[{\"match_all\":{}}]}},\
+                    {\"bool\":{\"boost\":0.5,\"must\":[{\"term\":{\"int_field\":{\"value\":1}}}]}}\
+                    ]}}",
+                   query.to_json().to_string());
+    }
+
+    #[test]
+    fn test_write_outcome_parsing() {
+        // ElasticSearch 1.x/2.x format
+        let legacy_created = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                             \"_version\":1,\"created\":true}").unwrap();
+        let legacy_updated = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                             \"_version\":2,\"created\":false}").unwrap();
+        let legacy_deleted = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                             \"_version\":3,\"found\":true}").unwrap();
+        assert_eq!(WriteOutcome::Created, IndexResult::from(&legacy_created).outcome);
+        assert_eq!(WriteOutcome::Updated, IndexResult::from(&legacy_updated).outcome);
+        assert_eq!(WriteOutcome::Deleted, DeleteResult::from(&legacy_deleted).outcome);
+
+        // ElasticSearch 5.x+ format
+        let updated = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                      \"_version\":2,\"result\":\"updated\"}").unwrap();
+        let not_found = Json::from_str("{\"_index\":\"i\",\"_type\":\"t\",\"_id\":\"1\",\
+                                        \"_version\":1,\"result\":\"not_found\"}").unwrap();
+        let updated_result = IndexResult::from(&updated);
+        assert_eq!(WriteOutcome::Updated, updated_result.outcome);
+        assert!(!updated_result.created);
+        let not_found_result = DeleteResult::from(&not_found);
+        assert_eq!(WriteOutcome::NotFound, not_found_result.outcome);
+        assert!(!not_found_result.found);
+    }
+
+    #[test]
+    fn test_shard_failures_parsing() {
+        let no_failures = Json::from_str("{\"_shards\":{\"total\":5,\"successful\":5,\"failed\":0},\
+                                          \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        let result = SearchResult::from(&no_failures);
+        assert_eq!(0, result.shards.failed);
+        assert!(result.shards.failures.is_empty());
+
+        let failures = Json::from_str("{\"_shards\":{\"total\":5,\"successful\":4,\"failed\":1,\
+                                       \"failures\":[{\"index\":\"idx\",\"shard\":2,\
+                                       \"status\":400,\"reason\":\"SearchParseException\"}]},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        let result = SearchResult::from(&failures);
+        assert_eq!(1, result.shards.failed);
+        assert_eq!(1, result.shards.failures.len());
+        assert_eq!(Some("idx".to_string()), result.shards.failures[0].index);
+        assert_eq!(Some(2), result.shards.failures[0].shard);
+        assert_eq!("SearchParseException", result.shards.failures[0].reason);
+        assert_eq!(Some("400".to_string()), result.shards.failures[0].status);
+        assert_eq!(None, result.shards.failures[0].reason_type);
+
+        let failures = Json::from_str("{\"_shards\":{\"total\":2,\"successful\":1,\"failed\":1,\
+                                       \"failures\":[{\"index\":\"idx\",\"shard\":0,\
+                                       \"node\":\"n1\",\"status\":\"BAD_REQUEST\",\
+                                       \"reason\":{\"type\":\"query_shard_exception\",\
+                                       \"reason\":\"No mapping found for [x]\"}}]},\
+                                       \"hits\":{\"total\":0,\"hits\":[]}}").unwrap();
+        let failure = &SearchResult::from(&failures).shards.failures[0];
+        assert_eq!(Some("n1".to_string()), failure.node);
+        assert_eq!(Some("BAD_REQUEST".to_string()), failure.status);
+        assert_eq!("No mapping found for [x]", failure.reason);
+        assert_eq!(Some("query_shard_exception".to_string()), failure.reason_type);
     }
 }
@@ -0,0 +1,491 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::Encodable;
+use rustc_serialize::json::{self, Json, ToJson};
+
+use ::Client;
+use ::date_math::encode_index_name;
+use ::error::EsError;
+use super::common::Options;
+use super::error_reason;
+use super::find_json_array;
+use super::format_query_string;
+use super::search::{SearchHitsHitsResult, SearchHitsResult};
+use super::unexpected_status;
+use super::update::UpdateBody;
+use super::FromJson;
+
+/// The types of action that can be performed as part of a bulk request
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActionType {
+    Index,
+    Create,
+    Update,
+    Delete
+}
+
+impl ToString for ActionType {
+    fn to_string(&self) -> String {
+        match self {
+            &ActionType::Index  => "index",
+            &ActionType::Create => "create",
+            &ActionType::Update => "update",
+            &ActionType::Delete => "delete"
+        }.to_string()
+    }
+}
+
+impl ActionType {
+    fn from_str(s: &str) -> Option<ActionType> {
+        match s {
+            "index"  => Some(ActionType::Index),
+            "create" => Some(ActionType::Create),
+            "update" => Some(ActionType::Update),
+            "delete" => Some(ActionType::Delete),
+            _        => None
+        }
+    }
+}
+
+/// The metadata of an individual action, any that are not set will default to
+/// those of the `BulkOperation` itself.
+pub struct ActionOptions {
+    index:    Option<String>,
+    doc_type: Option<String>,
+    id:       Option<String>,
+    routing:  Option<String>,
+    parent:   Option<String>,
+    version:  Option<i64>
+}
+
+impl ActionOptions {
+    pub fn new() -> ActionOptions {
+        ActionOptions {
+            index:    None,
+            doc_type: None,
+            id:       None,
+            routing:  None,
+            parent:   None,
+            version:  None
+        }
+    }
+
+    /// The document of a search hit, by its index, type and ID, and its
+    /// routing and parent if it has them
+    pub fn from_hit<T>(hit: &SearchHitsHitsResult<T>) -> ActionOptions {
+        ActionOptions {
+            index:    Some(hit.index.clone()),
+            doc_type: Some(hit.doc_type.clone()),
+            id:       Some(hit.id.clone()),
+            routing:  hit.routing.clone(),
+            parent:   hit.parent.clone(),
+            version:  None
+        }
+    }
+
+    pub fn with_index<S: Into<String>>(mut self, index: S) -> ActionOptions {
+        self.index = Some(index.into());
+        self
+    }
+
+    pub fn with_doc_type<S: Into<String>>(mut self, doc_type: S) -> ActionOptions {
+        self.doc_type = Some(doc_type.into());
+        self
+    }
+
+    pub fn with_id<S: Into<String>>(mut self, id: S) -> ActionOptions {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_routing<S: Into<String>>(mut self, routing: S) -> ActionOptions {
+        self.routing = Some(routing.into());
+        self
+    }
+
+    pub fn with_parent<S: Into<String>>(mut self, parent: S) -> ActionOptions {
+        self.parent = Some(parent.into());
+        self
+    }
+
+    pub fn with_version(mut self, version: i64) -> ActionOptions {
+        self.version = Some(version);
+        self
+    }
+}
+
+impl ToJson for ActionOptions {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        optional_add!(d, self.index, "_index");
+        optional_add!(d, self.doc_type, "_type");
+        optional_add!(d, self.id, "_id");
+        optional_add!(d, self.routing, "_routing");
+        optional_add!(d, self.parent, "_parent");
+        optional_add!(d, self.version, "_version");
+        Json::Object(d)
+    }
+}
+
+/// An individual action in a bulk request.
+///
+/// The documents of `Index` and `Create` actions are of type `E`, an `Update`
+/// has its own body, so that a request can both index typed documents and
+/// update others with partial documents or scripts.
+pub enum BulkAction<E: Encodable> {
+    Index(ActionOptions, E),
+    Create(ActionOptions, E),
+    Update(ActionOptions, UpdateBody),
+    Delete(ActionOptions)
+}
+
+impl<E: Encodable> BulkAction<E> {
+    fn action_type(&self) -> ActionType {
+        match self {
+            &BulkAction::Index(_, _)  => ActionType::Index,
+            &BulkAction::Create(_, _) => ActionType::Create,
+            &BulkAction::Update(_, _) => ActionType::Update,
+            &BulkAction::Delete(_)    => ActionType::Delete
+        }
+    }
+
+    fn options(&self) -> &ActionOptions {
+        match self {
+            &BulkAction::Index(ref opts, _)  => opts,
+            &BulkAction::Create(ref opts, _) => opts,
+            &BulkAction::Update(ref opts, _) => opts,
+            &BulkAction::Delete(ref opts)    => opts
+        }
+    }
+
+    /// The source line of this action, if any
+    fn source(&self) -> Result<Option<String>, EsError> {
+        Ok(match self {
+            &BulkAction::Index(_, ref doc)     => Some(try!(json::encode(doc))),
+            &BulkAction::Create(_, ref doc)    => Some(try!(json::encode(doc))),
+            &BulkAction::Update(_, ref update) => Some(try!(update.to_json()).to_string()),
+            &BulkAction::Delete(_)             => None
+        })
+    }
+
+    /// Appends this action to a bulk request body, each action is the metadata
+    /// line followed by the source line, if any.
+    fn add_to(&self, body: &mut String) -> Result<(), EsError> {
+        let mut meta = BTreeMap::new();
+        meta.insert(self.action_type().to_string(), self.options().to_json());
+        body.push_str(&Json::Object(meta).to_string());
+        body.push_str("\n");
+        if let Some(source) = try!(self.source()) {
+            body.push_str(&source);
+            body.push_str("\n");
+        }
+        Ok(())
+    }
+}
+
+/// A bulk operation, sends many actions in a single request
+pub struct BulkOperation<'a, 'b, E: Encodable + 'b> {
+    /// The HTTP client
+    client:      &'a mut Client,
+
+    /// The default index, if not specified per action
+    index:       Option<&'b str>,
+
+    /// The default type, if not specified per action
+    doc_type:    Option<&'b str>,
+
+    /// The actions
+    actions:     &'b [BulkAction<E>],
+
+    /// The actions built from search hits, sent after `actions`
+    hit_actions: Vec<BulkAction<E>>,
+
+    /// Whether to check the documents of delete actions exist first
+    if_exists:   bool,
+
+    /// Optional options
+    options:     Options<'b>
+}
+
+impl<'a, 'b, E: Encodable + 'b> BulkOperation<'a, 'b, E> {
+    pub fn new(client: &'a mut Client,
+               actions: &'b [BulkAction<E>]) -> BulkOperation<'a, 'b, E> {
+        BulkOperation {
+            client:      client,
+            index:       None,
+            doc_type:    None,
+            actions:     actions,
+            hit_actions: Vec::new(),
+            if_exists:   false,
+            options:     Options::new()
+        }
+    }
+
+    pub fn with_index(&mut self, index: &'b str) -> &mut Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn with_doc_type(&mut self, doc_type: &'b str) -> &mut Self {
+        self.doc_type = Some(doc_type);
+        self
+    }
+
+    add_option!(with_consistency, "consistency");
+    add_option!(with_refresh, "refresh");
+    add_option!(with_routing, "routing");
+    add_option!(with_timeout, "timeout");
+
+    /// Adds a delete of the document of each of the hits of a search, after
+    /// the other actions.  See `ActionOptions::from_hit`.
+    pub fn delete_hits<T>(&mut self, hits: &SearchHitsResult<T>) -> &mut Self {
+        self.hit_actions.extend(hits.hits.iter().map(|hit| {
+            BulkAction::Delete(ActionOptions::from_hit(hit))
+        }));
+        self
+    }
+
+    /// Adds an update of the document of each of the hits of a search, after
+    /// the other actions.  `f` gives the body of the update of each hit, e.g.
+    /// a partial document as `Json` or an `UpdateBody`, or `None` to leave
+    /// that document as it is.  See `ActionOptions::from_hit`.
+    pub fn update_hits_with<T, U, F>(&mut self, hits: &SearchHitsResult<T>, f: F) -> &mut Self
+        where U: Into<UpdateBody>,
+              F: Fn(&SearchHitsHitsResult<T>) -> Option<U> {
+        self.hit_actions.extend(hits.hits.iter().filter_map(|hit| {
+            f(hit).map(|update| BulkAction::Update(ActionOptions::from_hit(hit), update.into()))
+        }));
+        self
+    }
+
+    /// Check the documents of the delete actions exist, with a single
+    /// multi-get, before sending the bulk request.  Deletes of documents which
+    /// do not exist are not sent, their positions in the actions are in the
+    /// `skipped` of the result.  A delete without an index, either its own or
+    /// the default of this operation, or without an ID is always sent.  See
+    /// `DeleteOperation::with_if_exists`.
+    pub fn with_if_exists(&mut self, if_exists: bool) -> &mut Self {
+        self.if_exists = if_exists;
+        self
+    }
+
+    /// The positions of the delete actions for documents which do not exist
+    fn missing_deletes(&mut self) -> Result<Vec<usize>, EsError> {
+        let routing = self.options.iter()
+            .find(|&&(k, _)| k == "routing")
+            .map(|&(_, ref v)| v.clone());
+        let mut positions = Vec::new();
+        let mut docs = Vec::new();
+        for (i, action) in self.actions.iter().chain(self.hit_actions.iter()).enumerate() {
+            let opts = match *action {
+                BulkAction::Delete(ref opts) => opts,
+                _                            => continue
+            };
+            let index = opts.index.as_ref().map(|index| &index[..]).or(self.index);
+            if let (Some(index), Some(id)) = (index, opts.id.as_ref()) {
+                let mut doc = BTreeMap::new();
+                doc.insert("_index".to_string(), index.to_json());
+                doc.insert("_id".to_string(), id.to_json());
+                optional_add!(doc,
+                              opts.doc_type.as_ref().map(|doc_type| &doc_type[..]).or(self.doc_type),
+                              "_type");
+                optional_add!(doc, opts.routing.as_ref().or(routing.as_ref()), "_routing");
+                optional_add!(doc, opts.parent, "_parent");
+                docs.push(Json::Object(doc));
+                positions.push(i);
+            }
+        }
+        if docs.is_empty() {
+            return Ok(positions);
+        }
+
+        let mut body = BTreeMap::new();
+        body.insert("docs".to_string(), Json::Array(docs));
+        let (status_code, result) = try!(self.client.post_read_body_op("/_mget?_source=false",
+                                                                       &Json::Object(body)));
+        debug!(target: "rs_es::bulk", "[{}] Bulk existence check result status: {}",
+               self.client.request_id(), status_code);
+        let result = match status_code {
+            StatusCode::Ok => result.unwrap(),
+            _              => {
+                return Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        };
+        let found = try!(find_json_array(&result, "docs"));
+        if found.len() != positions.len() {
+            return Err(EsError::EsError(format!("Expected {} documents, got {}",
+                                                positions.len(),
+                                                found.len())));
+        }
+        // A document in an index which does not exist is an error rather than
+        // not found, either way there is nothing to delete
+        Ok(positions.into_iter()
+           .zip(found.iter())
+           .filter(|&(_, doc)| !doc.find("found").and_then(|f| f.as_boolean()).unwrap_or(false))
+           .map(|(i, _)| i)
+           .collect())
+    }
+
+    fn format_url(&self) -> String {
+        let mut url = String::new();
+        if let Some(index) = self.index {
+            url.push_str("/");
+            url.push_str(&encode_index_name(index));
+            if let Some(doc_type) = self.doc_type {
+                url.push_str("/");
+                url.push_str(doc_type);
+            }
+        }
+        url.push_str("/_bulk");
+        url.push_str(&format_query_string(&self.options));
+        url
+    }
+
+    /// Sends the bulk request.  The failure of individual actions does not
+    /// cause an error, these are reported in the items of the `BulkResult`.
+    pub fn send(&mut self) -> Result<BulkResult, EsError> {
+        let skipped = if self.if_exists {
+            try!(self.missing_deletes())
+        } else {
+            Vec::new()
+        };
+        let mut body = String::new();
+        for (i, action) in self.actions.iter().chain(self.hit_actions.iter()).enumerate() {
+            if skipped.binary_search(&i).is_err() {
+                try!(action.add_to(&mut body));
+            }
+        }
+        // ElasticSearch rejects a bulk request without any actions
+        if body.is_empty() {
+            debug!(target: "rs_es::bulk", "[{}] Bulk request skipped, no actions to send",
+                   self.client.request_id());
+            return Ok(BulkResult {
+                errors:  false,
+                took:    0,
+                items:   Vec::new(),
+                skipped: skipped
+            });
+        }
+        let url = self.format_url();
+        let (status_code, result) = try!(self.client.post_raw_body_op(&url, &body, false));
+        debug!(target: "rs_es::bulk", "[{}] Bulk result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => {
+                let mut bulk_result = try!(BulkResult::from_json(&result.unwrap()));
+                bulk_result.skipped = skipped;
+                Ok(bulk_result)
+            },
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The result of an individual action within a bulk request
+#[derive(Debug)]
+pub struct BulkItemResult {
+    pub action:   ActionType,
+    pub index:    String,
+    pub doc_type: String,
+    pub id:       String,
+    pub version:  Option<i64>,
+    pub status:   i64,
+    pub error:    Option<Json>,
+
+    /// The reason for the error, if any, the error is given as a string by
+    /// older versions of ElasticSearch and as an object by newer ones
+    pub reason:   Option<String>
+}
+
+impl BulkItemResult {
+    /// Whether this individual action succeeded
+    pub fn successful(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// This is required because the JSON keys do not match the struct, and the
+/// action type is the key of a single-entry object
+impl FromJson for BulkItemResult {
+    fn from_json(r: &Json) -> Result<BulkItemResult, EsError> {
+        let (key, inner) = try!(r.as_object()
+                                .and_then(|item| item.iter().next())
+                                .ok_or_else(|| EsError::EsError(format!("Invalid bulk item: {}",
+                                                                        r))));
+        let action = try!(ActionType::from_str(key).ok_or_else(|| {
+            EsError::EsError(format!("Unknown bulk action: {}", key))
+        }));
+        Ok(BulkItemResult {
+            action:   action,
+            index:    get_json_string!(inner, "_index"),
+            doc_type: get_json_string!(inner, "_type"),
+            id:       get_json_string!(inner, "_id"),
+            version:  inner.find("_version").and_then(|v| v.as_i64()),
+            status:   get_json_i64!(inner, "status"),
+            error:    inner.find("error").map(|e| e.clone()),
+            reason:   error_reason(Some(inner))
+        })
+    }
+}
+
+from_json_panicking!(BulkItemResult);
+
+/// The result of a bulk request
+#[derive(Debug)]
+pub struct BulkResult {
+    pub errors:  bool,
+    pub took:    i64,
+
+    /// The result of each action sent, in order
+    pub items:   Vec<BulkItemResult>,
+
+    /// The positions in the actions, followed by those built from search hits,
+    /// of the deletes which were not sent, see `with_if_exists`
+    pub skipped: Vec<usize>
+}
+
+impl BulkResult {
+    /// Whether every action sent succeeded
+    pub fn is_fully_successful(&self) -> bool {
+        !self.errors && self.items.iter().all(|item| item.successful())
+    }
+
+    /// The results of the actions which failed, in order
+    pub fn failed_items(&self) -> Vec<&BulkItemResult> {
+        self.items.iter().filter(|item| !item.successful()).collect()
+    }
+}
+
+impl FromJson for BulkResult {
+    fn from_json(r: &Json) -> Result<BulkResult, EsError> {
+        Ok(BulkResult {
+            errors:  get_json_bool!(r, "errors"),
+            took:    get_json_i64!(r, "took"),
+            items:   try!(try!(find_json_array(r, "items"))
+                          .iter()
+                          .map(|j| BulkItemResult::from_json(j))
+                          .collect::<Result<Vec<_>, _>>()),
+            skipped: Vec::new()
+        })
+    }
+}
+
+from_json_panicking!(BulkResult);
@@ -0,0 +1,272 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The cat APIs, for monitoring: shard recovery and thread pools.
+//!
+//! Each is requested as JSON with sizes in bytes.  The cat APIs give every
+//! value as a string, and some columns have been renamed between versions of
+//! ElasticSearch, so each row is parsed into a struct with the values typed.
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::Json;
+
+use ::Client;
+use ::error::EsError;
+use ::util::StrJoin;
+use super::common::Options;
+use super::error_reason;
+use super::format_multi;
+use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+
+/// The thread pools reported if none are given, `write` is the name of the
+/// `bulk` pool from ElasticSearch 6.x onwards
+const DEFAULT_POOLS: [&'static str; 3] = ["bulk", "write", "search"];
+
+/// The value of the first of the columns which the row has, columns are
+/// renamed between versions
+fn column<'a>(r: &'a Json, columns: &[&str]) -> Option<&'a str> {
+    columns.iter()
+        .filter_map(|c| r.find(c))
+        .filter_map(|v| v.as_string())
+        .next()
+}
+
+fn string_column(r: &Json, columns: &[&str]) -> Result<String, EsError> {
+    column(r, columns)
+        .map(|v| v.to_string())
+        .ok_or_else(|| EsError::EsError(format!("No column: {}", columns.iter().join(","))))
+}
+
+fn i64_column(r: &Json, columns: &[&str]) -> Result<i64, EsError> {
+    let value = try!(string_column(r, columns));
+    value.parse()
+        .map_err(|_| EsError::EsError(format!("Not a number: {}: {}", columns[0], value)))
+}
+
+/// A percentage, e.g. `84.5%`
+fn percent_column(r: &Json, columns: &[&str]) -> Result<f64, EsError> {
+    let value = try!(string_column(r, columns));
+    value.trim_right_matches('%')
+        .parse()
+        .map_err(|_| EsError::EsError(format!("Not a percentage: {}: {}", columns[0], value)))
+}
+
+/// The rows of a cat response
+fn rows<T: FromJson>(r: &Json) -> Result<Vec<T>, EsError> {
+    match r.as_array() {
+        Some(rows) => rows.iter().map(|row| T::from_json(row)).collect(),
+        None       => Err(EsError::EsError("Not an array of rows".to_string()))
+    }
+}
+
+/// Cat recovery, the progress of the recovery of each shard, including
+/// completed recoveries unless `with_active_only(&true)`
+pub struct CatRecoveryOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The indexes, all if empty
+    indexes: &'b [&'b str],
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> CatRecoveryOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> CatRecoveryOperation<'a, 'b> {
+        CatRecoveryOperation {
+            client:  client,
+            indexes: &[],
+            options: Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    add_option!(with_active_only, "active_only");
+
+    /// Sends the request.  If any of the indexes do not exist, the result is
+    /// an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<Vec<RecoveryRow>, EsError> {
+        let mut options = self.options.clone();
+        options.push(("format", "json".to_string()));
+        options.push(("bytes", "b".to_string()));
+        let indexes = if self.indexes.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", format_multi(&self.indexes))
+        };
+        let url = format!("/_cat/recovery{}{}", indexes, format_query_string(&options));
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::cat", "[{}] Cat recovery result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => rows(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(format_multi(&self.indexes)))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The recovery of a single shard
+#[derive(Debug, Clone)]
+pub struct RecoveryRow {
+    pub index:            String,
+    pub shard:            i64,
+
+    /// How long the recovery has taken, as given by ElasticSearch
+    pub time:             String,
+
+    /// e.g. `store`, `replica`, `snapshot`
+    pub recovery_type:    String,
+
+    /// e.g. `index`, `translog`, `done`
+    pub stage:            String,
+    pub source_host:      Option<String>,
+    pub target_host:      Option<String>,
+
+    /// The percentage of the files and bytes recovered so far
+    pub files_percent:    f64,
+    pub bytes_percent:    f64,
+    pub total_bytes:      Option<i64>,
+
+    /// The percentage of the translog operations replayed so far, not given
+    /// by every version of ElasticSearch
+    pub translog_percent: Option<f64>
+}
+
+impl FromJson for RecoveryRow {
+    fn from_json(r: &Json) -> Result<RecoveryRow, EsError> {
+        Ok(RecoveryRow {
+            index:            try!(string_column(r, &["index"])),
+            shard:            try!(i64_column(r, &["shard"])),
+            time:             try!(string_column(r, &["time"])),
+            recovery_type:    try!(string_column(r, &["type"])),
+            stage:            try!(string_column(r, &["stage"])),
+            source_host:      column(r, &["source_host"]).map(|h| h.to_string()),
+            target_host:      column(r, &["target_host"]).map(|h| h.to_string()),
+            files_percent:    try!(percent_column(r, &["files_percent"])),
+            bytes_percent:    try!(percent_column(r, &["bytes_percent"])),
+            total_bytes:      i64_column(r, &["bytes_total", "total_bytes"]).ok(),
+            translog_percent: percent_column(r, &["translog_ops_percent",
+                                                  "translog_percent"]).ok()
+        })
+    }
+}
+
+from_json_panicking!(RecoveryRow);
+
+/// Cat thread pool, the active threads, queued tasks and rejected tasks of
+/// thread pools on each node, by default the bulk and search pools
+pub struct CatThreadPoolOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The thread pools
+    pools:  &'b [&'b str]
+}
+
+impl<'a, 'b> CatThreadPoolOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> CatThreadPoolOperation<'a, 'b> {
+        CatThreadPoolOperation {
+            client: client,
+            pools:  &DEFAULT_POOLS
+        }
+    }
+
+    /// The thread pools, e.g. `index`, `get`
+    pub fn with_pools(&mut self, pools: &'b [&'b str]) -> &mut Self {
+        self.pools = pools;
+        self
+    }
+
+    /// Sends the request, there is a row for each of the thread pools on
+    /// each node
+    pub fn send(&mut self) -> Result<Vec<ThreadPoolRow>, EsError> {
+        let url = "/_cat/thread_pool?format=json&bytes=b";
+        let (status_code, result) = try!(self.client.get_op(url));
+        debug!(target: "rs_es::cat", "[{}] Cat thread pool result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => thread_pool_rows(&result.unwrap(), self.pools),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// A thread pool on a node
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadPoolRow {
+    /// The name of the node, or its host for ElasticSearch versions before 5.x
+    pub node:     String,
+    pub pool:     String,
+    pub active:   i64,
+    pub queue:    i64,
+
+    /// The number of tasks rejected since the node started, a rising count
+    /// means the node is overloaded
+    pub rejected: i64
+}
+
+/// ElasticSearch 5.x onwards gives a row for each pool on each node, earlier
+/// versions a row for each node with columns for each pool, e.g.
+/// `bulk.rejected`
+pub fn thread_pool_rows(r: &Json, pools: &[&str]) -> Result<Vec<ThreadPoolRow>, EsError> {
+    let nodes = match r.as_array() {
+        Some(nodes) => nodes,
+        None        => return Err(EsError::EsError("Not an array of rows".to_string()))
+    };
+    let mut rows = Vec::new();
+    for row in nodes.iter() {
+        let node = try!(string_column(row, &["node_name", "host"]));
+        match column(row, &["name"]) {
+            Some(pool) => {
+                if pools.contains(&pool) {
+                    rows.push(ThreadPoolRow {
+                        node:     node,
+                        pool:     pool.to_string(),
+                        active:   try!(i64_column(row, &["active"])),
+                        queue:    try!(i64_column(row, &["queue"])),
+                        rejected: try!(i64_column(row, &["rejected"]))
+                    });
+                }
+            },
+            None       => {
+                for pool in pools.iter() {
+                    let active = format!("{}.active", pool);
+                    if row.find(&active).is_none() {
+                        continue;
+                    }
+                    rows.push(ThreadPoolRow {
+                        node:     node.clone(),
+                        pool:     pool.to_string(),
+                        active:   try!(i64_column(row, &[&active[..]])),
+                        queue:    try!(i64_column(row, &[&format!("{}.queue", pool)[..]])),
+                        rejected: try!(i64_column(row, &[&format!("{}.rejected", pool)[..]]))
+                    });
+                }
+            }
+        }
+    }
+    Ok(rows)
+}
@@ -21,7 +21,7 @@ pub type Options<'a> = Vec<(&'a str, String)>;
 /// builder interface.
 macro_rules! add_option {
     ($n:ident, $e:expr) => (
-        pub fn $n<T: ToString>(&'a mut self, val: &T) -> &'a mut Self {
+        pub fn $n<T: ToString>(&mut self, val: &T) -> &mut Self {
             self.options.push(($e, val.to_string()));
             self
         }
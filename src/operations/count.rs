@@ -0,0 +1,179 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use ::query::Query;
+use super::common::Options;
+use super::error_reason;
+use super::find_json;
+use super::format_indexes_and_types;
+use super::format_query_string;
+use super::query_string_body;
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+
+struct CountBody<'a> {
+    query: &'a Query
+}
+
+impl<'a> ToJson for CountBody<'a> {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        d.insert("query".to_string(), self.query.to_json());
+        Json::Object(d)
+    }
+}
+
+enum QueryOption<'a> {
+    String(String),
+    Document(CountBody<'a>)
+}
+
+/// Count API, returns the number of documents matching a query.
+///
+/// The query can be specified either as a String as a query parameter or in the
+/// body using the Query DSL.  If neither is specified, all documents are
+/// counted.
+pub struct CountOperation<'a, 'b> {
+    /// The HTTP client
+    client:    &'a mut Client,
+
+    /// The indexes to which this query apply
+    indexes:   &'b [&'b str],
+
+    /// The types to which this query applies
+    doc_types: &'b [&'b str],
+
+    /// The query itself, either in parameter or Query DSL form.
+    query:     Option<QueryOption<'b>>,
+
+    /// Optional options
+    options:   Options<'b>
+}
+
+impl<'a, 'b> CountOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> CountOperation<'a, 'b> {
+        CountOperation {
+            client:    client,
+            indexes:   &[],
+            doc_types: &[],
+            query:     None,
+            options:   Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    pub fn with_query_string(&mut self, qs: String) -> &mut Self {
+        self.query = Some(QueryOption::String(qs));
+        self
+    }
+
+    pub fn with_query(&mut self, q: &'b Query) -> &mut Self {
+        self.query = Some(QueryOption::Document(CountBody { query: q }));
+        self
+    }
+
+    add_option!(with_df, "df");
+    add_option!(with_analyzer, "analyzer");
+    add_option!(with_default_operator, "default_operator");
+    add_option!(with_lenient, "lenient");
+    add_option!(with_min_score, "min_score");
+    add_option!(with_routing, "routing");
+
+    /// Sends the count.  If a query string would make the URI too long, it is
+    /// sent as an equivalent `query_string` query in the body of the request
+    /// instead.  If any of the indexes do not exist, the result is an
+    /// `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<CountResult, EsError> {
+        let indexes_and_types = format_indexes_and_types(&self.indexes,
+                                                         &self.doc_types);
+        let (status_code, result) = try!(match self.query {
+            Some(QueryOption::Document(ref d)) => {
+                let url = format!("/{}/_count{}",
+                                  indexes_and_types,
+                                  format_query_string(&self.options));
+                self.client.post_read_body_op(&url, &d.to_json())
+            },
+            Some(QueryOption::String(ref s))   => {
+                let mut options = self.options.clone();
+                options.push(("q", s.clone()));
+                let url = format!("/{}/_count{}",
+                                  indexes_and_types,
+                                  format_query_string(&options));
+                match self.client.check_uri_length(&url) {
+                    Ok(_)  => self.client.get_op(&url),
+                    Err(_) => {
+                        let (body, options) = query_string_body(&options);
+                        let url = format!("/{}/_count{}",
+                                          indexes_and_types,
+                                          format_query_string(&options));
+                        try!(self.client.check_uri_length(&url));
+                        self.client.post_read_body_op(&url, &body)
+                    }
+                }
+            },
+            None                               => {
+                let url = format!("/{}/_count{}",
+                                  indexes_and_types,
+                                  format_query_string(&self.options));
+                self.client.get_op(&url)
+            }
+        });
+        debug!(target: "rs_es::count", "[{}] Count result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => CountResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(indexes_and_types))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The result of a count request
+#[derive(Debug)]
+pub struct CountResult {
+    pub count:  i64,
+    pub shards: ShardCountResult
+}
+
+impl FromJson for CountResult {
+    fn from_json(r: &Json) -> Result<CountResult, EsError> {
+        Ok(CountResult {
+            count:  get_json_i64!(r, "count"),
+            shards: try!(ShardCountResult::from_json(try!(find_json(r, "_shards"))))
+        })
+    }
+}
+
+from_json_panicking!(CountResult);
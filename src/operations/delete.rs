@@ -24,28 +24,38 @@ use rustc_serialize::json::{Json, ToJson};
 use ::Client;
 use ::error::EsError;
 use ::query::Query;
+use super::bulk::{ActionOptions, BulkAction};
 use super::common::Options;
-use super::decode_json;
+use super::find_json;
 use super::format_indexes_and_types;
 use super::format_query_string;
+use super::query_string_body;
+use super::search::SearchResult;
+use super::unexpected_status;
+use super::FromJson;
 use super::ShardCountResult;
+use super::Timeout;
+use super::WriteOutcome;
 
 /// An ES DELETE operation for a specific document
 pub struct DeleteOperation<'a, 'b> {
     /// The HTTP client
-    client:   &'a mut Client,
+    client:    &'a mut Client,
 
     /// The index
-    index:    &'b str,
+    index:     &'b str,
 
     /// The type
-    doc_type: &'b str,
+    doc_type:  &'b str,
 
     /// The ID
-    id:       &'b str,
+    id:        &'b str,
+
+    /// Whether to check the document exists before deleting it
+    if_exists: bool,
 
     /// Optional options
-    options:  Options<'b>
+    options:   Options<'b>
 }
 
 impl<'a, 'b> DeleteOperation<'a, 'b> {
@@ -54,34 +64,88 @@ impl<'a, 'b> DeleteOperation<'a, 'b> {
                doc_type: &'b str,
                id:       &'b str) -> DeleteOperation<'a, 'b> {
         DeleteOperation {
-            client:   client,
-            index:    index,
-            doc_type: doc_type,
-            id:       id,
-            options:  Options::new()
+            client:    client,
+            index:     index,
+            doc_type:  doc_type,
+            id:        id,
+            if_exists: false,
+            options:   Options::new()
         }
     }
 
+    /// Check the document exists, with a HEAD request, before deleting it.
+    /// If it does not exist the DELETE is not sent, so no tombstone is
+    /// created, and the outcome is `WriteOutcome::Skipped`.  This is two
+    /// requests for each document which does exist, so is only worthwhile if
+    /// most do not.
+    pub fn with_if_exists(&mut self, if_exists: bool) -> &mut Self {
+        self.if_exists = if_exists;
+        self
+    }
+
     add_option!(with_version, "version");
     add_option!(with_routing, "routing");
     add_option!(with_parent, "parent");
     add_option!(with_consistency, "consistency");
     add_option!(with_refresh, "refresh");
-    add_option!(with_timeout, "timeout");
 
-    pub fn send(&'a mut self) -> Result<DeleteResult, EsError> {
+    /// How long to wait for the shard to be available, e.g.
+    /// `Timeout::new(5, TimeUnit::Seconds)` or a `Duration`.  This replaces any
+    /// timeout given before.
+    pub fn with_timeout<T: Into<Timeout>>(&mut self, timeout: T) -> &mut Self {
+        let timeout: Timeout = timeout.into();
+        self.options.retain(|&(k, _)| k != "timeout");
+        self.options.push(("timeout", timeout.to_string()));
+        self
+    }
+
+    /// Whether the document exists, with the same routing as the delete
+    fn exists(&mut self) -> Result<bool, EsError> {
+        let options:Options = self.options.iter()
+            .filter(|&&(k, _)| k == "routing" || k == "parent")
+            .cloned()
+            .collect();
+        let url = format!("/{}/{}/{}{}",
+                          self.index,
+                          self.doc_type,
+                          self.id,
+                          format_query_string(&options));
+        match try!(self.client.head_op(&url)) {
+            StatusCode::Ok       => Ok(true),
+            StatusCode::NotFound => Ok(false),
+            status_code          => Err(unexpected_status(self.client.request_id(),
+                                                          status_code,
+                                                          None))
+        }
+    }
+
+    pub fn send(&mut self) -> Result<DeleteResult, EsError> {
+        try!(self.client.check_id(self.id));
+        if self.if_exists && !try!(self.exists()) {
+            debug!(target: "rs_es::delete", "[{}] Delete skipped, document does not exist",
+                   self.client.request_id());
+            return Ok(DeleteResult {
+                found:    false,
+                index:    self.index.to_string(),
+                doc_type: self.doc_type.to_string(),
+                id:       self.id.to_string(),
+                version:  0,
+                outcome:  WriteOutcome::Skipped
+            });
+        }
         let url = format!("/{}/{}/{}{}",
                           self.index,
                           self.doc_type,
                           self.id,
                           format_query_string(&mut self.options));
         let (status_code, result) = try!(self.client.delete_op(&url));
-        info!("DELETE OPERATION STATUS: {:?} RESULT: {:?}", status_code, result);
+        debug!(target: "rs_es::delete", "[{}] Delete result status: {}",
+               self.client.request_id(), status_code);
         match status_code {
-            StatusCode::Ok =>
-                Ok(DeleteResult::from(&result.unwrap())),
+            StatusCode::Ok | StatusCode::NotFound =>
+                DeleteResult::from_json(&result.unwrap()),
             _ =>
-                Err(EsError::EsError(format!("Unexpected status: {}", status_code)))
+                Err(unexpected_status(self.client.request_id(), status_code, result))
         }
     }
 }
@@ -103,6 +167,10 @@ enum QueryOption<'a> {
     Document(DeleteByQueryBody<'a>)
 }
 
+/// How long the scrolling search of `send_scan_and_delete` is kept open
+/// between pages
+const SCAN_AND_DELETE_KEEP_ALIVE: &'static str = "1m";
+
 /// Delete-by-query API.
 ///
 /// The query can be specified either as a String as a query parameter or in the
@@ -135,22 +203,22 @@ impl<'a, 'b> DeleteByQueryOperation<'a, 'b> {
         }
     }
 
-    pub fn with_indexes(&'b mut self, indexes: &'b [&'b str]) -> &'b mut Self {
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
         self.indexes = indexes;
         self
     }
 
-    pub fn with_doc_types(&'b mut self, doc_types: &'b [&'b str]) -> &'b mut Self {
+    pub fn with_doc_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
         self.doc_types = doc_types;
         self
     }
 
-    pub fn with_query_string(&'b mut self, qs: String) -> &'b mut Self {
+    pub fn with_query_string(&mut self, qs: String) -> &mut Self {
         self.query = QueryOption::String(qs);
         self
     }
 
-    pub fn with_query(&'b mut self, q: &'b Query) -> &'b mut Self {
+    pub fn with_query(&mut self, q: &'b Query) -> &mut Self {
         self.query = QueryOption::Document(DeleteByQueryBody { query: q });
         self
     }
@@ -161,33 +229,178 @@ impl<'a, 'b> DeleteByQueryOperation<'a, 'b> {
     add_option!(with_routing, "routing");
     add_option!(with_consistency, "consistency");
 
-    pub fn send(&'a mut self) -> Result<Option<DeleteByQueryResult>, EsError> {
-        let options = match &self.query {
-            &QueryOption::Document(_)   => &mut self.options,
-            &QueryOption::String(ref s) => {
-                let opts = &mut self.options;
-                opts.push(("q", s.clone()));
-                opts
-            }
-        };
-        let url = format!("/{}/_query{}",
-                          format_indexes_and_types(&self.indexes, &self.doc_types),
-                          format_query_string(options));
+    /// Sends the delete-by-query.  If a query string would make the URI too
+    /// long, it is sent as an equivalent `query_string` query in the body of
+    /// the request instead.
+    pub fn send(&mut self) -> Result<Option<DeleteByQueryResult>, EsError> {
+        let indexes_and_types = format_indexes_and_types(&self.indexes,
+                                                         &self.doc_types);
         let (status_code, result) = try!(match self.query {
-            QueryOption::Document(ref d) => self.client.delete_body_op(&url,
-                                                                       &d.to_json()),
-            QueryOption::String(_)       => self.client.delete_op(&url)
+            QueryOption::Document(ref d) => {
+                let url = format!("/{}/_query{}",
+                                  indexes_and_types,
+                                  format_query_string(&self.options));
+                self.client.delete_body_op(&url, &d.to_json())
+            },
+            QueryOption::String(ref s)   => {
+                let mut options = self.options.clone();
+                options.push(("q", s.clone()));
+                let url = format!("/{}/_query{}",
+                                  indexes_and_types,
+                                  format_query_string(&options));
+                match self.client.check_uri_length(&url) {
+                    Ok(_)  => self.client.delete_op(&url),
+                    Err(_) => {
+                        let (body, options) = query_string_body(&options);
+                        let url = format!("/{}/_query{}",
+                                          indexes_and_types,
+                                          format_query_string(&options));
+                        try!(self.client.check_uri_length(&url));
+                        self.client.delete_body_op(&url, &body)
+                    }
+                }
+            }
         });
-        info!("DELETE BY QUERY STATUS: {:?}, RESULT: {:?}", status_code, result);
+        debug!(target: "rs_es::delete_by_query", "[{}] Delete-by-query result status: {}",
+               self.client.request_id(), status_code);
         match status_code {
             StatusCode::Ok =>
-                Ok(Some(DeleteByQueryResult::from(&result.unwrap()))),
+                DeleteByQueryResult::from_json(&result.unwrap()).map(|r| Some(r)),
             StatusCode::NotFound =>
                 Ok(None),
             _  =>
-                Err(EsError::EsError(format!("Unexpected status: {}", status_code)))
+                Err(unexpected_status(self.client.request_id(), status_code, result))
         }
     }
+
+    /// The value of an option, if set
+    fn option(&self, name: &str) -> Option<String> {
+        self.options.iter().find(|&&(k, _)| k == name).map(|&(_, ref v)| v.clone())
+    }
+
+    /// Deletes the matching documents without using the delete-by-query API,
+    /// which was removed in ElasticSearch 2.0 unless the plugin is installed.
+    /// Instead the matching documents are found with a scrolling search, and
+    /// deleted with a bulk request for each page of up to `batch_size`
+    /// documents, until none remain.  Each document is deleted with its own
+    /// routing and parent, if it has them, otherwise with the `routing`
+    /// option; the `consistency` option applies to the bulk requests.  The
+    /// scroll is cleared when done, even if there is an error.
+    ///
+    /// A document which cannot be deleted, e.g. due to a version conflict
+    /// because it was changed after it was found, is counted rather than
+    /// being an error.  As with `send`, the result is `None` if the indexes
+    /// do not exist.
+    pub fn send_scan_and_delete(&mut self, batch_size: i64)
+                                -> Result<Option<ScanAndDeleteResult>, EsError> {
+        let routing = self.option("routing");
+        let consistency = self.option("consistency");
+
+        // Only the metadata of each hit is needed
+        let mut body = match self.query {
+            QueryOption::Document(ref d) => d.to_json(),
+            QueryOption::String(ref s) if s.is_empty() => Json::Object(BTreeMap::new()),
+            QueryOption::String(ref s)   => {
+                let mut options = self.options.clone();
+                options.push(("q", s.clone()));
+                query_string_body(&options).0
+            }
+        };
+        if let Json::Object(ref mut d) = body {
+            d.insert("size".to_string(), batch_size.to_json());
+            d.insert("_source".to_string(), false.to_json());
+            d.insert("fields".to_string(), vec!["_routing", "_parent"].to_json());
+        }
+        let mut options = Options::new();
+        options.push(("scroll", SCAN_AND_DELETE_KEEP_ALIVE.to_string()));
+        if let Some(ref routing) = routing {
+            options.push(("routing", routing.clone()));
+        }
+        let url = format!("/{}/_search{}",
+                          format_indexes_and_types(&self.indexes, &self.doc_types),
+                          format_query_string(&options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url, &body));
+        debug!(target: "rs_es::delete_by_query", "[{}] Scan-and-delete search result status: {}",
+               self.client.request_id(), status_code);
+        let page = match status_code {
+            StatusCode::Ok       => try!(SearchResult::from_json(&result.unwrap())),
+            StatusCode::NotFound => return Ok(None),
+            _                    => {
+                return Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        };
+
+        let mut indices = HashMap::new();
+        let mut scroll_id = page.scroll_id.clone();
+        let deleted = self.delete_pages(page,
+                                        routing.as_ref(),
+                                        consistency.as_ref(),
+                                        &mut indices,
+                                        &mut scroll_id);
+        if let Some(ref scroll_id) = scroll_id {
+            // The scroll would expire anyway, so failing to clear it is not
+            // an error
+            if let Err(err) = self.client.clear_scroll(&[&scroll_id[..]]).send() {
+                debug!(target: "rs_es::delete_by_query", "[{}] Cannot clear scroll: {:?}",
+                       self.client.request_id(), err);
+            }
+        }
+        try!(deleted);
+        Ok(Some(ScanAndDeleteResult {
+            indices: indices
+        }))
+    }
+
+    /// Deletes the hits of each page of a scan-and-delete, `scroll_id` is
+    /// that of the latest page so that the scroll can be cleared
+    fn delete_pages(&mut self,
+                    mut page:    SearchResult,
+                    routing:     Option<&String>,
+                    consistency: Option<&String>,
+                    indices:     &mut HashMap<String, ScanAndDeleteIndexResult>,
+                    scroll_id:   &mut Option<String>) -> Result<(), EsError> {
+        while !page.hits.hits.is_empty() {
+            // Each document must be deleted with the routing it was indexed
+            // with.  If that is not known a 404 may only mean the delete was
+            // sent to the wrong shard, rather than that the document was
+            // already deleted.
+            let mut actions:Vec<BulkAction<Json>> = Vec::with_capacity(page.hits.hits.len());
+            let mut routed = Vec::with_capacity(page.hits.hits.len());
+            for hit in page.hits.hits.iter() {
+                let options = ActionOptions::from_hit(hit);
+                let own_routing = hit.routing.is_some() || hit.parent.is_some();
+                actions.push(BulkAction::Delete(match routing {
+                    Some(routing) if !own_routing => options.with_routing(routing.clone()),
+                    _                             => options
+                }));
+                routed.push(own_routing || routing.is_some());
+            }
+            let bulk_result = try!(match consistency {
+                Some(consistency) => {
+                    self.client.bulk(&actions).with_consistency(consistency).send()
+                },
+                None              => self.client.bulk(&actions).send()
+            });
+            for (item, &routed) in bulk_result.items.iter().zip(routed.iter()) {
+                let counts = indices.entry(item.index.clone())
+                    .or_insert(ScanAndDeleteIndexResult::new());
+                match item.status {
+                    200            => counts.deleted += 1,
+                    // Already deleted by something else
+                    404 if routed  => (),
+                    409            => counts.version_conflicts += 1,
+                    _              => counts.failed += 1
+                }
+            }
+
+            let next_scroll_id = try!(page.scroll_id.ok_or_else(|| {
+                EsError::EsError("No scroll ID in search result".to_string())
+            }));
+            page = try!(self.client.scroll(&next_scroll_id, SCAN_AND_DELETE_KEEP_ALIVE).send());
+            *scroll_id = page.scroll_id.clone().or(Some(next_scroll_id));
+        }
+        Ok(())
+    }
 }
 
 /// Result of a DELETE operation
@@ -197,22 +410,32 @@ pub struct DeleteResult {
     pub index:    String,
     pub doc_type: String,
     pub id:       String,
-    pub version:  i64
+
+    /// Zero if the delete was skipped, see `with_if_exists`
+    pub version:  i64,
+    pub outcome:  WriteOutcome
 }
 
 /// This is required because the JSON keys do not match the struct
-impl<'a> From<&'a Json> for DeleteResult {
-    fn from(r: &'a Json) -> DeleteResult {
-        DeleteResult {
-            found:    get_json_bool!(r, "found"),
+impl FromJson for DeleteResult {
+    fn from_json(r: &Json) -> Result<DeleteResult, EsError> {
+        let outcome = try!(WriteOutcome::from_json(r,
+                                                   "found",
+                                                   WriteOutcome::Deleted,
+                                                   WriteOutcome::NotFound));
+        Ok(DeleteResult {
+            found:    outcome == WriteOutcome::Deleted,
             index:    get_json_string!(r, "_index"),
             doc_type: get_json_string!(r, "_type"),
             id:       get_json_string!(r, "_id"),
-            version:  get_json_i64!(r, "_version")
-        }
+            version:  get_json_i64!(r, "_version"),
+            outcome:  outcome
+        })
     }
 }
 
+from_json_panicking!(DeleteResult);
+
 #[derive(Debug)]
 pub struct DeleteByQueryIndexResult {
     pub shards: ShardCountResult
@@ -225,15 +448,16 @@ impl DeleteByQueryIndexResult {
 }
 
 // Required because of change in names of keys
-impl<'a> From<&'a Json> for DeleteByQueryIndexResult {
-    fn from(r: &'a Json) -> DeleteByQueryIndexResult {
-        info!("Parsing DeleteByQueryIndexResult: {:?}", r);
-        DeleteByQueryIndexResult {
-            shards: decode_json(r.find("_shards").unwrap().clone()).unwrap()
-        }
+impl FromJson for DeleteByQueryIndexResult {
+    fn from_json(r: &Json) -> Result<DeleteByQueryIndexResult, EsError> {
+        Ok(DeleteByQueryIndexResult {
+            shards: try!(ShardCountResult::from_json(try!(find_json(r, "_shards"))))
+        })
     }
 }
 
+from_json_panicking!(DeleteByQueryIndexResult);
+
 /// The result of a Delete-by-query request
 #[derive(Debug)]
 pub struct DeleteByQueryResult {
@@ -252,17 +476,60 @@ impl DeleteByQueryResult {
 }
 
 // Required because of JSON structure and keys
-impl<'a> From<&'a Json> for DeleteByQueryResult {
-    fn from(r: &'a Json) -> DeleteByQueryResult {
-        info!("DeleteByQueryResult from: {:?}", r);
-
-        let indices = r.find("_indices").unwrap().as_object().unwrap();
+impl FromJson for DeleteByQueryResult {
+    fn from_json(r: &Json) -> Result<DeleteByQueryResult, EsError> {
+        let indices = try!(try!(find_json(r, "_indices"))
+                           .as_object()
+                           .ok_or_else(|| EsError::EsError("Invalid _indices".to_string())));
         let mut indices_map = HashMap::new();
         for (k, v) in indices {
-            indices_map.insert(k.clone(), DeleteByQueryIndexResult::from(v));
+            indices_map.insert(k.clone(), try!(DeleteByQueryIndexResult::from_json(v)));
         }
-        DeleteByQueryResult {
+        Ok(DeleteByQueryResult {
             indices: indices_map
+        })
+    }
+}
+
+from_json_panicking!(DeleteByQueryResult);
+
+/// The number of documents deleted from an index by a scan-and-delete
+#[derive(Debug)]
+pub struct ScanAndDeleteIndexResult {
+    pub deleted:           i64,
+
+    /// Documents which were changed after they were found
+    pub version_conflicts: i64,
+
+    /// Documents which could not be deleted for any other reason, including
+    /// those not found when deleted if their routing was not known
+    pub failed:            i64
+}
+
+impl ScanAndDeleteIndexResult {
+    fn new() -> ScanAndDeleteIndexResult {
+        ScanAndDeleteIndexResult {
+            deleted:           0,
+            version_conflicts: 0,
+            failed:            0
         }
     }
 }
+
+/// The result of `DeleteByQueryOperation::send_scan_and_delete`
+#[derive(Debug)]
+pub struct ScanAndDeleteResult {
+    pub indices: HashMap<String, ScanAndDeleteIndexResult>
+}
+
+impl ScanAndDeleteResult {
+    /// Whether every matching document was deleted
+    pub fn successful(&self) -> bool {
+        self.indices.values().all(|i| i.version_conflicts == 0 && i.failed == 0)
+    }
+
+    /// The total number of documents deleted, from all indexes
+    pub fn deleted(&self) -> i64 {
+        self.indices.values().map(|i| i.deleted).fold(0, |a, b| a + b)
+    }
+}
@@ -0,0 +1,362 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Debugging queries: explaining why a document does or does not match a
+//! query, and validating a query without running it.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use ::query::Query;
+use super::common::Options;
+use super::error_reason;
+use super::find_json;
+use super::format_indexes_and_types;
+use super::format_query_string;
+use super::query_string_body;
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+
+/// The query, either as a query string or in the body using the Query DSL
+enum QueryOption<'a> {
+    String(String),
+    Document(&'a Query)
+}
+
+/// Sends a query to `path`, a query string is sent as the `q` parameter
+/// unless the URI would be too long, in which case it is sent as an equivalent
+/// `query_string` query in the body instead.
+fn send_query(client:  &mut Client,
+              path:    &str,
+              query:   Option<&QueryOption>,
+              options: &Options) -> Result<(StatusCode, Option<Json>), EsError> {
+    match query {
+        Some(&QueryOption::Document(q)) => {
+            let mut body = BTreeMap::new();
+            body.insert("query".to_string(), q.to_json());
+            let url = format!("{}{}", path, format_query_string(options));
+            client.post_read_body_op(&url, &Json::Object(body))
+        },
+        Some(&QueryOption::String(ref s)) => {
+            let mut options = options.clone();
+            options.push(("q", s.clone()));
+            let url = format!("{}{}", path, format_query_string(&options));
+            match client.check_uri_length(&url) {
+                Ok(_)  => client.get_op(&url),
+                Err(_) => {
+                    let (body, options) = query_string_body(&options);
+                    let url = format!("{}{}", path, format_query_string(&options));
+                    try!(client.check_uri_length(&url));
+                    client.post_read_body_op(&url, &body)
+                }
+            }
+        },
+        None => {
+            let url = format!("{}{}", path, format_query_string(options));
+            client.get_op(&url)
+        }
+    }
+}
+
+/// Explain API, how a query scores a specific document, or why it does not
+/// match.  A query must be given, either as a query string or using the Query
+/// DSL.
+pub struct ExplainOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index, type and ID of the document
+    index:    &'b str,
+    doc_type: &'b str,
+    id:       &'b str,
+
+    /// The query, in parameter or Query DSL form
+    query:    Option<QueryOption<'b>>,
+
+    /// Optional options
+    options:  Options<'b>
+}
+
+impl<'a, 'b> ExplainOperation<'a, 'b> {
+    pub fn new(client:   &'a mut Client,
+               index:    &'b str,
+               doc_type: &'b str,
+               id:       &'b str) -> ExplainOperation<'a, 'b> {
+        ExplainOperation {
+            client:   client,
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            query:    None,
+            options:  Options::new()
+        }
+    }
+
+    pub fn with_query_string(&mut self, qs: String) -> &mut Self {
+        self.query = Some(QueryOption::String(qs));
+        self
+    }
+
+    pub fn with_query(&mut self, q: &'b Query) -> &mut Self {
+        self.query = Some(QueryOption::Document(q));
+        self
+    }
+
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+    add_option!(with_df, "df");
+    add_option!(with_analyzer, "analyzer");
+    add_option!(with_default_operator, "default_operator");
+    add_option!(with_lenient, "lenient");
+
+    /// Sends the request.  If the document does not exist the result is not
+    /// `found`, nor `matched`, and has no explanation.
+    pub fn send(&mut self) -> Result<ExplainResult, EsError> {
+        if self.query.is_none() {
+            return Err(EsError::EsError("Explain requires a query".to_string()));
+        }
+        let path = format!("/{}/{}/{}/_explain", self.index, self.doc_type, self.id);
+        let (status_code, result) = try!(send_query(self.client,
+                                                    &path,
+                                                    self.query.as_ref(),
+                                                    &self.options));
+        debug!(target: "rs_es::explain", "[{}] Explain result status: {}",
+               self.client.request_id(), status_code);
+        match (status_code, result) {
+            (StatusCode::Ok, Some(result))       => ExplainResult::from_json(&result),
+            (StatusCode::NotFound, Some(result)) => {
+                if result.find("error").is_some() {
+                    Err(unexpected_status(self.client.request_id(), status_code, Some(result)))
+                } else {
+                    let mut result = try!(ExplainResult::from_json(&result));
+                    result.found = false;
+                    Ok(result)
+                }
+            },
+            (status_code, result)                => {
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        }
+    }
+}
+
+/// A node of the tree explaining a score, the value of each node is computed
+/// from its details
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub value:       f64,
+    pub description: String,
+
+    /// Empty for a leaf node
+    pub details:     Vec<Explanation>
+}
+
+impl FromJson for Explanation {
+    fn from_json(r: &Json) -> Result<Explanation, EsError> {
+        let details = match r.find("details").and_then(|d| d.as_array()) {
+            Some(details) => try!(details.iter()
+                                  .map(|d| Explanation::from_json(d))
+                                  .collect::<Result<Vec<_>, _>>()),
+            None          => Vec::new()
+        };
+        Ok(Explanation {
+            value:       get_json_f64!(r, "value"),
+            description: get_json_string!(r, "description"),
+            details:     details
+        })
+    }
+}
+
+from_json_panicking!(Explanation);
+
+/// The result of an explain request
+#[derive(Debug)]
+pub struct ExplainResult {
+    pub index:       String,
+    pub doc_type:    String,
+    pub id:          String,
+
+    /// Whether the document exists, if not it does not match and there is no
+    /// explanation
+    pub found:       bool,
+    pub matched:     bool,
+
+    /// Absent if the document does not exist
+    pub explanation: Option<Explanation>
+}
+
+impl FromJson for ExplainResult {
+    fn from_json(r: &Json) -> Result<ExplainResult, EsError> {
+        Ok(ExplainResult {
+            index:       get_json_string!(r, "_index"),
+            doc_type:    get_json_string!(r, "_type"),
+            id:          get_json_string!(r, "_id"),
+            // Used if given, but usually a document which does not exist is
+            // only indicated by the status and the lack of an explanation
+            found:       r.find("found")
+                .and_then(|f| f.as_boolean())
+                .unwrap_or(r.find("explanation").is_some()),
+            matched:     get_json_bool!(r, "matched"),
+            explanation: match r.find("explanation") {
+                Some(explanation) => Some(try!(Explanation::from_json(explanation))),
+                None              => None
+            }
+        })
+    }
+}
+
+from_json_panicking!(ExplainResult);
+
+/// Validate API, whether a query is valid without running it, with either an
+/// explanation of the query as rewritten by each index or the reason it is
+/// invalid.  If no query is given, `match_all` is validated.
+pub struct ValidateQueryOperation<'a, 'b> {
+    /// The HTTP client
+    client:    &'a mut Client,
+
+    /// The indexes to which this query apply
+    indexes:   &'b [&'b str],
+
+    /// The types to which this query applies
+    doc_types: &'b [&'b str],
+
+    /// The query, in parameter or Query DSL form
+    query:     Option<QueryOption<'b>>,
+
+    /// Optional options
+    options:   Options<'b>
+}
+
+impl<'a, 'b> ValidateQueryOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> ValidateQueryOperation<'a, 'b> {
+        let mut options = Options::new();
+        options.push(("explain", "true".to_string()));
+        ValidateQueryOperation {
+            client:    client,
+            indexes:   &[],
+            doc_types: &[],
+            query:     None,
+            options:   options
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    pub fn with_query_string(&mut self, qs: String) -> &mut Self {
+        self.query = Some(QueryOption::String(qs));
+        self
+    }
+
+    pub fn with_query(&mut self, q: &'b Query) -> &mut Self {
+        self.query = Some(QueryOption::Document(q));
+        self
+    }
+
+    add_option!(with_df, "df");
+    add_option!(with_analyzer, "analyzer");
+    add_option!(with_default_operator, "default_operator");
+    add_option!(with_lenient, "lenient");
+    add_option!(with_rewrite, "rewrite");
+
+    /// Sends the request.  An invalid query is not an error, the result is not
+    /// `valid` and gives the reason.  If any of the indexes do not exist, the
+    /// result is an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<ValidateQueryResult, EsError> {
+        let indexes_and_types = format_indexes_and_types(&self.indexes,
+                                                         &self.doc_types);
+        let path = format!("/{}/_validate/query", indexes_and_types);
+        let (status_code, result) = try!(send_query(self.client,
+                                                    &path,
+                                                    self.query.as_ref(),
+                                                    &self.options));
+        debug!(target: "rs_es::explain", "[{}] Validate query result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => ValidateQueryResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(indexes_and_types))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The validation of a query by a single index
+#[derive(Debug)]
+pub struct QueryExplanation {
+    pub index:       String,
+    pub valid:       bool,
+
+    /// The query as rewritten by the index, if valid
+    pub explanation: Option<String>,
+
+    /// Why the query is invalid
+    pub error:       Option<String>
+}
+
+impl FromJson for QueryExplanation {
+    fn from_json(r: &Json) -> Result<QueryExplanation, EsError> {
+        Ok(QueryExplanation {
+            index:       get_json_string!(r, "index"),
+            valid:       get_json_bool!(r, "valid"),
+            explanation: r.find("explanation").and_then(|e| e.as_string()).map(|e| e.to_string()),
+            error:       r.find("error").and_then(|e| e.as_string()).map(|e| e.to_string())
+        })
+    }
+}
+
+from_json_panicking!(QueryExplanation);
+
+/// The result of a validate query request
+#[derive(Debug)]
+pub struct ValidateQueryResult {
+    pub valid:        bool,
+    pub shards:       ShardCountResult,
+
+    /// The validation by each index
+    pub explanations: Vec<QueryExplanation>
+}
+
+impl FromJson for ValidateQueryResult {
+    fn from_json(r: &Json) -> Result<ValidateQueryResult, EsError> {
+        let explanations = match r.find("explanations").and_then(|e| e.as_array()) {
+            Some(explanations) => try!(explanations.iter()
+                                       .map(|e| QueryExplanation::from_json(e))
+                                       .collect::<Result<Vec<_>, _>>()),
+            None               => Vec::new()
+        };
+        Ok(ValidateQueryResult {
+            valid:        get_json_bool!(r, "valid"),
+            shards:       try!(ShardCountResult::from_json(try!(find_json(r, "_shards")))),
+            explanations: explanations
+        })
+    }
+}
+
+from_json_panicking!(ValidateQueryResult);
@@ -14,6 +14,10 @@
  * limitations under the License.
  */
 
+use std::time::Duration;
+
+use hyper::status::StatusCode;
+
 use rustc_serialize::Decodable;
 use rustc_serialize::json::Json;
 
@@ -23,6 +27,10 @@ use ::util::StrJoin;
 use super::common::Options;
 use super::decode_json;
 use super::format_query_string;
+use super::timestamp_from_fields;
+use super::ttl_from_fields;
+use super::unexpected_status;
+use super::FromJson;
 
 /// An ES GET operation, to get a document by ID
 pub struct GetOperation<'a, 'b> {
@@ -55,38 +63,58 @@ impl<'a, 'b> GetOperation<'a, 'b> {
         }
     }
 
-    pub fn with_all_types(&'b mut self) -> &'b mut Self {
+    pub fn with_all_types(&mut self) -> &mut Self {
         self.doc_type = Some("_all");
         self
     }
 
-    pub fn with_doc_type(&'b mut self, doc_type: &'b str) -> &'b mut Self {
+    pub fn with_doc_type(&mut self, doc_type: &'b str) -> &mut Self {
         self.doc_type = Some(doc_type);
         self
     }
 
-    pub fn with_fields(&'b mut self, fields: &[&'b str]) -> &'b mut Self {
+    pub fn with_fields(&mut self, fields: &[&'b str]) -> &mut Self {
         self.options.push(("fields", fields.iter().join(",")));
         self
     }
 
+    // With `&false` the document is read from the last refresh rather than
+    // the transaction log, cheaper but possibly out of date
     add_option!(with_realtime, "realtime");
     add_option!(with_source, "_source");
     add_option!(with_routing, "routing");
     add_option!(with_preference, "preference");
     add_option!(with_refresh, "refresh");
+
+    // The document must be at this version, if it is not the result is an
+    // `EsError::ConflictError`
     add_option!(with_version, "version");
 
-    pub fn send(&'b mut self) -> Result<GetResult, EsError> {
+    pub fn send(&mut self) -> Result<GetResult, EsError> {
+        try!(self.client.check_id(self.id));
         let url = format!("/{}/{}/{}{}",
                           self.index,
-                          self.doc_type.unwrap(),
+                          self.doc_type.unwrap_or("_all"),
                           self.id,
                           format_query_string(&self.options));
-        // We're ignoring status_code as all valid codes should return a value,
-        // so anything else is an error.
-        let (_, result) = try!(self.client.get_op(&url));
-        Ok(GetResult::from(&result.unwrap()))
+        // A 404 is a valid response, it will have `found` set to false; unless
+        // it is an error, e.g. the index does not exist.
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::get", "[{}] Get result status: {}",
+               self.client.request_id(), status_code);
+        match (status_code, result) {
+            (StatusCode::Ok, Some(result))       => GetResult::from_json(&result),
+            (StatusCode::NotFound, Some(result)) => {
+                if result.find("error").is_some() {
+                    Err(unexpected_status(self.client.request_id(), status_code, Some(result)))
+                } else {
+                    GetResult::from_json(&result)
+                }
+            },
+            (status_code, result)                => {
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        }
     }
 }
 
@@ -98,7 +126,10 @@ pub struct GetResult {
     pub id:       String,
     pub version:  Option<i64>,
     pub found:    bool,
-    pub source:   Option<Json>
+    pub source:   Option<Json>,
+
+    /// The stored fields requested with `with_fields`
+    pub fields:   Option<Json>
 }
 
 impl GetResult {
@@ -111,19 +142,33 @@ impl GetResult {
             None      => Err(EsError::EsError("No source".to_string()))
         }
     }
+
+    /// The remaining time-to-live of the document, only present if `_ttl`
+    /// was requested with `with_fields` and is enabled for the type
+    pub fn ttl(&self) -> Option<Duration> {
+        ttl_from_fields(self.fields.as_ref())
+    }
+
+    /// The `_timestamp` of the document in milliseconds since the epoch, only
+    /// present if requested with `with_fields` and enabled for the type
+    pub fn timestamp(&self) -> Option<i64> {
+        timestamp_from_fields(self.fields.as_ref())
+    }
 }
 
 /// This is required because the JSON keys do not match the struct
-impl<'a> From<&'a Json> for GetResult {
-    fn from(r: &'a Json) -> GetResult {
-        info!("GetResult FROM: {:?}", r);
-        GetResult {
+impl FromJson for GetResult {
+    fn from_json(r: &Json) -> Result<GetResult, EsError> {
+        Ok(GetResult {
             index:    get_json_string!(r, "_index"),
             doc_type: get_json_string!(r, "_type"),
             id:       get_json_string!(r, "_id"),
-            version:  r.search("_version").map(|v| v.as_i64().unwrap()),
+            version:  r.search("_version").and_then(|v| v.as_i64()),
             found:    get_json_bool!(r, "found"),
-            source:   r.search("_source").map(|source| source.clone())
-        }
+            source:   r.search("_source").map(|source| source.clone()),
+            fields:   r.find("fields").map(|fields| fields.clone())
+        })
     }
 }
+
+from_json_panicking!(GetResult);
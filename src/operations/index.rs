@@ -14,6 +14,8 @@
  * limitations under the License.
  */
 
+use hyper::status::StatusCode;
+
 use rustc_serialize::Encodable;
 use rustc_serialize::json::Json;
 
@@ -21,15 +23,24 @@ use ::Client;
 use ::error::EsError;
 use super::common::Options;
 use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+use super::WriteOutcome;
 
-/// Values for the op_type option
+/// Values for the op_type option.  `Create` will fail with a
+/// `EsError::ConflictError` if a document with the same ID already exists,
+/// `Index` (the default) will overwrite it.
 pub enum OpType {
-    Create
+    Create,
+    Index
 }
 
 impl ToString for OpType {
     fn to_string(&self) -> String {
-        "create".to_string()
+        match self {
+            &OpType::Create => "create",
+            &OpType::Index  => "index"
+        }.to_string()
     }
 }
 
@@ -66,12 +77,12 @@ impl<'a, 'b, E: Encodable + 'b> IndexOperation<'a, 'b, E> {
         }
     }
 
-    pub fn with_doc(&'b mut self, doc: &'b E) -> &'b mut Self {
+    pub fn with_doc(&mut self, doc: &'b E) -> &mut Self {
         self.document = Some(doc);
         self
     }
 
-    pub fn with_id(&'b mut self, id: &'b str) -> &'b mut Self {
+    pub fn with_id(&mut self, id: &'b str) -> &mut Self {
         self.id = Some(id);
         self
     }
@@ -85,17 +96,17 @@ impl<'a, 'b, E: Encodable + 'b> IndexOperation<'a, 'b, E> {
     add_option!(with_timestamp, "timestamp");
     add_option!(with_refresh, "refresh");
     add_option!(with_timeout, "timeout");
+    add_option!(with_consistency, "consistency");
 
-    pub fn send(&'b mut self) -> Result<IndexResult, EsError> {
-        // Ignoring status_code as everything should return an IndexResult or
-        // already be an error
-        let (_, result) = try!(match self.id {
+    pub fn send(&mut self) -> Result<IndexResult, EsError> {
+        let (status_code, result) = try!(match self.id {
             Some(ref id) => {
+                try!(self.client.check_id(id));
                 let url = format!("/{}/{}/{}{}",
                                   self.index,
                                   self.doc_type,
                                   id,
-                                  format_query_string(&mut self.options));
+                                  format_query_string(&self.options));
                 match self.document {
                     Some(ref doc) => self.client.put_body_op(&url, doc),
                     None          => self.client.put_op(&url)
@@ -105,14 +116,19 @@ impl<'a, 'b, E: Encodable + 'b> IndexOperation<'a, 'b, E> {
                 let url = format!("/{}/{}{}",
                                   self.index,
                                   self.doc_type,
-                                  format_query_string(&mut self.options));
+                                  format_query_string(&self.options));
                 match self.document {
                     Some(ref doc) => self.client.post_body_op(&url, doc),
                     None          => self.client.post_op(&url)
                 }
             }
         });
-        Ok(IndexResult::from(&result.unwrap()))
+        debug!(target: "rs_es::index", "[{}] Index result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok | StatusCode::Created => IndexResult::from_json(&result.unwrap()),
+            _ => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
     }
 }
 
@@ -123,18 +139,26 @@ pub struct IndexResult {
     pub doc_type: String,
     pub id:       String,
     pub version:  i64,
-    pub created:  bool
+    pub created:  bool,
+    pub outcome:  WriteOutcome
 }
 
 /// This is required because the JSON keys do not match the struct
-impl<'a> From<&'a Json> for IndexResult {
-    fn from(r: &'a Json) -> IndexResult {
-        IndexResult {
+impl FromJson for IndexResult {
+    fn from_json(r: &Json) -> Result<IndexResult, EsError> {
+        let outcome = try!(WriteOutcome::from_json(r,
+                                                   "created",
+                                                   WriteOutcome::Created,
+                                                   WriteOutcome::Updated));
+        Ok(IndexResult {
             index:    get_json_string!(r, "_index"),
             doc_type: get_json_string!(r, "_type"),
             id:       get_json_string!(r, "_id"),
             version:  get_json_i64!(r, "_version"),
-            created:  get_json_bool!(r, "created")
-        }
+            created:  outcome == WriteOutcome::Created,
+            outcome:  outcome
+        })
     }
 }
+
+from_json_panicking!(IndexResult);
@@ -0,0 +1,551 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Index management: creating and deleting indexes, getting their mappings
+//! and settings, upgrading their segments, and whether an index exists or
+//! can be written to.  Refreshing is available directly via
+//! `Client::refresh`.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::{EsError, ErrorCause};
+use super::common::Options;
+use super::error_reason;
+use super::find_json;
+use super::format_multi;
+use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+
+/// Whether an index exists, see `Client::index_exists`
+pub fn index_exists(client: &mut Client, index: &str) -> Result<bool, EsError> {
+    let url = format!("/{}", format_multi(&[index]));
+    let status_code = try!(client.head_op(&url));
+    debug!(target: "rs_es::index_admin", "[{}] Index exists result status: {}",
+           client.request_id(), status_code);
+    match status_code {
+        StatusCode::Ok       => Ok(true),
+        StatusCode::NotFound => Ok(false),
+        _                    => Err(unexpected_status(client.request_id(), status_code, None))
+    }
+}
+
+/// Whether any of the blocks, keyed by block ID as in the cluster state, is
+/// at the `write` level
+fn blocks_writes(blocks: &Json) -> bool {
+    blocks.as_object().map(|blocks| {
+        blocks.values().any(|block| {
+            block.find("levels")
+                .and_then(|levels| levels.as_array())
+                .map(|levels| levels.iter().any(|level| level.as_string() == Some("write")))
+                .unwrap_or(false)
+        })
+    }).unwrap_or(false)
+}
+
+/// Whether an index can be written to, see `Client::is_writable`
+pub fn is_writable(client: &mut Client, index: &str) -> Result<bool, EsError> {
+    let url = format!("/_cluster/state/blocks/{}", format_multi(&[index]));
+    let (status_code, result) = try!(client.get_op(&url));
+    debug!(target: "rs_es::index_admin", "[{}] Index blocks result status: {}",
+           client.request_id(), status_code);
+    match status_code {
+        StatusCode::Ok       => {
+            let result = result.unwrap();
+            let global = result.find_path(&["blocks", "global"]);
+            // Only the blocks of the index are given, its name may differ
+            // from that requested, e.g. if it was a date-math name
+            let indexed = result.find_path(&["blocks", "indices"])
+                .and_then(|indices| indices.as_object())
+                .map(|indices| indices.values().any(blocks_writes))
+                .unwrap_or(false);
+            Ok(!indexed && !global.map(blocks_writes).unwrap_or(false))
+        },
+        StatusCode::NotFound => Err(EsError::IndexMissingError(
+            error_reason(result.as_ref()).unwrap_or(index.to_string()))),
+        _                    => Err(unexpected_status(client.request_id(), status_code, result))
+    }
+}
+
+/// Create an index, optionally with settings and mappings
+pub struct CreateIndexOperation<'a, 'b> {
+    /// The HTTP client
+    client:             &'a mut Client,
+
+    /// The index
+    index:              &'b str,
+
+    /// Settings, the shorthands below are added to these
+    settings:           Option<Json>,
+
+    /// Mappings, keyed by type
+    mappings:           Option<Json>,
+
+    number_of_shards:   Option<i64>,
+    number_of_replicas: Option<i64>,
+
+    /// Optional options
+    options:            Options<'b>
+}
+
+impl<'a, 'b> CreateIndexOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, index: &'b str) -> CreateIndexOperation<'a, 'b> {
+        CreateIndexOperation {
+            client:             client,
+            index:              index,
+            settings:           None,
+            mappings:           None,
+            number_of_shards:   None,
+            number_of_replicas: None,
+            options:            Options::new()
+        }
+    }
+
+    /// The index settings, this must be an object
+    pub fn with_settings<T: ToJson>(&mut self, settings: &T) -> &mut Self {
+        self.settings = Some(settings.to_json());
+        self
+    }
+
+    /// The mappings, an object keyed by type
+    pub fn with_mappings<T: ToJson>(&mut self, mappings: &T) -> &mut Self {
+        self.mappings = Some(mappings.to_json());
+        self
+    }
+
+    pub fn with_number_of_shards(&mut self, number_of_shards: i64) -> &mut Self {
+        self.number_of_shards = Some(number_of_shards);
+        self
+    }
+
+    pub fn with_number_of_replicas(&mut self, number_of_replicas: i64) -> &mut Self {
+        self.number_of_replicas = Some(number_of_replicas);
+        self
+    }
+
+    add_option!(with_timeout, "timeout");
+    add_option!(with_master_timeout, "master_timeout");
+
+    fn body(&self) -> Result<Json, EsError> {
+        let mut settings = match self.settings {
+            Some(Json::Object(ref settings)) => settings.clone(),
+            Some(_)                          => {
+                return Err(EsError::EsError("Index settings must be an object".to_string()))
+            },
+            None                             => BTreeMap::new()
+        };
+        optional_add!(settings, self.number_of_shards, "number_of_shards");
+        optional_add!(settings, self.number_of_replicas, "number_of_replicas");
+
+        let mut d = BTreeMap::new();
+        if !settings.is_empty() {
+            d.insert("settings".to_string(), Json::Object(settings));
+        }
+        optional_add!(d, self.mappings, "mappings");
+        Ok(Json::Object(d))
+    }
+
+    /// Sends the request.  If the index already exists the result is an
+    /// `EsError::IndexAlreadyExistsError`.
+    pub fn send(&mut self) -> Result<CreateIndexResult, EsError> {
+        let body = try!(self.body());
+        let url = format!("/{}{}", self.index, format_query_string(&self.options));
+        let (status_code, result) = match self.client.put_body_op(&url, &body) {
+            Ok(response)                          => response,
+            Err(EsError::ResponseError(ref err)) if is_already_exists(&err.error) => {
+                return Err(EsError::IndexAlreadyExistsError(self.index.to_string()))
+            },
+            Err(e)                                => return Err(e)
+        };
+        debug!(target: "rs_es::index_admin", "[{}] Create index result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => CreateIndexResult::from_json(&result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// ElasticSearch 1.x only gives the name of the exception in the reason, later
+/// versions give the error type
+fn is_already_exists(error: &ErrorCause) -> bool {
+    match error.error_type {
+        Some(ref error_type) => error_type.contains("already_exists"),
+        None                 => error.reason.contains("AlreadyExists")
+    }
+}
+
+/// The result of creating an index
+#[derive(Debug)]
+pub struct CreateIndexResult {
+    pub acknowledged: bool
+}
+
+impl FromJson for CreateIndexResult {
+    fn from_json(r: &Json) -> Result<CreateIndexResult, EsError> {
+        Ok(CreateIndexResult {
+            acknowledged: get_json_bool!(r, "acknowledged")
+        })
+    }
+}
+
+from_json_panicking!(CreateIndexResult);
+
+/// Delete an index
+pub struct DeleteIndexOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The index
+    index:   &'b str,
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> DeleteIndexOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, index: &'b str) -> DeleteIndexOperation<'a, 'b> {
+        DeleteIndexOperation {
+            client:  client,
+            index:   index,
+            options: Options::new()
+        }
+    }
+
+    add_option!(with_timeout, "timeout");
+    add_option!(with_master_timeout, "master_timeout");
+
+    /// Sends the request, the result is `false` if the index did not exist
+    pub fn send(&mut self) -> Result<bool, EsError> {
+        let url = format!("/{}{}", self.index, format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.delete_op(&url));
+        debug!(target: "rs_es::index_admin", "[{}] Delete index result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => Ok(true),
+            StatusCode::NotFound => Ok(false),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The single index in a response keyed by index name, the name may differ
+/// from that requested if it was an alias
+fn single_index<'r>(r: &'r Json, index: &str) -> Result<&'r Json, EsError> {
+    match r.as_object() {
+        Some(indexes) if indexes.len() == 1 => Ok(indexes.values().next().unwrap()),
+        _ => Err(EsError::EsError(format!("Expected a single index for: {}", index)))
+    }
+}
+
+/// Get the mappings of an index
+pub struct GetMappingOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The index
+    index:  &'b str
+}
+
+impl<'a, 'b> GetMappingOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, index: &'b str) -> GetMappingOperation<'a, 'b> {
+        GetMappingOperation {
+            client: client,
+            index:  index
+        }
+    }
+
+    /// Sends the request, the result is the mappings keyed by type
+    pub fn send(&mut self) -> Result<Json, EsError> {
+        let url = format!("/{}/_mapping", self.index);
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::index_admin", "[{}] Get mapping result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => {
+                let result = result.unwrap();
+                let index = try!(single_index(&result, self.index));
+                Ok(index.find("mappings")
+                   .map(|m| m.clone())
+                   .unwrap_or(Json::Object(BTreeMap::new())))
+            },
+            StatusCode::NotFound => Err(EsError::IndexMissingError(self.index.to_string())),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Get the settings of an index
+pub struct GetSettingsOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The index
+    index:  &'b str
+}
+
+impl<'a, 'b> GetSettingsOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, index: &'b str) -> GetSettingsOperation<'a, 'b> {
+        GetSettingsOperation {
+            client: client,
+            index:  index
+        }
+    }
+
+    /// Sends the request, the result is the settings as nested objects
+    pub fn send(&mut self) -> Result<Json, EsError> {
+        let url = format!("/{}/_settings", self.index);
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::index_admin", "[{}] Get settings result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => {
+                let result = result.unwrap();
+                let index = try!(single_index(&result, self.index));
+                Ok(index.find("settings")
+                   .map(|s| s.clone())
+                   .unwrap_or(Json::Object(BTreeMap::new())))
+            },
+            StatusCode::NotFound => Err(EsError::IndexMissingError(self.index.to_string())),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Upgrade status, how much of each index is in segments written by an older
+/// version of Lucene, e.g. to check before upgrading a cluster that no index
+/// would be unreadable
+pub struct UpgradeStatusOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The indexes, all if empty
+    indexes: &'b [&'b str]
+}
+
+impl<'a, 'b> UpgradeStatusOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> UpgradeStatusOperation<'a, 'b> {
+        UpgradeStatusOperation {
+            client:  client,
+            indexes: &[]
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    /// Sends the request.  If any of the indexes do not exist, the result is
+    /// an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<UpgradeStatusResult, EsError> {
+        let url = format!("/{}/_upgrade", format_multi(&self.indexes));
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::index_admin", "[{}] Upgrade status result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => UpgradeStatusResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(format_multi(&self.indexes)))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The upgrade status of an index
+#[derive(Debug)]
+pub struct IndexUpgradeStatus {
+    pub size_in_bytes:                    i64,
+
+    /// The size of the segments written by an older version of Lucene
+    pub size_to_upgrade_in_bytes:         i64,
+
+    /// The size of the segments written by the previous major version of
+    /// Lucene or earlier, which the next major version of ElasticSearch cannot
+    /// read.  Only reported by ElasticSearch 1.6 onwards.
+    pub size_to_upgrade_ancient_in_bytes: Option<i64>
+}
+
+impl FromJson for IndexUpgradeStatus {
+    fn from_json(r: &Json) -> Result<IndexUpgradeStatus, EsError> {
+        Ok(IndexUpgradeStatus {
+            size_in_bytes:                    get_json_i64!(r, "size_in_bytes"),
+            size_to_upgrade_in_bytes:         get_json_i64!(r, "size_to_upgrade_in_bytes"),
+            size_to_upgrade_ancient_in_bytes: r.find("size_to_upgrade_ancient_in_bytes")
+                .and_then(|s| s.as_i64())
+        })
+    }
+}
+
+from_json_panicking!(IndexUpgradeStatus);
+
+/// The result of an upgrade status request
+#[derive(Debug)]
+pub struct UpgradeStatusResult {
+    /// By index
+    pub indices: BTreeMap<String, IndexUpgradeStatus>
+}
+
+/// The indexes with any segments to upgrade
+pub fn needs_upgrade(result: &UpgradeStatusResult) -> Vec<&str> {
+    result.indices.iter()
+        .filter(|&(_, status)| status.size_to_upgrade_in_bytes > 0)
+        .map(|(index, _)| &index[..])
+        .collect()
+}
+
+/// The indexes with any segments which the next major version cannot read,
+/// these must be upgraded before upgrading the cluster
+pub fn needs_ancient_upgrade(result: &UpgradeStatusResult) -> Vec<&str> {
+    result.indices.iter()
+        .filter(|&(_, status)| status.size_to_upgrade_ancient_in_bytes.unwrap_or(0) > 0)
+        .map(|(index, _)| &index[..])
+        .collect()
+}
+
+/// ElasticSearch 1.x gives each index at the top level, later versions
+/// under `indices`
+impl FromJson for UpgradeStatusResult {
+    fn from_json(r: &Json) -> Result<UpgradeStatusResult, EsError> {
+        let r_indices = match r.find("indices") {
+            Some(indices) => indices,
+            None          => r
+        };
+        let mut indices = BTreeMap::new();
+        if let Some(r_indices) = r_indices.as_object() {
+            for (name, index) in r_indices.iter() {
+                indices.insert(name.clone(), try!(IndexUpgradeStatus::from_json(index)));
+            }
+        }
+        Ok(UpgradeStatusResult {
+            indices: indices
+        })
+    }
+}
+
+from_json_panicking!(UpgradeStatusResult);
+
+/// Upgrade indexes, rewriting any segments written by an older version of
+/// Lucene
+pub struct UpgradeOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The indexes, all if empty
+    indexes: &'b [&'b str],
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> UpgradeOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> UpgradeOperation<'a, 'b> {
+        UpgradeOperation {
+            client:  client,
+            indexes: &[],
+            options: Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    add_option!(with_only_ancient_segments, "only_ancient_segments");
+    add_option!(with_wait_for_completion, "wait_for_completion");
+
+    /// Sends the request.  If any of the indexes do not exist, the result is
+    /// an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<UpgradeResult, EsError> {
+        let url = format!("/{}/_upgrade{}",
+                          format_multi(&self.indexes),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_op(&url));
+        debug!(target: "rs_es::index_admin", "[{}] Upgrade result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => UpgradeResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(format_multi(&self.indexes)))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// An upgraded index
+#[derive(Debug)]
+pub struct UpgradedIndex {
+    /// The version of ElasticSearch the index is now compatible with, not
+    /// reported by ElasticSearch 1.x
+    pub upgrade_version:               Option<String>,
+
+    /// The Lucene version of the oldest segment remaining
+    pub oldest_lucene_segment_version: String
+}
+
+/// ElasticSearch 1.x gives only the Lucene version
+impl FromJson for UpgradedIndex {
+    fn from_json(r: &Json) -> Result<UpgradedIndex, EsError> {
+        match *r {
+            Json::String(ref version) => Ok(UpgradedIndex {
+                upgrade_version:               None,
+                oldest_lucene_segment_version: version.clone()
+            }),
+            _                         => Ok(UpgradedIndex {
+                upgrade_version:               r.find("upgrade_version")
+                    .and_then(|v| v.as_string())
+                    .map(|v| v.to_string()),
+                oldest_lucene_segment_version: get_json_string!(r,
+                                                                "oldest_lucene_segment_version")
+            })
+        }
+    }
+}
+
+from_json_panicking!(UpgradedIndex);
+
+/// The result of an upgrade request
+#[derive(Debug)]
+pub struct UpgradeResult {
+    pub shards:           ShardCountResult,
+
+    /// By index
+    pub upgraded_indices: BTreeMap<String, UpgradedIndex>
+}
+
+impl FromJson for UpgradeResult {
+    fn from_json(r: &Json) -> Result<UpgradeResult, EsError> {
+        let mut upgraded_indices = BTreeMap::new();
+        if let Some(r_indices) = r.find("upgraded_indices").and_then(|i| i.as_object()) {
+            for (name, index) in r_indices.iter() {
+                upgraded_indices.insert(name.clone(), try!(UpgradedIndex::from_json(index)));
+            }
+        }
+        Ok(UpgradeResult {
+            shards:           try!(ShardCountResult::from_json(try!(find_json(r, "_shards")))),
+            upgraded_indices: upgraded_indices
+        })
+    }
+}
+
+from_json_panicking!(UpgradeResult);
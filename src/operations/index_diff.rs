@@ -0,0 +1,174 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Comparing the mappings and settings of two indexes, e.g. to check that a
+//! migration changed only what was intended.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use rustc_serialize::json::Json;
+
+use ::Client;
+use ::error::EsError;
+
+/// Settings which are specific to each index, so always differ, and are
+/// therefore ignored.  A setting is also ignored if it is nested within one of
+/// these.
+const EPHEMERAL_SETTINGS: [&'static str; 4] = [
+    "index.creation_date",
+    "index.uuid",
+    "index.version",
+    "index.provided_name"
+];
+
+/// How a value differs between the first and second index
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// Only in the second index
+    Added(Json),
+
+    /// Only in the first index
+    Removed(Json),
+
+    /// In both, with the first and second value respectively
+    Changed(Json, Json)
+}
+
+/// A single difference, `path` is the dot-separated path to the value, e.g.
+/// `my_type.properties.my_field.analyzer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub path:   String,
+    pub change: Change
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.change {
+            Change::Added(ref v)        => write!(f, "+ {}: {}", self.path, v),
+            Change::Removed(ref v)      => write!(f, "- {}: {}", self.path, v),
+            Change::Changed(ref a, ref b) => write!(f, "~ {}: {} -> {}", self.path, a, b)
+        }
+    }
+}
+
+/// The differences between the mappings and settings of two indexes
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexDiff {
+    pub mappings: Vec<Difference>,
+    pub settings: Vec<Difference>
+}
+
+impl IndexDiff {
+    /// Compare mappings and settings, as returned by the get-mapping and
+    /// get-settings APIs for a single index
+    pub fn new(mappings_a: &Json,
+               mappings_b: &Json,
+               settings_a: &Json,
+               settings_b: &Json) -> IndexDiff {
+        let mut mappings = Vec::new();
+        diff_json("", mappings_a, mappings_b, &[], &mut mappings);
+        let mut settings = Vec::new();
+        diff_json("", settings_a, settings_b, &EPHEMERAL_SETTINGS, &mut settings);
+        IndexDiff {
+            mappings: mappings,
+            settings: settings
+        }
+    }
+
+    /// Whether the indexes are equivalent
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty() && self.settings.is_empty()
+    }
+}
+
+impl fmt::Display for IndexDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No differences");
+        }
+        for &(name, differences) in [("Mappings", &self.mappings),
+                                     ("Settings", &self.settings)].iter() {
+            if !differences.is_empty() {
+                try!(writeln!(f, "{}:", name));
+                for difference in differences {
+                    try!(writeln!(f, "  {}", difference));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a path is excluded, either exactly or by being within an excluded
+/// path
+fn is_excluded(path: &str, excluded: &[&str]) -> bool {
+    excluded.iter().any(|&e| {
+        path == e || (path.starts_with(e) && path[e.len()..].starts_with('.'))
+    })
+}
+
+/// Recursively compare two JSON values, objects are compared key-by-key, any
+/// other values (including arrays) are compared as a whole
+fn diff_json(path:        &str,
+             a:           &Json,
+             b:           &Json,
+             excluded:    &[&str],
+             differences: &mut Vec<Difference>) {
+    match (a, b) {
+        (&Json::Object(ref a), &Json::Object(ref b)) => {
+            let keys:BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                let child_path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                if is_excluded(&child_path, excluded) {
+                    continue;
+                }
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff_json(&child_path, a, b, excluded, differences),
+                    (Some(a), None)    => differences.push(Difference {
+                        path:   child_path,
+                        change: Change::Removed(a.clone())
+                    }),
+                    (None, Some(b))    => differences.push(Difference {
+                        path:   child_path,
+                        change: Change::Added(b.clone())
+                    }),
+                    (None, None)       => unreachable!()
+                }
+            }
+        },
+        (a, b) => if a != b {
+            differences.push(Difference {
+                path:   path.to_string(),
+                change: Change::Changed(a.clone(), b.clone())
+            })
+        }
+    }
+}
+
+/// Compare the mappings and settings of index `a` with index `b`
+pub fn compare_indices(client: &mut Client, a: &str, b: &str) -> Result<IndexDiff, EsError> {
+    let mappings_a = try!(client.get_mapping(a).send());
+    let mappings_b = try!(client.get_mapping(b).send());
+    let settings_a = try!(client.get_settings(a).send());
+    let settings_b = try!(client.get_settings(b).send());
+    Ok(IndexDiff::new(&mappings_a, &mappings_b, &settings_a, &settings_b))
+}
@@ -0,0 +1,291 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::{EsError, ResponseError};
+use ::util::StrJoin;
+use super::common::Options;
+use super::find_json_array;
+use super::format_query_string;
+use super::get::GetResult;
+use super::unexpected_status;
+use super::FromJson;
+
+/// The default maximum number of documents in each request made by
+/// `send_batched`
+const DEFAULT_BATCH_SIZE: usize = 500;
+
+/// A document to be retrieved as part of a multi-get request
+struct MGetDoc<'b> {
+    index:    &'b str,
+    doc_type: &'b str,
+    id:       &'b str,
+    routing:  Option<&'b str>,
+    version:  Option<i64>
+}
+
+impl<'b> ToJson for MGetDoc<'b> {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        d.insert("_index".to_string(), self.index.to_json());
+        d.insert("_type".to_string(), self.doc_type.to_json());
+        d.insert("_id".to_string(), self.id.to_json());
+        optional_add!(d, self.routing, "_routing");
+        optional_add!(d, self.version, "_version");
+        Json::Object(d)
+    }
+}
+
+/// An ES multi-get operation, to get many documents by ID in one request
+pub struct MGetOperation<'a, 'b> {
+    /// The HTTP connection
+    client:  &'a mut Client,
+
+    /// The documents to get, in the order they will be returned
+    docs:             Vec<MGetDoc<'b>>,
+
+    /// Optional options
+    options:          Options<'b>,
+
+    /// The maximum number of documents in each request, see `send_batched`
+    batch_size:       usize,
+
+    /// Whether `send_batched` groups documents by routing
+    group_by_routing: bool
+}
+
+impl<'a, 'b> MGetOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> MGetOperation<'a, 'b> {
+        MGetOperation {
+            client:           client,
+            docs:             Vec::new(),
+            options:          Options::new(),
+            batch_size:       DEFAULT_BATCH_SIZE,
+            group_by_routing: false
+        }
+    }
+
+    /// Add a single document, specified by index, type and ID
+    pub fn with_doc(&mut self,
+                    index:    &'b str,
+                    doc_type: &'b str,
+                    id:       &'b str) -> &mut Self {
+        self.docs.push(MGetDoc {
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            routing:  None,
+            version:  None
+        });
+        self
+    }
+
+    /// Add a single document which was indexed with a custom routing value
+    pub fn with_routed_doc(&mut self,
+                           index:    &'b str,
+                           doc_type: &'b str,
+                           id:       &'b str,
+                           routing:  &'b str) -> &mut Self {
+        self.docs.push(MGetDoc {
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            routing:  Some(routing),
+            version:  None
+        });
+        self
+    }
+
+    /// Add a single document which must be at the given version, if it is
+    /// not the document is an `EsError::ConflictError`
+    pub fn with_versioned_doc(&mut self,
+                              index:    &'b str,
+                              doc_type: &'b str,
+                              id:       &'b str,
+                              version:  i64) -> &mut Self {
+        self.docs.push(MGetDoc {
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            routing:  None,
+            version:  Some(version)
+        });
+        self
+    }
+
+    /// Add multiple documents, each specified as an (index, type, id) tuple
+    pub fn with_docs(&mut self,
+                     docs: &'b [(&'b str, &'b str, &'b str)]) -> &mut Self {
+        for &(index, doc_type, id) in docs {
+            self.docs.push(MGetDoc {
+                index:    index,
+                doc_type: doc_type,
+                id:       id,
+                routing:  None,
+                version:  None
+            });
+        }
+        self
+    }
+
+    pub fn with_fields(&mut self, fields: &[&'b str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    // With `&false` the documents are read from the last refresh rather
+    // than the transaction log, cheaper but possibly out of date
+    add_option!(with_realtime, "realtime");
+    add_option!(with_source, "_source");
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+    add_option!(with_refresh, "refresh");
+
+    /// The maximum number of documents in each request made by
+    /// `send_batched`, the default is 500
+    pub fn with_batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Whether `send_batched` should put documents with the same routing into
+    /// the same batches, so that each batch is sent to fewer shards
+    pub fn with_group_by_routing(&mut self, group_by_routing: bool) -> &mut Self {
+        self.group_by_routing = group_by_routing;
+        self
+    }
+
+    /// Sends the request.  If any document could not be retrieved, e.g. a
+    /// versioned document is at a different version, the whole request is an
+    /// error, see `send_batched` for an error for each document instead.
+    pub fn send(&mut self) -> Result<MGetResult, EsError> {
+        let url = format!("/_mget{}", format_query_string(&self.options));
+        let mut body = BTreeMap::new();
+        body.insert("docs".to_string(), self.docs.to_json());
+        let (status_code, result) = try!(self.client.post_read_body_op(&url,
+                                                                       &Json::Object(body)));
+        debug!(target: "rs_es::mget", "[{}] Multi-get result status: {}",
+               self.client.request_id(), status_code);
+        let result = match status_code {
+            StatusCode::Ok => result.unwrap(),
+            _              => return Err(unexpected_status(self.client.request_id(),
+                                                           status_code,
+                                                           result))
+        };
+        for doc in try!(find_json_array(&result, "docs")).iter() {
+            if let Some(error) = ResponseError::from_json(self.client.request_id(),
+                                                          status_code.to_u16(),
+                                                          doc) {
+                return Err(EsError::from(error));
+            }
+        }
+        MGetResult::from_json(&result)
+    }
+
+    /// Sends the documents in batches, see `with_batch_size` and
+    /// `with_group_by_routing`, one request at a time.  The result of each
+    /// document is in the same order as they were requested.
+    ///
+    /// Unlike `send`, a failure does not fail every document: a document
+    /// which could not be retrieved (e.g. the index does not exist) is an
+    /// error for that document only, and if a whole batch fails each document
+    /// in that batch is an `EsError::BatchFailed` with the error of the batch.
+    pub fn send_batched(&mut self) -> Vec<Result<GetResult, EsError>> {
+        let docs = &self.docs;
+        let mut order:Vec<usize> = (0..docs.len()).collect();
+        if self.group_by_routing {
+            // A stable sort, so within each routing the order is unchanged
+            order.sort_by(|&a, &b| docs[a].routing.cmp(&docs[b].routing));
+        }
+
+        let mut results:Vec<Option<Result<GetResult, EsError>>> =
+            docs.iter().map(|_| None).collect();
+        for batch in order.chunks(cmp::max(self.batch_size, 1)) {
+            let batch_docs:Vec<&MGetDoc> = batch.iter().map(|&i| &docs[i]).collect();
+            match send_docs(self.client, &self.options, &batch_docs) {
+                Ok(batch_results) => {
+                    for (&i, result) in batch.iter().zip(batch_results.into_iter()) {
+                        results[i] = Some(result);
+                    }
+                },
+                Err(e)            => {
+                    let e = Arc::new(e);
+                    for &i in batch {
+                        results[i] = Some(Err(EsError::BatchFailed(e.clone())));
+                    }
+                }
+            }
+        }
+        results.into_iter().map(|result| result.unwrap()).collect()
+    }
+}
+
+/// Sends a single batch, the result of each document is in the order given
+fn send_docs(client:  &mut Client,
+             options: &Options,
+             docs:    &[&MGetDoc]) -> Result<Vec<Result<GetResult, EsError>>, EsError> {
+    let url = format!("/_mget{}", format_query_string(options));
+    let mut body = BTreeMap::new();
+    body.insert("docs".to_string(), Json::Array(docs.iter().map(|d| d.to_json()).collect()));
+    let (status_code, result) = try!(client.post_read_body_op(&url, &Json::Object(body)));
+    debug!(target: "rs_es::mget", "[{}] Multi-get batch of {} result status: {}",
+           client.request_id(), docs.len(), status_code);
+    let result = match status_code {
+        StatusCode::Ok => result.unwrap(),
+        _              => return Err(unexpected_status(client.request_id(), status_code, result))
+    };
+    let results = try!(find_json_array(&result, "docs"));
+    if results.len() != docs.len() {
+        return Err(EsError::EsError(format!("[{}] Expected {} documents, got {}",
+                                            client.request_id(),
+                                            docs.len(),
+                                            results.len())));
+    }
+    Ok(results.iter().map(|r| {
+        match ResponseError::from_json(client.request_id(), status_code.to_u16(), r) {
+            Some(error) => Err(EsError::from(error)),
+            None        => GetResult::from_json(r)
+        }
+    }).collect())
+}
+
+/// The result of a multi-get request, the documents are in the same order as
+/// they were requested.
+#[derive(Debug)]
+pub struct MGetResult {
+    pub docs: Vec<GetResult>
+}
+
+impl FromJson for MGetResult {
+    fn from_json(r: &Json) -> Result<MGetResult, EsError> {
+        Ok(MGetResult {
+            docs: try!(try!(find_json_array(r, "docs"))
+                       .iter()
+                       .map(|j| GetResult::from_json(j))
+                       .collect::<Result<Vec<_>, _>>())
+        })
+    }
+}
+
+from_json_panicking!(MGetResult);
@@ -0,0 +1,123 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! The More Like This API, searching for documents like a stored document.
+//!
+//! This is ElasticSearch 1.x only, later versions have only the
+//! `more_like_this` query.
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::Decodable;
+use rustc_serialize::json::Json;
+
+use ::Client;
+use ::error::EsError;
+use ::util::StrJoin;
+use super::common::Options;
+use super::format_query_string;
+use super::search::SearchResult;
+use super::unexpected_status;
+use super::FromJson;
+
+/// More Like This API, a search for the documents most like a stored
+/// document, by the terms of some or all of its fields.  The result is that
+/// of any other search.
+pub struct MoreLikeThisOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index, type and ID of the document
+    index:    &'b str,
+    doc_type: &'b str,
+    id:       &'b str,
+
+    /// Optional options
+    options:  Options<'b>
+}
+
+impl<'a, 'b> MoreLikeThisOperation<'a, 'b> {
+    pub fn new(client:   &'a mut Client,
+               index:    &'b str,
+               doc_type: &'b str,
+               id:       &'b str) -> MoreLikeThisOperation<'a, 'b> {
+        MoreLikeThisOperation {
+            client:   client,
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            options:  Options::new()
+        }
+    }
+
+    /// The fields of the document to find similar documents by, by default
+    /// every field
+    pub fn with_mlt_fields(&mut self, fields: &[&'b str]) -> &mut Self {
+        self.options.push(("mlt_fields", fields.iter().join(",")));
+        self
+    }
+
+    /// The indexes and types to search, by default those of the document
+    pub fn with_search_indices(&mut self, indexes: &[&'b str]) -> &mut Self {
+        self.options.push(("search_indices", indexes.iter().join(",")));
+        self
+    }
+
+    pub fn with_search_types(&mut self, doc_types: &[&'b str]) -> &mut Self {
+        self.options.push(("search_types", doc_types.iter().join(",")));
+        self
+    }
+
+    add_option!(with_min_term_freq, "min_term_freq");
+    add_option!(with_min_doc_freq, "min_doc_freq");
+    add_option!(with_max_doc_freq, "max_doc_freq");
+    add_option!(with_max_query_terms, "max_query_terms");
+    add_option!(with_min_word_len, "min_word_len");
+    add_option!(with_max_word_len, "max_word_len");
+    add_option!(with_percent_terms_to_match, "percent_terms_to_match");
+    add_option!(with_boost_terms, "boost_terms");
+    add_option!(with_search_size, "search_size");
+    add_option!(with_search_from, "search_from");
+    add_option!(with_routing, "routing");
+
+    /// Sends the request.  If the document does not exist the result is an
+    /// `EsError::DocumentMissingError`.
+    pub fn send(&mut self) -> Result<SearchResult, EsError> {
+        SearchResult::from_json(&try!(self.send_raw()))
+    }
+
+    /// Sends the request, decoding the source of each hit as `T`
+    pub fn send_typed<T: Decodable>(&mut self) -> Result<SearchResult<T>, EsError> {
+        SearchResult::decode(try!(self.send_raw()))
+    }
+
+    fn send_raw(&mut self) -> Result<Json, EsError> {
+        try!(self.client.check_id(self.id));
+        let url = format!("/{}/{}/{}/_mlt{}",
+                          self.index,
+                          self.doc_type,
+                          self.id,
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::mlt", "[{}] More like this result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => Ok(result.unwrap()),
+            StatusCode::NotFound => Err(EsError::DocumentMissingError(self.id.to_string())),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
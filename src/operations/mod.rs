@@ -14,27 +14,50 @@
  * limitations under the License.
  */
 
+use std::cmp;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
 use hyper::status::StatusCode;
 
 use rustc_serialize::Decodable;
-use rustc_serialize::json::{Decoder, Json};
+use rustc_serialize::json::{Decoder, Json, ToJson};
 
 use Client;
-use error::EsError;
-use util::StrJoin;
+use date_math::encode_index_name;
+use error::{EsError, ResponseError};
+use util::{percent_encode, StrJoin};
+
+use self::common::Options;
+use self::search::highlight::HighlightResult;
 
 // Specific operations
 #[macro_use]
 mod common;
 
+pub mod bulk;
+pub mod cat;
+pub mod count;
 pub mod delete;
+pub mod explain;
 pub mod get;
 pub mod index;
+pub mod index_admin;
+pub mod index_diff;
+pub mod mget;
+pub mod mlt;
+pub mod msearch;
+pub mod percolate;
+pub mod references;
+pub mod script;
 pub mod search;
+pub mod stats;
+pub mod termvectors;
+pub mod update;
 
 // Common utility functions
 
-/// Produces a query string for a URL
+/// Produces a query string for a URL, the values are percent-encoded
 fn format_query_string(options: &[(&str, String)]) -> String {
     let mut st = String::new();
     if options.is_empty() {
@@ -42,18 +65,58 @@ fn format_query_string(options: &[(&str, String)]) -> String {
     }
     st.push_str("?");
     st.push_str(&options.iter().map(|&(ref k, ref v)| {
-        format!("{}={}", k, v)
+        format!("{}={}", k, percent_encode(v))
     }).join("&"));
     st
 }
 
+/// Options which configure the `q` parameter, and the equivalent fields of a
+/// `query_string` query
+const QUERY_STRING_OPTIONS: [(&'static str, &'static str); 7] = [
+    ("q",                        "query"),
+    ("df",                       "default_field"),
+    ("analyzer",                 "analyzer"),
+    ("default_operator",         "default_operator"),
+    ("lenient",                  "lenient"),
+    ("analyze_wildcard",         "analyze_wildcard"),
+    ("lowercase_expanded_terms", "lowercase_expanded_terms")
+];
+
+/// For when a URI would be too long: converts the `q` parameter, and those
+/// options which modify it, into the equivalent `query_string` query for a
+/// request body.  Returns the body and the remaining options.
+fn query_string_body<'a>(options: &Options<'a>) -> (Json, Options<'a>) {
+    let mut query_string = BTreeMap::new();
+    let mut remaining = Options::new();
+    for &(k, ref v) in options {
+        match QUERY_STRING_OPTIONS.iter().find(|&&(opt, _)| opt == k) {
+            Some(&(_, field)) => {
+                let value = match &v[..] {
+                    "true"  => Json::Boolean(true),
+                    "false" => Json::Boolean(false),
+                    _       => Json::String(v.clone())
+                };
+                query_string.insert(field.to_string(), value);
+            },
+            None              => remaining.push((k, v.clone()))
+        }
+    }
+    let mut body = BTreeMap::new();
+    if !query_string.is_empty() {
+        let mut query = BTreeMap::new();
+        query.insert("query_string".to_string(), Json::Object(query_string));
+        body.insert("query".to_string(), Json::Object(query));
+    }
+    (Json::Object(body), remaining)
+}
+
 /// A repeating convention in the ElasticSearch REST API is parameters that can
-/// take multiple values
+/// take multiple values.  Date-math index names are encoded, see `date_math`.
 fn format_multi(parts: &[&str]) -> String {
     if parts.is_empty() {
         return "_all".to_string()
     } else {
-        parts.iter().join(",")
+        parts.iter().map(|part| encode_index_name(part)).join(",")
     }
 }
 
@@ -85,7 +148,7 @@ impl<'a, 'b> RefreshOperation<'a, 'b> {
         }
     }
 
-    pub fn with_indexes(&'b mut self, indexes: &'b [&'b str]) -> &'b mut Self {
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
         self.indexes = indexes;
         self
     }
@@ -95,8 +158,8 @@ impl<'a, 'b> RefreshOperation<'a, 'b> {
                           format_multi(&self.indexes));
         let (status_code, result) = try!(self.client.post_op(&url));
         match status_code {
-            StatusCode::Ok => Ok(RefreshResult::from(&result.unwrap())),
-            _              => Err(EsError::EsError(format!("Unexpected status: {}", status_code)))
+            StatusCode::Ok => RefreshResult::from_json(&result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
         }
     }
 }
@@ -104,18 +167,326 @@ impl<'a, 'b> RefreshOperation<'a, 'b> {
 
 // Results
 
+/// A fallible conversion from the JSON of a response, all results implement
+/// this.  For compatibility they also implement `From<&Json>`, which panics if
+/// the JSON is not as expected.
+pub trait FromJson: Sized {
+    fn from_json(r: &Json) -> Result<Self, EsError>;
+}
+
 // Result helpers
 
+/// A field which must be present
+fn find_json<'a>(r: &'a Json, field: &str) -> Result<&'a Json, EsError> {
+    r.find(field).ok_or_else(|| EsError::EsError(format!("Missing field: {}", field)))
+}
+
+/// A field which must be present and an array
+fn find_json_array<'a>(r: &'a Json, field: &str) -> Result<&'a Vec<Json>, EsError> {
+    try!(find_json(r, field))
+        .as_array()
+        .ok_or_else(|| EsError::EsError(format!("Not an array: {}", field)))
+}
+
+/// The error for a response with an unexpected status, this is the error
+/// reported by ElasticSearch if there is one
+fn unexpected_status(request_id:  &str,
+                     status_code: StatusCode,
+                     result:      Option<Json>) -> EsError {
+    match result.and_then(|r| ResponseError::from_json(request_id, status_code.to_u16(), &r)) {
+        Some(error) => EsError::from(error),
+        None        => EsError::EsError(format!("[{}] Unexpected status: {}",
+                                                request_id,
+                                                status_code))
+    }
+}
+
+/// A numeric meta-field (e.g. `_ttl`) from the `fields` of a document.  Unlike
+/// other fields these are a single value rather than an array, but either is
+/// accepted.
+fn meta_field_i64(fields: Option<&Json>, field: &str) -> Option<i64> {
+    fields.and_then(|fields| fields.find(field)).and_then(|value| match *value {
+        Json::Array(ref values) => values.first().and_then(|v| v.as_i64()),
+        _                       => value.as_i64()
+    })
+}
+
+/// A string meta-field (e.g. `_routing`) of a document.  ElasticSearch 2.x
+/// onwards gives these alongside the `_id`, earlier versions only in the
+/// `fields` if requested.
+fn meta_field_string(r: &Json, field: &str) -> Option<String> {
+    r.find(field)
+        .or_else(|| r.find("fields").and_then(|fields| fields.find(field)))
+        .and_then(|value| match *value {
+            Json::Array(ref values) => values.first(),
+            _                       => Some(value)
+        })
+        .and_then(|value| value.as_string())
+        .map(|value| value.to_string())
+}
+
+/// The remaining time-to-live of a document, from its `_ttl` field.  This is
+/// zero if the document has expired but not yet been purged.
+fn ttl_from_fields(fields: Option<&Json>) -> Option<Duration> {
+    meta_field_i64(fields, "_ttl").map(|ttl| Duration::from_millis(cmp::max(ttl, 0) as u64))
+}
+
+/// The `_timestamp` of a document, in milliseconds since the epoch
+fn timestamp_from_fields(fields: Option<&Json>) -> Option<i64> {
+    meta_field_i64(fields, "_timestamp")
+}
+
+/// Fields with no highlights are simply absent
+fn highlight_result_from(r: &Json) -> Option<HighlightResult> {
+    r.as_object().map(|fields| {
+        fields.iter().filter_map(|(field, fragments)| {
+            fragments.as_array().map(|fragments| {
+                (field.clone(),
+                 fragments.iter()
+                 .filter_map(|f| f.as_string())
+                 .map(|f| f.to_string())
+                 .collect())
+            })
+        }).collect()
+    })
+}
+
 fn decode_json<T: Decodable>(doc: Json) -> Result<T, EsError> {
     Ok(try!(Decodable::decode(&mut Decoder::new(doc))))
 }
 
+/// The reason given in an error response from ElasticSearch.  Older versions
+/// give the error as a string, newer versions as an object with a `reason`.
+fn error_reason(r: Option<&Json>) -> Option<String> {
+    r.and_then(|r| r.find("error")).and_then(|e| {
+        match *e {
+            Json::String(ref reason) => Some(reason.clone()),
+            _                        => e.find("reason")
+                .and_then(|reason| reason.as_string())
+                .map(|reason| reason.to_string())
+        }
+    })
+}
+
+/// The details of a failure on an individual shard
+#[derive(Debug, Clone)]
+pub struct ShardFailure {
+    pub index:       Option<String>,
+    pub shard:       Option<i64>,
+    pub node:        Option<String>,
+
+    /// The status, a number in ElasticSearch 1.x (e.g. `400`), and a name in
+    /// later versions (e.g. `BAD_REQUEST`) which give it
+    pub status:      Option<String>,
+    pub reason:      String,
+
+    /// The type of the exception, e.g. `query_parsing_exception`, not given
+    /// by ElasticSearch 1.x
+    pub reason_type: Option<String>
+}
+
+/// The reason is a String in ElasticSearch 1.x, but an object in later
+/// versions
+impl FromJson for ShardFailure {
+    fn from_json(r: &Json) -> Result<ShardFailure, EsError> {
+        let reason = match r.find("reason") {
+            Some(&Json::String(ref reason)) => reason.clone(),
+            Some(reason)                    => match reason.find("reason") {
+                Some(&Json::String(ref inner)) => inner.clone(),
+                _                              => reason.to_string()
+            },
+            None                            => "Unknown".to_string()
+        };
+        Ok(ShardFailure {
+            index:       r.find("index").and_then(|i| i.as_string()).map(|i| i.to_string()),
+            shard:       r.find("shard").and_then(|s| s.as_i64()),
+            node:        r.find("node").and_then(|n| n.as_string()).map(|n| n.to_string()),
+            status:      match r.find("status") {
+                Some(&Json::String(ref status)) => Some(status.clone()),
+                Some(status)                    => status.as_i64().map(|s| s.to_string()),
+                None                            => None
+            },
+            reason:      reason,
+            reason_type: r.find_path(&["reason", "type"])
+                .and_then(|t| t.as_string())
+                .map(|t| t.to_string())
+        })
+    }
+}
+
+from_json_panicking!(ShardFailure);
+
 /// Shared struct for operations that include counts of success/failed shards
-#[derive(Debug, RustcDecodable)]
+#[derive(Debug)]
 pub struct ShardCountResult {
     pub total:      i64,
     pub successful: i64,
-    pub failed:     i64
+    pub failed:     i64,
+    pub failures:   Vec<ShardFailure>
+}
+
+impl ShardCountResult {
+    /// Converts any failed shards into an error
+    fn check_failures(&self) -> Result<(), EsError> {
+        if self.failed > 0 {
+            Err(EsError::ShardFailureError(self.failures.clone()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The `failures` are only present if there were any
+impl FromJson for ShardCountResult {
+    fn from_json(r: &Json) -> Result<ShardCountResult, EsError> {
+        let failures = match r.find("failures").and_then(|f| f.as_array()) {
+            Some(failures) => try!(failures.iter()
+                                   .map(|f| ShardFailure::from_json(f))
+                                   .collect::<Result<Vec<_>, _>>()),
+            None           => Vec::new()
+        };
+        Ok(ShardCountResult {
+            total:      get_json_i64!(r, "total"),
+            successful: get_json_i64!(r, "successful"),
+            failed:     get_json_i64!(r, "failed"),
+            failures:   failures
+        })
+    }
+}
+
+from_json_panicking!(ShardCountResult);
+
+/// The outcome of an operation which writes (or deletes) a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOutcome {
+    Created,
+    Updated,
+    Noop,
+    Deleted,
+    NotFound,
+
+    /// Nothing was written as the document did not exist, see
+    /// `DeleteOperation::with_if_exists`
+    Skipped
+}
+
+impl WriteOutcome {
+    fn from_str(s: &str) -> Option<WriteOutcome> {
+        match s {
+            "created"   => Some(WriteOutcome::Created),
+            "updated"   => Some(WriteOutcome::Updated),
+            "noop"      => Some(WriteOutcome::Noop),
+            "deleted"   => Some(WriteOutcome::Deleted),
+            "not_found" => Some(WriteOutcome::NotFound),
+            _           => None
+        }
+    }
+
+    /// Newer versions of ElasticSearch report the outcome in a `result` field,
+    /// older versions only have a boolean flag (e.g. `created` or `found`)
+    /// which is used as a fallback.
+    fn from_json(r: &Json,
+                 flag: &str,
+                 if_set: WriteOutcome,
+                 if_not_set: WriteOutcome) -> Result<WriteOutcome, EsError> {
+        match r.find("result").and_then(|res| res.as_string()) {
+            Some(res) => WriteOutcome::from_str(res).ok_or_else(|| {
+                EsError::EsError(format!("Unknown result: {}", res))
+            }),
+            None      => match r.find(flag).and_then(|f| f.as_boolean()) {
+                Some(true) => Ok(if_set),
+                _          => Ok(if_not_set)
+            }
+        }
+    }
+}
+
+/// A unit of time, of a `Timeout`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeUnit {
+    Millis,
+    Seconds,
+    Minutes,
+    Hours
+}
+
+impl ToString for TimeUnit {
+    fn to_string(&self) -> String {
+        match *self {
+            TimeUnit::Millis  => "ms",
+            TimeUnit::Seconds => "s",
+            TimeUnit::Minutes => "m",
+            TimeUnit::Hours   => "h"
+        }.to_string()
+    }
+}
+
+/// A timeout, as given to ElasticSearch, e.g. `10ms`.  This is made from an
+/// amount and a `TimeUnit`, or from a `Duration`.  A string can also be
+/// converted, it is sent unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timeout(String);
+
+impl Timeout {
+    pub fn new(amount: u64, unit: TimeUnit) -> Timeout {
+        Timeout(format!("{}{}", amount, unit.to_string()))
+    }
+}
+
+impl ToString for Timeout {
+    fn to_string(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl ToJson for Timeout {
+    fn to_json(&self) -> Json {
+        Json::String(self.0.clone())
+    }
+}
+
+impl<'a> From<&'a str> for Timeout {
+    fn from(timeout: &'a str) -> Timeout {
+        Timeout(timeout.to_string())
+    }
+}
+
+impl From<String> for Timeout {
+    fn from(timeout: String) -> Timeout {
+        Timeout(timeout)
+    }
+}
+
+/// For compatibility with `with_timeout(&"10s")`, as taken when timeouts were
+/// untyped options
+impl<'a, 'b> From<&'a &'b str> for Timeout {
+    fn from(timeout: &'a &'b str) -> Timeout {
+        Timeout(timeout.to_string())
+    }
+}
+
+impl<'a> From<&'a String> for Timeout {
+    fn from(timeout: &'a String) -> Timeout {
+        Timeout(timeout.clone())
+    }
+}
+
+/// In the largest unit in which the duration is exact, any fraction of a
+/// millisecond is rounded up
+impl From<Duration> for Timeout {
+    fn from(duration: Duration) -> Timeout {
+        let millis = duration.as_secs() * 1000 +
+            (duration.subsec_nanos() as u64 + 999999) / 1000000;
+        let units = [(3600000, TimeUnit::Hours),
+                     (60000, TimeUnit::Minutes),
+                     (1000, TimeUnit::Seconds)];
+        for &(unit_millis, unit) in units.iter() {
+            if millis > 0 && millis % unit_millis == 0 {
+                return Timeout::new(millis / unit_millis, unit);
+            }
+        }
+        Timeout::new(millis, TimeUnit::Millis)
+    }
 }
 
 /// Result of a refresh request
@@ -123,10 +494,12 @@ pub struct RefreshResult {
     pub shards: ShardCountResult
 }
 
-impl<'a> From<&'a Json> for RefreshResult {
-    fn from(r: &'a Json) -> RefreshResult {
-        RefreshResult {
-            shards: decode_json(r.find("_shards").unwrap().clone()).unwrap()
-        }
+impl FromJson for RefreshResult {
+    fn from_json(r: &Json) -> Result<RefreshResult, EsError> {
+        Ok(RefreshResult {
+            shards: try!(ShardCountResult::from_json(try!(find_json(r, "_shards"))))
+        })
     }
 }
+
+from_json_panicking!(RefreshResult);
@@ -0,0 +1,176 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Implementation of the Multi Search API, several searches sent in a single
+//! request.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::{EsError, ResponseError};
+use ::util::StrJoin;
+use super::common::Options;
+use super::find_json_array;
+use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+use super::search::{SearchQueryOperationBody, SearchResult, SearchType};
+
+/// A single search within a multi-search
+struct MultiSearchItem<'b> {
+    indexes:     &'b [&'b str],
+    doc_types:   &'b [&'b str],
+
+    /// The search type of this search, rather than that of every search
+    search_type: Option<SearchType>,
+    body:        SearchQueryOperationBody<'b>
+}
+
+/// Multi Search API, each search is performed independently, so one failing
+/// does not affect the others
+pub struct MultiSearchOperation<'a, 'b> {
+    /// The HTTP client
+    client:      &'a mut Client,
+
+    /// The searches, in the order the results will be returned
+    searches:    Vec<MultiSearchItem<'b>>,
+
+    /// The search type of every search, unless given for a search
+    search_type: Option<String>,
+
+    /// Optional options
+    options:     Options<'b>
+}
+
+impl<'a, 'b> MultiSearchOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> MultiSearchOperation<'a, 'b> {
+        MultiSearchOperation {
+            client:      client,
+            searches:    Vec::new(),
+            search_type: None,
+            options:     Options::new()
+        }
+    }
+
+    /// Add a search of the given indexes and types, either of which may be
+    /// empty to search all of them
+    pub fn with_search(&mut self,
+                       indexes:   &'b [&'b str],
+                       doc_types: &'b [&'b str],
+                       body:      SearchQueryOperationBody<'b>) -> &mut Self {
+        self.searches.push(MultiSearchItem {
+            indexes:     indexes,
+            doc_types:   doc_types,
+            search_type: None,
+            body:        body
+        });
+        self
+    }
+
+    /// Add a search with its own search type, overriding any given by
+    /// `with_search_type`
+    pub fn with_search_of_type(&mut self,
+                               indexes:     &'b [&'b str],
+                               doc_types:   &'b [&'b str],
+                               search_type: SearchType,
+                               body:        SearchQueryOperationBody<'b>) -> &mut Self {
+        self.searches.push(MultiSearchItem {
+            indexes:     indexes,
+            doc_types:   doc_types,
+            search_type: Some(search_type),
+            body:        body
+        });
+        self
+    }
+
+    /// The search type of every search, e.g. `SearchType::Count`
+    pub fn with_search_type(&mut self, search_type: SearchType) -> &mut Self {
+        self.search_type = Some(search_type.to_string());
+        self
+    }
+
+    /// A search type not in `SearchType`
+    pub fn with_search_type_str(&mut self, search_type: &'b str) -> &mut Self {
+        self.search_type = Some(search_type.to_string());
+        self
+    }
+
+    /// The newline-delimited body, a header line then a body line for each
+    /// search
+    fn body(&self) -> String {
+        let mut body = String::new();
+        for search in self.searches.iter() {
+            let mut header = BTreeMap::new();
+            if !search.indexes.is_empty() {
+                header.insert("index".to_string(), search.indexes.iter().join(",").to_json());
+            }
+            if !search.doc_types.is_empty() {
+                header.insert("type".to_string(), search.doc_types.iter().join(",").to_json());
+            }
+            let search_type = search.search_type
+                .map(|search_type| search_type.to_string())
+                .or(self.search_type.clone());
+            optional_add!(header, search_type, "search_type");
+            body.push_str(&Json::Object(header).to_string());
+            body.push_str("\n");
+            body.push_str(&search.body.to_json().to_string());
+            body.push_str("\n");
+        }
+        body
+    }
+
+    pub fn send(&mut self) -> Result<MultiSearchResult, EsError> {
+        let body = self.body();
+        let url = format!("/_msearch{}", format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_raw_body_op(&url, &body, true));
+        debug!(target: "rs_es::msearch", "[{}] Multi-search result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => MultiSearchResult::from_responses(self.client.request_id(),
+                                                                &result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The results of a multi-search, in the same order as the searches
+pub struct MultiSearchResult {
+    pub responses: Vec<Result<SearchResult, EsError>>
+}
+
+impl MultiSearchResult {
+    /// Parses a multi-search response, an error in any one search is an error
+    /// for that search only
+    pub fn from_responses(request_id: &str, r: &Json) -> Result<MultiSearchResult, EsError> {
+        let responses = try!(find_json_array(r, "responses"));
+        Ok(MultiSearchResult {
+            responses: responses.iter().map(|response| {
+                // ElasticSearch 1.x does not give the status of each search
+                let status = response.find("status")
+                    .and_then(|s| s.as_u64())
+                    .unwrap_or(500) as u16;
+                match ResponseError::from_json(request_id, status, response) {
+                    Some(error) => Err(EsError::from(error)),
+                    None        => SearchResult::from_json(response)
+                }
+            }).collect()
+        })
+    }
+}
@@ -0,0 +1,419 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Percolation: finding which of the queries registered in an index match a
+//! document.
+//!
+//! Queries are registered by indexing them, as a document with a `query`
+//! field, into the `.percolator` type of an index.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::{EsError, ResponseError};
+use ::query::Query;
+use super::common::Options;
+use super::error_reason;
+use super::find_json;
+use super::find_json_array;
+use super::format_query_string;
+use super::highlight_result_from;
+use super::search::highlight::{Highlight, HighlightResult};
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+
+/// The body of a percolation, shared by `PercolateOperation` and
+/// `PercolateAction`
+struct PercolateBody<'b> {
+    /// The document
+    doc:          Option<Json>,
+
+    /// A query to filter, or score, the registered queries by
+    query:        Option<&'b Query>,
+
+    /// The maximum number of matches
+    size:         Option<u64>,
+
+    /// Whether to score the matches, by the `query`
+    track_scores: Option<bool>,
+
+    /// The highlighting of the document by each match
+    highlight:    Option<&'b Highlight>
+}
+
+impl<'b> PercolateBody<'b> {
+    fn new() -> PercolateBody<'b> {
+        PercolateBody {
+            doc:          None,
+            query:        None,
+            size:         None,
+            track_scores: None,
+            highlight:    None
+        }
+    }
+}
+
+impl<'b> ToJson for PercolateBody<'b> {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        optional_add!(d, self.doc, "doc");
+        optional_add!(d, self.query, "query");
+        optional_add!(d, self.size, "size");
+        optional_add!(d, self.track_scores, "track_scores");
+        optional_add!(d, self.highlight, "highlight");
+        Json::Object(d)
+    }
+}
+
+/// Percolate API, which of the registered queries match a document.  The
+/// matches can be scored (`with_track_scores` and a `with_query`) and
+/// highlighted, showing which part of the document each query matched.
+pub struct PercolateOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index
+    index:    &'b str,
+
+    /// The type of the document
+    doc_type: &'b str,
+
+    /// The ID of an existing document, rather than the `doc` of the body
+    id:       Option<&'b str>,
+
+    /// The document, query, size, etc.
+    body:     PercolateBody<'b>,
+
+    /// Optional options
+    options:  Options<'b>
+}
+
+impl<'a, 'b> PercolateOperation<'a, 'b> {
+    pub fn new(client:   &'a mut Client,
+               index:    &'b str,
+               doc_type: &'b str) -> PercolateOperation<'a, 'b> {
+        PercolateOperation {
+            client:   client,
+            index:    index,
+            doc_type: doc_type,
+            id:       None,
+            body:     PercolateBody::new(),
+            options:  Options::new()
+        }
+    }
+
+    pub fn with_doc<T: ToJson>(&mut self, doc: &T) -> &mut Self {
+        self.body.doc = Some(doc.to_json());
+        self.id = None;
+        self
+    }
+
+    /// Percolate an existing document, by ID, rather than one given with
+    /// `with_doc`
+    pub fn with_id(&mut self, id: &'b str) -> &mut Self {
+        self.id = Some(id);
+        self.body.doc = None;
+        self
+    }
+
+    /// Only match the registered queries whose documents match this query
+    pub fn with_query(&mut self, query: &'b Query) -> &mut Self {
+        self.body.query = Some(query);
+        self
+    }
+
+    pub fn with_size(&mut self, size: u64) -> &mut Self {
+        self.body.size = Some(size);
+        self
+    }
+
+    /// Score each match by the query given with `with_query`, the matches are
+    /// then sorted by score
+    pub fn with_track_scores(&mut self, track_scores: bool) -> &mut Self {
+        self.body.track_scores = Some(track_scores);
+        self
+    }
+
+    /// Highlight the document by each of the matching queries, this requires
+    /// `with_size`
+    pub fn with_highlight(&mut self, highlight: &'b Highlight) -> &mut Self {
+        self.body.highlight = Some(highlight);
+        self
+    }
+
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+
+    pub fn send(&mut self) -> Result<PercolateResult, EsError> {
+        if self.body.doc.is_none() && self.id.is_none() {
+            return Err(EsError::EsError("A percolate must have either a doc or an id"
+                                        .to_string()))
+        }
+        let id = self.id;
+        let url = format!("/{}/{}/{}_percolate{}",
+                          self.index,
+                          self.doc_type,
+                          id.map(|id| format!("{}/", id)).unwrap_or(String::new()),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url,
+                                                                       &self.body.to_json()));
+        debug!(target: "rs_es::percolate", "[{}] Percolate result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => PercolateResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(match id {
+                Some(id) => EsError::DocumentMissingError(id.to_string()),
+                None     => EsError::IndexMissingError(
+                    error_reason(result.as_ref()).unwrap_or(self.index.to_string()))
+            }),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// A single percolation of a multi-percolate, either giving the matches or
+/// only counting them
+pub struct PercolateAction<'b> {
+    /// Whether only the number of matches is wanted
+    count:    bool,
+    index:    &'b str,
+    doc_type: &'b str,
+
+    /// The ID of an existing document, rather than the `doc` of the body
+    id:       Option<&'b str>,
+    routing:  Option<&'b str>,
+    body:     PercolateBody<'b>
+}
+
+impl<'b> PercolateAction<'b> {
+    /// A percolation giving the matching queries
+    pub fn percolate(index: &'b str, doc_type: &'b str) -> PercolateAction<'b> {
+        PercolateAction::new(false, index, doc_type)
+    }
+
+    /// A percolation giving only the number of matching queries
+    pub fn count(index: &'b str, doc_type: &'b str) -> PercolateAction<'b> {
+        PercolateAction::new(true, index, doc_type)
+    }
+
+    fn new(count: bool, index: &'b str, doc_type: &'b str) -> PercolateAction<'b> {
+        PercolateAction {
+            count:    count,
+            index:    index,
+            doc_type: doc_type,
+            id:       None,
+            routing:  None,
+            body:     PercolateBody::new()
+        }
+    }
+
+    pub fn with_doc<T: ToJson>(mut self, doc: &T) -> Self {
+        self.body.doc = Some(doc.to_json());
+        self.id = None;
+        self
+    }
+
+    /// Percolate an existing document, by ID, rather than one given with
+    /// `with_doc`
+    pub fn with_id(mut self, id: &'b str) -> Self {
+        self.id = Some(id);
+        self.body.doc = None;
+        self
+    }
+
+    pub fn with_routing(mut self, routing: &'b str) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
+    /// Only match the registered queries whose documents match this query
+    pub fn with_query(mut self, query: &'b Query) -> Self {
+        self.body.query = Some(query);
+        self
+    }
+
+    pub fn with_size(mut self, size: u64) -> Self {
+        self.body.size = Some(size);
+        self
+    }
+
+    /// Score each match by the query given with `with_query`
+    pub fn with_track_scores(mut self, track_scores: bool) -> Self {
+        self.body.track_scores = Some(track_scores);
+        self
+    }
+
+    /// Highlight the document by each of the matching queries, this requires
+    /// `with_size`
+    pub fn with_highlight(mut self, highlight: &'b Highlight) -> Self {
+        self.body.highlight = Some(highlight);
+        self
+    }
+
+    /// The header line, naming the document, and the body line
+    fn add_to(&self, actions: &mut String) {
+        let mut header = BTreeMap::new();
+        header.insert("index".to_string(), self.index.to_json());
+        header.insert("type".to_string(), self.doc_type.to_json());
+        optional_add!(header, self.id, "id");
+        optional_add!(header, self.routing, "routing");
+        let mut d = BTreeMap::new();
+        d.insert((if self.count { "count" } else { "percolate" }).to_string(),
+                 Json::Object(header));
+        actions.push_str(&Json::Object(d).to_string());
+        actions.push_str("\n");
+        actions.push_str(&self.body.to_json().to_string());
+        actions.push_str("\n");
+    }
+}
+
+/// Multi Percolate API, several percolations in a single request.  Each is
+/// performed independently, so one failing does not affect the others.
+pub struct MultiPercolateOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The percolations, in the order the results will be returned
+    actions: &'b [PercolateAction<'b>],
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> MultiPercolateOperation<'a, 'b> {
+    pub fn new(client:  &'a mut Client,
+               actions: &'b [PercolateAction<'b>]) -> MultiPercolateOperation<'a, 'b> {
+        MultiPercolateOperation {
+            client:  client,
+            actions: actions,
+            options: Options::new()
+        }
+    }
+
+    add_option!(with_preference, "preference");
+
+    pub fn send(&mut self) -> Result<MultiPercolateResult, EsError> {
+        let mut body = String::new();
+        for action in self.actions.iter() {
+            if action.body.doc.is_none() && action.id.is_none() {
+                return Err(EsError::EsError("A percolate must have either a doc or an id"
+                                            .to_string()))
+            }
+            action.add_to(&mut body);
+        }
+        let url = format!("/_mpercolate{}", format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_raw_body_op(&url, &body, true));
+        debug!(target: "rs_es::percolate", "[{}] Multi-percolate result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => MultiPercolateResult::from_responses(self.client.request_id(),
+                                                                   &result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// A registered query which matched the document
+#[derive(Debug)]
+pub struct PercolateMatch {
+    pub index:     String,
+
+    /// The ID of the registered query
+    pub id:        String,
+
+    /// Only present if scores were requested with `with_track_scores`
+    pub score:     Option<f64>,
+
+    /// The highlighted fragments of the document, only present if
+    /// highlighting was requested
+    pub highlight: Option<HighlightResult>
+}
+
+impl FromJson for PercolateMatch {
+    fn from_json(r: &Json) -> Result<PercolateMatch, EsError> {
+        Ok(PercolateMatch {
+            index:     get_json_string!(r, "_index"),
+            id:        get_json_string!(r, "_id"),
+            score:     r.find("_score").and_then(|s| s.as_f64()),
+            highlight: r.find("highlight").and_then(|h| highlight_result_from(h))
+        })
+    }
+}
+
+from_json_panicking!(PercolateMatch);
+
+/// The result of a percolate request
+#[derive(Debug)]
+pub struct PercolateResult {
+    pub took:    u64,
+    pub shards:  ShardCountResult,
+
+    /// The number of matches, which may be more than the `matches` if a size
+    /// was given
+    pub total:   u64,
+    pub matches: Vec<PercolateMatch>
+}
+
+impl FromJson for PercolateResult {
+    fn from_json(r: &Json) -> Result<PercolateResult, EsError> {
+        let matches = match r.find("matches") {
+            Some(_) => try!(try!(find_json_array(r, "matches")).iter()
+                            .map(|m| PercolateMatch::from_json(m))
+                            .collect()),
+            None    => Vec::new()
+        };
+        Ok(PercolateResult {
+            took:    get_json_i64!(r, "took") as u64,
+            shards:  try!(ShardCountResult::from_json(try!(find_json(r, "_shards")))),
+            total:   get_json_i64!(r, "total") as u64,
+            matches: matches
+        })
+    }
+}
+
+from_json_panicking!(PercolateResult);
+
+/// The results of a multi-percolate, in the same order as the percolations.
+/// The result of a count has no `matches`.
+pub struct MultiPercolateResult {
+    pub responses: Vec<Result<PercolateResult, EsError>>
+}
+
+impl MultiPercolateResult {
+    /// Parses a multi-percolate response, an error in any one percolation is
+    /// an error for that percolation only
+    pub fn from_responses(request_id: &str, r: &Json)
+                          -> Result<MultiPercolateResult, EsError> {
+        let responses = try!(find_json_array(r, "responses"));
+        Ok(MultiPercolateResult {
+            responses: responses.iter().map(|response| {
+                // The status of each percolation is not given
+                let status = response.find("status")
+                    .and_then(|s| s.as_u64())
+                    .unwrap_or(500) as u16;
+                match ResponseError::from_json(request_id, status, response) {
+                    Some(error) => Err(EsError::from(error)),
+                    None        => PercolateResult::from_json(response)
+                }
+            }).collect()
+        })
+    }
+}
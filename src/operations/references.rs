@@ -0,0 +1,256 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Checking that the documents and scripts referred to by a query exist.
+//!
+//! A terms lookup, an indexed shape or an indexed script which does not exist
+//! is not an error, ElasticSearch just matches nothing.  `verify_references`
+//! finds every such reference in a query and checks each exists, at the cost
+//! of one extra request for all the documents and one for each script.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use super::find_json_array;
+use super::unexpected_status;
+
+/// The index of an indexed shape if none is given
+const DEFAULT_SHAPE_INDEX: &'static str = "shapes";
+
+/// The field of an indexed shape if none is given
+const DEFAULT_SHAPE_PATH: &'static str = "shape";
+
+/// The language of an indexed script if none is given
+const DEFAULT_SCRIPT_LANG: &'static str = "groovy";
+
+/// A document referred to by a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentReference {
+    pub index:    String,
+    pub doc_type: String,
+    pub id:       String,
+    pub routing:  Option<String>,
+
+    /// The field of the document which is used
+    pub path:     String
+}
+
+impl DocumentReference {
+    fn from_json(r: &Json, default_index: Option<&str>, default_path: Option<&str>)
+                 -> Option<DocumentReference> {
+        let get = |key| r.find(key).and_then(|v| v.as_string());
+        let id = match r.find("id") {
+            Some(&Json::String(ref id)) => id.clone(),
+            Some(&Json::I64(id))        => id.to_string(),
+            Some(&Json::U64(id))        => id.to_string(),
+            _                           => return None
+        };
+        match (get("index").or(default_index), get("type"), get("path").or(default_path)) {
+            (Some(index), Some(doc_type), Some(path)) => Some(DocumentReference {
+                index:    index.to_string(),
+                doc_type: doc_type.to_string(),
+                id:       id,
+                routing:  get("routing").map(|routing| routing.to_string()),
+                path:     path.to_string()
+            }),
+            _                                         => None
+        }
+    }
+}
+
+/// Something referred to by a query, which must exist for the query to match
+/// anything
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reference {
+    /// The document holding the terms of a terms lookup
+    TermsLookup(DocumentReference),
+
+    /// The document holding the shape of a geo-shape query or filter
+    IndexedShape(DocumentReference),
+
+    /// A script stored in the cluster, `lang` is the default scripting
+    /// language if `None`
+    IndexedScript {
+        lang: Option<String>,
+        id:   String
+    }
+}
+
+/// Finds every reference in a query (or filter, or any other part of a
+/// search), in the order they appear, each only once
+pub fn find_references(r: &Json) -> Vec<Reference> {
+    let mut references = Vec::new();
+    add_references(r, &mut references);
+    references
+}
+
+fn add_reference(reference: Reference, references: &mut Vec<Reference>) {
+    if !references.contains(&reference) {
+        references.push(reference);
+    }
+}
+
+fn add_references(r: &Json, references: &mut Vec<Reference>) {
+    match *r {
+        Json::Object(ref d) => {
+            for (key, value) in d.iter() {
+                match (&key[..], value) {
+                    ("terms", &Json::Object(ref terms))        => {
+                        for lookup in terms.values() {
+                            if let Some(doc) = DocumentReference::from_json(lookup, None, None) {
+                                add_reference(Reference::TermsLookup(doc), references);
+                            }
+                        }
+                    },
+                    ("indexed_shape", shape)                   => {
+                        if let Some(doc) = DocumentReference::from_json(shape,
+                                                                        Some(DEFAULT_SHAPE_INDEX),
+                                                                        Some(DEFAULT_SHAPE_PATH)) {
+                            add_reference(Reference::IndexedShape(doc), references);
+                        }
+                    },
+                    // ElasticSearch 1.x, alongside the `lang` and `params`
+                    ("script_id", &Json::String(ref id))       => {
+                        add_reference(Reference::IndexedScript {
+                            lang: d.get("lang").and_then(|l| l.as_string()).map(|l| l.to_string()),
+                            id:   id.clone()
+                        }, references);
+                    },
+                    ("script", &Json::Object(ref script))      => {
+                        if let Some(id) = script.get("id").and_then(|id| id.as_string()) {
+                            add_reference(Reference::IndexedScript {
+                                lang: script.get("lang")
+                                    .and_then(|l| l.as_string())
+                                    .map(|l| l.to_string()),
+                                id:   id.to_string()
+                            }, references);
+                        }
+                    },
+                    _                                          => ()
+                }
+                add_references(value, references);
+            }
+        },
+        Json::Array(ref values) => {
+            for value in values.iter() {
+                add_references(value, references);
+            }
+        },
+        _                       => ()
+    }
+}
+
+/// The references of a query, and which of them do not exist
+#[derive(Debug)]
+pub struct ReferenceReport {
+    /// Every reference, in the order they appear in the query
+    pub references: Vec<Reference>,
+
+    /// The references which do not exist
+    pub missing:    Vec<Reference>
+}
+
+impl ReferenceReport {
+    /// Whether every reference exists
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Checks that every document and indexed script referred to by the query
+/// exists.  A document in an index which does not exist is missing.
+pub fn verify_references<Q: ToJson>(client: &mut Client, query: &Q)
+                                    -> Result<ReferenceReport, EsError> {
+    let references = find_references(&query.to_json());
+    let mut missing = Vec::new();
+
+    let docs:Vec<(&Reference, &DocumentReference)> = references.iter()
+        .filter_map(|reference| match *reference {
+            Reference::TermsLookup(ref doc)  => Some((reference, doc)),
+            Reference::IndexedShape(ref doc) => Some((reference, doc)),
+            _                                => None
+        })
+        .collect();
+    if !docs.is_empty() {
+        let body:Vec<Json> = docs.iter()
+            .map(|&(_, doc)| {
+                let mut d = BTreeMap::new();
+                d.insert("_index".to_string(), doc.index.to_json());
+                d.insert("_type".to_string(), doc.doc_type.to_json());
+                d.insert("_id".to_string(), doc.id.to_json());
+                optional_add!(d, doc.routing, "_routing");
+                Json::Object(d)
+            })
+            .collect();
+        let mut d = BTreeMap::new();
+        d.insert("docs".to_string(), Json::Array(body));
+        let (status_code, result) = try!(client.post_read_body_op("/_mget?_source=false",
+                                                                  &Json::Object(d)));
+        debug!(target: "rs_es::references", "[{}] Document references result status: {}",
+               client.request_id(), status_code);
+        let result = match status_code {
+            StatusCode::Ok => result.unwrap(),
+            _              => return Err(unexpected_status(client.request_id(), status_code, result))
+        };
+        let found = try!(find_json_array(&result, "docs"));
+        if found.len() != docs.len() {
+            return Err(EsError::EsError(format!("Expected {} documents, got {}",
+                                                docs.len(),
+                                                found.len())));
+        }
+        for (&(reference, _), doc) in docs.iter().zip(found.iter()) {
+            if !doc.find("found").and_then(|f| f.as_boolean()).unwrap_or(false) {
+                missing.push(reference.clone());
+            }
+        }
+    }
+
+    for reference in references.iter() {
+        if let Reference::IndexedScript { ref lang, ref id } = *reference {
+            let url = format!("/_scripts/{}/{}",
+                              lang.as_ref().map(|l| &l[..]).unwrap_or(DEFAULT_SCRIPT_LANG),
+                              id);
+            let (status_code, result) = try!(client.get_op(&url));
+            debug!(target: "rs_es::references", "[{}] Script reference result status: {}",
+                   client.request_id(), status_code);
+            match status_code {
+                StatusCode::Ok       => {
+                    let found = result.as_ref()
+                        .and_then(|r| r.find("found"))
+                        .and_then(|f| f.as_boolean())
+                        .unwrap_or(true);
+                    if !found {
+                        missing.push(reference.clone());
+                    }
+                },
+                StatusCode::NotFound => missing.push(reference.clone()),
+                _                    => {
+                    return Err(unexpected_status(client.request_id(), status_code, result))
+                }
+            }
+        }
+    }
+
+    Ok(ReferenceReport {
+        references: references,
+        missing:    missing
+    })
+}
@@ -0,0 +1,151 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Scripts, as used by several APIs, e.g. script fields of a search and
+//! scripted updates.
+
+use std::collections::BTreeMap;
+
+use rustc_serialize::json::{Json, ToJson};
+
+/// Where the source of a script is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptKind {
+    /// The source is given in full
+    Inline,
+
+    /// The source is the ID of a script indexed in the cluster
+    Indexed,
+
+    /// The source is the name of a file in the `config/scripts` directory of
+    /// each node
+    File
+}
+
+/// How a script is given in a request body
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScriptFormat {
+    /// The script, its `lang` and `params` alongside any other keys, as in
+    /// ElasticSearch 1.x, e.g. `{"script": "...", "params": {...}}`
+    Flat,
+
+    /// A `script` object with the script, its `lang` and `params`, as in
+    /// ElasticSearch 2.x onwards, e.g.
+    /// `{"script": {"inline": "...", "params": {...}}}`
+    Nested
+}
+
+/// A script, with optional parameters.  If `lang` is not specified the
+/// default scripting language is used.
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub source: String,
+    pub kind:   ScriptKind,
+    pub lang:   Option<String>,
+    pub params: BTreeMap<String, Json>
+}
+
+impl Script {
+    /// An inline script
+    pub fn new<S: Into<String>>(source: S) -> Script {
+        Script::of_kind(source, ScriptKind::Inline)
+    }
+
+    /// A script indexed in the cluster, by ID
+    pub fn indexed<S: Into<String>>(id: S) -> Script {
+        Script::of_kind(id, ScriptKind::Indexed)
+    }
+
+    /// A script in a file on each node, by name without the extension
+    pub fn file<S: Into<String>>(name: S) -> Script {
+        Script::of_kind(name, ScriptKind::File)
+    }
+
+    pub fn of_kind<S: Into<String>>(source: S, kind: ScriptKind) -> Script {
+        Script {
+            source: source.into(),
+            kind:   kind,
+            lang:   None,
+            params: BTreeMap::new()
+        }
+    }
+
+    pub fn with_lang<S: Into<String>>(mut self, lang: S) -> Script {
+        self.lang = Some(lang.into());
+        self
+    }
+
+    pub fn with_params(mut self, params: BTreeMap<String, Json>) -> Script {
+        self.params = params;
+        self
+    }
+
+    pub fn with_param<S: Into<String>, T: ToJson>(mut self, name: S, value: &T) -> Script {
+        self.params.insert(name.into(), value.to_json());
+        self
+    }
+
+    /// Adds the script to an object, where the script is given by the
+    /// `script` (or `script_id` or `script_file`), `lang` and `params` keys
+    /// alongside any others, e.g. the options of a scripted update
+    pub fn add_to(&self, d: &mut BTreeMap<String, Json>) {
+        let key = match self.kind {
+            ScriptKind::Inline  => "script",
+            ScriptKind::Indexed => "script_id",
+            ScriptKind::File    => "script_file"
+        };
+        d.insert(key.to_string(), self.source.to_json());
+        self.add_lang_and_params(d);
+    }
+
+    /// Adds the script to an object in the given format
+    pub fn add_to_as(&self, d: &mut BTreeMap<String, Json>, format: ScriptFormat) {
+        match format {
+            ScriptFormat::Flat   => self.add_to(d),
+            ScriptFormat::Nested => {
+                d.insert("script".to_string(), self.to_nested_json());
+            }
+        }
+    }
+
+    /// The script as an object, as given by ElasticSearch 2.x onwards
+    pub fn to_nested_json(&self) -> Json {
+        let key = match self.kind {
+            ScriptKind::Inline  => "inline",
+            ScriptKind::Indexed => "id",
+            ScriptKind::File    => "file"
+        };
+        let mut d = BTreeMap::new();
+        d.insert(key.to_string(), self.source.to_json());
+        self.add_lang_and_params(&mut d);
+        Json::Object(d)
+    }
+
+    fn add_lang_and_params(&self, d: &mut BTreeMap<String, Json>) {
+        optional_add!(d, self.lang, "lang");
+        if !self.params.is_empty() {
+            d.insert("params".to_string(), self.params.to_json());
+        }
+    }
+}
+
+impl ToJson for Script {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        self.add_to(&mut d);
+        Json::Object(d)
+    }
+}
@@ -0,0 +1,339 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Aggregations, and their results.
+//!
+//! Aggregations are requested with `SearchQueryOperation::with_aggs`, see
+//! `Aggregations`.
+//!
+//! A bucket aggregation on a high-cardinality field can return hundreds of
+//! thousands of buckets, so the aggregations are kept as the JSON of the
+//! response and each bucket is only parsed as it is iterated over.  A limit on
+//! the number of buckets can be set with `Client::with_max_buckets`.
+
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::slice;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::error::EsError;
+
+/// The aggregations to request with a search, keyed by name
+pub struct Aggregations {
+    aggs: BTreeMap<String, Aggregation>
+}
+
+impl Aggregations {
+    pub fn new() -> Aggregations {
+        Aggregations {
+            aggs: BTreeMap::new()
+        }
+    }
+
+    /// Add an aggregation, its result will have the same name
+    pub fn with<S: Into<String>>(mut self, name: S, agg: Aggregation) -> Aggregations {
+        self.aggs.insert(name.into(), agg);
+        self
+    }
+}
+
+impl ToJson for Aggregations {
+    fn to_json(&self) -> Json {
+        self.aggs.to_json()
+    }
+}
+
+/// A single aggregation
+pub enum Aggregation {
+    Terms(TermsAggregation),
+
+    /// A single-value metric of a field
+    Metric(Metric, String)
+}
+
+impl Aggregation {
+    /// Convenience function for a metric aggregation
+    pub fn metric<S: Into<String>>(metric: Metric, field: S) -> Aggregation {
+        Aggregation::Metric(metric, field.into())
+    }
+}
+
+impl ToJson for Aggregation {
+    fn to_json(&self) -> Json {
+        match self {
+            &Aggregation::Terms(ref terms)             => terms.to_json(),
+            &Aggregation::Metric(ref metric, ref field) => {
+                let mut inner = BTreeMap::new();
+                inner.insert("field".to_string(), field.to_json());
+                let mut d = BTreeMap::new();
+                d.insert(metric.to_string(), Json::Object(inner));
+                Json::Object(d)
+            }
+        }
+    }
+}
+
+/// The single-value metric aggregations, the result of each is available as
+/// `AggregationResult::value`
+pub enum Metric {
+    Min,
+    Max,
+    Avg,
+    Sum,
+    ValueCount,
+
+    /// The approximate number of distinct values
+    Cardinality
+}
+
+impl ToString for Metric {
+    fn to_string(&self) -> String {
+        match self {
+            &Metric::Min         => "min",
+            &Metric::Max         => "max",
+            &Metric::Avg         => "avg",
+            &Metric::Sum         => "sum",
+            &Metric::ValueCount  => "value_count",
+            &Metric::Cardinality => "cardinality"
+        }.to_string()
+    }
+}
+
+/// A terms aggregation, a bucket for each of the most frequent values of a
+/// field, the key of each bucket is the value
+pub struct TermsAggregation {
+    field:         String,
+    size:          Option<u64>,
+    min_doc_count: Option<u64>,
+    aggs:          Option<Aggregations>
+}
+
+impl TermsAggregation {
+    pub fn new<S: Into<String>>(field: S) -> TermsAggregation {
+        TermsAggregation {
+            field:         field.into(),
+            size:          None,
+            min_doc_count: None,
+            aggs:          None
+        }
+    }
+
+    /// The number of buckets, by default 10
+    pub fn with_size(mut self, size: u64) -> TermsAggregation {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_min_doc_count(mut self, min_doc_count: u64) -> TermsAggregation {
+        self.min_doc_count = Some(min_doc_count);
+        self
+    }
+
+    /// Sub-aggregations, calculated for each bucket, see `Bucket::get`
+    pub fn with_aggs(mut self, aggs: Aggregations) -> TermsAggregation {
+        self.aggs = Some(aggs);
+        self
+    }
+
+    pub fn build(self) -> Aggregation {
+        Aggregation::Terms(self)
+    }
+}
+
+impl ToJson for TermsAggregation {
+    fn to_json(&self) -> Json {
+        let mut inner = BTreeMap::new();
+        inner.insert("field".to_string(), self.field.to_json());
+        optional_add!(inner, self.size, "size");
+        optional_add!(inner, self.min_doc_count, "min_doc_count");
+        let mut d = BTreeMap::new();
+        d.insert("terms".to_string(), Json::Object(inner));
+        optional_add!(d, self.aggs, "aggs");
+        Json::Object(d)
+    }
+}
+
+/// The aggregations of a search, keyed by name
+#[derive(Debug)]
+pub struct AggregationsResult {
+    aggs: Json
+}
+
+impl AggregationsResult {
+    /// From the `aggregations` of a search response
+    pub fn new(aggs: Json) -> AggregationsResult {
+        AggregationsResult {
+            aggs: aggs
+        }
+    }
+
+    /// The aggregation with the given name, if any
+    pub fn get(&self, name: &str) -> Option<AggregationResult> {
+        self.aggs.find(name).map(|agg| AggregationResult { raw: agg })
+    }
+
+    /// The aggregations as returned by ElasticSearch
+    pub fn raw(&self) -> &Json {
+        &self.aggs
+    }
+}
+
+/// A single aggregation, either a metric (e.g. `avg`) with a value, or a
+/// bucket aggregation (e.g. `terms`) with buckets
+#[derive(Debug, Clone, Copy)]
+pub struct AggregationResult<'a> {
+    raw: &'a Json
+}
+
+impl<'a> AggregationResult<'a> {
+    /// The aggregation as returned by ElasticSearch
+    pub fn raw(&self) -> &'a Json {
+        self.raw
+    }
+
+    /// The value of a single-value metric aggregation, `None` for other
+    /// aggregations or if there were no values
+    pub fn value(&self) -> Option<f64> {
+        self.raw.find("value").and_then(|v| v.as_f64())
+    }
+
+    /// The number of buckets, without parsing them, `None` if this is not a
+    /// bucket aggregation
+    pub fn bucket_count(&self) -> Option<usize> {
+        match self.raw.find("buckets") {
+            Some(&Json::Array(ref buckets))  => Some(buckets.len()),
+            Some(&Json::Object(ref buckets)) => Some(buckets.len()),
+            _                                => None
+        }
+    }
+
+    /// The buckets, each is parsed as it is iterated over.  This is empty if
+    /// this is not a bucket aggregation.
+    pub fn buckets(&self) -> Buckets<'a> {
+        Buckets {
+            inner: match self.raw.find("buckets") {
+                Some(&Json::Array(ref buckets))  => BucketsInner::List(buckets.iter()),
+                Some(&Json::Object(ref buckets)) => BucketsInner::Keyed(buckets.iter()),
+                _                                => BucketsInner::List([].iter())
+            }
+        }
+    }
+}
+
+/// A bucket of a bucket aggregation
+#[derive(Debug)]
+pub struct Bucket<'a> {
+    /// The key of the bucket, a string or a number depending on the field
+    pub key:           Json,
+
+    /// The key formatted as a string, e.g. for dates
+    pub key_as_string: Option<String>,
+
+    pub doc_count:     i64,
+
+    raw:               &'a Json
+}
+
+impl<'a> Bucket<'a> {
+    fn from_json(key: Option<&str>, r: &'a Json) -> Result<Bucket<'a>, EsError> {
+        let key = match (r.find("key"), key) {
+            (Some(key), _)    => key.clone(),
+            (None, Some(key)) => Json::String(key.to_string()),
+            (None, None)      => return Err(EsError::EsError("Bucket has no key".to_string()))
+        };
+        Ok(Bucket {
+            key:           key,
+            key_as_string: r.find("key_as_string")
+                .and_then(|k| k.as_string())
+                .map(|k| k.to_string()),
+            doc_count:     get_json_i64!(r, "doc_count"),
+            raw:           r
+        })
+    }
+
+    /// A sub-aggregation of this bucket
+    pub fn get(&self, name: &str) -> Option<AggregationResult<'a>> {
+        self.raw.find(name).map(|agg| AggregationResult { raw: agg })
+    }
+
+    /// The bucket as returned by ElasticSearch
+    pub fn raw(&self) -> &'a Json {
+        self.raw
+    }
+}
+
+/// Buckets are returned as an array, or an object keyed by the key of each
+/// bucket if the aggregation is `keyed`
+enum BucketsInner<'a> {
+    List(slice::Iter<'a, Json>),
+    Keyed(btree_map::Iter<'a, String, Json>)
+}
+
+/// An iterator over the buckets of an aggregation, parsing each in turn
+pub struct Buckets<'a> {
+    inner: BucketsInner<'a>
+}
+
+impl<'a> Iterator for Buckets<'a> {
+    type Item = Result<Bucket<'a>, EsError>;
+
+    fn next(&mut self) -> Option<Result<Bucket<'a>, EsError>> {
+        match self.inner {
+            BucketsInner::List(ref mut buckets)  => {
+                buckets.next().map(|b| Bucket::from_json(None, b))
+            },
+            BucketsInner::Keyed(ref mut buckets) => {
+                buckets.next().map(|(k, b)| Bucket::from_json(Some(k), b))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.inner {
+            BucketsInner::List(ref buckets)  => buckets.size_hint(),
+            BucketsInner::Keyed(ref buckets) => buckets.size_hint()
+        }
+    }
+}
+
+/// Checks that no aggregation, including sub-aggregations, has more than
+/// `max_buckets` buckets, without parsing any buckets
+pub fn check_bucket_counts(aggs: &Json, max_buckets: usize) -> Result<(), EsError> {
+    if let Some(aggs) = aggs.as_object() {
+        for (name, agg) in aggs.iter() {
+            let agg = AggregationResult { raw: agg };
+            match agg.bucket_count() {
+                Some(count) if count > max_buckets => {
+                    return Err(EsError::TooManyBucketsError(name.clone(), count))
+                },
+                Some(_)                            => {
+                    let buckets:Vec<&Json> = match agg.raw.find("buckets") {
+                        Some(&Json::Array(ref buckets))  => buckets.iter().collect(),
+                        Some(&Json::Object(ref buckets)) => buckets.values().collect(),
+                        _                                => Vec::new()
+                    };
+                    for bucket in buckets {
+                        try!(check_bucket_counts(bucket, max_buckets));
+                    }
+                },
+                None                               => try!(check_bucket_counts(agg.raw,
+                                                                                max_buckets))
+            }
+        }
+    }
+    Ok(())
+}
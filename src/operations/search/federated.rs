@@ -0,0 +1,244 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Searching several indexes separately, each with its own boost, and merging
+//! the results by score.
+//!
+//! Each index is searched independently as part of a single multi-search
+//! request, so a failure of one index does not prevent results being returned
+//! from the others.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use ::query::Query;
+use ::util::StrJoin;
+use super::super::error_reason;
+use super::super::find_json;
+use super::super::unexpected_status;
+use super::super::FromJson;
+use super::super::ShardCountResult;
+use super::SearchHitsHitsResult;
+use super::SearchHitsResult;
+
+/// Search several indexes, merging the hits by score, where the score of each
+/// hit is multiplied by the boost of the index it came from.
+pub struct FederatedSearch<'a, 'b> {
+    /// The HTTP client
+    client:    &'a mut Client,
+
+    /// The indexes to search, with their boosts, in order of precedence
+    indexes:   Vec<(&'b str, f64)>,
+
+    /// The types to which the query applies
+    doc_types: &'b [&'b str],
+
+    /// The query, if not specified all documents match
+    query:     Option<&'b Query>,
+
+    /// From, of the merged results
+    from:      i64,
+
+    /// Size, of the merged results
+    size:      i64
+}
+
+impl<'a, 'b> FederatedSearch<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> FederatedSearch<'a, 'b> {
+        FederatedSearch {
+            client:    client,
+            indexes:   Vec::new(),
+            doc_types: &[],
+            query:     None,
+            from:      0,
+            size:      10
+        }
+    }
+
+    /// Add an index to be searched, `boost` must be positive.  Where hits from
+    /// different indexes have the same boosted score, the index added first
+    /// takes precedence.
+    pub fn with_index(&mut self, index: &'b str, boost: f64) -> &mut Self {
+        self.indexes.push((index, boost));
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    pub fn with_query(&mut self, query: &'b Query) -> &mut Self {
+        self.query = Some(query);
+        self
+    }
+
+    pub fn with_from(&mut self, from: i64) -> &mut Self {
+        self.from = from;
+        self
+    }
+
+    pub fn with_size(&mut self, size: i64) -> &mut Self {
+        self.size = size;
+        self
+    }
+
+    /// The multi-search request, each index is asked for enough hits to fill
+    /// the requested page of merged results.
+    fn body(&self) -> String {
+        let mut search = BTreeMap::new();
+        search.insert("from".to_string(), 0i64.to_json());
+        search.insert("size".to_string(), (self.from + self.size).to_json());
+        optional_add!(search, self.query, "query");
+        let search = Json::Object(search).to_string();
+
+        let mut body = String::new();
+        for &(index, _) in self.indexes.iter() {
+            let mut header = BTreeMap::new();
+            header.insert("index".to_string(), index.to_json());
+            if !self.doc_types.is_empty() {
+                header.insert("type".to_string(), self.doc_types.iter().join(",").to_json());
+            }
+            body.push_str(&Json::Object(header).to_string());
+            body.push_str("\n");
+            body.push_str(&search);
+            body.push_str("\n");
+        }
+        body
+    }
+
+    pub fn send(&mut self) -> Result<FederatedSearchResult, EsError> {
+        let body = self.body();
+        let (status_code, result) = try!(self.client.post_raw_body_op("/_msearch", &body, true));
+        debug!(target: "rs_es::search", "[{}] Federated search result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => FederatedSearchResult::from_responses(&self.indexes,
+                                                                    &result.unwrap(),
+                                                                    self.from,
+                                                                    self.size),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// A hit from a federated search
+#[derive(Debug)]
+pub struct FederatedHit {
+    /// The score of the hit multiplied by the boost of its index
+    pub boosted_score: f64,
+
+    /// The hit itself, with its original score
+    pub hit:           SearchHitsHitsResult
+}
+
+/// The merged results of a federated search
+pub struct FederatedSearchResult {
+    /// The total number of matching documents, across all successful indexes
+    pub total:  i64,
+
+    /// The requested page of the merged hits
+    pub hits:   Vec<FederatedHit>,
+
+    /// The shard counts, including any shard failures, of each index that was
+    /// successfully searched
+    pub shards: BTreeMap<String, ShardCountResult>,
+
+    /// The error of each index that could not be searched at all
+    pub errors: BTreeMap<String, String>
+}
+
+impl FederatedSearchResult {
+    /// Merges the responses to a multi-search, where `indexes` is the index
+    /// and boost of each search in the same order as the responses.
+    pub fn from_responses(indexes: &[(&str, f64)],
+                          r:       &Json,
+                          from:    i64,
+                          size:    i64) -> Result<FederatedSearchResult, EsError> {
+        let responses = match r.find("responses").and_then(|r| r.as_array()) {
+            Some(responses) if responses.len() == indexes.len() => responses,
+            _ => return Err(EsError::EsError("Unexpected multi-search response".to_string()))
+        };
+
+        let mut total = 0;
+        let mut lists = Vec::with_capacity(indexes.len());
+        let mut shards = BTreeMap::new();
+        let mut errors = BTreeMap::new();
+        for (&(index, boost), response) in indexes.iter().zip(responses.iter()) {
+            if let Some(reason) = error_reason(Some(response)) {
+                errors.insert(index.to_string(), reason);
+                continue;
+            }
+            shards.insert(index.to_string(),
+                          try!(ShardCountResult::from_json(try!(find_json(response, "_shards")))));
+            let hits = try!(SearchHitsResult::from_json(try!(find_json(response, "hits"))));
+            total += hits.total;
+            lists.push((boost, hits.hits));
+        }
+
+        Ok(FederatedSearchResult {
+            total:  total,
+            hits:   merge_by_score(lists, from, size),
+            shards: shards,
+            errors: errors
+        })
+    }
+}
+
+/// A k-way merge of lists of hits, each already in descending order of score,
+/// returning the page `from`..`from + size`.  Ties are broken by the order of
+/// the lists, then by the order within each list, so the merge is stable.
+fn merge_by_score(lists: Vec<(f64, Vec<SearchHitsHitsResult>)>,
+                  from:  i64,
+                  size:  i64) -> Vec<FederatedHit> {
+    let mut lists:Vec<_> = lists.into_iter()
+        .map(|(boost, hits)| (boost, hits.into_iter().peekable()))
+        .collect();
+    let mut merged = Vec::new();
+    let mut position = 0;
+    while position < from + size {
+        let mut best:Option<(usize, f64)> = None;
+        for (i, &mut (boost, ref mut hits)) in lists.iter_mut().enumerate() {
+            if let Some(hit) = hits.peek() {
+                // A hit without a score, e.g. of a sorted search, ranks last
+                let score = hit.score.unwrap_or(0.0) * boost;
+                best = match best {
+                    Some((_, best_score)) if best_score >= score => best,
+                    _                                           => Some((i, score))
+                };
+            }
+        }
+        match best {
+            Some((i, score)) => {
+                let hit = lists[i].1.next().unwrap();
+                if position >= from {
+                    merged.push(FederatedHit {
+                        boosted_score: score,
+                        hit:           hit
+                    });
+                }
+                position += 1;
+            },
+            None             => break
+        }
+    }
+    merged
+}
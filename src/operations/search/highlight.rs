@@ -0,0 +1,223 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::query::Query;
+
+/// The highlighter implementation to use
+#[derive(Clone)]
+pub enum HighlighterType {
+    Plain,
+    Fvh,
+    Postings
+}
+
+impl ToJson for HighlighterType {
+    fn to_json(&self) -> Json {
+        match self {
+            &HighlighterType::Plain    => "plain",
+            &HighlighterType::Fvh      => "fvh",
+            &HighlighterType::Postings => "postings"
+        }.to_json()
+    }
+}
+
+/// The order in which fragments are returned
+#[derive(Clone)]
+pub enum FragmentOrder {
+    None,
+    Score
+}
+
+impl ToJson for FragmentOrder {
+    fn to_json(&self) -> Json {
+        match self {
+            &FragmentOrder::None  => "none",
+            &FragmentOrder::Score => "score"
+        }.to_json()
+    }
+}
+
+/// How highlighted fragments are encoded
+#[derive(Clone)]
+pub enum Encoder {
+    Default,
+    Html
+}
+
+impl ToJson for Encoder {
+    fn to_json(&self) -> Json {
+        match self {
+            &Encoder::Default => "default",
+            &Encoder::Html    => "html"
+        }.to_json()
+    }
+}
+
+/// Highlight settings, these can be applied either globally or to a specific
+/// field
+#[derive(Clone)]
+pub struct Setting {
+    highlighter_type:    Option<HighlighterType>,
+    fragment_size:       Option<i64>,
+    number_of_fragments: Option<i64>,
+    no_match_size:       Option<i64>,
+    pre_tags:            Option<Vec<String>>,
+    post_tags:           Option<Vec<String>>,
+    order:               Option<FragmentOrder>,
+    require_field_match: Option<bool>,
+    highlight_query:     Option<Query>
+}
+
+impl Setting {
+    pub fn new() -> Setting {
+        Setting {
+            highlighter_type:    None,
+            fragment_size:       None,
+            number_of_fragments: None,
+            no_match_size:       None,
+            pre_tags:            None,
+            post_tags:           None,
+            order:               None,
+            require_field_match: None,
+            highlight_query:     None
+        }
+    }
+
+    pub fn with_type(mut self, highlighter_type: HighlighterType) -> Setting {
+        self.highlighter_type = Some(highlighter_type);
+        self
+    }
+
+    pub fn with_fragment_size(mut self, fragment_size: i64) -> Setting {
+        self.fragment_size = Some(fragment_size);
+        self
+    }
+
+    pub fn with_number_of_fragments(mut self, number_of_fragments: i64) -> Setting {
+        self.number_of_fragments = Some(number_of_fragments);
+        self
+    }
+
+    pub fn with_no_match_size(mut self, no_match_size: i64) -> Setting {
+        self.no_match_size = Some(no_match_size);
+        self
+    }
+
+    pub fn with_tags(mut self, pre_tags: &[&str], post_tags: &[&str]) -> Setting {
+        self.pre_tags = Some(pre_tags.iter().map(|t| t.to_string()).collect());
+        self.post_tags = Some(post_tags.iter().map(|t| t.to_string()).collect());
+        self
+    }
+
+    pub fn with_order(mut self, order: FragmentOrder) -> Setting {
+        self.order = Some(order);
+        self
+    }
+
+    pub fn with_require_field_match(mut self, require_field_match: bool) -> Setting {
+        self.require_field_match = Some(require_field_match);
+        self
+    }
+
+    /// Highlight using a different query to the search query
+    pub fn with_highlight_query(mut self, highlight_query: Query) -> Setting {
+        self.highlight_query = Some(highlight_query);
+        self
+    }
+
+    fn add_to(&self, d: &mut BTreeMap<String, Json>) {
+        optional_add!(d, self.highlighter_type, "type");
+        optional_add!(d, self.fragment_size, "fragment_size");
+        optional_add!(d, self.number_of_fragments, "number_of_fragments");
+        optional_add!(d, self.no_match_size, "no_match_size");
+        optional_add!(d, self.pre_tags, "pre_tags");
+        optional_add!(d, self.post_tags, "post_tags");
+        optional_add!(d, self.order, "order");
+        optional_add!(d, self.require_field_match, "require_field_match");
+        optional_add!(d, self.highlight_query, "highlight_query");
+    }
+}
+
+impl ToJson for Setting {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        self.add_to(&mut d);
+        Json::Object(d)
+    }
+}
+
+/// The `highlight` section of a search request
+#[derive(Clone)]
+pub struct Highlight {
+    global:  Setting,
+    encoder: Option<Encoder>,
+    fields:  BTreeMap<String, Setting>
+}
+
+impl Highlight {
+    pub fn new() -> Highlight {
+        Highlight {
+            global:  Setting::new(),
+            encoder: None,
+            fields:  BTreeMap::new()
+        }
+    }
+
+    /// Settings which apply to all fields, unless overridden per field
+    pub fn with_global(mut self, global: Setting) -> Highlight {
+        self.global = global;
+        self
+    }
+
+    pub fn with_encoder(mut self, encoder: Encoder) -> Highlight {
+        self.encoder = Some(encoder);
+        self
+    }
+
+    /// Highlight a field, using the global settings
+    pub fn with_field<S: Into<String>>(self, field: S) -> Highlight {
+        self.with_field_setting(field, Setting::new())
+    }
+
+    /// Highlight several fields, using the global settings
+    pub fn with_fields(self, fields: &[&str]) -> Highlight {
+        fields.iter().fold(self, |highlight, field| highlight.with_field(*field))
+    }
+
+    /// Highlight a field with field-specific settings
+    pub fn with_field_setting<S: Into<String>>(mut self, field: S, setting: Setting) -> Highlight {
+        self.fields.insert(field.into(), setting);
+        self
+    }
+}
+
+impl ToJson for Highlight {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        self.global.add_to(&mut d);
+        optional_add!(d, self.encoder, "encoder");
+        d.insert("fields".to_string(), self.fields.to_json());
+        Json::Object(d)
+    }
+}
+
+/// The highlighted fragments of a hit, keyed by field name.  Fields with no
+/// highlights are absent.
+pub type HighlightResult = HashMap<String, Vec<String>>;
@@ -0,0 +1,2498 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::slice;
+use std::time::Duration;
+use std::vec;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use ::query::{DistanceUnit, JsonVal, Location, Query};
+use ::util::StrJoin;
+use super::common::Options;
+use super::decode_json;
+use super::error_reason;
+use super::explain::Explanation;
+use super::find_json;
+use super::find_json_array;
+use super::format_indexes_and_types;
+use super::format_query_string;
+use super::highlight_result_from;
+use super::meta_field_string;
+use super::query_string_body;
+use super::script::Script;
+use super::timestamp_from_fields;
+use super::ttl_from_fields;
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+use super::Timeout;
+
+use self::aggregations::{check_bucket_counts, Aggregations, AggregationsResult};
+use self::highlight::{Highlight, HighlightResult};
+use self::suggest::{suggest_result_from, Suggest, SuggestResult};
+
+pub mod aggregations;
+pub mod federated;
+pub mod highlight;
+pub mod suggest;
+
+/// Defaults for options of every search made by a client, see
+/// `Client::with_default_search_options`.
+///
+/// Each search starts from these defaults.  An option set on the search
+/// itself (e.g. `with_timeout`) takes the place of the default, rather than
+/// being sent as well, and `clear_timeout` etc. removes both the default and
+/// any value set on the search.
+#[derive(Debug, Clone, Default)]
+pub struct DefaultSearchOptions {
+    pub preference:      Option<String>,
+    pub timeout:         Option<String>,
+    pub terminate_after: Option<i64>
+}
+
+impl DefaultSearchOptions {
+    pub fn new() -> DefaultSearchOptions {
+        DefaultSearchOptions::default()
+    }
+
+    pub fn with_preference<S: Into<String>>(mut self, preference: S) -> DefaultSearchOptions {
+        self.preference = Some(preference.into());
+        self
+    }
+
+    pub fn with_timeout<T: Into<Timeout>>(mut self, timeout: T) -> DefaultSearchOptions {
+        let timeout: Timeout = timeout.into();
+        self.timeout = Some(timeout.to_string());
+        self
+    }
+
+    pub fn with_terminate_after(mut self, terminate_after: i64) -> DefaultSearchOptions {
+        self.terminate_after = Some(terminate_after);
+        self
+    }
+}
+
+/// Adds a default to the options of a search, unless the search has its own
+/// value for that option
+fn add_default_option<'b>(options: &mut Options<'b>, name: &'b str, default: Option<String>) {
+    if let Some(default) = default {
+        if !options.iter().any(|&(k, _)| k == name) {
+            options.push((name, default));
+        }
+    }
+}
+
+/// Sets the scroll of a search, replacing any set before
+fn set_scroll(options: &mut Options, keep_alive: &str) {
+    options.retain(|&(k, _)| k != "scroll");
+    options.push(("scroll", keep_alive.to_string()));
+}
+
+/// Search API using a query string
+pub struct SearchURIOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The indexes to which this query applies
+    indexes: &'b [&'b str],
+
+    /// The types to which this query applies
+    doc_types: &'b [&'b str],
+
+    /// Optional options
+    options: Options<'b>,
+
+    /// The client's defaults, less any which have been cleared
+    defaults: DefaultSearchOptions,
+
+    /// Whether to fail if any shards failed
+    fail_on_shard_failure: bool,
+
+    /// What to do with hits without a source, see `send_typed`
+    missing_source: MissingSource
+}
+
+/// Options for the various search_type parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchType {
+    DFSQueryThenFetch,
+    DFSQueryAndFetch,
+    QueryThenFetch,
+    QueryAndFetch,
+
+    /// Only the total and any aggregations, no hits, ElasticSearch 1.x only
+    Count,
+
+    /// A scrolling search which does not sort the hits, the first page has no
+    /// hits.  ElasticSearch 1.x only.
+    Scan
+}
+
+impl ToString for SearchType {
+    fn to_string(&self) -> String {
+        match self {
+            &SearchType::DFSQueryThenFetch => "dfs_query_then_fetch",
+            &SearchType::DFSQueryAndFetch  => "dfs_query_and_fetch",
+            &SearchType::QueryThenFetch    => "query_then_fetch",
+            &SearchType::QueryAndFetch     => "query_and_fetch",
+            &SearchType::Count             => "count",
+            &SearchType::Scan              => "scan"
+        }.to_string()
+    }
+}
+
+/// Which shards, or nodes, a search is run on
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preference {
+    /// Only the primary shards
+    Primary,
+
+    /// The primary shards if available, otherwise replicas
+    PrimaryFirst,
+
+    /// The shards on the node receiving the request if possible
+    Local,
+
+    /// Only the given node, by ID
+    OnlyNode(String),
+
+    /// The given node, by ID, if possible
+    PreferNode(String),
+
+    /// Only the given shards
+    Shards(Vec<u32>),
+
+    /// Any string, e.g. a session ID, so that searches with the same value
+    /// use the same shards and so see consistent results when paging.  It
+    /// must not start with `_`.
+    Custom(String)
+}
+
+impl ToString for Preference {
+    fn to_string(&self) -> String {
+        match self {
+            &Preference::Primary               => "_primary".to_string(),
+            &Preference::PrimaryFirst          => "_primary_first".to_string(),
+            &Preference::Local                 => "_local".to_string(),
+            &Preference::OnlyNode(ref node)    => format!("_only_node:{}", node),
+            &Preference::PreferNode(ref node)  => format!("_prefer_node:{}", node),
+            &Preference::Shards(ref shards)    => {
+                format!("_shards:{}", shards.iter().map(|s| s.to_string()).join(","))
+            },
+            &Preference::Custom(ref custom)    => custom.clone()
+        }
+    }
+}
+
+impl<'a, 'b> SearchURIOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> SearchURIOperation<'a, 'b> {
+        let defaults = client.search_defaults.clone();
+        SearchURIOperation {
+            client:    client,
+            indexes:   &[],
+            doc_types: &[],
+            options:   Options::new(),
+            defaults:  defaults,
+            fail_on_shard_failure: false,
+            missing_source:        MissingSource::Keep
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    pub fn with_query(&mut self, qs: String) -> &mut Self {
+        self.options.push(("q", qs));
+        self
+    }
+
+    add_option!(with_df, "df");
+    add_option!(with_analyzer, "analyzer");
+    add_option!(with_lowercase_expanded_terms, "lowercase_expanded_terms");
+    add_option!(with_analyze_wildcard, "analyze_wildcard");
+    add_option!(with_default_operator, "default_operator");
+    add_option!(with_lenient, "lenient");
+    add_option!(with_explain, "explain");
+    add_option!(with_version, "version");
+    add_option!(with_source, "_source");
+    add_option!(with_sort, "sort");
+    add_option!(with_routing, "routing");
+
+    pub fn with_preference(&mut self, preference: Preference) -> &mut Self {
+        self.with_preference_str(&preference)
+    }
+
+    /// A preference not in `Preference`, replacing any given before
+    pub fn with_preference_str<T: ToString>(&mut self, preference: &T) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "preference");
+        self.options.push(("preference", preference.to_string()));
+        self
+    }
+
+    add_option!(with_track_scores, "track_scores");
+
+    /// See `SearchQueryOperation::with_timeout`, this replaces any timeout
+    /// given before
+    pub fn with_timeout<T: Into<Timeout>>(&mut self, timeout: T) -> &mut Self {
+        let timeout: Timeout = timeout.into();
+        self.options.retain(|&(k, _)| k != "timeout");
+        self.options.push(("timeout", timeout.to_string()));
+        self
+    }
+
+    add_option!(with_terminate_after, "terminate_after");
+    add_option!(with_from, "from");
+    add_option!(with_size, "size");
+
+    pub fn with_search_type(&mut self, search_type: SearchType) -> &mut Self {
+        self.with_search_type_str(&search_type)
+    }
+
+    /// A search type not in `SearchType`, replacing any given before
+    pub fn with_search_type_str<T: ToString>(&mut self, search_type: &T) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "search_type");
+        self.options.push(("search_type", search_type.to_string()));
+        self
+    }
+
+    /// Makes this a scrolling search, see `SearchQueryOperation::with_scroll`
+    pub fn with_scroll(&mut self, keep_alive: &str) -> &mut Self {
+        set_scroll(&mut self.options, keep_alive);
+        self
+    }
+
+    pub fn with_fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    /// Removes the preference, including the client's default
+    pub fn clear_preference(&mut self) -> &mut Self {
+        self.defaults.preference = None;
+        self.options.retain(|&(k, _)| k != "preference");
+        self
+    }
+
+    /// Removes the timeout, including the client's default
+    pub fn clear_timeout(&mut self) -> &mut Self {
+        self.defaults.timeout = None;
+        self.options.retain(|&(k, _)| k != "timeout");
+        self
+    }
+
+    /// Removes the terminate-after limit, including the client's default
+    pub fn clear_terminate_after(&mut self) -> &mut Self {
+        self.defaults.terminate_after = None;
+        self.options.retain(|&(k, _)| k != "terminate_after");
+        self
+    }
+
+    /// By default a search succeeds if some shards failed, the failures are
+    /// reported in the `shards` of the result.  This makes such searches an
+    /// `EsError::ShardFailureError` instead.
+    pub fn with_fail_on_shard_failure(&mut self, fail: bool) -> &mut Self {
+        self.fail_on_shard_failure = fail;
+        self
+    }
+
+    /// What `send_typed` does with hits which have no source, e.g. as the
+    /// source is disabled in the mapping.  By default they are kept, with a
+    /// `source` of `None`.
+    pub fn with_missing_source(&mut self, missing_source: MissingSource) -> &mut Self {
+        self.missing_source = missing_source;
+        self
+    }
+
+    /// Sends the search.  If the URI would be too long, the query is sent as
+    /// an equivalent `query_string` query in the body of the request instead.
+    pub fn send(&mut self) -> Result<SearchResult, EsError> {
+        SearchResult::from_json(&try!(self.send_raw()))
+    }
+
+    /// Sends the search, decoding the source of each hit as `T`, hits without
+    /// a source are handled as given by `with_missing_source`
+    pub fn send_typed<T: Decodable>(&mut self) -> Result<SearchResult<T>, EsError> {
+        SearchResult::decode_with(try!(self.send_raw()), self.missing_source)
+    }
+
+    fn send_raw(&mut self) -> Result<Json, EsError> {
+        let mut options = self.options.clone();
+        add_default_option(&mut options, "preference", self.defaults.preference.clone());
+        add_default_option(&mut options, "timeout", self.defaults.timeout.clone());
+        add_default_option(&mut options,
+                           "terminate_after",
+                           self.defaults.terminate_after.map(|t| t.to_string()));
+
+        let indexes_and_types = format_indexes_and_types(&self.indexes,
+                                                         &self.doc_types);
+        let url = format!("/{}/_search{}",
+                          indexes_and_types,
+                          format_query_string(&options));
+        let (status_code, result) = match self.client.check_uri_length(&url) {
+            Ok(_)  => try!(self.client.get_op(&url)),
+            Err(_) => {
+                let (body, options) = query_string_body(&options);
+                let url = format!("/{}/_search{}",
+                                  indexes_and_types,
+                                  format_query_string(&options));
+                try!(self.client.check_uri_length(&url));
+                try!(self.client.post_read_body_op(&url, &body))
+            }
+        };
+        debug!(target: "rs_es::search", "[{}] Search result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => {
+                let result = result.unwrap();
+                if self.fail_on_shard_failure {
+                    try!(try!(ShardCountResult::from_json(try!(find_json(&result, "_shards"))))
+                         .check_failures());
+                }
+                Ok(result)
+            },
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Which parts of the source document to return with each hit
+pub enum Source {
+    /// No source, e.g. when only `fields` are required
+    Disabled,
+
+    /// Only the given fields, these may include wildcards
+    Fields(Vec<String>),
+
+    /// Only the fields matching `include` (or all fields if empty), except
+    /// those matching `exclude`
+    Filter {
+        include: Vec<String>,
+        exclude: Vec<String>
+    }
+}
+
+impl Source {
+    pub fn fields(fields: &[&str]) -> Source {
+        Source::Fields(fields.iter().map(|f| f.to_string()).collect())
+    }
+
+    pub fn filter(include: &[&str], exclude: &[&str]) -> Source {
+        Source::Filter {
+            include: include.iter().map(|f| f.to_string()).collect(),
+            exclude: exclude.iter().map(|f| f.to_string()).collect()
+        }
+    }
+}
+
+impl ToJson for Source {
+    fn to_json(&self) -> Json {
+        match self {
+            &Source::Disabled                            => false.to_json(),
+            &Source::Fields(ref fields)                  => fields.to_json(),
+            &Source::Filter { ref include, ref exclude } => {
+                let mut d = BTreeMap::new();
+                if !include.is_empty() {
+                    d.insert("include".to_string(), include.to_json());
+                }
+                if !exclude.is_empty() {
+                    d.insert("exclude".to_string(), exclude.to_json());
+                }
+                Json::Object(d)
+            }
+        }
+    }
+}
+
+/// Order of a sort
+pub enum Order {
+    Asc,
+    Desc
+}
+
+impl ToJson for Order {
+    fn to_json(&self) -> Json {
+        match self {
+            &Order::Asc  => "asc",
+            &Order::Desc => "desc"
+        }.to_json()
+    }
+}
+
+/// How to choose a value to sort by for multi-valued fields
+pub enum Mode {
+    Min,
+    Max,
+    Avg,
+    Sum
+}
+
+impl ToJson for Mode {
+    fn to_json(&self) -> Json {
+        match self {
+            &Mode::Min => "min",
+            &Mode::Max => "max",
+            &Mode::Avg => "avg",
+            &Mode::Sum => "sum"
+        }.to_json()
+    }
+}
+
+/// Where documents missing the sort field should be placed
+pub enum Missing {
+    First,
+    Last,
+    Value(JsonVal)
+}
+
+impl ToJson for Missing {
+    fn to_json(&self) -> Json {
+        match self {
+            &Missing::First        => "_first".to_json(),
+            &Missing::Last         => "_last".to_json(),
+            &Missing::Value(ref v) => v.to_json()
+        }
+    }
+}
+
+/// A sort on a specific field
+pub struct SortField {
+    field:         String,
+    order:         Option<Order>,
+    mode:          Option<Mode>,
+    missing:       Option<Missing>,
+    unmapped_type: Option<String>
+}
+
+impl SortField {
+    pub fn new<S: Into<String>>(field: S, order: Option<Order>) -> SortField {
+        SortField {
+            field:         field.into(),
+            order:         order,
+            mode:          None,
+            missing:       None,
+            unmapped_type: None
+        }
+    }
+
+    /// Sort by relevance, the default order is descending
+    pub fn score(order: Option<Order>) -> SortField {
+        SortField::new("_score", order)
+    }
+
+    /// Sort in index order, the cheapest sort, e.g. for a scroll where the
+    /// order does not matter
+    pub fn doc() -> SortField {
+        SortField::new("_doc", None)
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> SortField {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn with_missing(mut self, missing: Missing) -> SortField {
+        self.missing = Some(missing);
+        self
+    }
+
+    pub fn with_unmapped_type<S: Into<String>>(mut self, unmapped_type: S) -> SortField {
+        self.unmapped_type = Some(unmapped_type.into());
+        self
+    }
+
+    pub fn build(self) -> SortBy {
+        SortBy::Field(self)
+    }
+}
+
+impl ToJson for SortField {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        let mut inner = BTreeMap::new();
+        optional_add!(inner, self.order, "order");
+        optional_add!(inner, self.mode, "mode");
+        optional_add!(inner, self.missing, "missing");
+        optional_add!(inner, self.unmapped_type, "unmapped_type");
+        d.insert(self.field.clone(), Json::Object(inner));
+        Json::Object(d)
+    }
+}
+
+/// A sort by distance from a geographic point
+pub struct GeoDistance {
+    field:    String,
+    location: Location,
+    order:    Option<Order>,
+    unit:     Option<DistanceUnit>,
+    mode:     Option<Mode>
+}
+
+impl GeoDistance {
+    pub fn new<S, L>(field: S, location: L) -> GeoDistance
+        where S: Into<String>,
+              L: Into<Location>
+    {
+        GeoDistance {
+            field:    field.into(),
+            location: location.into(),
+            order:    None,
+            unit:     None,
+            mode:     None
+        }
+    }
+
+    pub fn with_order(mut self, order: Order) -> GeoDistance {
+        self.order = Some(order);
+        self
+    }
+
+    pub fn with_unit(mut self, unit: DistanceUnit) -> GeoDistance {
+        self.unit = Some(unit);
+        self
+    }
+
+    pub fn with_mode(mut self, mode: Mode) -> GeoDistance {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn build(self) -> SortBy {
+        SortBy::Distance(self)
+    }
+}
+
+impl ToJson for GeoDistance {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        let mut inner = BTreeMap::new();
+        inner.insert(self.field.clone(), self.location.to_json());
+        optional_add!(inner, self.order, "order");
+        optional_add!(inner, self.unit, "unit", unit, unit.to_string().to_json());
+        optional_add!(inner, self.mode, "mode");
+        d.insert("_geo_distance".to_string(), Json::Object(inner));
+        Json::Object(d)
+    }
+}
+
+/// A single element of a sort
+pub enum SortBy {
+    Field(SortField),
+    Distance(GeoDistance)
+}
+
+impl ToJson for SortBy {
+    fn to_json(&self) -> Json {
+        match self {
+            &SortBy::Field(ref field)       => field.to_json(),
+            &SortBy::Distance(ref distance) => distance.to_json()
+        }
+    }
+}
+
+/// A full sort clause, the elements are in priority order
+pub struct Sort {
+    fields: Vec<SortBy>
+}
+
+impl Sort {
+    pub fn new(fields: Vec<SortBy>) -> Sort {
+        Sort {
+            fields: fields
+        }
+    }
+
+    /// Convenience function for sorting by a single field
+    pub fn field<S: Into<String>>(field: S, order: Order) -> Sort {
+        Sort::new(vec![SortField::new(field, Some(order)).build()])
+    }
+}
+
+impl ToJson for Sort {
+    fn to_json(&self) -> Json {
+        self.fields.to_json()
+    }
+}
+
+/// How the score of the original query and the rescore query are combined
+pub enum RescoreMode {
+    Total,
+    Multiply,
+    Avg,
+    Max,
+    Min
+}
+
+impl ToJson for RescoreMode {
+    fn to_json(&self) -> Json {
+        match self {
+            &RescoreMode::Total    => "total",
+            &RescoreMode::Multiply => "multiply",
+            &RescoreMode::Avg      => "avg",
+            &RescoreMode::Max      => "max",
+            &RescoreMode::Min      => "min"
+        }.to_json()
+    }
+}
+
+/// Rescoring of the top hits of each shard by a second, usually more
+/// expensive, query
+pub struct Rescore {
+    window_size:          Option<u64>,
+    query:                Query,
+    query_weight:         Option<f64>,
+    rescore_query_weight: Option<f64>,
+    score_mode:           Option<RescoreMode>
+}
+
+impl Rescore {
+    pub fn new(query: Query) -> Rescore {
+        Rescore {
+            window_size:          None,
+            query:                query,
+            query_weight:         None,
+            rescore_query_weight: None,
+            score_mode:           None
+        }
+    }
+
+    /// The number of hits rescored on each shard, by default the size of the
+    /// search
+    pub fn with_window_size(mut self, window_size: u64) -> Rescore {
+        self.window_size = Some(window_size);
+        self
+    }
+
+    pub fn with_query_weight(mut self, query_weight: f64) -> Rescore {
+        self.query_weight = Some(query_weight);
+        self
+    }
+
+    pub fn with_rescore_query_weight(mut self, rescore_query_weight: f64) -> Rescore {
+        self.rescore_query_weight = Some(rescore_query_weight);
+        self
+    }
+
+    pub fn with_score_mode(mut self, score_mode: RescoreMode) -> Rescore {
+        self.score_mode = Some(score_mode);
+        self
+    }
+}
+
+impl ToJson for Rescore {
+    fn to_json(&self) -> Json {
+        let mut inner = BTreeMap::new();
+        inner.insert("rescore_query".to_string(), self.query.to_json());
+        optional_add!(inner, self.query_weight, "query_weight");
+        optional_add!(inner, self.rescore_query_weight, "rescore_query_weight");
+        optional_add!(inner, self.score_mode, "score_mode");
+        let mut d = BTreeMap::new();
+        optional_add!(d, self.window_size, "window_size");
+        d.insert("query".to_string(), Json::Object(inner));
+        Json::Object(d)
+    }
+}
+
+/// The body of a search, as used by `SearchQueryOperation`.  This can also be
+/// built separately, e.g. for a multi-search, see `MultiSearchOperation`.
+pub struct SearchQueryOperationBody<'b> {
+    /// The query
+    query: Option<&'b Query>,
+
+    /// Timeout
+    timeout: Option<Timeout>,
+
+    /// From
+    from: i64,
+
+    /// Size
+    size: i64,
+
+    /// Terminate early (marked as experimental in the ES docs)
+    terminate_after: Option<i64>,
+
+    /// Stats groups to which the query belongs
+    stats: Option<Vec<String>>,
+
+    /// Stored fields to return, including meta-fields such as `_ttl`
+    fields: Option<Vec<String>>,
+
+    /// Fields to return the field data of, i.e. the indexed terms
+    fielddata_fields: Option<Vec<String>>,
+
+    /// Fields computed by a script for each hit, keyed by name
+    script_fields: Option<BTreeMap<String, Script>>,
+
+    /// Which parts of the source to return
+    source: Option<Source>,
+
+    /// Parts of the source to return as fields, keyed by name
+    partial_fields: Option<BTreeMap<String, Source>>,
+
+    /// Minimum score to use
+    min_score: Option<f64>,
+
+    /// Sort, if not specified results are sorted by relevance
+    sort: Option<&'b Sort>,
+
+    /// Compute the score of each hit even if sorted by something else
+    track_scores: Option<bool>,
+
+    /// Highlighting
+    highlight: Option<&'b Highlight>,
+
+    /// Explain how each hit's score was computed
+    explain: Option<bool>,
+
+    /// Return the version of each hit
+    version: Option<bool>,
+
+    /// Return the sequence number and primary term of each hit
+    seq_no_primary_term: Option<bool>,
+
+    /// The sort values of the last hit of the previous page
+    search_after: Option<Vec<Json>>,
+
+    /// A filter applied to the hits after aggregations are calculated
+    post_filter: Option<&'b Query>,
+
+    /// Aggregations
+    aggs: Option<&'b Aggregations>,
+
+    /// Rescorers, applied in order
+    rescore: Vec<&'b Rescore>,
+
+    /// Suggestions
+    suggest: Option<&'b Suggest>,
+
+    /// The boost of the score of hits from each index
+    indices_boost: Option<BTreeMap<String, f64>>
+}
+
+impl<'a> ToJson for SearchQueryOperationBody<'a> {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        d.insert("from".to_string(), self.from.to_json());
+        d.insert("size".to_string(), self.size.to_json());
+        optional_add!(d, self.query, "query");
+        optional_add!(d, self.timeout, "timeout");
+        optional_add!(d, self.terminate_after, "terminate_after");
+        optional_add!(d, self.stats, "stats");
+        optional_add!(d, self.fields, "fields");
+        optional_add!(d, self.fielddata_fields, "fielddata_fields");
+        optional_add!(d, self.script_fields, "script_fields");
+        optional_add!(d, self.source, "_source");
+        optional_add!(d, self.partial_fields, "partial_fields");
+        optional_add!(d, self.min_score, "min_score");
+        optional_add!(d, self.sort, "sort");
+        optional_add!(d, self.track_scores, "track_scores");
+        optional_add!(d, self.highlight, "highlight");
+        optional_add!(d, self.explain, "explain");
+        optional_add!(d, self.version, "version");
+        optional_add!(d, self.seq_no_primary_term, "seq_no_primary_term");
+        optional_add!(d, self.search_after, "search_after");
+        optional_add!(d, self.post_filter, "post_filter");
+        optional_add!(d, self.aggs, "aggs");
+        optional_add!(d, self.suggest, "suggest");
+        optional_add!(d, self.indices_boost, "indices_boost");
+        // A single rescorer is sent on its own, several as an array
+        match self.rescore.len() {
+            0 => (),
+            1 => { d.insert("rescore".to_string(), self.rescore[0].to_json()); },
+            _ => {
+                d.insert("rescore".to_string(),
+                         Json::Array(self.rescore.iter().map(|r| r.to_json()).collect()));
+            }
+        }
+        Json::Object(d)
+    }
+}
+
+impl<'b> SearchQueryOperationBody<'b> {
+    pub fn new() -> SearchQueryOperationBody<'b> {
+        SearchQueryOperationBody {
+            query:               None,
+            timeout:             None,
+            from:                0,
+            size:                10,
+            terminate_after:     None,
+            stats:               None,
+            fields:              None,
+            fielddata_fields:    None,
+            script_fields:       None,
+            source:              None,
+            partial_fields:      None,
+            min_score:           None,
+            sort:                None,
+            track_scores:        None,
+            highlight:           None,
+            explain:             None,
+            version:             None,
+            seq_no_primary_term: None,
+            search_after:        None,
+            post_filter:         None,
+            aggs:                None,
+            rescore:             Vec::new(),
+            suggest:             None,
+            indices_boost:       None
+        }
+    }
+
+    pub fn with_query(mut self, query: &'b Query) -> Self {
+        self.query = Some(query);
+        self
+    }
+
+    pub fn with_timeout<T: Into<Timeout>>(mut self, timeout: T) -> Self {
+        self.timeout = Some(timeout.into());
+        self
+    }
+
+    pub fn with_from(mut self, from: i64) -> Self {
+        self.from = from;
+        self
+    }
+
+    pub fn with_size(mut self, size: i64) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_terminate_after(mut self, terminate_after: i64) -> Self {
+        self.terminate_after = Some(terminate_after);
+        self
+    }
+
+    pub fn with_fields(mut self, fields: &[&str]) -> Self {
+        self.fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    pub fn with_fielddata_fields(mut self, fields: &[&str]) -> Self {
+        self.fielddata_fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    pub fn with_script_fields(mut self, script_fields: BTreeMap<String, Script>) -> Self {
+        self.script_fields = script_fields_option(script_fields);
+        self
+    }
+
+    pub fn with_source(mut self, source: Source) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    pub fn with_partial_fields(mut self, name: &str, include: &[&str], exclude: &[&str]) -> Self {
+        add_partial_field(&mut self.partial_fields, name, include, exclude);
+        self
+    }
+
+    pub fn with_min_score(mut self, min_score: f64) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    pub fn with_sort(mut self, sort: &'b Sort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    pub fn with_track_scores(mut self, track_scores: bool) -> Self {
+        self.track_scores = Some(track_scores);
+        self
+    }
+
+    pub fn with_highlight(mut self, highlight: &'b Highlight) -> Self {
+        self.highlight = Some(highlight);
+        self
+    }
+
+    pub fn with_post_filter(mut self, post_filter: &'b Query) -> Self {
+        self.post_filter = Some(post_filter);
+        self
+    }
+
+    pub fn with_aggs(mut self, aggs: &'b Aggregations) -> Self {
+        self.aggs = Some(aggs);
+        self
+    }
+
+    pub fn with_rescore(mut self, rescore: &'b Rescore) -> Self {
+        self.rescore.push(rescore);
+        self
+    }
+
+    pub fn with_suggest(mut self, suggest: &'b Suggest) -> Self {
+        self.suggest = Some(suggest);
+        self
+    }
+
+    pub fn with_version(mut self, version: bool) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn with_indices_boost<I, S>(mut self, boosts: I) -> Self
+        where I: IntoIterator<Item=(S, f64)>, S: Into<String>
+    {
+        self.indices_boost = indices_boost_option(boosts);
+        self
+    }
+}
+
+/// Adds a partial field, the source filtered as by `Source::filter`
+fn add_partial_field(partial_fields: &mut Option<BTreeMap<String, Source>>,
+                     name:           &str,
+                     include:        &[&str],
+                     exclude:        &[&str]) {
+    let filter = Source::filter(include, exclude);
+    match *partial_fields {
+        Some(ref mut partial_fields) => { partial_fields.insert(name.to_string(), filter); },
+        None                         => {
+            let mut fields = BTreeMap::new();
+            fields.insert(name.to_string(), filter);
+            *partial_fields = Some(fields);
+        }
+    }
+}
+
+/// No script fields are omitted from the request entirely
+fn script_fields_option(script_fields: BTreeMap<String, Script>) -> Option<BTreeMap<String, Script>> {
+    if script_fields.is_empty() {
+        None
+    } else {
+        Some(script_fields)
+    }
+}
+
+/// As for script fields, no boosts are omitted from the request
+fn indices_boost_option<I, S>(boosts: I) -> Option<BTreeMap<String, f64>>
+    where I: IntoIterator<Item=(S, f64)>, S: Into<String>
+{
+    let boosts:BTreeMap<String, f64> = boosts.into_iter().map(|(i, b)| (i.into(), b)).collect();
+    if boosts.is_empty() {
+        None
+    } else {
+        Some(boosts)
+    }
+}
+
+/// Search API using a Query DSL body
+pub struct SearchQueryOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The indexes to which this query applies
+    indexes: &'b [&'b str],
+
+    /// The types to which the query applies
+    doc_types: &'b [&'b str],
+
+    /// Optionals
+    options: Options<'b>,
+
+    /// The query body
+    body: SearchQueryOperationBody<'b>,
+
+    /// The client's defaults, less any which have been cleared
+    defaults: DefaultSearchOptions,
+
+    /// Whether to fail if any shards failed
+    fail_on_shard_failure: bool,
+
+    /// What to do with hits without a source, see `send_typed`
+    missing_source: MissingSource
+}
+
+impl <'a, 'b> SearchQueryOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> SearchQueryOperation<'a, 'b> {
+        let defaults = client.search_defaults.clone();
+        SearchQueryOperation {
+            client:    client,
+            indexes:   &[],
+            doc_types: &[],
+            options:   Options::new(),
+            body:      SearchQueryOperationBody::new(),
+            defaults:  defaults,
+            fail_on_shard_failure: false,
+            missing_source:        MissingSource::Keep
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    pub fn with_query(&mut self, query: &'b Query) -> &mut Self {
+        self.body.query = Some(query);
+        self
+    }
+
+    /// How long to wait for the hits from each shard, the hits found so far
+    /// are returned after this, e.g. `Timeout::new(100, TimeUnit::Millis)`,
+    /// a `Duration`, or a string such as `100ms`.  This replaces any timeout
+    /// given before.
+    pub fn with_timeout<T: Into<Timeout>>(&mut self, timeout: T) -> &mut Self {
+        self.body.timeout = Some(timeout.into());
+        self
+    }
+
+    pub fn with_from(&mut self, from: i64) -> &mut Self {
+        self.body.from = from;
+        self
+    }
+
+    pub fn with_size(&mut self, size: i64) -> &mut Self {
+        self.body.size = size;
+        self
+    }
+
+    /// Stop searching each shard once this many documents have been found
+    /// on it, `terminated_early` on the result says whether any did
+    pub fn with_terminate_after(&mut self, terminate_after: i64) -> &mut Self {
+        self.body.terminate_after = Some(terminate_after);
+        self
+    }
+
+    pub fn with_stats<S>(&mut self, stats: &[S]) -> &mut Self
+        where S: ToString
+    {
+        self.body.stats = Some(stats.iter().map(|s| s.to_string()).collect());
+        self
+    }
+
+    /// The stored fields to return for each hit, these are available from the
+    /// `fields` of each hit.  The meta-fields `_ttl` and `_timestamp` are also
+    /// available via `ttl` and `timestamp`.
+    pub fn with_fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.body.fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// The field data of each of the fields, i.e. the terms as indexed, for
+    /// each hit.  These are available from the `fields` of each hit, as for
+    /// stored fields.  ElasticSearch 1.x and 2.x only.
+    pub fn with_fielddata_fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.body.fielddata_fields = Some(fields.iter().map(|f| f.to_string()).collect());
+        self
+    }
+
+    /// Fields computed by a script for each hit, keyed by name.  The values
+    /// are returned in the `fields` of each hit, see
+    /// `SearchHitsHitsResult::field`.
+    pub fn with_script_fields(&mut self, script_fields: BTreeMap<String, Script>) -> &mut Self {
+        self.body.script_fields = script_fields_option(script_fields);
+        self
+    }
+
+    /// Which parts of the source document to return, by default all of it
+    pub fn with_source(&mut self, source: Source) -> &mut Self {
+        self.body.source = Some(source);
+        self
+    }
+
+    /// The parts of the source matching `include` (or all of it if empty),
+    /// except those matching `exclude`, returned as a field of each hit with
+    /// the given name.  This can be called more than once for several partial
+    /// fields.  ElasticSearch 1.x only, see `with_source` for later versions.
+    pub fn with_partial_fields(&mut self,
+                               name:    &str,
+                               include: &[&str],
+                               exclude: &[&str]) -> &mut Self {
+        add_partial_field(&mut self.body.partial_fields, name, include, exclude);
+        self
+    }
+
+    pub fn with_min_score(&mut self, min_score: f64) -> &mut Self {
+        self.body.min_score = Some(min_score);
+        self
+    }
+
+    pub fn with_sort(&mut self, sort: &'b Sort) -> &mut Self {
+        self.body.sort = Some(sort);
+        self
+    }
+
+    /// By default the score of each hit is not computed if the hits are
+    /// sorted by something else, and is `None`.  This computes the scores
+    /// anyway.
+    pub fn with_track_scores(&mut self, track_scores: bool) -> &mut Self {
+        self.body.track_scores = Some(track_scores);
+        self
+    }
+
+    pub fn with_highlight(&mut self, highlight: &'b Highlight) -> &mut Self {
+        self.body.highlight = Some(highlight);
+        self
+    }
+
+    /// Filter the hits, but not any aggregations, e.g. for faceted search
+    pub fn with_post_filter(&mut self, post_filter: &'b Query) -> &mut Self {
+        self.body.post_filter = Some(post_filter);
+        self
+    }
+
+    /// Aggregations, the results are in the `aggs` of the result
+    pub fn with_aggs(&mut self, aggs: &'b Aggregations) -> &mut Self {
+        self.body.aggs = Some(aggs);
+        self
+    }
+
+    /// Rescore the top hits, this can be called more than once to add
+    /// rescorers which are applied in turn
+    pub fn with_rescore(&mut self, rescore: &'b Rescore) -> &mut Self {
+        self.body.rescore.push(rescore);
+        self
+    }
+
+    /// Suggestions, e.g. spelling corrections of the text of the query, the
+    /// results are in the `suggest` of the result
+    pub fn with_suggest(&mut self, suggest: &'b Suggest) -> &mut Self {
+        self.body.suggest = Some(suggest);
+        self
+    }
+
+    /// Multiply the score of hits from each index by a boost, e.g. to favour
+    /// more recent indexes.  The indexes are those searched, see
+    /// `with_indexes`, which can be given in any order.
+    pub fn with_indices_boost<I, S>(&mut self, boosts: I) -> &mut Self
+        where I: IntoIterator<Item=(S, f64)>, S: Into<String>
+    {
+        self.body.indices_boost = indices_boost_option(boosts);
+        self
+    }
+
+    /// Each hit will include the shard and node it came from, and an
+    /// explanation of its score
+    pub fn with_explain(&mut self, explain: bool) -> &mut Self {
+        self.body.explain = Some(explain);
+        self
+    }
+
+    /// By default a search succeeds if some shards failed, the failures are
+    /// reported in the `shards` of the result.  This makes such searches an
+    /// `EsError::ShardFailureError` instead.
+    pub fn with_fail_on_shard_failure(&mut self, fail: bool) -> &mut Self {
+        self.fail_on_shard_failure = fail;
+        self
+    }
+
+    /// What `send_typed` does with hits which have no source, e.g. as the
+    /// source is disabled in the mapping.  By default they are kept, with a
+    /// `source` of `None`.
+    pub fn with_missing_source(&mut self, missing_source: MissingSource) -> &mut Self {
+        self.missing_source = missing_source;
+        self
+    }
+
+    /// Each hit will include the version of the document, e.g. so that it
+    /// can be updated with optimistic concurrency control
+    pub fn with_version(&mut self, version: bool) -> &mut Self {
+        self.body.version = Some(version);
+        self
+    }
+
+    /// Each hit will include the sequence number and primary term of the
+    /// document, this requires ElasticSearch 6.7 or later
+    pub fn with_seq_no_primary_term(&mut self, seq_no_primary_term: bool) -> &mut Self {
+        self.body.seq_no_primary_term = Some(seq_no_primary_term);
+        self
+    }
+
+    /// Return the hits after those with the given sort values, i.e. the `sort`
+    /// of the last hit of the previous page.  This requires a sort which
+    /// uniquely orders documents, sending a search with `search_after` but no
+    /// sort is an error.  If `values` is empty, it is ignored.
+    pub fn with_search_after(&mut self, values: &[Json]) -> &mut Self {
+        self.body.search_after = if values.is_empty() {
+            None
+        } else {
+            Some(values.to_vec())
+        };
+        self
+    }
+
+    add_option!(with_routing, "routing");
+
+    pub fn with_preference(&mut self, preference: Preference) -> &mut Self {
+        self.with_preference_str(&preference)
+    }
+
+    /// A preference not in `Preference`, replacing any given before
+    pub fn with_preference_str<T: ToString>(&mut self, preference: &T) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "preference");
+        self.options.push(("preference", preference.to_string()));
+        self
+    }
+
+    pub fn with_search_type(&mut self, search_type: SearchType) -> &mut Self {
+        self.with_search_type_str(&search_type)
+    }
+
+    /// A search type not in `SearchType`, replacing any given before
+    pub fn with_search_type_str<T: ToString>(&mut self, search_type: &T) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "search_type");
+        self.options.push(("search_type", search_type.to_string()));
+        self
+    }
+
+    add_option!(with_query_cache, "query_cache");
+
+    /// Removes the preference, including the client's default
+    pub fn clear_preference(&mut self) -> &mut Self {
+        self.defaults.preference = None;
+        self.options.retain(|&(k, _)| k != "preference");
+        self
+    }
+
+    /// Removes the timeout, including the client's default
+    pub fn clear_timeout(&mut self) -> &mut Self {
+        self.defaults.timeout = None;
+        self.body.timeout = None;
+        self
+    }
+
+    /// Removes the terminate-after limit, including the client's default
+    pub fn clear_terminate_after(&mut self) -> &mut Self {
+        self.defaults.terminate_after = None;
+        self.body.terminate_after = None;
+        self
+    }
+
+    /// Makes this a scrolling search, `keep_alive` (e.g. `1m`) is how long
+    /// the search is kept open for the next page to be requested.  The next
+    /// page is requested with `Client::scroll` and the `scroll_id` of the
+    /// result.  This replaces any keep-alive given before.
+    pub fn with_scroll(&mut self, keep_alive: &str) -> &mut Self {
+        set_scroll(&mut self.options, keep_alive);
+        self
+    }
+
+    /// Sends the search as a scrolling search, returning an iterator over
+    /// every hit which requests each following page as the previous is
+    /// used.  The size of the search is the size of each page.
+    ///
+    /// This works with a `search_type` of `scan`, for which the first
+    /// response has no hits, only the scroll ID.  The scroll is only sent with
+    /// this search, later searches with this operation do not scroll unless
+    /// `with_scroll` is given.
+    pub fn scan(&mut self, keep_alive: &str) -> Result<ScanIterator, EsError> {
+        let mut options = self.options.clone();
+        set_scroll(&mut options, keep_alive);
+        let result = try!(self.send_with(options));
+        Ok(ScanIterator::new(self.client, result, keep_alive))
+    }
+
+    pub fn send(&mut self) -> Result<SearchResult, EsError> {
+        let options = self.options.clone();
+        self.send_with(options)
+    }
+
+    /// Sends the search, decoding the source of each hit as `T`, hits without
+    /// a source are handled as given by `with_missing_source`
+    pub fn send_typed<T: Decodable>(&mut self) -> Result<SearchResult<T>, EsError> {
+        let options = self.options.clone();
+        SearchResult::decode_with(try!(self.send_raw(options)), self.missing_source)
+    }
+
+    /// Sends the search with the given options rather than those of this
+    /// operation
+    fn send_with(&mut self, options: Options<'b>) -> Result<SearchResult, EsError> {
+        let mut r = try!(self.send_raw(options));
+        // Moved rather than cloned, as there may be a very large number of
+        // buckets
+        let aggs = take_field(&mut r, "aggregations");
+        let mut result = try!(SearchResult::from_json(&r));
+        result.aggs = aggs.map(AggregationsResult::new);
+        Ok(result)
+    }
+
+    /// Whether any document matches the query, which is cheaper than a search
+    /// or count as each shard stops at the first match.  Only the query and
+    /// `min_score` of the body are sent, along with any routing and
+    /// preference.  ElasticSearch 1.4 to 2.x only.
+    pub fn exists(&mut self) -> Result<bool, EsError> {
+        let mut options:Options = self.options.iter()
+            .filter(|&&(k, _)| k == "routing" || k == "preference")
+            .cloned()
+            .collect();
+        add_default_option(&mut options, "preference", self.defaults.preference.clone());
+        let mut body = BTreeMap::new();
+        optional_add!(body, self.body.query, "query");
+        optional_add!(body, self.body.min_score, "min_score");
+
+        let indexes_and_types = format_indexes_and_types(&self.indexes, &self.doc_types);
+        let url = format!("/{}/_search/exists{}",
+                          indexes_and_types,
+                          format_query_string(&options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url,
+                                                                       &Json::Object(body)));
+        debug!(target: "rs_es::search", "[{}] Search exists result status: {}",
+               self.client.request_id(), status_code);
+        let exists = result.as_ref()
+            .and_then(|r| r.find("exists"))
+            .and_then(|e| e.as_boolean());
+        match (status_code, exists) {
+            (StatusCode::Ok, _)             => Ok(exists.unwrap_or(true)),
+            // Nothing matched, rather than an error
+            (StatusCode::NotFound, Some(_)) => Ok(false),
+            (StatusCode::NotFound, None)    => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(indexes_and_types))),
+            _                               => {
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        }
+    }
+
+    fn send_raw(&mut self, mut options: Options<'b>) -> Result<Json, EsError> {
+        if self.body.search_after.is_some() && self.body.sort.is_none() {
+            return Err(EsError::EsError("search_after requires a sort".to_string()));
+        }
+        add_default_option(&mut options, "preference", self.defaults.preference.clone());
+        let mut body = self.body.to_json();
+        if let Json::Object(ref mut d) = body {
+            if self.body.timeout.is_none() {
+                optional_add!(d, self.defaults.timeout, "timeout");
+            }
+            if self.body.terminate_after.is_none() {
+                optional_add!(d, self.defaults.terminate_after, "terminate_after");
+            }
+        }
+        let url = format!("/{}/_search{}",
+                          format_indexes_and_types(&self.indexes, &self.doc_types),
+                          format_query_string(&options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url, &body));
+        debug!(target: "rs_es::search", "[{}] Search result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => {
+                let result = result.unwrap();
+                if self.fail_on_shard_failure {
+                    try!(try!(ShardCountResult::from_json(try!(find_json(&result, "_shards"))))
+                         .check_failures());
+                }
+                if let (Some(max_buckets), Some(aggs)) = (self.client.max_buckets,
+                                                          result.find("aggregations")) {
+                    try!(check_bucket_counts(aggs, max_buckets));
+                }
+                Ok(result)
+            },
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The template of a search template, either given in full or stored
+enum Template<'b> {
+    Inline(Json),
+    Id(&'b str)
+}
+
+/// A template and its parameters, as sent to search by the template or to
+/// render it
+struct TemplateBody<'b> {
+    template: Option<Template<'b>>,
+
+    /// The values of the placeholders, keyed by name
+    params:   BTreeMap<String, Json>
+}
+
+impl<'b> TemplateBody<'b> {
+    fn new() -> TemplateBody<'b> {
+        TemplateBody {
+            template: None,
+            params:   BTreeMap::new()
+        }
+    }
+
+    /// The body of the request, a template is required
+    fn to_json(&self) -> Result<Json, EsError> {
+        let mut body = BTreeMap::new();
+        match self.template {
+            Some(Template::Inline(ref template)) => {
+                body.insert("inline".to_string(), template.clone());
+            },
+            Some(Template::Id(id))               => {
+                body.insert("id".to_string(), id.to_json());
+            },
+            None                                 => {
+                return Err(EsError::EsError("A search template requires a template"
+                                            .to_string()))
+            }
+        }
+        body.insert("params".to_string(), self.params.to_json());
+        Ok(Json::Object(body))
+    }
+}
+
+/// Search Template API, a search by a mustache template of the search body,
+/// with parameters to fill its `{{placeholders}}`.  ElasticSearch 2.x
+/// onwards.
+pub struct SearchTemplateOperation<'a, 'b> {
+    /// The HTTP client
+    client:    &'a mut Client,
+
+    /// The indexes to which this query applies
+    indexes:   &'b [&'b str],
+
+    /// The types to which the query applies
+    doc_types: &'b [&'b str],
+
+    /// The template and its parameters
+    body:      TemplateBody<'b>,
+
+    /// Optional options
+    options:   Options<'b>
+}
+
+impl<'a, 'b> SearchTemplateOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> SearchTemplateOperation<'a, 'b> {
+        SearchTemplateOperation {
+            client:    client,
+            indexes:   &[],
+            doc_types: &[],
+            body:      TemplateBody::new(),
+            options:   Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_types(&mut self, doc_types: &'b [&'b str]) -> &mut Self {
+        self.doc_types = doc_types;
+        self
+    }
+
+    /// An inline template, the search body with placeholders as strings,
+    /// e.g. `{"query": {"match": {"title": "{{text}}"}}}`.  The template is
+    /// sent as an object, not as an encoded string.
+    pub fn with_template<T: ToJson>(&mut self, template: &T) -> &mut Self {
+        self.body.template = Some(Template::Inline(template.to_json()));
+        self
+    }
+
+    /// A template stored in the cluster, by ID
+    pub fn with_template_id(&mut self, id: &'b str) -> &mut Self {
+        self.body.template = Some(Template::Id(id));
+        self
+    }
+
+    /// The value of a placeholder
+    pub fn with_param<S: Into<String>, T: ToJson>(&mut self, name: S, value: &T) -> &mut Self {
+        self.body.params.insert(name.into(), value.to_json());
+        self
+    }
+
+    add_option!(with_routing, "routing");
+    add_option!(with_scroll, "scroll");
+
+    pub fn with_preference(&mut self, preference: Preference) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "preference");
+        self.options.push(("preference", preference.to_string()));
+        self
+    }
+
+    pub fn with_search_type(&mut self, search_type: SearchType) -> &mut Self {
+        self.options.retain(|&(k, _)| k != "search_type");
+        self.options.push(("search_type", search_type.to_string()));
+        self
+    }
+
+    pub fn send(&mut self) -> Result<SearchResult, EsError> {
+        SearchResult::from_json(&try!(self.send_raw()))
+    }
+
+    /// Sends the search, decoding the source of each hit as `T`
+    pub fn send_typed<T: Decodable>(&mut self) -> Result<SearchResult<T>, EsError> {
+        SearchResult::decode(try!(self.send_raw()))
+    }
+
+    fn send_raw(&mut self) -> Result<Json, EsError> {
+        let body = try!(self.body.to_json());
+        let url = format!("/{}/_search/template{}",
+                          format_indexes_and_types(&self.indexes, &self.doc_types),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url, &body));
+        debug!(target: "rs_es::search", "[{}] Search template result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => Ok(result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Render Search Template API, the search body a template gives with the
+/// parameters, without searching.  For debugging templates, ElasticSearch
+/// 2.x onwards.
+pub struct RenderSearchTemplateOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The template and its parameters
+    body:   TemplateBody<'b>
+}
+
+impl<'a, 'b> RenderSearchTemplateOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> RenderSearchTemplateOperation<'a, 'b> {
+        RenderSearchTemplateOperation {
+            client: client,
+            body:   TemplateBody::new()
+        }
+    }
+
+    /// An inline template, see `SearchTemplateOperation::with_template`
+    pub fn with_template<T: ToJson>(&mut self, template: &T) -> &mut Self {
+        self.body.template = Some(Template::Inline(template.to_json()));
+        self
+    }
+
+    /// A template stored in the cluster, by ID
+    pub fn with_template_id(&mut self, id: &'b str) -> &mut Self {
+        self.body.template = Some(Template::Id(id));
+        self
+    }
+
+    /// The value of a placeholder
+    pub fn with_param<S: Into<String>, T: ToJson>(&mut self, name: S, value: &T) -> &mut Self {
+        self.body.params.insert(name.into(), value.to_json());
+        self
+    }
+
+    /// Sends the request, the result is the rendered search body
+    pub fn send(&mut self) -> Result<Json, EsError> {
+        let body = try!(self.body.to_json());
+        let (status_code, result) = try!(self.client.post_read_body_op("/_render/template",
+                                                                       &body));
+        debug!(target: "rs_es::search", "[{}] Render template result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => find_json(&result.unwrap(), "template_output").map(|t| t.clone()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Put a search template in the cluster, for use by ID
+pub struct PutSearchTemplateOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The ID of the template
+    id:       &'b str,
+
+    /// The template
+    template: Option<Json>
+}
+
+impl<'a, 'b> PutSearchTemplateOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, id: &'b str) -> PutSearchTemplateOperation<'a, 'b> {
+        PutSearchTemplateOperation {
+            client:   client,
+            id:       id,
+            template: None
+        }
+    }
+
+    /// The template, either JSON or a string, e.g. if the template is not
+    /// valid JSON until its placeholders are filled in
+    pub fn with_template<T: ToJson>(&mut self, template: &T) -> &mut Self {
+        self.template = Some(template.to_json());
+        self
+    }
+
+    /// Sends the request, a template is required
+    pub fn send(&mut self) -> Result<(), EsError> {
+        try!(self.client.check_id(self.id));
+        let template = match self.template {
+            Some(ref template) => template.clone(),
+            None               => {
+                return Err(EsError::EsError("A search template requires a template"
+                                            .to_string()))
+            }
+        };
+        let mut body = BTreeMap::new();
+        body.insert("template".to_string(), template);
+        let url = format!("/_search/template/{}", self.id);
+        let (status_code, result) = try!(self.client.put_body_op(&url, &Json::Object(body)));
+        debug!(target: "rs_es::search", "[{}] Put search template result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok | StatusCode::Created => Ok(()),
+            _                                    => {
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        }
+    }
+}
+
+/// Get a search template stored in the cluster
+pub struct GetSearchTemplateOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The ID of the template
+    id:     &'b str
+}
+
+impl<'a, 'b> GetSearchTemplateOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, id: &'b str) -> GetSearchTemplateOperation<'a, 'b> {
+        GetSearchTemplateOperation {
+            client: client,
+            id:     id
+        }
+    }
+
+    /// Sends the request, if there is no such template the result is not
+    /// `found`
+    pub fn send(&mut self) -> Result<GetSearchTemplateResult, EsError> {
+        try!(self.client.check_id(self.id));
+        let url = format!("/_search/template/{}", self.id);
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::search", "[{}] Get search template result status: {}",
+               self.client.request_id(), status_code);
+        match (status_code, result) {
+            (StatusCode::Ok, Some(result))       => GetSearchTemplateResult::from_json(&result),
+            (StatusCode::NotFound, Some(result)) => {
+                if result.find("error").is_some() {
+                    Err(unexpected_status(self.client.request_id(), status_code, Some(result)))
+                } else {
+                    GetSearchTemplateResult::from_json(&result)
+                }
+            },
+            (StatusCode::NotFound, None)         => Ok(GetSearchTemplateResult {
+                found:    false,
+                template: None
+            }),
+            (status_code, result)                => {
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+            }
+        }
+    }
+}
+
+/// A stored search template
+#[derive(Debug, Clone)]
+pub struct GetSearchTemplateResult {
+    pub found:    bool,
+
+    /// The template.  ElasticSearch stores the template as a string, it is
+    /// parsed if it is valid JSON, otherwise it is given as a string.
+    pub template: Option<Json>
+}
+
+impl FromJson for GetSearchTemplateResult {
+    fn from_json(r: &Json) -> Result<GetSearchTemplateResult, EsError> {
+        let template = r.find("template")
+            .or(r.find_path(&["script", "source"]))
+            .map(|t| match t.as_string().map(|s| Json::from_str(s)) {
+                Some(Ok(parsed)) => parsed,
+                _                => t.clone()
+            });
+        Ok(GetSearchTemplateResult {
+            found:    r.find("found").and_then(|f| f.as_boolean()).unwrap_or(template.is_some()),
+            template: template
+        })
+    }
+}
+
+from_json_panicking!(GetSearchTemplateResult);
+
+/// Delete a search template stored in the cluster
+pub struct DeleteSearchTemplateOperation<'a, 'b> {
+    /// The HTTP client
+    client: &'a mut Client,
+
+    /// The ID of the template
+    id:     &'b str
+}
+
+impl<'a, 'b> DeleteSearchTemplateOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, id: &'b str) -> DeleteSearchTemplateOperation<'a, 'b> {
+        DeleteSearchTemplateOperation {
+            client: client,
+            id:     id
+        }
+    }
+
+    /// Sends the request, the result is `false` if the template did not
+    /// exist
+    pub fn send(&mut self) -> Result<bool, EsError> {
+        try!(self.client.check_id(self.id));
+        let url = format!("/_search/template/{}", self.id);
+        let (status_code, result) = try!(self.client.delete_op(&url));
+        debug!(target: "rs_es::search", "[{}] Delete search template result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => Ok(true),
+            StatusCode::NotFound => Ok(false),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// A single search hit, `T` is the type of the source document.  By default
+/// this is the raw `Json`, see `send_typed` on the search operations for
+/// decoding it into another type.
+#[derive(Debug)]
+pub struct SearchHitsHitsResult<T = Json> {
+    pub index:           String,
+    pub doc_type:        String,
+    pub id:              String,
+
+    /// `None` if the scores were not computed, as the hits were sorted by
+    /// something other than score without `with_track_scores`
+    pub score:           Option<f64>,
+    pub source:          Option<T>,
+    pub fields:          Option<Json>,
+    pub highlight:       Option<HighlightResult>,
+
+    /// The shard and node of the hit, only present if `explain` was requested
+    pub shard:           Option<i32>,
+    pub node:            Option<String>,
+
+    /// How the score of the hit was computed, only present if `explain` was
+    /// requested
+    pub explanation:     Option<Explanation>,
+
+    /// The values the hit was sorted by, only present if a sort was specified
+    pub sort:            Option<Vec<Json>>,
+
+    /// The version of the document, only present if `with_version` was
+    /// requested
+    pub version:         Option<i64>,
+
+    /// The routing value of the document, only present if it was indexed
+    /// with one.  ElasticSearch 1.x only gives it if requested with
+    /// `with_fields`, e.g. `&["_source", "_routing"]`.
+    pub routing:         Option<String>,
+
+    /// The parent of the document, only present if it has one.  As for the
+    /// routing, ElasticSearch 1.x only gives it if requested.
+    pub parent:          Option<String>,
+
+    /// The sequence number and primary term of the document, only present if
+    /// `with_seq_no_primary_term` was requested (ElasticSearch 6.7 onwards)
+    pub seq_no:          Option<i64>,
+    pub primary_term:    Option<i64>,
+
+    /// The names of the queries which matched the hit, only present if any
+    /// named queries matched, see `with_name` on each query
+    pub matched_queries: Option<Vec<String>>
+}
+
+impl SearchHitsHitsResult {
+    /// Get the source document as a struct, the raw JSON version is available
+    /// directly from the source field
+    pub fn source<T: Decodable>(self) -> Result<T, EsError> {
+        match self.source {
+            Some(source) => decode_json(source),
+            None         => Err(EsError::EsError("No source field".to_string()))
+        }
+    }
+
+    /// As `source`, but leaves the hit intact so its ID, score and fields can
+    /// still be used.  The source is cloned to decode it, and any error will
+    /// contain the document's ID.
+    pub fn source_ref<T: Decodable>(&self) -> Result<T, EsError> {
+        match self.source {
+            Some(ref source) => decode_source(self.id.clone(), source.clone()),
+            None             => Err(EsError::EsError(format!("No source field for document {}",
+                                                             self.id)))
+        }
+    }
+}
+
+impl<T> SearchHitsHitsResult<T> {
+    /// The first value of a stored field, see `with_fields`.  Stored fields
+    /// are returned as arrays even if they have a single value.  The result is
+    /// `None` if the hit does not have the field.
+    pub fn field<F: Decodable>(&self, name: &str) -> Result<Option<F>, EsError> {
+        let value = self.fields.as_ref().and_then(|fields| fields.find(name)).and_then(|v| {
+            match *v {
+                Json::Array(ref values) => values.first(),
+                _                       => Some(v)
+            }
+        });
+        match value {
+            Some(value) => decode_json(value.clone()).map(|v| Some(v)),
+            None        => Ok(None)
+        }
+    }
+
+    /// Every value of a stored field, e.g. of a multi-valued field, empty if
+    /// the hit does not have the field
+    pub fn field_values<F: Decodable>(&self, name: &str) -> Result<Vec<F>, EsError> {
+        match self.fields.as_ref().and_then(|fields| fields.find(name)) {
+            Some(&Json::Array(ref values)) => {
+                values.iter().map(|value| decode_json(value.clone())).collect()
+            },
+            Some(value)                    => decode_json(value.clone()).map(|v| vec![v]),
+            None                           => Ok(Vec::new())
+        }
+    }
+
+    /// The remaining time-to-live of the document, only present if `_ttl`
+    /// was requested with `with_fields` and is enabled for the type
+    pub fn ttl(&self) -> Option<Duration> {
+        ttl_from_fields(self.fields.as_ref())
+    }
+
+    /// The `_timestamp` of the document in milliseconds since the epoch, only
+    /// present if requested with `with_fields` and enabled for the type
+    pub fn timestamp(&self) -> Option<i64> {
+        timestamp_from_fields(self.fields.as_ref())
+    }
+
+    /// Everything other than the source document
+    fn with_source(r: &Json, source: Option<T>) -> Result<SearchHitsHitsResult<T>, EsError> {
+        Ok(SearchHitsHitsResult {
+            index:           get_json_string!(r, "_index"),
+            doc_type:        get_json_string!(r, "_type"),
+            id:              get_json_string!(r, "_id"),
+            score:           r.find("_score").and_then(|s| s.as_f64()),
+            source:          source,
+            fields:          r.find("fields").map(|s| s.clone()),
+            highlight:       r.find("highlight").and_then(|h| highlight_result_from(h)),
+            shard:           r.find("_shard").and_then(|s| shard_from(s)),
+            node:            r.find("_node").and_then(|n| n.as_string()).map(|n| n.to_string()),
+            explanation:     match r.find("_explanation") {
+                Some(explanation) => Some(try!(Explanation::from_json(explanation))),
+                None              => None
+            },
+            sort:            r.find("sort").and_then(|s| s.as_array()).map(|s| s.clone()),
+            version:         r.find("_version").and_then(|v| v.as_i64()),
+            routing:         meta_field_string(r, "_routing"),
+            parent:          meta_field_string(r, "_parent"),
+            seq_no:          r.find("_seq_no").and_then(|s| s.as_i64()),
+            primary_term:    r.find("_primary_term").and_then(|p| p.as_i64()),
+            matched_queries: r.find("matched_queries").and_then(|m| m.as_array()).and_then(|m| {
+                match m.len() {
+                    0 => None,
+                    _ => Some(m.iter()
+                              .filter_map(|q| q.as_string())
+                              .map(|q| q.to_string())
+                              .collect())
+                }
+            })
+        })
+    }
+}
+
+/// Older versions of ElasticSearch give the shard as a number, newer versions
+/// as a string of the form `[index][shard]`
+fn shard_from(r: &Json) -> Option<i32> {
+    match *r {
+        Json::String(ref s) => s.trim_right_matches(']')
+            .rsplit('[')
+            .next()
+            .and_then(|shard| shard.parse().ok()),
+        _                   => r.as_i64().map(|shard| shard as i32)
+    }
+}
+
+impl<T: Decodable> SearchHitsHitsResult<T> {
+    /// The source is moved out of the hit and decoded, rather than cloned
+    fn decode(mut r: Json) -> Result<SearchHitsHitsResult<T>, EsError> {
+        let source = match take_field(&mut r, "_source") {
+            Some(source) => Some(try!(decode_source(get_json_string!(r, "_id"), source))),
+            None         => None
+        };
+        SearchHitsHitsResult::with_source(&r, source)
+    }
+}
+
+impl FromJson for SearchHitsHitsResult {
+    fn from_json(r: &Json) -> Result<SearchHitsHitsResult, EsError> {
+        SearchHitsHitsResult::with_source(r, r.find("_source").map(|s| s.clone()))
+    }
+}
+
+from_json_panicking!(SearchHitsHitsResult);
+
+/// What to do with a hit which has no source, when decoding the source of
+/// each hit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MissingSource {
+    /// Keep the hit, with a `source` of `None`
+    Keep,
+
+    /// Leave the hit out of the result, the `total` is unchanged
+    Skip,
+
+    /// The whole search is an error
+    Error
+}
+
+/// Decode a source document, any error will contain the document's ID
+fn decode_source<T: Decodable>(id: String, source: Json) -> Result<T, EsError> {
+    match decode_json(source) {
+        Ok(source)                 => Ok(source),
+        Err(EsError::JsonError(e)) => Err(EsError::DocumentDecodeError(id, e)),
+        Err(e)                     => Err(e)
+    }
+}
+
+/// Removes a field from a JSON object, so it can be consumed without cloning
+fn take_field(r: &mut Json, field: &str) -> Option<Json> {
+    match *r {
+        Json::Object(ref mut d) => d.remove(field),
+        _                       => None
+    }
+}
+
+pub struct SearchHitsResult<T = Json> {
+    pub total:     i64,
+
+    /// The highest score of any hit, `None` if the hits were sorted by
+    /// something other than score without `with_track_scores`
+    pub max_score: Option<f64>,
+    pub hits:      Vec<SearchHitsHitsResult<T>>
+}
+
+/// ElasticSearch gives `null` if the scores were not computed
+fn max_score_from(r: &Json) -> Option<f64> {
+    r.find("max_score").and_then(|m| m.as_f64())
+}
+
+impl FromJson for SearchHitsResult {
+    fn from_json(r: &Json) -> Result<SearchHitsResult, EsError> {
+        Ok(SearchHitsResult {
+            total:     get_json_i64!(r, "total"),
+            max_score: max_score_from(r),
+            hits:      try!(try!(find_json_array(r, "hits"))
+                            .iter()
+                            .map(|j| SearchHitsHitsResult::from_json(j))
+                            .collect::<Result<Vec<_>, _>>())
+        })
+    }
+}
+
+from_json_panicking!(SearchHitsResult);
+
+impl SearchHitsResult {
+    /// An iterator which decodes the source of each hit as `T`.  A hit which
+    /// cannot be decoded, or which has no source, is an error for that hit
+    /// only.
+    pub fn into_typed<T: Decodable>(self) -> TypedHits<T> {
+        TypedHits {
+            hits:    self.hits.into_iter(),
+            phantom: PhantomData
+        }
+    }
+}
+
+/// An iterator over the decoded source documents of search hits, see
+/// `SearchHitsResult::into_typed`
+pub struct TypedHits<T> {
+    hits:    vec::IntoIter<SearchHitsHitsResult>,
+    phantom: PhantomData<T>
+}
+
+impl<T: Decodable> Iterator for TypedHits<T> {
+    type Item = Result<T, EsError>;
+
+    fn next(&mut self) -> Option<Result<T, EsError>> {
+        self.hits.next().map(|hit| {
+            match hit.source {
+                Some(source) => decode_source(hit.id, source),
+                None         => Err(EsError::EsError(format!("No source field for document {}",
+                                                             hit.id)))
+            }
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hits.size_hint()
+    }
+}
+
+impl SearchHitsResult {
+    /// An iterator which decodes the source of each hit as `T`, leaving the
+    /// hits intact, see `SearchHitsHitsResult::source_ref`
+    pub fn sources<T: Decodable>(&self) -> Sources<T> {
+        Sources {
+            hits:    self.hits.iter(),
+            phantom: PhantomData
+        }
+    }
+}
+
+/// An iterator over the decoded source documents of borrowed search hits, see
+/// `SearchHitsResult::sources`
+pub struct Sources<'a, T> {
+    hits:    slice::Iter<'a, SearchHitsHitsResult>,
+    phantom: PhantomData<T>
+}
+
+impl<'a, T: Decodable> Iterator for Sources<'a, T> {
+    type Item = Result<T, EsError>;
+
+    fn next(&mut self) -> Option<Result<T, EsError>> {
+        self.hits.next().map(|hit| hit.source_ref())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hits.size_hint()
+    }
+}
+
+impl<T> SearchHitsResult<T> {
+    pub fn iter(&self) -> slice::Iter<SearchHitsHitsResult<T>> {
+        self.hits.iter()
+    }
+
+    /// The ID of each hit, in order
+    pub fn ids(&self) -> Ids<T> {
+        Ids {
+            hits: self.hits.iter()
+        }
+    }
+}
+
+impl<T> IntoIterator for SearchHitsResult<T> {
+    type Item = SearchHitsHitsResult<T>;
+    type IntoIter = vec::IntoIter<SearchHitsHitsResult<T>>;
+
+    fn into_iter(self) -> vec::IntoIter<SearchHitsHitsResult<T>> {
+        self.hits.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SearchHitsResult<T> {
+    type Item = &'a SearchHitsHitsResult<T>;
+    type IntoIter = slice::Iter<'a, SearchHitsHitsResult<T>>;
+
+    fn into_iter(self) -> slice::Iter<'a, SearchHitsHitsResult<T>> {
+        self.hits.iter()
+    }
+}
+
+/// An iterator over the IDs of search hits, see `SearchHitsResult::ids`
+pub struct Ids<'a, T: 'a> {
+    hits: slice::Iter<'a, SearchHitsHitsResult<T>>
+}
+
+impl<'a, T> Iterator for Ids<'a, T> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.hits.next().map(|hit| &hit.id[..])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.hits.size_hint()
+    }
+}
+
+impl<T: Decodable> SearchHitsResult<T> {
+    fn decode(mut r: Json, missing_source: MissingSource)
+              -> Result<SearchHitsResult<T>, EsError> {
+        let hits = match take_field(&mut r, "hits") {
+            Some(Json::Array(hits)) => hits,
+            _                       => return Err(EsError::EsError("No hits".to_string()))
+        };
+        let mut decoded = Vec::with_capacity(hits.len());
+        for hit in hits {
+            let hit = try!(SearchHitsHitsResult::decode(hit));
+            if hit.source.is_none() {
+                match missing_source {
+                    MissingSource::Keep  => (),
+                    MissingSource::Skip  => continue,
+                    MissingSource::Error => {
+                        return Err(EsError::EsError(format!("No source field for document {}",
+                                                            hit.id)))
+                    }
+                }
+            }
+            decoded.push(hit);
+        }
+        Ok(SearchHitsResult {
+            total:     get_json_i64!(r, "total"),
+            max_score: max_score_from(&r),
+            hits:      decoded
+        })
+    }
+}
+
+pub struct SearchResult<T = Json> {
+    /// How long the search took in milliseconds, zero if not given
+    pub took:             i64,
+
+    /// Whether the search timed out, in which case the hits are those found
+    /// before the timeout, see `with_timeout`
+    pub timed_out:        bool,
+    pub shards:           ShardCountResult,
+    pub hits:             SearchHitsResult<T>,
+
+    /// Whether the search stopped early as `terminate_after` documents were
+    /// found on a shard, in which case the total is a lower bound.  Only
+    /// present if `terminate_after` was given.
+    pub terminated_early: Option<bool>,
+
+    /// The aggregations, if any were requested
+    pub aggs:             Option<AggregationsResult>,
+
+    /// The ID with which to get the next page of a scrolling search, see
+    /// `with_scroll`
+    pub scroll_id:        Option<String>,
+
+    /// The suggestions, if any were requested
+    pub suggest:          Option<SuggestResult>
+}
+
+/// Some versions of ElasticSearch do not give `took` and `timed_out` in every
+/// search response, e.g. of a scroll
+fn took_from(r: &Json) -> i64 {
+    r.find("took").and_then(|t| t.as_i64()).unwrap_or(0)
+}
+
+fn timed_out_from(r: &Json) -> bool {
+    r.find("timed_out").and_then(|t| t.as_boolean()).unwrap_or(false)
+}
+
+/// The scroll ID of a search response, if it is a scrolling search
+fn scroll_id_from(r: &Json) -> Option<String> {
+    r.find("_scroll_id").and_then(|s| s.as_string()).map(|s| s.to_string())
+}
+
+/// The suggestions of a search response, if any were requested
+fn suggest_from(r: &Json) -> Result<Option<SuggestResult>, EsError> {
+    match r.find("suggest") {
+        Some(suggest) => suggest_result_from(suggest).map(|suggest| Some(suggest)),
+        None          => Ok(None)
+    }
+}
+
+impl FromJson for SearchResult {
+    fn from_json(r: &Json) -> Result<SearchResult, EsError> {
+        Ok(SearchResult {
+            took:             took_from(r),
+            timed_out:        timed_out_from(r),
+            shards:           try!(ShardCountResult::from_json(try!(find_json(r, "_shards")))),
+            hits:             try!(SearchHitsResult::from_json(try!(find_json(r, "hits")))),
+            terminated_early: r.find("terminated_early").and_then(|t| t.as_boolean()),
+            aggs:             r.find("aggregations")
+                .map(|aggs| AggregationsResult::new(aggs.clone())),
+            scroll_id:        scroll_id_from(r),
+            suggest:          try!(suggest_from(r))
+        })
+    }
+}
+
+from_json_panicking!(SearchResult);
+
+impl<T> SearchResult<T> {
+    /// An iterator over the hits, the same as `hits.iter()`
+    pub fn hits_iter(&self) -> slice::Iter<SearchHitsHitsResult<T>> {
+        self.hits.iter()
+    }
+
+    /// The ID of each hit, in order
+    pub fn ids(&self) -> Ids<T> {
+        self.hits.ids()
+    }
+
+    /// Whether some hits may be missing, as the search timed out or some
+    /// shards failed
+    pub fn is_partial(&self) -> bool {
+        self.timed_out || self.shards.failed > 0
+    }
+
+    /// Whether a page of a scrolling search has no hits, i.e. every hit has
+    /// been returned by the previous pages
+    pub fn is_exhausted(&self) -> bool {
+        self.hits.hits.is_empty()
+    }
+
+    /// The number of hits from each shard, keyed by index and shard.  This is
+    /// only available if `explain` was requested, otherwise it is empty.
+    pub fn hits_by_shard(&self) -> BTreeMap<(String, i32), i64> {
+        let mut counts = BTreeMap::new();
+        for hit in self.hits.hits.iter() {
+            if let Some(shard) = hit.shard {
+                *counts.entry((hit.index.clone(), shard)).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+}
+
+impl SearchResult {
+    /// An iterator which decodes the source of each hit as `T`, see
+    /// `SearchHitsResult::into_typed`
+    pub fn into_typed<T: Decodable>(self) -> TypedHits<T> {
+        self.hits.into_typed()
+    }
+
+    /// An iterator which decodes the source of each hit as `T`, leaving the
+    /// result intact, see `SearchHitsResult::sources`
+    pub fn sources<T: Decodable>(&self) -> Sources<T> {
+        self.hits.sources()
+    }
+}
+
+impl<T: Decodable> SearchResult<T> {
+    /// Decodes a search response, the source of each hit is decoded as `T`.
+    /// If any fail to decode, the error contains the ID of the document.
+    /// Hits without a source are kept, with a `source` of `None`.
+    pub fn decode(r: Json) -> Result<SearchResult<T>, EsError> {
+        SearchResult::decode_with(r, MissingSource::Keep)
+    }
+
+    /// As `decode`, with hits without a source handled as given
+    pub fn decode_with(mut r: Json, missing_source: MissingSource)
+                       -> Result<SearchResult<T>, EsError> {
+        let hits = match take_field(&mut r, "hits") {
+            Some(hits) => try!(SearchHitsResult::decode(hits, missing_source)),
+            None       => return Err(EsError::EsError("No hits".to_string()))
+        };
+        Ok(SearchResult {
+            took:             took_from(&r),
+            timed_out:        timed_out_from(&r),
+            shards:           try!(ShardCountResult::from_json(try!(find_json(&r, "_shards")))),
+            hits:             hits,
+            terminated_early: r.find("terminated_early").and_then(|t| t.as_boolean()),
+            aggs:             take_field(&mut r, "aggregations").map(AggregationsResult::new),
+            scroll_id:        scroll_id_from(&r),
+            suggest:          try!(suggest_from(&r))
+        })
+    }
+}
+
+/// An iterator over every hit of a scrolling search, see
+/// `SearchQueryOperation::scan`.  If requesting a page fails the error is
+/// returned, and the iterator then ends.  The scroll is cleared once every
+/// hit has been returned, or when the iterator is dropped.
+pub struct ScanIterator<'a> {
+    /// The HTTP client
+    client:     &'a mut Client,
+
+    /// The scroll ID of the last page, `None` once the scroll is cleared
+    scroll_id:  Option<String>,
+
+    /// How long to keep the search open for the following page
+    keep_alive: String,
+
+    /// The total number of hits
+    total:      i64,
+
+    /// Whether there are no more pages to request
+    done:       bool,
+
+    /// The hits of the current page not yet returned
+    hits:       vec::IntoIter<SearchHitsHitsResult>
+}
+
+impl<'a> ScanIterator<'a> {
+    fn new(client:     &'a mut Client,
+           first:      SearchResult,
+           keep_alive: &str) -> ScanIterator<'a> {
+        // The first page of a `scan` search type has no hits, so only a page
+        // after the first with no hits marks the end
+        ScanIterator {
+            client:     client,
+            scroll_id:  first.scroll_id,
+            keep_alive: keep_alive.to_string(),
+            total:      first.hits.total,
+            done:       first.hits.total == 0,
+            hits:       first.hits.hits.into_iter()
+        }
+    }
+
+    /// The total number of hits of the search
+    pub fn total(&self) -> i64 {
+        self.total
+    }
+
+    fn next_page(&mut self, scroll_id: &str) -> Result<(), EsError> {
+        let page = try!(ScrollOperation::new(self.client, scroll_id, &self.keep_alive).send());
+        if page.scroll_id.is_some() {
+            self.scroll_id = page.scroll_id.clone();
+        }
+        if page.is_exhausted() {
+            self.done = true;
+            self.clear();
+        }
+        self.hits = page.hits.hits.into_iter();
+        Ok(())
+    }
+
+    /// Clears the scroll, a failure is only logged as the scroll will expire
+    /// anyway after the keep-alive
+    fn clear(&mut self) {
+        if let Some(scroll_id) = self.scroll_id.take() {
+            let scroll_ids = [&scroll_id[..]];
+            let result = ClearScrollOperation::new(self.client, Some(&scroll_ids[..])).send();
+            if let Err(e) = result {
+                debug!(target: "rs_es::search", "[{}] Failed to clear scroll: {}",
+                       self.client.request_id(), e);
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for ScanIterator<'a> {
+    type Item = Result<SearchHitsHitsResult, EsError>;
+
+    fn next(&mut self) -> Option<Result<SearchHitsHitsResult, EsError>> {
+        loop {
+            if let Some(hit) = self.hits.next() {
+                return Some(Ok(hit));
+            }
+            if self.done {
+                return None;
+            }
+            let scroll_id = match self.scroll_id.clone() {
+                Some(scroll_id) => scroll_id,
+                None            => {
+                    self.done = true;
+                    return None;
+                }
+            };
+            if let Err(e) = self.next_page(&scroll_id) {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<'a> Drop for ScanIterator<'a> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// The next page of a scrolling search
+pub struct ScrollOperation<'a, 'b> {
+    /// The HTTP client
+    client:     &'a mut Client,
+
+    /// The scroll ID from the previous page
+    scroll_id:  &'b str,
+
+    /// How long to keep the search open for the following page
+    keep_alive: &'b str
+}
+
+impl<'a, 'b> ScrollOperation<'a, 'b> {
+    pub fn new(client:     &'a mut Client,
+               scroll_id:  &'b str,
+               keep_alive: &'b str) -> ScrollOperation<'a, 'b> {
+        ScrollOperation {
+            client:     client,
+            scroll_id:  scroll_id,
+            keep_alive: keep_alive
+        }
+    }
+
+    pub fn send(&mut self) -> Result<SearchResult, EsError> {
+        SearchResult::from_json(&try!(self.send_raw()))
+    }
+
+    /// Sends the request, decoding the source of each hit as `T`
+    pub fn send_typed<T: Decodable>(&mut self) -> Result<SearchResult<T>, EsError> {
+        SearchResult::decode(try!(self.send_raw()))
+    }
+
+    fn send_raw(&mut self) -> Result<Json, EsError> {
+        let url = format!("/_search/scroll{}",
+                          format_query_string(&[("scroll", self.keep_alive.to_string())]));
+        let (status_code, result) = try!(self.client.post_raw_body_op(&url,
+                                                                      self.scroll_id,
+                                                                      true));
+        debug!(target: "rs_es::search", "[{}] Scroll result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => Ok(result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Clear scroll, freeing the resources of scrolling searches before their
+/// keep-alive expires
+pub struct ClearScrollOperation<'a, 'b> {
+    /// The HTTP client
+    client:     &'a mut Client,
+
+    /// The scroll IDs to clear, every scroll if `None`
+    scroll_ids: Option<&'b [&'b str]>
+}
+
+impl<'a, 'b> ClearScrollOperation<'a, 'b> {
+    pub fn new(client:     &'a mut Client,
+               scroll_ids: Option<&'b [&'b str]>) -> ClearScrollOperation<'a, 'b> {
+        ClearScrollOperation {
+            client:     client,
+            scroll_ids: scroll_ids
+        }
+    }
+
+    /// Sends the request, the result is whether the scrolls were cleared.  If
+    /// they have already expired or been cleared the result is `false`
+    /// rather than an error.
+    pub fn send(&mut self) -> Result<bool, EsError> {
+        let (status_code, result) = try!(match self.scroll_ids {
+            Some(scroll_ids) => {
+                if scroll_ids.is_empty() {
+                    return Err(EsError::EsError("No scroll IDs to clear".to_string()));
+                }
+                let mut body = BTreeMap::new();
+                body.insert("scroll_id".to_string(),
+                            Json::Array(scroll_ids.iter().map(|id| id.to_json()).collect()));
+                self.client.delete_idempotent_body_op("/_search/scroll", &Json::Object(body))
+            },
+            None             => self.client.delete_idempotent_op("/_search/scroll/_all")
+        });
+        debug!(target: "rs_es::search", "[{}] Clear scroll result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => {
+                Ok(result
+                   .and_then(|r| r.find("succeeded").and_then(|s| s.as_boolean()))
+                   .unwrap_or(true))
+            },
+            StatusCode::NotFound => Ok(false),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
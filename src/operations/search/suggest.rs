@@ -0,0 +1,266 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Suggestions, e.g. spelling corrections, requested alongside a search or on
+//! their own.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use super::super::common::Options;
+use super::super::error_reason;
+use super::super::find_json_array;
+use super::super::format_multi;
+use super::super::format_query_string;
+use super::super::unexpected_status;
+use super::super::FromJson;
+
+/// The kind of suggester, and the field it suggests from
+#[derive(Clone)]
+pub enum Suggester {
+    /// Suggests a correction for each term of the text
+    Term(String),
+
+    /// Suggests a correction of the whole text
+    Phrase(String),
+
+    /// Suggests completions of the text, the field must be mapped with the
+    /// `completion` type
+    Completion(String)
+}
+
+/// A single suggestion, of the given text
+#[derive(Clone)]
+pub struct Suggestion {
+    text:      String,
+    suggester: Suggester,
+    size:      Option<u64>
+}
+
+impl Suggestion {
+    pub fn new<S: Into<String>>(text: S, suggester: Suggester) -> Suggestion {
+        Suggestion {
+            text:      text.into(),
+            suggester: suggester,
+            size:      None
+        }
+    }
+
+    pub fn term<S: Into<String>, F: Into<String>>(text: S, field: F) -> Suggestion {
+        Suggestion::new(text, Suggester::Term(field.into()))
+    }
+
+    pub fn phrase<S: Into<String>, F: Into<String>>(text: S, field: F) -> Suggestion {
+        Suggestion::new(text, Suggester::Phrase(field.into()))
+    }
+
+    pub fn completion<S: Into<String>, F: Into<String>>(text: S, field: F) -> Suggestion {
+        Suggestion::new(text, Suggester::Completion(field.into()))
+    }
+
+    /// The maximum number of options for each entry
+    pub fn with_size(mut self, size: u64) -> Suggestion {
+        self.size = Some(size);
+        self
+    }
+}
+
+impl ToJson for Suggestion {
+    fn to_json(&self) -> Json {
+        let (suggester, field) = match self.suggester {
+            Suggester::Term(ref field)       => ("term", field),
+            Suggester::Phrase(ref field)     => ("phrase", field),
+            Suggester::Completion(ref field) => ("completion", field)
+        };
+        let mut inner = BTreeMap::new();
+        inner.insert("field".to_string(), field.to_json());
+        optional_add!(inner, self.size, "size");
+        let mut d = BTreeMap::new();
+        d.insert("text".to_string(), self.text.to_json());
+        d.insert(suggester.to_string(), Json::Object(inner));
+        Json::Object(d)
+    }
+}
+
+/// The `suggest` section of a search request, the suggestions are keyed by
+/// name
+#[derive(Clone)]
+pub struct Suggest {
+    suggestions: BTreeMap<String, Suggestion>
+}
+
+impl Suggest {
+    pub fn new() -> Suggest {
+        Suggest {
+            suggestions: BTreeMap::new()
+        }
+    }
+
+    /// Add a suggestion, its result will have the same name
+    pub fn with<S: Into<String>>(mut self, name: S, suggestion: Suggestion) -> Suggest {
+        self.suggestions.insert(name.into(), suggestion);
+        self
+    }
+}
+
+impl ToJson for Suggest {
+    fn to_json(&self) -> Json {
+        self.suggestions.to_json()
+    }
+}
+
+/// A suggested replacement or completion
+#[derive(Debug, Clone)]
+pub struct SuggestOption {
+    pub text:    String,
+    pub score:   f64,
+
+    /// The number of documents containing the suggested text, only given by
+    /// the term suggester
+    pub freq:    Option<i64>,
+
+    /// The payload of the completion, only given by the completion suggester
+    /// if the completion was indexed with one
+    pub payload: Option<Json>
+}
+
+impl FromJson for SuggestOption {
+    fn from_json(r: &Json) -> Result<SuggestOption, EsError> {
+        // The completion suggester gives the score as `_score` from
+        // ElasticSearch 5.x onwards
+        let score = match r.find("score").or(r.find("_score")).and_then(|s| s.as_f64()) {
+            Some(score) => score,
+            None        => return Err(EsError::EsError("Suggestion has no score".to_string()))
+        };
+        Ok(SuggestOption {
+            text:    get_json_string!(r, "text"),
+            score:   score,
+            freq:    r.find("freq").and_then(|f| f.as_i64()),
+            payload: r.find("payload").map(|p| p.clone())
+        })
+    }
+}
+
+/// The options for a part of the text, e.g. for each term with the term
+/// suggester
+#[derive(Debug, Clone)]
+pub struct SuggestEntry {
+    /// The part of the text, and its position in the text
+    pub text:    String,
+    pub offset:  i64,
+    pub length:  i64,
+    pub options: Vec<SuggestOption>
+}
+
+impl FromJson for SuggestEntry {
+    fn from_json(r: &Json) -> Result<SuggestEntry, EsError> {
+        Ok(SuggestEntry {
+            text:    get_json_string!(r, "text"),
+            offset:  get_json_i64!(r, "offset"),
+            length:  get_json_i64!(r, "length"),
+            options: try!(try!(find_json_array(r, "options")).iter()
+                          .map(|o| SuggestOption::from_json(o))
+                          .collect())
+        })
+    }
+}
+
+/// The suggestions of a search, the entries of each suggestion keyed by name
+pub type SuggestResult = BTreeMap<String, Vec<SuggestEntry>>;
+
+/// Parses the `suggest` section of a search response
+pub fn suggest_result_from(r: &Json) -> Result<SuggestResult, EsError> {
+    let suggestions = match r.as_object() {
+        Some(suggestions) => suggestions,
+        None              => return Err(EsError::EsError("Suggest is not an object".to_string()))
+    };
+    let mut result = BTreeMap::new();
+    for (name, entries) in suggestions.iter() {
+        let entries = match entries.as_array() {
+            Some(entries) => try!(entries.iter().map(|e| SuggestEntry::from_json(e)).collect()),
+            None          => {
+                return Err(EsError::EsError(format!("Suggestion {} is not an array", name)))
+            }
+        };
+        result.insert(name.clone(), entries);
+    }
+    Ok(result)
+}
+
+/// Suggest API, suggestions without a search, e.g. completions for typeahead.
+/// The result has the suggestions by name, as for a search.
+pub struct SuggestOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The indexes to suggest from
+    indexes: &'b [&'b str],
+
+    /// The suggestions
+    suggest: &'b Suggest,
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> SuggestOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client, suggest: &'b Suggest) -> SuggestOperation<'a, 'b> {
+        SuggestOperation {
+            client:  client,
+            indexes: &[],
+            suggest: suggest,
+            options: Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+
+    /// Sends the request.  If any of the indexes do not exist, the result is
+    /// an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<SuggestResult, EsError> {
+        let url = format!("/{}/_suggest{}",
+                          format_multi(&self.indexes),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_read_body_op(&url,
+                                                                       &self.suggest.to_json()));
+        debug!(target: "rs_es::suggest", "[{}] Suggest result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => {
+                // The suggestions are alongside the shard counts
+                let mut result = result.unwrap();
+                if let Json::Object(ref mut d) = result {
+                    d.remove("_shards");
+                }
+                suggest_result_from(&result)
+            },
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(format_multi(&self.indexes)))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Statistics: the indices stats and nodes stats APIs.
+//!
+//! Each section of the statistics is available as JSON, the fielddata and
+//! completion sections are also parsed, including the memory used by each
+//! field if requested with `with_fields`.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::Json;
+
+use ::Client;
+use ::error::EsError;
+use ::util::StrJoin;
+use super::common::Options;
+use super::error_reason;
+use super::find_json;
+use super::format_multi;
+use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+use super::ShardCountResult;
+
+/// The path segment for the requested metrics, e.g. `/fielddata,completion`,
+/// empty for all metrics
+fn format_metrics(metrics: &[&str]) -> String {
+    if metrics.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", metrics.iter().join(","))
+    }
+}
+
+/// Indices stats, statistics for each index and totals across all the
+/// indexes
+pub struct IndicesStatsOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The indexes, all if empty
+    indexes: &'b [&'b str],
+
+    /// The metrics, e.g. `fielddata`, all if empty
+    metrics: &'b [&'b str],
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> IndicesStatsOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> IndicesStatsOperation<'a, 'b> {
+        IndicesStatsOperation {
+            client:  client,
+            indexes: &[],
+            metrics: &[],
+            options: Options::new()
+        }
+    }
+
+    pub fn with_indexes(&mut self, indexes: &'b [&'b str]) -> &mut Self {
+        self.indexes = indexes;
+        self
+    }
+
+    pub fn with_metrics(&mut self, metrics: &'b [&'b str]) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The fields for which to report fielddata and completion memory,
+    /// wildcards are allowed, e.g. `*` for every field
+    pub fn with_fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    add_option!(with_level, "level");
+
+    /// Sends the request.  If any of the indexes do not exist, the result is
+    /// an `EsError::IndexMissingError`.
+    pub fn send(&mut self) -> Result<IndicesStatsResult, EsError> {
+        let url = format!("/{}/_stats{}{}",
+                          format_multi(&self.indexes),
+                          format_metrics(&self.metrics),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::stats", "[{}] Indices stats result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => IndicesStatsResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(format_multi(&self.indexes)))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// Nodes stats, the statistics of the indexes held by each node
+pub struct NodesStatsOperation<'a, 'b> {
+    /// The HTTP client
+    client:  &'a mut Client,
+
+    /// The nodes, all if empty
+    nodes:   &'b [&'b str],
+
+    /// The index metrics, e.g. `fielddata`, all if empty
+    metrics: &'b [&'b str],
+
+    /// Optional options
+    options: Options<'b>
+}
+
+impl<'a, 'b> NodesStatsOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> NodesStatsOperation<'a, 'b> {
+        NodesStatsOperation {
+            client:  client,
+            nodes:   &[],
+            metrics: &[],
+            options: Options::new()
+        }
+    }
+
+    /// The nodes, by ID, name or address, e.g. `_local`
+    pub fn with_nodes(&mut self, nodes: &'b [&'b str]) -> &mut Self {
+        self.nodes = nodes;
+        self
+    }
+
+    pub fn with_metrics(&mut self, metrics: &'b [&'b str]) -> &mut Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The fields for which to report fielddata and completion memory,
+    /// wildcards are allowed, e.g. `*` for every field
+    pub fn with_fields(&mut self, fields: &[&str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    /// Sends the request, only the statistics of the indexes on each node are
+    /// requested
+    pub fn send(&mut self) -> Result<NodesStatsResult, EsError> {
+        let nodes = if self.nodes.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", self.nodes.iter().join(","))
+        };
+        let url = format!("/_nodes{}/stats/indices{}{}",
+                          nodes,
+                          format_metrics(&self.metrics),
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.get_op(&url));
+        debug!(target: "rs_es::stats", "[{}] Nodes stats result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => NodesStatsResult::from_json(&result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The given size of each field of a `fields` object, e.g. the
+/// `memory_size_in_bytes` of each field of the fielddata statistics
+fn field_sizes(r: &Json, size: &str) -> Result<BTreeMap<String, i64>, EsError> {
+    let mut sizes = BTreeMap::new();
+    if let Some(fields) = r.find("fields").and_then(|f| f.as_object()) {
+        for (name, field) in fields.iter() {
+            sizes.insert(name.clone(), get_json_i64!(field, size));
+        }
+    }
+    Ok(sizes)
+}
+
+/// Fielddata memory, the `fields` are only present for the fields requested
+/// with `with_fields`
+#[derive(Debug)]
+pub struct FielddataStats {
+    pub memory_size_in_bytes: i64,
+    pub evictions:            i64,
+
+    /// Memory in bytes, by field
+    pub fields:               BTreeMap<String, i64>
+}
+
+impl FromJson for FielddataStats {
+    fn from_json(r: &Json) -> Result<FielddataStats, EsError> {
+        Ok(FielddataStats {
+            memory_size_in_bytes: get_json_i64!(r, "memory_size_in_bytes"),
+            evictions:            get_json_i64!(r, "evictions"),
+            fields:               try!(field_sizes(r, "memory_size_in_bytes"))
+        })
+    }
+}
+
+from_json_panicking!(FielddataStats);
+
+/// Completion suggester memory, the `fields` are only present for the fields
+/// requested with `with_fields`
+#[derive(Debug)]
+pub struct CompletionStats {
+    pub size_in_bytes: i64,
+
+    /// Memory in bytes, by field
+    pub fields:        BTreeMap<String, i64>
+}
+
+impl FromJson for CompletionStats {
+    fn from_json(r: &Json) -> Result<CompletionStats, EsError> {
+        Ok(CompletionStats {
+            size_in_bytes: get_json_i64!(r, "size_in_bytes"),
+            fields:        try!(field_sizes(r, "size_in_bytes"))
+        })
+    }
+}
+
+from_json_panicking!(CompletionStats);
+
+/// The statistics of one or more indexes, only the requested metrics are
+/// present
+#[derive(Debug)]
+pub struct IndexStats {
+    pub fielddata:  Option<FielddataStats>,
+    pub completion: Option<CompletionStats>,
+
+    /// Every metric, as returned by ElasticSearch
+    pub raw:        Json
+}
+
+impl FromJson for IndexStats {
+    fn from_json(r: &Json) -> Result<IndexStats, EsError> {
+        Ok(IndexStats {
+            fielddata:  match r.find("fielddata") {
+                Some(fielddata) => Some(try!(FielddataStats::from_json(fielddata))),
+                None            => None
+            },
+            completion: match r.find("completion") {
+                Some(completion) => Some(try!(CompletionStats::from_json(completion))),
+                None             => None
+            },
+            raw:        r.clone()
+        })
+    }
+}
+
+from_json_panicking!(IndexStats);
+
+/// The statistics of the primary shards, and of all shards including replicas
+#[derive(Debug)]
+pub struct PrimariesAndTotal {
+    pub primaries: IndexStats,
+    pub total:     IndexStats
+}
+
+impl FromJson for PrimariesAndTotal {
+    fn from_json(r: &Json) -> Result<PrimariesAndTotal, EsError> {
+        Ok(PrimariesAndTotal {
+            primaries: try!(IndexStats::from_json(try!(find_json(r, "primaries")))),
+            total:     try!(IndexStats::from_json(try!(find_json(r, "total"))))
+        })
+    }
+}
+
+from_json_panicking!(PrimariesAndTotal);
+
+/// The result of an indices stats request
+#[derive(Debug)]
+pub struct IndicesStatsResult {
+    pub shards:  ShardCountResult,
+
+    /// Totals across all the requested indexes
+    pub all:     PrimariesAndTotal,
+
+    /// By index
+    pub indices: BTreeMap<String, PrimariesAndTotal>
+}
+
+impl FromJson for IndicesStatsResult {
+    fn from_json(r: &Json) -> Result<IndicesStatsResult, EsError> {
+        let mut indices = BTreeMap::new();
+        if let Some(r_indices) = r.find("indices").and_then(|i| i.as_object()) {
+            for (name, index) in r_indices.iter() {
+                indices.insert(name.clone(), try!(PrimariesAndTotal::from_json(index)));
+            }
+        }
+        Ok(IndicesStatsResult {
+            shards:  try!(ShardCountResult::from_json(try!(find_json(r, "_shards")))),
+            all:     try!(PrimariesAndTotal::from_json(try!(find_json(r, "_all")))),
+            indices: indices
+        })
+    }
+}
+
+from_json_panicking!(IndicesStatsResult);
+
+/// The statistics of a single node
+#[derive(Debug)]
+pub struct NodeStats {
+    pub name:    String,
+    pub host:    Option<String>,
+
+    /// The statistics of all the shards on the node
+    pub indices: IndexStats
+}
+
+impl FromJson for NodeStats {
+    fn from_json(r: &Json) -> Result<NodeStats, EsError> {
+        Ok(NodeStats {
+            name:    get_json_string!(r, "name"),
+            host:    r.find("host").and_then(|h| h.as_string()).map(|h| h.to_string()),
+            indices: try!(IndexStats::from_json(try!(find_json(r, "indices"))))
+        })
+    }
+}
+
+from_json_panicking!(NodeStats);
+
+/// The result of a nodes stats request
+#[derive(Debug)]
+pub struct NodesStatsResult {
+    pub cluster_name: String,
+
+    /// By node ID
+    pub nodes:        BTreeMap<String, NodeStats>
+}
+
+impl FromJson for NodesStatsResult {
+    fn from_json(r: &Json) -> Result<NodesStatsResult, EsError> {
+        let mut nodes = BTreeMap::new();
+        if let Some(r_nodes) = r.find("nodes").and_then(|n| n.as_object()) {
+            for (id, node) in r_nodes.iter() {
+                nodes.insert(id.clone(), try!(NodeStats::from_json(node)));
+            }
+        }
+        Ok(NodesStatsResult {
+            cluster_name: get_json_string!(r, "cluster_name"),
+            nodes:        nodes
+        })
+    }
+}
+
+from_json_panicking!(NodesStatsResult);
@@ -0,0 +1,505 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Term vectors: the terms of the fields of a document, or of several, with
+//! their frequencies, positions and offsets.
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::{EsError, ResponseError};
+use ::util::StrJoin;
+use super::common::Options;
+use super::error_reason;
+use super::find_json_array;
+use super::format_query_string;
+use super::unexpected_status;
+use super::FromJson;
+
+/// Term Vectors API, the terms of a stored document, by ID, or of an
+/// artificial document given with `with_doc`.  By default the term
+/// frequencies, positions and offsets are given, and the field statistics,
+/// but not the term statistics.
+pub struct TermVectorsOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index and type of the document
+    index:    &'b str,
+    doc_type: &'b str,
+
+    /// The ID of a stored document
+    id:       Option<&'b str>,
+
+    /// An artificial document, rather than a stored one
+    doc:      Option<Json>,
+
+    /// Optional options
+    options:  Options<'b>
+}
+
+impl<'a, 'b> TermVectorsOperation<'a, 'b> {
+    pub fn new(client:   &'a mut Client,
+               index:    &'b str,
+               doc_type: &'b str) -> TermVectorsOperation<'a, 'b> {
+        TermVectorsOperation {
+            client:   client,
+            index:    index,
+            doc_type: doc_type,
+            id:       None,
+            doc:      None,
+            options:  Options::new()
+        }
+    }
+
+    /// A stored document, by ID
+    pub fn with_id(&mut self, id: &'b str) -> &mut Self {
+        self.id = Some(id);
+        self.doc = None;
+        self
+    }
+
+    /// An artificial document, which is analyzed as if it were in the index
+    /// but is not stored
+    pub fn with_doc<T: ToJson>(&mut self, doc: &T) -> &mut Self {
+        self.doc = Some(doc.to_json());
+        self.id = None;
+        self
+    }
+
+    /// The fields to give the terms of, by default every field with stored
+    /// term vectors
+    pub fn with_fields(&mut self, fields: &[&'b str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    // The `doc_freq` and `ttf` of each term, these can be slow to compute
+    add_option!(with_term_statistics, "term_statistics");
+    add_option!(with_field_statistics, "field_statistics");
+    add_option!(with_positions, "positions");
+    add_option!(with_offsets, "offsets");
+    add_option!(with_payloads, "payloads");
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+    add_option!(with_realtime, "realtime");
+
+    /// Sends the request.  A stored document which does not exist is not an
+    /// error, the result is not `found`.
+    pub fn send(&mut self) -> Result<TermVectorsResult, EsError> {
+        let (status_code, result) = match (self.id, self.doc.as_ref()) {
+            (Some(id), _)     => {
+                try!(self.client.check_id(id));
+                let url = format!("/{}/{}/{}/_termvectors{}",
+                                  self.index,
+                                  self.doc_type,
+                                  id,
+                                  format_query_string(&self.options));
+                try!(self.client.get_op(&url))
+            },
+            (None, Some(doc)) => {
+                let url = format!("/{}/{}/_termvectors{}",
+                                  self.index,
+                                  self.doc_type,
+                                  format_query_string(&self.options));
+                let mut body = BTreeMap::new();
+                body.insert("doc".to_string(), doc.clone());
+                try!(self.client.post_read_body_op(&url, &Json::Object(body)))
+            },
+            (None, None)      => {
+                return Err(EsError::EsError("Term vectors require either an id or a doc"
+                                            .to_string()))
+            }
+        };
+        debug!(target: "rs_es::termvectors", "[{}] Term vectors result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       => TermVectorsResult::from_json(&result.unwrap()),
+            StatusCode::NotFound => Err(EsError::IndexMissingError(
+                error_reason(result.as_ref()).unwrap_or(self.index.to_string()))),
+            _                    => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// An occurrence of a term, each part is only present if requested
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub position:     Option<i64>,
+    pub start_offset: Option<i64>,
+    pub end_offset:   Option<i64>,
+
+    /// Base64 encoded
+    pub payload:      Option<String>
+}
+
+impl FromJson for Token {
+    fn from_json(r: &Json) -> Result<Token, EsError> {
+        Ok(Token {
+            position:     r.find("position").and_then(|p| p.as_i64()),
+            start_offset: r.find("start_offset").and_then(|o| o.as_i64()),
+            end_offset:   r.find("end_offset").and_then(|o| o.as_i64()),
+            payload:      r.find("payload").and_then(|p| p.as_string()).map(|p| p.to_string())
+        })
+    }
+}
+
+/// A term of a field of the document
+#[derive(Debug, Clone)]
+pub struct TermVector {
+    /// The number of times the term occurs in the field of the document
+    pub term_freq: i64,
+
+    /// The number of documents containing the term, and the total number of
+    /// times it occurs in every document, only present if term statistics
+    /// were requested
+    pub doc_freq:  Option<i64>,
+    pub ttf:       Option<i64>,
+
+    /// Each occurrence, empty if neither positions nor offsets were requested
+    pub tokens:    Vec<Token>
+}
+
+impl FromJson for TermVector {
+    fn from_json(r: &Json) -> Result<TermVector, EsError> {
+        let tokens = match r.find("tokens").and_then(|t| t.as_array()) {
+            Some(tokens) => try!(tokens.iter().map(|t| Token::from_json(t)).collect()),
+            None         => Vec::new()
+        };
+        Ok(TermVector {
+            term_freq: get_json_i64!(r, "term_freq"),
+            doc_freq:  r.find("doc_freq").and_then(|f| f.as_i64()),
+            ttf:       r.find("ttf").and_then(|t| t.as_i64()),
+            tokens:    tokens
+        })
+    }
+}
+
+/// The statistics of a field across every document of the shard
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldStatistics {
+    pub sum_doc_freq: i64,
+    pub doc_count:    i64,
+    pub sum_ttf:      i64
+}
+
+impl FromJson for FieldStatistics {
+    fn from_json(r: &Json) -> Result<FieldStatistics, EsError> {
+        Ok(FieldStatistics {
+            sum_doc_freq: get_json_i64!(r, "sum_doc_freq"),
+            doc_count:    get_json_i64!(r, "doc_count"),
+            sum_ttf:      get_json_i64!(r, "sum_ttf")
+        })
+    }
+}
+
+/// The terms of a field of the document
+#[derive(Debug, Clone)]
+pub struct FieldTermVectors {
+    /// Only present if field statistics were requested, as they are by
+    /// default
+    pub field_statistics: Option<FieldStatistics>,
+
+    /// Keyed by term
+    pub terms:            BTreeMap<String, TermVector>
+}
+
+impl FromJson for FieldTermVectors {
+    fn from_json(r: &Json) -> Result<FieldTermVectors, EsError> {
+        let field_statistics = match r.find("field_statistics") {
+            Some(statistics) => Some(try!(FieldStatistics::from_json(statistics))),
+            None             => None
+        };
+        let mut terms = BTreeMap::new();
+        if let Some(d) = r.find("terms").and_then(|t| t.as_object()) {
+            for (term, vector) in d.iter() {
+                terms.insert(term.clone(), try!(TermVector::from_json(vector)));
+            }
+        }
+        Ok(FieldTermVectors {
+            field_statistics: field_statistics,
+            terms:            terms
+        })
+    }
+}
+
+/// The result of a term vectors request
+#[derive(Debug)]
+pub struct TermVectorsResult {
+    pub index:        String,
+    pub doc_type:     String,
+
+    /// Not given for an artificial document by some versions of
+    /// ElasticSearch
+    pub id:           Option<String>,
+    pub version:      Option<i64>,
+    pub found:        bool,
+
+    /// Keyed by field, empty if the document was not found
+    pub term_vectors: BTreeMap<String, FieldTermVectors>
+}
+
+impl FromJson for TermVectorsResult {
+    fn from_json(r: &Json) -> Result<TermVectorsResult, EsError> {
+        let mut term_vectors = BTreeMap::new();
+        if let Some(d) = r.find("term_vectors").and_then(|t| t.as_object()) {
+            for (field, vectors) in d.iter() {
+                term_vectors.insert(field.clone(), try!(FieldTermVectors::from_json(vectors)));
+            }
+        }
+        Ok(TermVectorsResult {
+            index:        get_json_string!(r, "_index"),
+            doc_type:     get_json_string!(r, "_type"),
+            id:           r.find("_id").and_then(|i| i.as_string()).map(|i| i.to_string()),
+            version:      r.find("_version").and_then(|v| v.as_i64()),
+            found:        get_json_bool!(r, "found"),
+            term_vectors: term_vectors
+        })
+    }
+}
+
+from_json_panicking!(TermVectorsResult);
+
+/// A document of a multi term vectors request, with any options which differ
+/// from those of the request
+pub struct MultiTermVectorsDoc<'b> {
+    /// The index and type, if not those of the request
+    index:            Option<&'b str>,
+    doc_type:         Option<&'b str>,
+    id:               &'b str,
+    routing:          Option<&'b str>,
+    fields:           Option<&'b [&'b str]>,
+    term_statistics:  Option<bool>,
+    field_statistics: Option<bool>,
+    positions:        Option<bool>,
+    offsets:          Option<bool>,
+    payloads:         Option<bool>
+}
+
+impl<'b> MultiTermVectorsDoc<'b> {
+    /// A document in the index and of the type of the request
+    pub fn new(id: &'b str) -> MultiTermVectorsDoc<'b> {
+        MultiTermVectorsDoc {
+            index:            None,
+            doc_type:         None,
+            id:               id,
+            routing:          None,
+            fields:           None,
+            term_statistics:  None,
+            field_statistics: None,
+            positions:        None,
+            offsets:          None,
+            payloads:         None
+        }
+    }
+
+    pub fn with_index(mut self, index: &'b str) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn with_doc_type(mut self, doc_type: &'b str) -> Self {
+        self.doc_type = Some(doc_type);
+        self
+    }
+
+    pub fn with_routing(mut self, routing: &'b str) -> Self {
+        self.routing = Some(routing);
+        self
+    }
+
+    pub fn with_fields(mut self, fields: &'b [&'b str]) -> Self {
+        self.fields = Some(fields);
+        self
+    }
+
+    pub fn with_term_statistics(mut self, term_statistics: bool) -> Self {
+        self.term_statistics = Some(term_statistics);
+        self
+    }
+
+    pub fn with_field_statistics(mut self, field_statistics: bool) -> Self {
+        self.field_statistics = Some(field_statistics);
+        self
+    }
+
+    pub fn with_positions(mut self, positions: bool) -> Self {
+        self.positions = Some(positions);
+        self
+    }
+
+    pub fn with_offsets(mut self, offsets: bool) -> Self {
+        self.offsets = Some(offsets);
+        self
+    }
+
+    pub fn with_payloads(mut self, payloads: bool) -> Self {
+        self.payloads = Some(payloads);
+        self
+    }
+}
+
+impl<'b> ToJson for MultiTermVectorsDoc<'b> {
+    fn to_json(&self) -> Json {
+        let mut d = BTreeMap::new();
+        optional_add!(d, self.index, "_index");
+        optional_add!(d, self.doc_type, "_type");
+        d.insert("_id".to_string(), self.id.to_json());
+        optional_add!(d, self.routing, "_routing");
+        if let Some(fields) = self.fields {
+            d.insert("fields".to_string(),
+                     Json::Array(fields.iter().map(|f| f.to_json()).collect()));
+        }
+        optional_add!(d, self.term_statistics, "term_statistics");
+        optional_add!(d, self.field_statistics, "field_statistics");
+        optional_add!(d, self.positions, "positions");
+        optional_add!(d, self.offsets, "offsets");
+        optional_add!(d, self.payloads, "payloads");
+        Json::Object(d)
+    }
+}
+
+/// Multi Term Vectors API, the term vectors of several stored documents in a
+/// single request.  The documents are given either with `with_ids`, in the
+/// index and of the type of the request, or each with `with_doc`, optionally
+/// with its own index, type and options.
+pub struct MultiTermVectorsOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index and type of any documents which do not give their own
+    index:    Option<&'b str>,
+    doc_type: Option<&'b str>,
+
+    /// Documents by ID, which requires an index and type
+    ids:      &'b [&'b str],
+
+    /// Documents, in the order the results will be returned, after any `ids`
+    docs:     Vec<MultiTermVectorsDoc<'b>>,
+
+    /// Optional options
+    options:  Options<'b>
+}
+
+impl<'a, 'b> MultiTermVectorsOperation<'a, 'b> {
+    pub fn new(client: &'a mut Client) -> MultiTermVectorsOperation<'a, 'b> {
+        MultiTermVectorsOperation {
+            client:   client,
+            index:    None,
+            doc_type: None,
+            ids:      &[],
+            docs:     Vec::new(),
+            options:  Options::new()
+        }
+    }
+
+    pub fn with_index(&mut self, index: &'b str) -> &mut Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub fn with_doc_type(&mut self, doc_type: &'b str) -> &mut Self {
+        self.doc_type = Some(doc_type);
+        self
+    }
+
+    /// Documents by ID, the request must have an index and type
+    pub fn with_ids(&mut self, ids: &'b [&'b str]) -> &mut Self {
+        self.ids = ids;
+        self
+    }
+
+    pub fn with_doc(&mut self, doc: MultiTermVectorsDoc<'b>) -> &mut Self {
+        self.docs.push(doc);
+        self
+    }
+
+    /// The fields of every document which does not give its own
+    pub fn with_fields(&mut self, fields: &[&'b str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    add_option!(with_term_statistics, "term_statistics");
+    add_option!(with_field_statistics, "field_statistics");
+    add_option!(with_positions, "positions");
+    add_option!(with_offsets, "offsets");
+    add_option!(with_payloads, "payloads");
+    add_option!(with_routing, "routing");
+    add_option!(with_preference, "preference");
+    add_option!(with_realtime, "realtime");
+
+    /// Sends the request.  A document which does not exist is not `found`,
+    /// and one which could not be retrieved, e.g. as its index does not
+    /// exist, is an error for that document only.
+    pub fn send(&mut self) -> Result<MultiTermVectorsResult, EsError> {
+        if !self.ids.is_empty() && (self.index.is_none() || self.doc_type.is_none()) {
+            return Err(EsError::EsError("Term vectors by ids require an index and type"
+                                        .to_string()));
+        }
+        let path = match (self.index, self.doc_type) {
+            (Some(index), Some(doc_type)) => format!("/{}/{}", index, doc_type),
+            (Some(index), None)           => format!("/{}", index),
+            (None, _)                     => String::new()
+        };
+        let url = format!("{}/_mtermvectors{}", path, format_query_string(&self.options));
+        let mut body = BTreeMap::new();
+        if !self.ids.is_empty() {
+            body.insert("ids".to_string(),
+                        Json::Array(self.ids.iter().map(|id| id.to_json()).collect()));
+        }
+        if !self.docs.is_empty() {
+            body.insert("docs".to_string(), self.docs.to_json());
+        }
+        let (status_code, result) = try!(self.client.post_read_body_op(&url,
+                                                                       &Json::Object(body)));
+        debug!(target: "rs_es::termvectors", "[{}] Multi term vectors result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok => MultiTermVectorsResult::from_docs(self.client.request_id(),
+                                                                status_code.to_u16(),
+                                                                &result.unwrap()),
+            _              => Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The results of a multi term vectors request, in the same order as the
+/// documents
+pub struct MultiTermVectorsResult {
+    pub docs: Vec<Result<TermVectorsResult, EsError>>
+}
+
+impl MultiTermVectorsResult {
+    /// Parses a multi term vectors response, an error for any one document is
+    /// an error for that document only
+    pub fn from_docs(request_id: &str, status: u16, r: &Json)
+                     -> Result<MultiTermVectorsResult, EsError> {
+        let docs = try!(find_json_array(r, "docs"));
+        Ok(MultiTermVectorsResult {
+            docs: docs.iter().map(|doc| {
+                match ResponseError::from_json(request_id, status, doc) {
+                    Some(error) => Err(EsError::from(error)),
+                    None        => TermVectorsResult::from_json(doc)
+                }
+            }).collect()
+        })
+    }
+}
@@ -0,0 +1,386 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::BTreeMap;
+
+use hyper::status::StatusCode;
+
+use rustc_serialize::Decodable;
+use rustc_serialize::json::{Json, ToJson};
+
+use ::Client;
+use ::error::EsError;
+use ::util::StrJoin;
+use super::common::Options;
+use super::decode_json;
+use super::format_query_string;
+use super::script::{Script, ScriptFormat};
+use super::unexpected_status;
+use super::FromJson;
+use super::WriteOutcome;
+
+/// The body of an update, either a partial document or a script.  As sent by
+/// `UpdateOperation`, or as the update action of a bulk request.
+#[derive(Debug, Clone)]
+pub struct UpdateBody {
+    doc:             Option<Json>,
+    script:          Option<Script>,
+
+    /// Parameters of the script, in addition to those given with it
+    script_params:   BTreeMap<String, Json>,
+    script_format:   ScriptFormat,
+    upsert:          Option<Json>,
+    scripted_upsert: Option<bool>,
+    doc_as_upsert:   Option<bool>,
+    detect_noop:     Option<bool>
+}
+
+impl UpdateBody {
+    fn new() -> UpdateBody {
+        UpdateBody {
+            doc:             None,
+            script:          None,
+            script_params:   BTreeMap::new(),
+            script_format:   ScriptFormat::Flat,
+            upsert:          None,
+            scripted_upsert: None,
+            doc_as_upsert:   None,
+            detect_noop:     None
+        }
+    }
+
+    /// A partial document to be merged into the existing document
+    pub fn doc<T: ToJson>(doc: &T) -> UpdateBody {
+        UpdateBody::from(doc.to_json())
+    }
+
+    /// A script to update the existing document
+    pub fn script(script: Script) -> UpdateBody {
+        let mut body = UpdateBody::new();
+        body.script = Some(script);
+        body
+    }
+
+    /// See `UpdateOperation::with_upsert`
+    pub fn with_upsert<T: ToJson>(mut self, upsert: &T) -> UpdateBody {
+        self.upsert = Some(upsert.to_json());
+        self
+    }
+
+    /// See `UpdateOperation::with_scripted_upsert`
+    pub fn with_scripted_upsert(mut self, scripted_upsert: bool) -> UpdateBody {
+        self.scripted_upsert = Some(scripted_upsert);
+        self
+    }
+
+    /// See `UpdateOperation::with_doc_as_upsert`
+    pub fn with_doc_as_upsert(mut self, doc_as_upsert: bool) -> UpdateBody {
+        self.doc_as_upsert = Some(doc_as_upsert);
+        self
+    }
+
+    /// See `UpdateOperation::with_detect_noop`
+    pub fn with_detect_noop(mut self, detect_noop: bool) -> UpdateBody {
+        self.detect_noop = Some(detect_noop);
+        self
+    }
+
+    /// See `UpdateOperation::with_script_format`
+    pub fn with_script_format(mut self, script_format: ScriptFormat) -> UpdateBody {
+        self.script_format = script_format;
+        self
+    }
+
+    /// The body as sent, an update is either a partial document or a script,
+    /// but not both
+    pub fn to_json(&self) -> Result<Json, EsError> {
+        let mut d = BTreeMap::new();
+        match (&self.doc, &self.script) {
+            (&Some(ref doc), &None)       => {
+                d.insert("doc".to_string(), doc.clone());
+            },
+            (&None, &Some(ref script))    => {
+                let mut script = script.clone();
+                for (name, value) in self.script_params.iter() {
+                    script.params.insert(name.clone(), value.clone());
+                }
+                script.add_to_as(&mut d, self.script_format);
+            },
+            (&Some(_), &Some(_))          => {
+                return Err(EsError::EsError("An update cannot have both a doc and a script"
+                                            .to_string()))
+            },
+            (&None, &None)                => {
+                return Err(EsError::EsError("An update must have either a doc or a script"
+                                            .to_string()))
+            }
+        }
+        optional_add!(d, self.upsert, "upsert");
+        optional_add!(d, self.scripted_upsert, "scripted_upsert");
+        optional_add!(d, self.doc_as_upsert, "doc_as_upsert");
+        optional_add!(d, self.detect_noop, "detect_noop");
+        Ok(Json::Object(d))
+    }
+}
+
+/// A partial document, see `UpdateBody::doc`
+impl From<Json> for UpdateBody {
+    fn from(doc: Json) -> UpdateBody {
+        let mut body = UpdateBody::new();
+        body.doc = Some(doc);
+        body
+    }
+}
+
+/// An ES update operation, to update a specific document either by merging a
+/// partial document or by executing a script
+pub struct UpdateOperation<'a, 'b> {
+    /// The HTTP client
+    client:   &'a mut Client,
+
+    /// The index
+    index:    &'b str,
+
+    /// The type
+    doc_type: &'b str,
+
+    /// The ID
+    id:       &'b str,
+
+    /// Optional options
+    options:  Options<'b>,
+
+    /// The update itself
+    body:     UpdateBody
+}
+
+impl<'a, 'b> UpdateOperation<'a, 'b> {
+    pub fn new(client:   &'a mut Client,
+               index:    &'b str,
+               doc_type: &'b str,
+               id:       &'b str) -> UpdateOperation<'a, 'b> {
+        UpdateOperation {
+            client:   client,
+            index:    index,
+            doc_type: doc_type,
+            id:       id,
+            options:  Options::new(),
+            body:     UpdateBody::new()
+        }
+    }
+
+    /// A partial document to be merged into the existing document
+    pub fn with_doc<T: ToJson>(&mut self, doc: &T) -> &mut Self {
+        self.body.doc = Some(doc.to_json());
+        self
+    }
+
+    /// A script to update the existing document, `params` may be empty.  If
+    /// `lang` is not specified the default scripting language is used.
+    pub fn with_script<S: Into<String>>(&mut self,
+                                        script: S,
+                                        params: BTreeMap<String, Json>,
+                                        lang:   Option<&str>) -> &mut Self {
+        self.with_script_of(Script::new(script), params, lang)
+    }
+
+    /// A script indexed in the cluster, by ID, see `with_script`
+    pub fn with_indexed_script<S: Into<String>>(&mut self,
+                                                id:     S,
+                                                params: BTreeMap<String, Json>,
+                                                lang:   Option<&str>) -> &mut Self {
+        self.with_script_of(Script::indexed(id), params, lang)
+    }
+
+    /// A script in a file on each node, by name, see `with_script`
+    pub fn with_script_file<S: Into<String>>(&mut self,
+                                             name:   S,
+                                             params: BTreeMap<String, Json>,
+                                             lang:   Option<&str>) -> &mut Self {
+        self.with_script_of(Script::file(name), params, lang)
+    }
+
+    fn with_script_of(&mut self,
+                      script: Script,
+                      params: BTreeMap<String, Json>,
+                      lang:   Option<&str>) -> &mut Self {
+        let script = script.with_params(params);
+        self.body.script = Some(match lang {
+            Some(lang) => script.with_lang(lang),
+            None       => script
+        });
+        self
+    }
+
+    /// Parameters of the script, added to any given with the script itself
+    pub fn with_script_params(&mut self, params: BTreeMap<String, Json>) -> &mut Self {
+        self.body.script_params.extend(params);
+        self
+    }
+
+    /// How the script is given in the request, `ScriptFormat::Flat` by
+    /// default, which is deprecated from ElasticSearch 2.x onwards
+    pub fn with_script_format(&mut self, script_format: ScriptFormat) -> &mut Self {
+        self.body.script_format = script_format;
+        self
+    }
+
+    /// The document to be inserted if the document does not already exist
+    pub fn with_upsert<T: ToJson>(&mut self, upsert: &T) -> &mut Self {
+        self.body.upsert = Some(upsert.to_json());
+        self
+    }
+
+    /// Run the script on the upsert document if the document does not already
+    /// exist, rather than inserting the upsert document as it is
+    pub fn with_scripted_upsert(&mut self, scripted_upsert: bool) -> &mut Self {
+        self.body.scripted_upsert = Some(scripted_upsert);
+        self
+    }
+
+    /// Use the partial document as the upsert document
+    pub fn with_doc_as_upsert(&mut self, doc_as_upsert: bool) -> &mut Self {
+        self.body.doc_as_upsert = Some(doc_as_upsert);
+        self
+    }
+
+    /// Do not bump the version if the partial document would not change the
+    /// existing document
+    pub fn with_detect_noop(&mut self, detect_noop: bool) -> &mut Self {
+        self.body.detect_noop = Some(detect_noop);
+        self
+    }
+
+    pub fn with_fields(&mut self, fields: &[&'b str]) -> &mut Self {
+        self.options.push(("fields", fields.iter().join(",")));
+        self
+    }
+
+    add_option!(with_retry_on_conflict, "retry_on_conflict");
+    add_option!(with_routing, "routing");
+    add_option!(with_parent, "parent");
+    add_option!(with_timeout, "timeout");
+    add_option!(with_refresh, "refresh");
+    add_option!(with_consistency, "consistency");
+    add_option!(with_version, "version");
+    add_option!(with_version_type, "version_type");
+
+    /// Sends the update.  If the document does not exist, and no upsert
+    /// document was given, the result is `EsError::DocumentMissingError`.  If
+    /// the document is not at the given `version`, or was changed by another
+    /// request more than `retry_on_conflict` times during the update, the
+    /// result is `EsError::ConflictError`, the update can then be retried.
+    pub fn send(&mut self) -> Result<UpdateResult, EsError> {
+        try!(self.client.check_id(self.id));
+        let body = try!(self.body.to_json());
+        let url = format!("/{}/{}/{}/_update{}",
+                          self.index,
+                          self.doc_type,
+                          self.id,
+                          format_query_string(&self.options));
+        let (status_code, result) = try!(self.client.post_body_op(&url, &body));
+        debug!(target: "rs_es::update", "[{}] Update result status: {}",
+               self.client.request_id(), status_code);
+        match status_code {
+            StatusCode::Ok       =>
+                UpdateResult::from_json(&result.unwrap()),
+            StatusCode::Created  => {
+                // An upsert, older versions of ElasticSearch only report this
+                // by the status
+                let mut update_result = try!(UpdateResult::from_json(&result.unwrap()));
+                update_result.outcome = WriteOutcome::Created;
+                Ok(update_result)
+            },
+            StatusCode::NotFound =>
+                Err(EsError::DocumentMissingError(format!("{}/{}/{}",
+                                                          self.index,
+                                                          self.doc_type,
+                                                          self.id))),
+            _                    =>
+                Err(unexpected_status(self.client.request_id(), status_code, result))
+        }
+    }
+}
+
+/// The result of an update operation
+#[derive(Debug)]
+pub struct UpdateResult {
+    pub index:    String,
+    pub doc_type: String,
+    pub id:       String,
+    pub version:  i64,
+    pub outcome:  WriteOutcome,
+
+    /// The updated document, only present if `fields` were requested
+    pub get:      Option<UpdateGetResult>
+}
+
+/// This is required because the JSON keys do not match the struct
+impl FromJson for UpdateResult {
+    fn from_json(r: &Json) -> Result<UpdateResult, EsError> {
+        Ok(UpdateResult {
+            index:    get_json_string!(r, "_index"),
+            doc_type: get_json_string!(r, "_type"),
+            id:       get_json_string!(r, "_id"),
+            version:  get_json_i64!(r, "_version"),
+            outcome:  try!(WriteOutcome::from_json(r,
+                                                   "created",
+                                                   WriteOutcome::Created,
+                                                   WriteOutcome::Updated)),
+            get:      match r.find("get") {
+                Some(get) => Some(try!(UpdateGetResult::from_json(get))),
+                None      => None
+            }
+        })
+    }
+}
+
+from_json_panicking!(UpdateResult);
+
+/// The updated document, as requested by `with_fields`
+#[derive(Debug)]
+pub struct UpdateGetResult {
+    pub found:  bool,
+
+    /// The source of the document, only present if `_source` was one of the
+    /// fields requested
+    pub source: Option<Json>,
+
+    /// The other fields requested
+    pub fields: Option<Json>
+}
+
+impl UpdateGetResult {
+    /// Decodes the source of the updated document to a struct
+    pub fn source<T: Decodable>(&self) -> Result<T, EsError> {
+        match self.source {
+            Some(ref doc) => decode_json(doc.clone()),
+            None          => Err(EsError::EsError("No source".to_string()))
+        }
+    }
+}
+
+impl FromJson for UpdateGetResult {
+    fn from_json(r: &Json) -> Result<UpdateGetResult, EsError> {
+        Ok(UpdateGetResult {
+            found:  get_json_bool!(r, "found"),
+            source: r.find("_source").map(|s| s.clone()),
+            fields: r.find("fields").map(|f| f.clone())
+        })
+    }
+}
+
+from_json_panicking!(UpdateGetResult);
@@ -0,0 +1,124 @@
+/*
+ * Copyright 2015 Ben Ashford
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Retrying requests which fail because ElasticSearch is temporarily
+//! overloaded or unreachable, see `Client::with_retry_policy`; and observing
+//! the retries and failovers of a client, see `Client::with_observer`.
+
+use std::cmp;
+use std::time::Duration;
+
+use hyper::status::StatusCode;
+
+use ::error::EsError;
+
+/// How many times to attempt a request, and how long to wait between attempts.
+/// The wait doubles after each attempt, starting at `backoff`.
+///
+/// Only requests which can safely be repeated (e.g. get, search, count) are
+/// retried, unless `non_idempotent` is set, and only if ElasticSearch responds
+/// with `429 Too Many Requests` or `503 Service Unavailable`, or the connection
+/// failed or timed out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first
+    pub max_attempts:   u32,
+
+    /// The wait after the first attempt
+    pub backoff:        Duration,
+
+    /// Whether to also retry requests which may not be safe to repeat, e.g.
+    /// index and bulk requests, which could then be applied twice, and
+    /// deletes, which could then be reported as not found
+    pub non_idempotent: bool
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, backoff: Duration) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts:   max_attempts,
+            backoff:        backoff,
+            non_idempotent: false
+        }
+    }
+
+    /// Also retry requests which may not be safe to repeat, see
+    /// `non_idempotent`
+    pub fn with_non_idempotent(mut self, non_idempotent: bool) -> RetryPolicy {
+        self.non_idempotent = non_idempotent;
+        self
+    }
+
+    /// Whether a request should be attempted again, `attempt` is the number of
+    /// the attempt that has just been made, starting at 1
+    pub fn should_retry(&self, status_code: StatusCode, attempt: u32) -> bool {
+        attempt < self.max_attempts && match status_code {
+            StatusCode::TooManyRequests |
+            StatusCode::ServiceUnavailable => true,
+            _                              => false
+        }
+    }
+
+    /// Whether a request should be attempted again after an error sending it,
+    /// only connection errors and timeouts are retried
+    pub fn should_retry_error(&self, err: &EsError, attempt: u32) -> bool {
+        attempt < self.max_attempts && (err.is_connection_error() || err.is_timeout())
+    }
+
+    /// The wait after the given attempt, starting at 1
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        // Capped to avoid overflow, which would be a very long wait anyway
+        self.backoff * (1 << cmp::min(attempt.saturating_sub(1), 16))
+    }
+}
+
+/// Something which happened while sending a request, these are given to the
+/// client's `Observer`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClientEvent {
+    /// The request will be attempted again after `delay`, `attempt` is the
+    /// number of the next attempt (starting at 1 for the first) and `reason`
+    /// the response status or error of the previous attempt
+    RetryScheduled {
+        attempt: u32,
+        delay:   Duration,
+        reason:  String
+    },
+
+    /// A request to `host` failed to connect or timed out, with the number of
+    /// consecutive requests to that host which have failed in this way
+    HostMarkedDead {
+        host:                 String,
+        consecutive_failures: u32
+    },
+
+    /// A retry is being sent to a different host from the previous attempt
+    FailoverTo {
+        host: String
+    }
+}
+
+/// Receives the events of a client's requests, e.g. to record metrics.  This
+/// is implemented for closures taking the request ID and the event.
+pub trait Observer: Send + Sync {
+    fn on_event(&self, request_id: &str, event: &ClientEvent);
+}
+
+impl<F> Observer for F where F: Fn(&str, &ClientEvent) + Send + Sync {
+    fn on_event(&self, request_id: &str, event: &ClientEvent) {
+        self(request_id, event)
+    }
+}
@@ -31,10 +31,14 @@ macro_rules! optional_add {
     };
 }
 
-// Macros to read values from Json structs
+// Macros to read values from Json structs, these return early with an error
+// if the field is missing or of the wrong type
 macro_rules! get_json_thing {
     ($r:ident,$f:expr,$t:ident) => {
-        $r.find($f).unwrap().$t().unwrap()
+        try!($r.find($f)
+             .and_then(|v| v.$t())
+             .ok_or_else(|| $crate::error::EsError::EsError(format!("Missing or invalid field: {}",
+                                                                   $f))))
     }
 }
 
@@ -62,6 +66,18 @@ macro_rules! get_json_f64 {
     }
 }
 
+// Implements `From<&Json>` for a type which implements `FromJson`, this panics
+// if the JSON cannot be converted
+macro_rules! from_json_panicking {
+    ($t:ident) => {
+        impl<'a> From<&'a ::rustc_serialize::json::Json> for $t {
+            fn from(r: &'a ::rustc_serialize::json::Json) -> $t {
+                <$t as $crate::operations::FromJson>::from_json(r).unwrap()
+            }
+        }
+    }
+}
+
 // A custom String-join trait as the stdlib one is currently marked as unstable.
 pub trait StrJoin {
     fn join(self, join: &str) -> String;
@@ -80,3 +96,18 @@ impl<I, S> StrJoin for I where
         s
     }
 }
+
+/// Percent-encodes every byte of a string, as UTF-8, other than the unreserved
+/// characters of RFC 3986, for use as a value in a query string
+pub fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'...b'Z' | b'a'...b'z' | b'0'...b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(b as char)
+            },
+            _ => encoded.push_str(&format!("%{:02X}", b))
+        }
+    }
+    encoded
+}
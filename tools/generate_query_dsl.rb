@@ -37,7 +37,8 @@ end
 class Field
   JSON_SUBS = {'match_type' => 'type',
                'doc_type'   => 'type',
-               'span_match' => 'match'}
+               'span_match' => 'match',
+               'query_name' => '_name'}
 
   # Name of the field
   attr_accessor :name
@@ -162,6 +163,14 @@ class ESDSLGen
       ]
     end
 
+    # The name of a query is part of the query itself, e.g. alongside the other
+    # parameters of a field, rather than outside it as with filters
+    def common_query_options
+      [
+        f('query_name', 'String', true)
+      ]
+    end
+
     def common_filter_options
       [
         f('_cache', 'bool', true),
@@ -403,7 +412,7 @@ class ESDSLGen
         ],
         'TermsQuery' => [
           f('field', 'String'),
-          f('values', 'Vec<JsonVal>'),
+          f('values', 'Terms'),
           f('minimum_should_match', 'MinimumShouldMatch', true)
         ],
         'WildcardQuery' => [
@@ -525,7 +534,7 @@ class ESDSLGen
         ],
         'TermsFilter' => [
           f('field', 'String'),
-          f('values', 'Vec<JsonVal>'),
+          f('values', 'Terms'),
           f('execution', 'Execution', true),
         ],
         'TypeFilter' => [
@@ -574,6 +583,9 @@ class ESDSLGen
       }
 
       query_structs.tap do |all_structs|
+        all_structs.each do |name, fields|
+          fields.concat(common_query_options)
+        end
         filter_structs.each do |name, fields|
           all_structs[name] = fields.concat(common_filter_options)
         end
@@ -630,14 +642,14 @@ class ESDSLGen
 
               #[allow(dead_code, unused_variables)]
               fn add_optionals(&self, m: &mut BTreeMap<String, Json>) {
-                  <% fields.select(&:optional).reject {|f| /^_/ =~ f.json_name }.each do |op_f| %>
+                  <% fields.select(&:optional).reject {|f| /^_/ =~ f.name }.each do |op_f| %>
                       optional_add!(m, self.<%= op_f.name %>, "<%= op_f.json_name %>");
                   <% end %>
               }
 
               #[allow(dead_code, unused_variables)]
               fn add_core_optionals(&self, m: &mut BTreeMap<String, Json>) {
-                  <% fields.select(&:optional).select {|f| /^_/ =~ f.json_name }.each do |op_f| %>
+                  <% fields.select(&:optional).select {|f| /^_/ =~ f.name }.each do |op_f| %>
                       optional_add!(m, self.<%= op_f.name %>, "<%= op_f.json_name %>");
                   <% end %>
               }